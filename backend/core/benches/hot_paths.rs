@@ -0,0 +1,103 @@
+//! Benchmarks of the ingest and retrieval hot paths: chunking, embedding,
+//! and search over stores of growing size.
+//!
+//! Run with `cargo bench -p knora-core`; compare against a saved baseline
+//! with `cargo bench -p knora-core -- --save-baseline main` and
+//! `--baseline main`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use knora_core::models::ChunkingStrategy;
+use knora_core::services::chunker::Chunker;
+use knora_core::{DocumentProcessor, VectorStore};
+use std::fs;
+use std::hint::black_box;
+
+const WORDS: &[&str] = &[
+    "refund", "policy", "customer", "invoice", "shipping", "warranty", "account", "payment", "order", "return",
+    "employee", "leave", "manager", "approval", "travel", "expense", "report", "quarter", "budget", "review",
+    "security", "password", "access", "device", "network", "backup", "incident", "support", "ticket", "release",
+];
+
+/// Deterministic filler prose of roughly `sentences` sentences, varied by
+/// `seed` so documents don't share a vocabulary exactly
+fn prose(seed: usize, sentences: usize) -> String {
+    let mut state = seed.wrapping_mul(2654435761).wrapping_add(1);
+    let mut next_word = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        WORDS[state % WORDS.len()]
+    };
+    (0..sentences)
+        .map(|_| {
+            let words: Vec<&str> = (0..12).map(|_| next_word()).collect();
+            format!("The {}.", words.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn chunking(c: &mut Criterion) {
+    let text = prose(1, 2_000);
+    let mut group = c.benchmark_group("create_chunks");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+
+    group.bench_function("default", |b| {
+        let processor = DocumentProcessor::new(1000, 200);
+        b.iter(|| processor.create_chunks(black_box(&text)))
+    });
+    for strategy in [ChunkingStrategy::Sentence, ChunkingStrategy::Recursive, ChunkingStrategy::Token] {
+        let chunker = Chunker::new(strategy, 1000, 200);
+        group.bench_function(BenchmarkId::new("strategy", format!("{:?}", strategy)), |b| {
+            b.iter(|| chunker.chunk(black_box(&text)))
+        });
+    }
+    group.finish();
+}
+
+/// A store of `documents` indexed files of about ten chunks each
+fn store_with(documents: usize) -> (tempfile::TempDir, VectorStore) {
+    let dir = tempfile::tempdir().unwrap();
+    let files = dir.path().join("files");
+    fs::create_dir_all(&files).unwrap();
+    let processor = DocumentProcessor::new(200, 0);
+    let processed = (0..documents)
+        .map(|i| {
+            let path = files.join(format!("doc-{}.txt", i));
+            fs::write(&path, prose(i, 20)).unwrap();
+            processor.process_file(path.to_str().unwrap()).unwrap()
+        })
+        .collect();
+
+    let mut store = VectorStore::new(dir.path().join("store").to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+    store.add_documents(processed).unwrap();
+    (dir, store)
+}
+
+fn embedding(c: &mut Criterion) {
+    let (_dir, store) = store_with(100);
+    let mut group = c.benchmark_group("generate_embeddings");
+    for batch in [1, 32, 256] {
+        let texts: Vec<String> = (0..batch).map(|i| prose(i, 8)).collect();
+        group.throughput(Throughput::Elements(batch as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(batch), &texts, |b, texts| {
+            b.iter(|| store.generate_embeddings(black_box(texts)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    group.sample_size(20);
+    for documents in [10, 100, 1_000] {
+        let (_dir, store) = store_with(documents);
+        group.bench_with_input(BenchmarkId::new("documents", documents), &store, |b, store| {
+            b.iter(|| store.search(black_box("refund policy for returned orders"), 5, 0.0).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chunking, embedding, search);
+criterion_main!(benches);
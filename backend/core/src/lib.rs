@@ -0,0 +1,33 @@
+//! The KnoRa RAG pipeline without the HTTP server: [`DocumentProcessor`]
+//! turns files into chunks, [`VectorStore`] indexes and searches them, and
+//! [`LLMHandler`] answers questions from the results with any
+//! [`LLMProvider`].
+//!
+//! ```no_run
+//! use knora_core::{AnswerOptions, DocumentProcessor, LLMHandler, OllamaLLM, VectorStore};
+//! use std::sync::Arc;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let processor = DocumentProcessor::new(1000, 40);
+//! let mut store = VectorStore::new("data/vector_store", "all-MiniLM-L6-v2")?;
+//! store.add_documents(vec![processor.process_file("handbook.pdf")?])?;
+//!
+//! let query = "How long do refunds take?";
+//! let chunks = store.search(query, 5, 0.0)?;
+//! let llm = OllamaLLM::new("http://localhost:11434".to_string(), "llama3.1".to_string());
+//! let answer = LLMHandler::new(Arc::new(llm))
+//!     .generate_answer(query, &chunks, &AnswerOptions::default())
+//!     .await?;
+//! println!("{}", answer["answer"]);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod models;
+pub mod services;
+
+pub use models::{ProcessedDocument, SearchResult};
+pub use services::{
+    AnswerOptions, DocumentProcessor, GeminiLLM, GroqLLM, LLMHandler, LLMProvider, OllamaLLM, OpenAICompatibleLLM,
+    VectorStore,
+};
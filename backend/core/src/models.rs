@@ -0,0 +1,1000 @@
+use crate::services::document_dates::{parse_date, DocumentDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use validator::{Validate, ValidationError};
+
+/// Most chunks a request may retrieve per query
+pub const MAX_K: usize = 100;
+/// Most requests in one batch
+pub const MAX_BATCH_SIZE: u64 = 100;
+/// Most cases in one evaluation run
+pub const MAX_EVAL_CASES: u64 = 500;
+/// Most questions one test set request may generate
+pub const MAX_TESTSET_SIZE: usize = 200;
+/// Most documents one comparison may cover
+pub const MAX_COMPARE_DOCUMENTS: u64 = 5;
+/// Most items a batch or evaluation may work on at once
+pub const MAX_CONCURRENCY: usize = 16;
+/// Most extracted text a processed document keeps outside its chunks
+pub const MAX_DOCUMENT_TEXT: usize = 1024 * 1024;
+
+fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank"));
+    }
+    Ok(())
+}
+
+fn valid_date(value: &str) -> Result<(), ValidationError> {
+    parse_date(value).map(|_| ()).ok_or_else(|| ValidationError::new("date"))
+}
+
+fn no_empty_strings(values: &[String]) -> Result<(), ValidationError> {
+    if values.iter().any(String::is_empty) {
+        return Err(ValidationError::new("empty_item"));
+    }
+    Ok(())
+}
+
+/// Collection names and tags are short words: letters, digits, spaces,
+/// and `-_.`
+fn valid_label(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() || value.len() > MAX_LABEL_LEN {
+        return Err(ValidationError::new("length"));
+    }
+    if !value.chars().all(|c| c.is_alphanumeric() || " -_.".contains(c)) {
+        return Err(ValidationError::new("charset"));
+    }
+    Ok(())
+}
+
+fn valid_labels(values: &[String]) -> Result<(), ValidationError> {
+    values.iter().try_for_each(|value| valid_label(value))
+}
+
+/// Longest collection name or tag
+pub const MAX_LABEL_LEN: usize = 64;
+
+/// Strategy used to split extracted text into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingStrategy {
+    /// Fixed-size character windows
+    Fixed,
+    /// Split on paragraphs, lines, sentences, then words until pieces fit
+    Recursive,
+    /// Pack whole sentences up to the chunk size
+    #[default]
+    Sentence,
+    /// Fixed-size windows of whitespace tokens
+    Token,
+    /// Break between sentences where the topic shifts
+    Semantic,
+    /// One chunk per Markdown heading section
+    Markdown,
+    /// Word-aligned windows of the chunk size advancing by a fixed stride
+    Window,
+}
+
+impl std::str::FromStr for ChunkingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "fixed" => Ok(ChunkingStrategy::Fixed),
+            "recursive" => Ok(ChunkingStrategy::Recursive),
+            "sentence" => Ok(ChunkingStrategy::Sentence),
+            "token" => Ok(ChunkingStrategy::Token),
+            "semantic" => Ok(ChunkingStrategy::Semantic),
+            "markdown" => Ok(ChunkingStrategy::Markdown),
+            "window" => Ok(ChunkingStrategy::Window),
+            other => Err(format!("Unknown chunking strategy: {}", other)),
+        }
+    }
+}
+
+/// Unit in which `chunk_overlap` is counted for the packing strategies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverlapUnit {
+    /// Carry over exactly N whitespace-separated tokens
+    #[default]
+    Tokens,
+    /// Carry over exactly N whole sentences (or split pieces for the
+    /// recursive strategy)
+    Sentences,
+}
+
+/// Default chunking settings applied to one file type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkingProfile {
+    pub strategy: ChunkingStrategy,
+    pub chunk_size: usize,
+    pub chunk_overlap: usize,
+}
+
+/// What to do when an answer makes claims the retrieved context doesn't support
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrictMode {
+    /// Return the answer as generated
+    #[default]
+    Off,
+    /// Ask once for a corrected answer, refusing if it is still unsupported
+    Regenerate,
+    /// Reply that the documents don't contain enough information
+    Refuse,
+}
+
+impl std::str::FromStr for StrictMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "none" => Ok(StrictMode::Off),
+            "regenerate" => Ok(StrictMode::Regenerate),
+            "refuse" => Ok(StrictMode::Refuse),
+            other => Err(format!("Unknown strict mode: {}", other)),
+        }
+    }
+}
+
+/// How the store is searched for a query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetrievalMode {
+    /// Embed the query as asked
+    #[default]
+    Standard,
+    /// Hypothetical document embeddings: draft an answer with the LLM and
+    /// search with it, which finds more for short or vague questions
+    Hyde,
+    /// Embed the query, then add chunks mentioning the entities it names
+    /// and entities related to them, for relational questions
+    Graph,
+}
+
+impl std::str::FromStr for RetrievalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "standard" | "off" => Ok(RetrievalMode::Standard),
+            "hyde" => Ok(RetrievalMode::Hyde),
+            "graph" => Ok(RetrievalMode::Graph),
+            other => Err(format!("Unknown retrieval mode: {}", other)),
+        }
+    }
+}
+
+/// How retrieved chunks are turned into an answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SynthesisStrategy {
+    /// Put the top chunks into a single prompt
+    #[default]
+    Stuff,
+    /// Extract relevant facts from each chunk, then answer from the notes
+    MapReduce,
+    /// Answer from the first chunk, then improve the answer chunk by chunk
+    Refine,
+}
+
+/// Optional per-request overrides of the chunking defaults
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct ChunkingParams {
+    pub chunking_strategy: Option<ChunkingStrategy>,
+    #[validate(range(min = 1, message = "chunk_size must be at least 1"))]
+    pub chunk_size: Option<usize>,
+    pub chunk_overlap: Option<usize>,
+    /// Distance between window starts for the window strategy; defaults to
+    /// `chunk_size - chunk_overlap`
+    #[validate(range(min = 1, message = "chunk_stride must be at least 1"))]
+    pub chunk_stride: Option<usize>,
+    pub overlap_unit: Option<OverlapUnit>,
+}
+
+/// Context attached to a document when it's uploaded, for filtering
+/// searches and listing documents
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate)]
+pub struct DocumentLabels {
+    /// Collection the document belongs to, e.g. "handbook"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(custom(
+        function = valid_label,
+        message = "collection must be at most 64 letters, digits, spaces, or -_."
+    ))]
+    pub collection: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[validate(
+        length(max = 20, message = "At most 20 tags are allowed"),
+        custom(function = valid_labels, message = "Each tag must be at most 64 letters, digits, spaces, or -_.")
+    )]
+    pub tags: Vec<String>,
+    /// Language of the document's text, e.g. "en" or "de"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 2, max = 16, message = "language must be a code such as en or pt-BR"))]
+    pub language: Option<String>,
+    /// Days to keep the document after it's first indexed, overriding any
+    /// retention period of its tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(range(min = 1, max = 36500, message = "retain_days must be between 1 and 36500"))]
+    pub retain_days: Option<u32>,
+}
+
+impl DocumentLabels {
+    pub fn is_empty(&self) -> bool {
+        self.collection.is_none() && self.tags.is_empty() && self.language.is_none() && self.retain_days.is_none()
+    }
+
+    /// Add tags from a comma-separated list, trimmed and lowercased, each
+    /// once
+    pub fn add_tags(&mut self, tags: &str) {
+        for tag in tags.split(',').map(|tag| tag.trim().to_lowercase()).filter(|tag| !tag.is_empty()) {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+}
+
+/// Position of a chunk within the structure of its source file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    /// 1-based PDF page number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<usize>,
+    /// Excel sheet name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sheet: Option<String>,
+    /// 1-based PPTX slide number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slide: Option<usize>,
+    /// Markdown heading the chunk falls under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+impl SourceLocation {
+    pub fn is_empty(&self) -> bool {
+        *self == SourceLocation::default()
+    }
+}
+
+/// Represents a chunk of a document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    /// Shared with the store's metadata for the chunk once indexed, so the
+    /// text is held once
+    pub text: Arc<str>,
+    pub size: usize,
+    pub chunk_id: usize,
+    /// Index of the enclosing parent section when small-to-big chunking is enabled
+    #[serde(default)]
+    pub parent_id: Option<usize>,
+    #[serde(default, skip_serializing_if = "SourceLocation::is_empty")]
+    pub location: SourceLocation,
+}
+
+/// Stable identifier of an indexed document, derived from its file path
+pub fn document_id(file_path: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(file_path.as_bytes());
+    hex::encode(&digest[..8])
+}
+
+/// Represents a processed document with its metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessedDocument {
+    #[serde(default)]
+    pub document_id: String,
+    pub file_path: String,
+    pub file_name: String,
+    pub file_type: String,
+    /// The extracted text, up to `MAX_DOCUMENT_TEXT` bytes of it; the rest
+    /// of a larger file's text is only in its chunks. Returned to callers
+    /// but not kept by the store.
+    pub text: String,
+    /// Length of the full extracted text
+    #[serde(default)]
+    pub text_length: usize,
+    pub chunks: Vec<DocumentChunk>,
+    pub num_chunks: usize,
+    pub file_size: u64,
+    /// Larger sections that `chunks` were split from (empty when disabled)
+    #[serde(default)]
+    pub parent_chunks: Vec<DocumentChunk>,
+    #[serde(default)]
+    pub chunking_strategy: ChunkingStrategy,
+    /// When the document applies from, if one could be found
+    #[serde(default)]
+    pub date: Option<DocumentDate>,
+    #[serde(flatten)]
+    pub labels: DocumentLabels,
+}
+
+/// Represents a search result from the vector store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub file_path: String,
+    pub file_name: String,
+    pub file_type: String,
+    pub chunk_id: usize,
+    pub chunk_size: usize,
+    pub text: String,
+    pub similarity_score: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<usize>,
+    /// Full parent section, used as LLM context instead of the matched chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_text: Option<String>,
+    #[serde(default, skip_serializing_if = "SourceLocation::is_empty")]
+    pub location: SourceLocation,
+    /// Relevance from LLM reranking, 0.0 to 1.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rerank_score: Option<f32>,
+    /// Parts of `text` matching the query, for the UI to emphasise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highlights: Option<Highlights>,
+    /// Date of the chunk's document (YYYY-MM-DD)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_date: Option<String>,
+}
+
+/// A range of a chunk's text, counted in characters rather than bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where a search result matches its query
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Highlights {
+    /// Every occurrence of a query term
+    pub terms: Vec<TextSpan>,
+    /// The sentence containing the most distinct query terms
+    pub passage: Option<TextSpan>,
+}
+
+/// Represents a response from the LLM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct LLMResponse {
+    pub answer: String,
+    pub sources: Vec<HashMap<String, serde_json::Value>>,
+    pub context_used: String,
+    pub num_sources: usize,
+    pub llm_type: String,
+    pub model_used: String,
+}
+
+/// Represents statistics from the vector store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct VectorStoreStats {
+    pub total_vectors: usize,
+    pub total_documents: usize,
+    pub embedding_model: String,
+    pub dimension: usize,
+    pub store_path: String,
+    pub documents: Vec<String>,
+    pub storage_size_mb: f64,
+}
+
+/// Request to process files
+#[derive(Debug, Deserialize, Validate)]
+pub struct ProcessFileRequest {
+    #[validate(custom(function = not_blank, message = "file_path must not be empty"))]
+    pub file_path: String,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub chunking: ChunkingParams,
+}
+
+/// Who besides its owner may search a document
+#[derive(Debug, Deserialize)]
+pub struct ShareRequest {
+    /// API key names, or "*" for everyone; replaces the current list
+    pub shared_with: Vec<String>,
+}
+
+/// Response from processing files
+#[derive(Debug, Serialize)]
+pub struct ProcessFileResponse {
+    pub success: bool,
+    pub message: String,
+    pub document: Option<ProcessedDocument>,
+}
+
+/// Request to search documents
+#[derive(Debug, Deserialize, Validate)]
+pub struct SearchRequest {
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[validate(range(min = 0.0, max = 1.0, message = "score_threshold must be between 0 and 1"))]
+    pub score_threshold: Option<f32>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub filters: SearchFilters,
+    /// Also return how every candidate chunk was scored and filtered
+    #[serde(default)]
+    pub debug: bool,
+}
+
+/// Restricts a search to some documents or file types; an empty list
+/// matches everything
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+#[serde(default)]
+pub struct SearchFilters {
+    /// Document ids, file paths, or file names
+    #[serde(alias = "file_paths")]
+    pub documents: Vec<String>,
+    /// Extensions such as ".pdf" or "pdf"
+    pub file_types: Vec<String>,
+    /// Only documents dated on or before this day (YYYY-MM-DD); undated
+    /// documents are always searched
+    #[validate(custom(function = valid_date, message = "as_of must be a date such as 2024-03-15"))]
+    pub as_of: Option<String>,
+    /// Only documents in one of these collections
+    pub collections: Vec<String>,
+    /// Only documents with every one of these tags
+    pub tags: Vec<String>,
+    /// Only documents this user owns or was shared, and unowned ones; set
+    /// from the caller's API key, never from the request
+    #[serde(skip)]
+    pub visible_to: Option<String>,
+}
+
+impl SearchFilters {
+    /// Whether a document with `date` existed as of the filter's date
+    pub fn matches_date(&self, date: Option<&DocumentDate>) -> bool {
+        let as_of = self.as_of.as_deref().and_then(parse_date);
+        match (as_of, date.and_then(DocumentDate::day)) {
+            (Some(as_of), Some(date)) => date <= as_of,
+            _ => true,
+        }
+    }
+
+    /// Whether a document labeled `labels` is in the filter's collections
+    /// and has its tags
+    pub fn matches_labels(&self, labels: &DocumentLabels) -> bool {
+        let in_collection = self.collections.is_empty()
+            || labels
+                .collection
+                .as_ref()
+                .is_some_and(|collection| self.collections.iter().any(|c| c.eq_ignore_ascii_case(collection)));
+        in_collection
+            && self
+                .tags
+                .iter()
+                .all(|tag| labels.tags.iter().any(|t| t.eq_ignore_ascii_case(tag.trim())))
+    }
+
+    pub fn matches_file_type(&self, file_type: &str) -> bool {
+        let normalize = |t: &str| t.trim().trim_start_matches('.').to_lowercase();
+        self.file_types.is_empty() || self.file_types.iter().any(|t| normalize(t) == normalize(file_type))
+    }
+}
+
+/// Response from search
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    pub query: String,
+    pub count: usize,
+    /// Scoring details when the request asked for them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<serde_json::Value>,
+}
+
+/// Sampling settings beyond temperature. Unset fields use the provider's
+/// defaults; providers ignore settings they don't support (top_k is only
+/// honoured by Ollama and Gemini).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct SamplingParams {
+    /// Nucleus sampling mass, in (0, 1]
+    #[validate(range(exclusive_min = 0.0, max = 1.0, message = "top_p must be greater than 0 and at most 1"))]
+    pub top_p: Option<f32>,
+    /// Sample from only the k most likely tokens
+    #[validate(range(min = 1, message = "top_k must be at least 1"))]
+    pub top_k: Option<u32>,
+    /// Sequences that end the completion; at most `MAX_STOP_SEQUENCES`
+    #[validate(
+        length(max = SamplingParams::MAX_STOP_SEQUENCES, message = "At most 4 stop sequences are allowed"),
+        custom(function = no_empty_strings, message = "Stop sequences must not be empty")
+    )]
+    pub stop: Vec<String>,
+    /// Best-effort deterministic sampling where the provider supports it
+    pub seed: Option<u64>,
+    /// In [-2, 2]; positive values discourage repeating tokens
+    #[validate(range(min = -2.0, max = 2.0, message = "frequency_penalty must be between -2 and 2"))]
+    pub frequency_penalty: Option<f32>,
+    /// In [-2, 2]; positive values encourage new topics
+    #[validate(range(min = -2.0, max = 2.0, message = "presence_penalty must be between -2 and 2"))]
+    pub presence_penalty: Option<f32>,
+}
+
+impl SamplingParams {
+    /// Most stop sequences OpenAI-compatible providers accept
+    pub const MAX_STOP_SEQUENCES: u64 = 4;
+}
+
+/// Request to generate answer
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AnswerRequest {
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    pub retrieved_chunks: Vec<SearchResult>,
+    #[validate(range(min = 1, message = "max_tokens must be at least 1"))]
+    pub max_tokens: Option<usize>,
+    #[validate(range(min = 0.0, max = 2.0, message = "temperature must be between 0 and 2"))]
+    pub temperature: Option<f32>,
+    /// LLM provider to answer with; defaults to `LLM_PROVIDER`
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Model to answer with; defaults to the provider's configured model
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Replaces the configured system prompt for this request
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Name of a prompt template to build the user message from
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Earlier turns of the conversation, oldest first
+    #[serde(default)]
+    pub history: Vec<ChatMessage>,
+    /// Let the model call registered tools before answering
+    #[serde(default)]
+    pub use_tools: bool,
+    /// Guard against unsupported claims; defaults to `STRICT_MODE`
+    #[serde(default)]
+    pub strict_mode: Option<StrictMode>,
+    /// Language to answer in, e.g. "German", or "auto" to match the query
+    #[serde(default)]
+    pub language: Option<String>,
+    /// How to combine the retrieved chunks; map_reduce and refine read more
+    /// of them than the default stuff strategy
+    #[serde(default)]
+    pub strategy: SynthesisStrategy,
+    /// Also suggest follow-up questions the retrieved context can answer
+    #[serde(default)]
+    pub follow_ups: bool,
+    /// Tokens of retrieved text to fit into the context, choosing chunks by
+    /// score and novelty instead of taking the top few
+    #[validate(range(min = 1, message = "context_budget must be at least 1"))]
+    pub context_budget: Option<usize>,
+    /// top_p, top_k, stop, seed and penalties, given at the top level
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub sampling: SamplingParams,
+}
+
+/// A query and the documents a good retrieval should return for it
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RetrievalCase {
+    /// Also read as "question", so generated test sets can be used as-is
+    #[serde(alias = "question")]
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    /// Document ids, file paths, or file names
+    #[validate(length(min = 1, message = "expected_documents must not be empty"))]
+    pub expected_documents: Vec<String>,
+}
+
+/// Gold set to measure retrieval against
+#[derive(Debug, Deserialize, Validate)]
+pub struct RetrievalEvalRequest {
+    #[validate(length(min = 1, max = MAX_EVAL_CASES, message = "cases must hold between 1 and 500 cases"), nested)]
+    pub cases: Vec<RetrievalCase>,
+    /// Chunks retrieved per query; defaults to 5
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub filters: SearchFilters,
+}
+
+/// A question for end-to-end evaluation, optionally with the answer a
+/// good run should give
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct RagCase {
+    #[validate(custom(function = not_blank, message = "question must not be empty"))]
+    pub question: String,
+    #[serde(default)]
+    pub reference_answer: Option<String>,
+}
+
+/// Labeled questions to run through retrieval and generation and grade
+#[derive(Debug, Deserialize, Validate)]
+pub struct RagEvalRequest {
+    /// Label for the saved run, e.g. "chunk-size-800"
+    #[serde(default)]
+    pub name: Option<String>,
+    #[validate(length(min = 1, max = MAX_EVAL_CASES, message = "cases must hold between 1 and 500 cases"), nested)]
+    pub cases: Vec<RagCase>,
+    /// Chunks retrieved per question; defaults to 5
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub filters: SearchFilters,
+    /// Provider and model that answer; default to the configured ones
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Provider and model that grade; default to the answering ones
+    #[serde(default)]
+    pub judge_provider: Option<String>,
+    #[serde(default)]
+    pub judge_model: Option<String>,
+    /// Questions evaluated at once
+    #[validate(range(min = 1, max = MAX_CONCURRENCY, message = "concurrency must be between 1 and 16"))]
+    pub concurrency: Option<usize>,
+}
+
+/// Documents to generate labeled evaluation questions from
+#[derive(Debug, Deserialize, Validate)]
+pub struct TestsetRequest {
+    /// Document ids, file paths, or file names; every document when empty
+    #[serde(default, alias = "documents")]
+    pub file_paths: Vec<String>,
+    /// Questions to generate; defaults to 10
+    #[validate(range(min = 1, max = MAX_TESTSET_SIZE, message = "n must be between 1 and 200"))]
+    pub n: Option<usize>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Chunks turned into questions at once
+    #[validate(range(min = 1, max = MAX_CONCURRENCY, message = "concurrency must be between 1 and 16"))]
+    pub concurrency: Option<usize>,
+}
+
+/// Question answered from the store in one call: retrieval and generation
+/// both happen server-side
+#[derive(Debug, Deserialize, Validate)]
+pub struct QueryRequest {
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    /// Named pipeline from `PIPELINES_FILE` whose stages fill the settings
+    /// left unset here; defaults to `DEFAULT_PIPELINE`
+    #[serde(default)]
+    pub pipeline: Option<String>,
+    /// Chunks to retrieve; defaults to 5
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[validate(range(min = 0.0, max = 1.0, message = "score_threshold must be between 0 and 1"))]
+    pub score_threshold: Option<f32>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub filters: SearchFilters,
+    /// Temporary workspace to search instead of the knowledge base
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// `standard` or `hyde`; defaults to `RETRIEVAL_MODE`
+    #[serde(default)]
+    pub retrieval: Option<RetrievalMode>,
+    /// Also search LLM-written paraphrases and sub-questions of the query,
+    /// merging the results with rank fusion
+    #[serde(default)]
+    pub expand_query: bool,
+    /// Break a complex question into sub-questions, search and answer each,
+    /// then combine the answers
+    #[serde(default)]
+    pub decompose: bool,
+    /// Return how the search scored and filtered candidates and which of
+    /// them reached the answer's context
+    #[serde(default)]
+    pub debug: bool,
+    #[validate(range(min = 1, message = "max_tokens must be at least 1"))]
+    pub max_tokens: Option<usize>,
+    #[validate(range(min = 0.0, max = 2.0, message = "temperature must be between 0 and 2"))]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub history: Vec<ChatMessage>,
+    #[serde(default)]
+    pub use_tools: bool,
+    #[serde(default)]
+    pub strict_mode: Option<StrictMode>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub strategy: SynthesisStrategy,
+    /// Also suggest follow-up questions the retrieved context can answer
+    #[serde(default)]
+    pub follow_ups: bool,
+    /// Tokens of retrieved text to fit into the context, choosing chunks by
+    /// score and novelty instead of taking the top few
+    #[validate(range(min = 1, message = "context_budget must be at least 1"))]
+    pub context_budget: Option<usize>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub sampling: SamplingParams,
+}
+
+/// Question comparing several documents, each searched on its own
+#[derive(Debug, Deserialize, Validate)]
+pub struct CompareRequest {
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    /// Document ids, file paths, or file names; two to five
+    #[validate(length(min = 2, max = MAX_COMPARE_DOCUMENTS, message = "documents must list between 2 and 5 documents"))]
+    pub documents: Vec<String>,
+    /// Chunks retrieved from each document; defaults to 4
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[validate(range(min = 0.0, max = 1.0, message = "score_threshold must be between 0 and 1"))]
+    pub score_threshold: Option<f32>,
+    #[validate(range(min = 1, message = "max_tokens must be at least 1"))]
+    pub max_tokens: Option<usize>,
+    #[validate(range(min = 0.0, max = 2.0, message = "temperature must be between 0 and 2"))]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub sampling: SamplingParams,
+}
+
+/// Whether an answer from an experiment variant helped
+#[derive(Debug, Deserialize, Validate)]
+pub struct ExperimentFeedbackRequest {
+    /// `experiment.request_id` from the `/api/query` response
+    #[validate(custom(function = not_blank, message = "request_id must not be empty"))]
+    pub request_id: String,
+    pub helpful: bool,
+}
+
+/// Several answer requests processed together
+#[derive(Debug, Deserialize, Validate)]
+pub struct BatchAnswerRequest {
+    #[validate(length(min = 1, max = MAX_BATCH_SIZE, message = "requests must hold between 1 and 100 requests"), nested)]
+    pub requests: Vec<AnswerRequest>,
+    /// Answers generated at once; defaults to 4, at most 16
+    #[serde(default)]
+    #[validate(range(min = 1, max = MAX_CONCURRENCY, message = "concurrency must be between 1 and 16"))]
+    pub concurrency: Option<usize>,
+}
+
+/// A query sent over the chat WebSocket; earlier turns on the same
+/// connection are used as history
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChatRequest {
+    #[validate(custom(function = not_blank, message = "query must not be empty"))]
+    pub query: String,
+    #[validate(range(min = 1, max = MAX_K, message = "k must be between 1 and 100"))]
+    pub k: Option<usize>,
+    #[validate(range(min = 0.0, max = 1.0, message = "score_threshold must be between 0 and 1"))]
+    pub score_threshold: Option<f32>,
+    #[validate(range(min = 1, message = "max_tokens must be at least 1"))]
+    pub max_tokens: Option<usize>,
+    #[validate(range(min = 0.0, max = 2.0, message = "temperature must be between 0 and 2"))]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub retrieval: Option<RetrievalMode>,
+    #[serde(default)]
+    pub follow_ups: bool,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub sampling: SamplingParams,
+    /// Forget the connection's earlier turns before answering
+    #[serde(default)]
+    pub reset: bool,
+}
+
+/// Shape of a document summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryStyle {
+    /// A short overview paragraph followed by key points
+    #[default]
+    Executive,
+    /// Bullet points only
+    Bullets,
+    /// Section-by-section detail
+    Detailed,
+    /// Two or three sentences
+    Brief,
+}
+
+/// Request to summarize a whole document
+#[derive(Debug, Deserialize, Validate)]
+pub struct SummarizeRequest {
+    /// Indexed document id, file path, or file name; a path on disk that
+    /// isn't indexed is processed on the fly
+    #[validate(custom(function = not_blank, message = "file_path must not be empty"))]
+    pub file_path: String,
+    #[serde(default)]
+    pub style: SummaryStyle,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Language to summarize in; defaults to the document's own
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// A single chat message in OpenAI role/content form
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: String,
+    /// Tools the assistant asked to call in this turn
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// For `tool` messages, the call this is the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "system".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "user".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: content.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn assistant_tool_calls(content: impl Into<String>, tool_calls: Vec<ToolCall>) -> Self {
+        ChatMessage {
+            role: "assistant".to_string(),
+            content: content.into(),
+            tool_calls,
+            ..Default::default()
+        }
+    }
+
+    pub fn tool_result(tool_call_id: impl Into<String>, content: impl Into<String>) -> Self {
+        ChatMessage {
+            role: "tool".to_string(),
+            content: content.into(),
+            tool_call_id: Some(tool_call_id.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// A model's request to call a tool, in OpenAI function-calling form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_call_type")]
+    pub kind: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON-encoded arguments
+    pub arguments: String,
+}
+
+fn function_call_type() -> String {
+    "function".to_string()
+}
+
+/// Response from LLM
+#[derive(Debug, Serialize)]
+#[allow(dead_code)]
+pub struct AnswerResponse {
+    pub answer: String,
+    pub sources: Vec<HashMap<String, serde_json::Value>>,
+    pub context_used: String,
+    pub num_sources: usize,
+    pub llm_type: String,
+    pub model_used: String,
+}
+
+/// Document metadata for storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    pub file_path: String,
+    pub file_name: String,
+    pub file_type: String,
+    pub chunk_id: usize,
+    pub chunk_size: usize,
+    pub text: Arc<str>,
+    #[serde(default)]
+    pub parent_id: Option<usize>,
+    #[serde(default, skip_serializing_if = "SourceLocation::is_empty")]
+    pub location: SourceLocation,
+}
+
+/// Cache statistics
+#[derive(Debug, Serialize)]
+#[allow(dead_code)]
+pub struct CacheStats {
+    pub size: usize,
+    pub max_size: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub hit_rate: String,
+}
+
+/// Supported file extensions
+#[allow(dead_code)]
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[".pdf", ".txt", ".doc", ".docx", ".csv", ".xlsx", ".xls", ".md", ".pptx"];
+
+/// LLM Models
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LLMModel {
+    pub id: String,
+    pub name: String,
+    pub max_tokens: usize,
+}
+
+/// Groq models known at release time, used when the live catalog can't be fetched
+pub fn get_supported_models() -> Vec<LLMModel> {
+    vec![
+        LLMModel {
+            id: "openai/gpt-oss-120b".to_string(),
+            name: "OpenAI GPT-OSS 120B".to_string(),
+            max_tokens: 8192,
+        },
+        LLMModel {
+            id: "openai/gpt-oss-20b".to_string(),
+            name: "OpenAI GPT-OSS 20B".to_string(),
+            max_tokens: 8192,
+        },
+        LLMModel {
+            id: "llama-3.3-70b-versatile".to_string(),
+            name: "LLaMA 3.3 70B Versatile".to_string(),
+            max_tokens: 32768,
+        },
+        LLMModel {
+            id: "llama-3.1-8b-instant".to_string(),
+            name: "LLaMA 3.1 8B Instant".to_string(),
+            max_tokens: 8192,
+        },
+    ]
+}
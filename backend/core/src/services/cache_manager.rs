@@ -0,0 +1,675 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// A cache from keys to values, whether kept in memory, on disk, or in
+/// Redis. Backends that can fail log it and treat the entry as missing.
+pub trait Cache<K: ?Sized, V>: Send + Sync {
+    fn get(&self, key: &K) -> Option<V>;
+
+    fn put(&self, key: &K, value: V);
+
+    /// Drop the entry for `key`, if any
+    fn invalidate(&self, key: &K) -> bool;
+
+    /// Drop every entry `stale` is true for. Returns how many were dropped.
+    fn invalidate_where(&self, stale: &dyn Fn(&V) -> bool) -> usize;
+
+    /// Drop expired entries now rather than when they're next read or
+    /// make room. Returns how many were dropped.
+    fn purge_expired(&self) -> usize {
+        0
+    }
+
+    fn stats(&self) -> CacheStats;
+}
+
+/// Where the embedding and answer caches are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    /// In this process, lost on restart unless saved
+    #[default]
+    Memory,
+    /// One file per entry in a directory
+    Disk,
+    /// A Redis server, shared by every instance
+    Redis,
+}
+
+impl std::str::FromStr for CacheBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "memory" => Ok(CacheBackend::Memory),
+            "disk" => Ok(CacheBackend::Disk),
+            "redis" => Ok(CacheBackend::Redis),
+            other => Err(format!("Unknown cache backend: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct CacheStats {
+    pub size: usize,
+    /// 0 for Redis, which bounds itself
+    pub max_size: usize,
+    pub hits: usize,
+    pub misses: usize,
+    pub hit_rate: String,
+    /// Entries dropped to make room, least recently used first
+    #[serde(default)]
+    pub evictions: usize,
+    /// Entries found past their TTL, counted as misses too
+    #[serde(default)]
+    pub expirations: usize,
+    /// Seconds entries live for; `None` when they don't expire
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+/// Lookup and eviction counts of backends that don't keep their own
+#[derive(Default)]
+pub(crate) struct CacheCounters {
+    pub hits: AtomicUsize,
+    pub misses: AtomicUsize,
+    pub evictions: AtomicUsize,
+    pub expirations: AtomicUsize,
+}
+
+impl CacheCounters {
+    pub fn count(counter: &AtomicUsize, n: usize) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, size: usize, max_size: usize, ttl: Option<Duration>) -> CacheStats {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let (hits, misses) = (load(&self.hits), load(&self.misses));
+        CacheStats {
+            size,
+            max_size,
+            hits,
+            misses,
+            hit_rate: hit_rate(hits, misses),
+            evictions: load(&self.evictions),
+            expirations: load(&self.expirations),
+            ttl_secs: ttl.map(|ttl| ttl.as_secs()),
+        }
+    }
+}
+
+fn hit_rate(hits: usize, misses: usize) -> String {
+    let total = hits + misses;
+    if total > 0 {
+        format!("{:.1}%", (hits as f64 / total as f64) * 100.0)
+    } else {
+        "0.0%".to_string()
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    expires_at: Option<Instant>,
+    /// Position in `LruState::order`
+    last_used: u64,
+}
+
+struct LruState<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Keys by when they were last used, oldest first
+    order: BTreeMap<u64, String>,
+    next_use: u64,
+    hits: usize,
+    misses: usize,
+    evictions: usize,
+    expirations: usize,
+}
+
+impl<V> LruState<V> {
+    fn touch(&mut self, key: &str) {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return;
+        };
+        self.order.remove(&entry.last_used);
+        entry.last_used = self.next_use;
+        self.order.insert(self.next_use, key.to_string());
+        self.next_use += 1;
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.last_used);
+        Some(entry)
+    }
+
+    /// Remove the entries expired at `now`, returning how many there were
+    fn remove_expired(&mut self, now: Instant) -> usize {
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at.is_some_and(|expires_at| now >= expires_at))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        self.expirations += expired.len();
+        expired.len()
+    }
+}
+
+/// An entry as written to disk
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedEntry<V> {
+    pub key: String,
+    pub value: V,
+    /// Milliseconds since the Unix epoch
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Bounded map from string keys that evicts the least recently used entry
+/// when full and drops entries older than their TTL
+pub struct LruCache<V> {
+    max_size: usize,
+    ttl: Option<Duration>,
+    state: Mutex<LruState<V>>,
+}
+
+impl<V: Clone> LruCache<V> {
+    /// Holds up to `max_size` entries that never expire
+    pub fn new(max_size: usize) -> Self {
+        LruCache {
+            max_size,
+            ttl: None,
+            state: Mutex::new(LruState {
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                next_use: 0,
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+                expirations: 0,
+            }),
+        }
+    }
+
+    /// Expire entries `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        let expired = match state.entries.get(key) {
+            None => {
+                state.misses += 1;
+                return None;
+            }
+            Some(entry) => entry.expires_at.is_some_and(|expires_at| now >= expires_at),
+        };
+        if expired {
+            state.remove(key);
+            state.expirations += 1;
+            state.misses += 1;
+            return None;
+        }
+        state.touch(key);
+        state.hits += 1;
+        state.entries.get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Store `value`, living for the cache's TTL
+    pub fn put(&self, key: &str, value: V) {
+        self.put_at(key, value, self.ttl, Instant::now());
+    }
+
+    /// Store `value` for `ttl` instead of the cache's TTL
+    pub fn put_with_ttl(&self, key: &str, value: V, ttl: Duration) {
+        self.put_at(key, value, Some(ttl), Instant::now());
+    }
+
+    fn put_at(&self, key: &str, value: V, ttl: Option<Duration>, now: Instant) {
+        if self.max_size == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.remove(key);
+
+        // Expired entries go before live ones are evicted
+        if state.entries.len() >= self.max_size {
+            state.remove_expired(now);
+        }
+        while state.entries.len() >= self.max_size {
+            let Some((_, oldest)) = state.order.pop_first() else {
+                break;
+            };
+            state.entries.remove(&oldest);
+            state.evictions += 1;
+        }
+
+        let last_used = state.next_use;
+        state.next_use += 1;
+        state.order.insert(last_used, key.to_string());
+        state.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at: ttl.map(|ttl| now + ttl),
+                last_used,
+            },
+        );
+    }
+
+    /// Drop the entry for `key`, if any
+    pub fn invalidate(&self, key: &str) -> bool {
+        self.state.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Drop every entry `stale` is true for. Returns how many were dropped.
+    pub fn invalidate_where(&self, stale: impl Fn(&V) -> bool) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let keys: Vec<String> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| stale(&entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            state.remove(key);
+        }
+        keys.len()
+    }
+
+    /// Drop every expired entry, returning how many there were
+    pub fn purge_expired(&self) -> usize {
+        self.state.lock().unwrap().remove_expired(Instant::now())
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        CacheStats {
+            size: state.entries.len(),
+            max_size: self.max_size,
+            hits: state.hits,
+            misses: state.misses,
+            hit_rate: hit_rate(state.hits, state.misses),
+            evictions: state.evictions,
+            expirations: state.expirations,
+            ttl_secs: self.ttl.map(|ttl| ttl.as_secs()),
+        }
+    }
+
+    /// Write the live entries to `path` as JSON, least recently used
+    /// first, replacing the file only once it's complete. Returns how many
+    /// were written.
+    pub fn save(&self, path: &Path) -> Result<usize>
+    where
+        V: Serialize,
+    {
+        let (now, wall_now) = (Instant::now(), SystemTime::now());
+        let saved: Vec<SavedEntry<V>> = {
+            let state = self.state.lock().unwrap();
+            state
+                .order
+                .values()
+                .filter_map(|key| {
+                    let entry = state.entries.get(key)?;
+                    let remaining = match entry.expires_at {
+                        Some(expires_at) if now >= expires_at => return None,
+                        Some(expires_at) => Some(expires_at - now),
+                        None => None,
+                    };
+                    Some(SavedEntry {
+                        key: key.clone(),
+                        value: entry.value.clone(),
+                        expires_at: remaining.map(|remaining| unix_millis(wall_now + remaining)),
+                    })
+                })
+                .collect()
+        };
+
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(&saved)?)
+            .with_context(|| format!("Failed to write cache to {}", temp.display()))?;
+        fs::rename(&temp, path)?;
+        Ok(saved.len())
+    }
+
+    /// Add the unexpired entries saved to `path` by `save`, keeping their
+    /// order of use. Returns how many were added; none without a file.
+    pub fn load(&self, path: &Path) -> Result<usize>
+    where
+        V: DeserializeOwned,
+    {
+        let json = match fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let saved: Vec<SavedEntry<V>> = serde_json::from_slice(&json)
+            .with_context(|| format!("Invalid cache file {}", path.display()))?;
+
+        let (now, wall_now) = (Instant::now(), unix_millis(SystemTime::now()));
+        let mut loaded = 0;
+        for entry in saved {
+            let ttl = match entry.expires_at {
+                Some(expires_at) if expires_at <= wall_now => continue,
+                Some(expires_at) => Some(Duration::from_millis(expires_at - wall_now)),
+                None => None,
+            };
+            self.put_at(&entry.key, entry.value, ttl, now);
+            loaded += 1;
+        }
+        Ok(loaded.min(self.max_size))
+    }
+
+    /// Drop every entry and reset the counters
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.order.clear();
+        state.hits = 0;
+        state.misses = 0;
+        state.evictions = 0;
+        state.expirations = 0;
+    }
+}
+
+impl<V: Clone + Send> Cache<str, V> for LruCache<V> {
+    fn get(&self, key: &str) -> Option<V> {
+        LruCache::get(self, key)
+    }
+
+    fn put(&self, key: &str, value: V) {
+        LruCache::put(self, key, value)
+    }
+
+    fn invalidate(&self, key: &str) -> bool {
+        LruCache::invalidate(self, key)
+    }
+
+    fn invalidate_where(&self, stale: &dyn Fn(&V) -> bool) -> usize {
+        LruCache::invalidate_where(self, stale)
+    }
+
+    fn purge_expired(&self) -> usize {
+        LruCache::purge_expired(self)
+    }
+
+    fn stats(&self) -> CacheStats {
+        LruCache::stats(self)
+    }
+}
+
+pub struct EmbeddingCache {
+    cache: LruCache<Vec<f32>>,
+}
+
+impl EmbeddingCache {
+    pub fn new(max_size: usize) -> Self {
+        EmbeddingCache {
+            cache: LruCache::new(max_size),
+        }
+    }
+
+    /// Expire embeddings `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.cache = self.cache.with_ttl(ttl);
+        self
+    }
+
+    fn get_key(text: &str) -> String {
+        use sha2::{Sha256, Digest};
+        use hex::encode;
+
+        let mut hasher = Sha256::new();
+        hasher.update(text);
+        encode(hasher.finalize())
+    }
+
+    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
+        self.cache.get(&Self::get_key(text))
+    }
+
+    pub fn put(&self, text: &str, embedding: Vec<f32>) {
+        self.cache.put(&Self::get_key(text), embedding);
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&self) {
+        self.cache.clear();
+        info!("Embedding cache cleared");
+    }
+}
+
+impl Cache<str, Vec<f32>> for EmbeddingCache {
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        EmbeddingCache::get(self, text)
+    }
+
+    fn put(&self, text: &str, embedding: Vec<f32>) {
+        EmbeddingCache::put(self, text, embedding)
+    }
+
+    fn invalidate(&self, text: &str) -> bool {
+        self.cache.invalidate(&Self::get_key(text))
+    }
+
+    fn invalidate_where(&self, stale: &dyn Fn(&Vec<f32>) -> bool) -> usize {
+        self.cache.invalidate_where(stale)
+    }
+
+    fn purge_expired(&self) -> usize {
+        self.cache.purge_expired()
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.get_stats()
+    }
+}
+
+#[allow(dead_code)]
+pub struct QueryResponseCache {
+    cache: LruCache<serde_json::Value>,
+}
+
+impl QueryResponseCache {
+    #[allow(dead_code)]
+    pub fn new(max_size: usize) -> Self {
+        QueryResponseCache {
+            cache: LruCache::new(max_size),
+        }
+    }
+
+    /// Expire responses `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.cache = self.cache.with_ttl(ttl);
+        self
+    }
+
+    #[allow(dead_code)]
+    fn get_key(query: &str, context_hash: &str) -> String {
+        use sha2::{Sha256, Digest};
+        use hex::encode;
+
+        let combined = format!("{}_{}", query, context_hash);
+        let mut hasher = Sha256::new();
+        hasher.update(combined);
+        encode(hasher.finalize())
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, query: &str, context_hash: &str) -> Option<serde_json::Value> {
+        self.cache.get(&Self::get_key(query, context_hash))
+    }
+
+    #[allow(dead_code)]
+    pub fn put(&self, query: &str, context_hash: &str, response: serde_json::Value) {
+        self.cache.put(&Self::get_key(query, context_hash), response);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&self) {
+        self.cache.clear();
+        info!("Query response cache cleared");
+    }
+
+    /// Drop the responses drawing on any of the documents at `file_paths`.
+    /// Returns how many were dropped.
+    pub fn invalidate_documents(&self, file_paths: &[String]) -> usize {
+        self.cache.invalidate_where(|response| {
+            response["sources"].as_array().is_some_and(|sources| {
+                sources
+                    .iter()
+                    .filter_map(|source| source["file_path"].as_str())
+                    .any(|path| file_paths.iter().any(|p| p == path))
+            })
+        })
+    }
+
+    /// Write the cached responses to `path`, see `LruCache::save`
+    pub fn save(&self, path: &Path) -> Result<usize> {
+        self.cache.save(path)
+    }
+
+    /// Add the responses saved to `path`, see `LruCache::load`
+    pub fn load(&self, path: &Path) -> Result<usize> {
+        self.cache.load(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_evicts_least_recently_used() {
+        let cache = LruCache::new(2);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        // Reading "a" makes "b" the least recently used
+        assert_eq!(cache.get("a"), Some(1));
+        cache.put("c", 3);
+
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+        // Replacing an entry doesn't evict another
+        cache.put("c", 4);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("c"), Some(4));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (4, 1, 1));
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let cache = LruCache::new(2).with_ttl(Duration::from_secs(60));
+        let start = Instant::now();
+        cache.put_at("a", 1, cache.ttl, start);
+        cache.put_at("short", 2, Some(Duration::from_secs(5)), start);
+
+        assert_eq!(cache.get_at("short", start + Duration::from_secs(4)), Some(2));
+        assert_eq!(cache.get_at("short", start + Duration::from_secs(5)), None);
+        assert_eq!(cache.get_at("a", start + Duration::from_secs(59)), Some(1));
+        assert_eq!(cache.get_at("a", start + Duration::from_secs(60)), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().expirations, 2);
+
+        // Expired entries make room before live ones are evicted
+        cache.put_at("old", 1, Some(Duration::from_secs(1)), start);
+        cache.put_at("live", 2, None, start);
+        cache.put_at("new", 3, None, start + Duration::from_secs(2));
+        assert_eq!(cache.get_at("live", start + Duration::from_secs(2)), Some(2));
+        assert_eq!(cache.stats().evictions, 0);
+
+        // Or are purged without waiting to be read
+        let cache = LruCache::new(2);
+        cache.put_with_ttl("brief", 1, Duration::ZERO);
+        cache.put("lasting", 2);
+        assert_eq!(cache.purge_expired(), 1);
+        assert_eq!(cache.get("lasting"), Some(2));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load_keep_order_and_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join("responses.json");
+        let cache = LruCache::new(3);
+        cache.put("a", "first".to_string());
+        cache.put_with_ttl("b", "second".to_string(), Duration::from_secs(3600));
+        cache.put_with_ttl("gone", "expired".to_string(), Duration::ZERO);
+        cache.get("a");
+        assert_eq!(cache.save(&path).unwrap(), 2);
+
+        let restored = LruCache::new(2);
+        assert_eq!(restored.load(&path).unwrap(), 2);
+        assert_eq!(restored.get("gone"), None);
+        // "b" was used least recently, so it's evicted first
+        restored.put("c", "third".to_string());
+        assert_eq!(restored.get("b"), None);
+        assert_eq!(restored.get("a"), Some("first".to_string()));
+
+        assert_eq!(LruCache::<String>::new(2).load(&dir.path().join("missing.json")).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_invalidate_documents_drops_responses_citing_them() {
+        let cache = QueryResponseCache::new(10);
+        let response = |paths: &[&str]| {
+            serde_json::json!({
+                "answer": "...",
+                "sources": paths.iter().map(|p| serde_json::json!({ "file_path": p })).collect::<Vec<_>>()
+            })
+        };
+        cache.put("refunds", "h1", response(&["uploads/policy.pdf", "uploads/faq.md"]));
+        cache.put("holidays", "h2", response(&["uploads/handbook.pdf"]));
+        cache.put("hello", "h3", response(&[]));
+
+        assert_eq!(cache.invalidate_documents(&["uploads/faq.md".to_string()]), 1);
+        assert_eq!(cache.get("refunds", "h1"), None);
+        assert!(cache.get("holidays", "h2").is_some());
+        assert!(cache.get("hello", "h3").is_some());
+    }
+}
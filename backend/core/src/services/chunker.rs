@@ -0,0 +1,541 @@
+use crate::models::{ChunkingStrategy, DocumentChunk, OverlapUnit, SourceLocation};
+use std::collections::HashSet;
+
+/// Separators tried in order by the recursive strategy, coarsest first
+const RECURSIVE_SEPARATORS: &[&str] = &["\n\n", "\n", ". ", " "];
+
+/// Fraction of a sentence's terms that must already appear in the current
+/// chunk for the semantic strategy to keep them together
+const SEMANTIC_CONTINUITY_THRESHOLD: f32 = 0.2;
+
+/// Splits text into chunks according to a chunking strategy
+pub struct Chunker {
+    strategy: ChunkingStrategy,
+    chunk_size: usize,
+    chunk_overlap: usize,
+    overlap_unit: OverlapUnit,
+    stride: Option<usize>,
+}
+
+impl Chunker {
+    /// `chunk_size` is measured in tokens for the token strategy and in
+    /// characters for every other strategy. `chunk_overlap` is measured in
+    /// characters for the fixed strategy and in overlap units (tokens by
+    /// default) for the sentence, recursive, and markdown strategies.
+    pub fn new(strategy: ChunkingStrategy, chunk_size: usize, chunk_overlap: usize) -> Self {
+        let chunk_size = chunk_size.max(1);
+        Chunker {
+            strategy,
+            chunk_size,
+            chunk_overlap: chunk_overlap.min(chunk_size - 1),
+            overlap_unit: OverlapUnit::default(),
+            stride: None,
+        }
+    }
+
+    pub fn with_overlap_unit(mut self, overlap_unit: OverlapUnit) -> Self {
+        self.overlap_unit = overlap_unit;
+        self
+    }
+
+    /// Set the window strategy's stride, clamped to `1..=chunk_size`
+    pub fn with_stride(mut self, stride: usize) -> Self {
+        self.stride = Some(stride.clamp(1, self.chunk_size));
+        self
+    }
+
+    pub fn strategy(&self) -> ChunkingStrategy {
+        self.strategy
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn chunk(&self, text: &str) -> Vec<DocumentChunk> {
+        if text.trim().is_empty() {
+            return Vec::new();
+        }
+
+        let pieces = match self.strategy {
+            ChunkingStrategy::Fixed => self.fixed_chunks(text),
+            ChunkingStrategy::Recursive => self.recursive_chunks(text),
+            ChunkingStrategy::Sentence => self.sentence_chunks(text),
+            ChunkingStrategy::Token => self.token_chunks(text),
+            ChunkingStrategy::Semantic => self.semantic_chunks(text),
+            ChunkingStrategy::Markdown => self.markdown_chunks(text),
+            ChunkingStrategy::Window => self.window_chunks(text),
+        };
+
+        pieces
+            .iter()
+            .map(|piece| piece.trim())
+            .filter(|piece| !piece.is_empty())
+            .enumerate()
+            .map(|(chunk_id, piece)| DocumentChunk {
+                text: piece.into(),
+                size: piece.len(),
+                chunk_id,
+                parent_id: None,
+                location: SourceLocation::default(),
+            })
+            .collect()
+    }
+
+    /// Split text into sentence slices that keep their terminators, so the
+    /// slices concatenate back to the original text.
+    pub fn sentence_spans(text: &str) -> Vec<&str> {
+        text.split_inclusive(['.', '!', '?', '\n']).collect()
+    }
+
+    fn split_sentences(text: &str) -> Vec<&str> {
+        text.split(['.', '!', '?', '\n'])
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// Fixed-size character windows
+    fn fixed_chunks(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let step = self.chunk_size - self.chunk_overlap;
+        let mut chunks = Vec::new();
+        let mut start = 0;
+
+        loop {
+            let end = (start + self.chunk_size).min(chars.len());
+            chunks.push(chars[start..end].iter().collect());
+            if end == chars.len() {
+                break;
+            }
+            start += step;
+        }
+
+        chunks
+    }
+
+    /// Split on the coarsest separator that yields pieces under the chunk
+    /// size, then merge neighbouring pieces back up to the chunk size.
+    fn recursive_chunks(&self, text: &str) -> Vec<String> {
+        let pieces = self.split_recursive(text, RECURSIVE_SEPARATORS);
+        self.merge_pieces(&pieces)
+    }
+
+    fn split_recursive<'a>(&self, text: &'a str, separators: &[&str]) -> Vec<&'a str> {
+        if text.len() <= self.chunk_size {
+            return vec![text];
+        }
+
+        match separators.split_first() {
+            Some((separator, rest)) => text
+                .split_inclusive(separator)
+                .flat_map(|piece| self.split_recursive(piece, rest))
+                .collect(),
+            None => split_at_char_boundaries(text, self.chunk_size),
+        }
+    }
+
+    fn merge_pieces(&self, pieces: &[&str]) -> Vec<String> {
+        self.pack_units(pieces, "")
+    }
+
+    /// Greedily pack whole sentences up to the chunk size
+    fn sentence_chunks(&self, text: &str) -> Vec<String> {
+        let units: Vec<&str> = Self::split_sentences(text)
+            .into_iter()
+            .flat_map(|sentence| self.split_oversized(sentence))
+            .collect();
+        self.pack_units(&units, " ")
+    }
+
+    /// Split a unit longer than the chunk size at word boundaries, falling
+    /// back to character boundaries for single overlong words
+    fn split_oversized<'a>(&self, unit: &'a str) -> Vec<&'a str> {
+        if unit.len() <= self.chunk_size {
+            return vec![unit];
+        }
+
+        self.split_recursive(unit, &[" "])
+            .into_iter()
+            .map(str::trim)
+            .filter(|piece| !piece.is_empty())
+            .collect()
+    }
+
+    /// Pack units (each no longer than the chunk size) into chunks of at most
+    /// `chunk_size` characters, starting each chunk after the first with an
+    /// exact carry-over from the end of the previous chunk.
+    fn pack_units(&self, units: &[&str], joiner: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current: Vec<String> = Vec::new();
+        // Units in `current` that were not carried over from the previous chunk
+        let mut fresh_units = 0;
+
+        for &unit in units {
+            if fresh_units > 0
+                && joined_len(&current, joiner) + joiner.len() + unit.len() > self.chunk_size
+            {
+                let carry = self.carry_over(&current, joiner, joiner.len() + unit.len());
+                chunks.push(current.join(joiner));
+                current = carry;
+                fresh_units = 0;
+            }
+
+            current.push(unit.to_string());
+            fresh_units += 1;
+        }
+
+        if fresh_units > 0 {
+            chunks.push(current.join(joiner));
+        }
+
+        chunks
+    }
+
+    /// The last `chunk_overlap` tokens or units of a finished chunk, trimmed
+    /// from the front only as far as needed to leave `reserve` characters of
+    /// room in the next chunk. The carry-over therefore never exceeds the
+    /// configured overlap, and equals it whenever the previous chunk is long
+    /// enough and the chunk size allows.
+    fn carry_over(&self, units: &[String], joiner: &str, reserve: usize) -> Vec<String> {
+        if self.chunk_overlap == 0 {
+            return Vec::new();
+        }
+
+        let budget = self.chunk_size.saturating_sub(reserve);
+
+        match self.overlap_unit {
+            OverlapUnit::Tokens => {
+                let text = units.join(joiner);
+                let tokens: Vec<&str> = text.split_whitespace().collect();
+                let mut start = tokens.len().saturating_sub(self.chunk_overlap);
+
+                // With an empty joiner the carried text needs its own separator
+                let trailing = if joiner.is_empty() { " " } else { "" };
+                let carried_len = |start: usize| {
+                    let words = &tokens[start..];
+                    words.iter().map(|t| t.len()).sum::<usize>() + words.len().saturating_sub(1) + trailing.len()
+                };
+
+                while start < tokens.len() && carried_len(start) > budget {
+                    start += 1;
+                }
+
+                if start == tokens.len() {
+                    Vec::new()
+                } else {
+                    vec![format!("{}{}", tokens[start..].join(" "), trailing)]
+                }
+            }
+            OverlapUnit::Sentences => {
+                let mut start = units.len().saturating_sub(self.chunk_overlap);
+                while start < units.len() && joined_len(&units[start..], joiner) > budget {
+                    start += 1;
+                }
+                units[start..].to_vec()
+            }
+        }
+    }
+
+    /// Windows of whitespace-separated tokens
+    fn token_chunks(&self, text: &str) -> Vec<String> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let step = self.chunk_size - self.chunk_overlap;
+        let mut chunks = Vec::new();
+        let mut start = 0;
+
+        while start < tokens.len() {
+            let end = (start + self.chunk_size).min(tokens.len());
+            chunks.push(tokens[start..end].join(" "));
+            if end == tokens.len() {
+                break;
+            }
+            start += step;
+        }
+
+        chunks
+    }
+
+    /// Keep adjacent sentences together while they share vocabulary with the
+    /// chunk being built; start a new chunk at topic shifts.
+    fn semantic_chunks(&self, text: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        let mut current_terms: HashSet<String> = HashSet::new();
+
+        for sentence in Self::split_sentences(text) {
+            let terms = term_set(sentence);
+
+            let overlap = if terms.is_empty() {
+                1.0
+            } else {
+                terms.intersection(&current_terms).count() as f32 / terms.len() as f32
+            };
+
+            let topic_shift = overlap < SEMANTIC_CONTINUITY_THRESHOLD
+                && current.len() >= self.chunk_size / 2;
+            let too_large = current.len() + sentence.len() > self.chunk_size;
+
+            if !current.is_empty() && (topic_shift || too_large) {
+                chunks.push(std::mem::take(&mut current));
+                current_terms.clear();
+            }
+
+            if !current.is_empty() {
+                current.push_str(". ");
+            }
+            current.push_str(sentence);
+            current_terms.extend(terms);
+        }
+
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+
+    /// Windows of up to `chunk_size` characters that start and end on word
+    /// boundaries, each starting at the first word at least `stride`
+    /// characters after the previous window's start. Unlike overlap carried
+    /// over from sentence breaks, consecutive windows overlap by a consistent
+    /// amount regardless of punctuation.
+    fn window_chunks(&self, text: &str) -> Vec<String> {
+        let stride = self
+            .stride
+            .unwrap_or(self.chunk_size - self.chunk_overlap)
+            .max(1);
+
+        let mut words: Vec<(usize, usize)> = Vec::new();
+        let mut word_start = None;
+        for (i, c) in text.char_indices() {
+            match (c.is_whitespace(), word_start) {
+                (false, None) => word_start = Some(i),
+                (true, Some(start)) => {
+                    words.push((start, i));
+                    word_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = word_start {
+            words.push((start, text.len()));
+        }
+
+        let mut chunks = Vec::new();
+        let mut first = 0;
+
+        while first < words.len() {
+            let window_start = words[first].0;
+
+            let mut last = first;
+            while last + 1 < words.len() && words[last + 1].1 - window_start <= self.chunk_size {
+                last += 1;
+            }
+            chunks.push(text[window_start..words[last].1].to_string());
+
+            if last + 1 == words.len() {
+                break;
+            }
+
+            // Never skip past the word after this window, so no text is lost
+            first = (first + 1..=last + 1)
+                .find(|&i| words[i].0 >= window_start + stride)
+                .unwrap_or(last + 1);
+        }
+
+        chunks
+    }
+
+    /// One chunk per heading section; oversized sections are split recursively
+    fn markdown_chunks(&self, text: &str) -> Vec<String> {
+        let mut sections: Vec<String> = Vec::new();
+
+        for line in text.lines() {
+            if line.trim_start().starts_with('#') || sections.is_empty() {
+                sections.push(String::new());
+            }
+            if let Some(section) = sections.last_mut() {
+                section.push_str(line);
+                section.push('\n');
+            }
+        }
+
+        sections
+            .iter()
+            .flat_map(|section| {
+                if section.len() <= self.chunk_size {
+                    vec![section.clone()]
+                } else {
+                    self.recursive_chunks(section)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Length of `units` joined with `joiner`
+fn joined_len(units: &[String], joiner: &str) -> usize {
+    units.iter().map(|u| u.len()).sum::<usize>() + joiner.len() * units.len().saturating_sub(1)
+}
+
+fn split_at_char_boundaries(text: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        let mut end = (start + max_len).min(text.len());
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == start {
+            // A single character wider than max_len
+            end = start + text[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        pieces.push(&text[start..end]);
+        start = end;
+    }
+
+    pieces
+}
+
+fn term_set(text: &str) -> HashSet<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| s.len() > 2)
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "# Intro\nRust is a systems language. It is fast and safe.\n\n\
+                        # Usage\nCargo builds Rust crates. Cargo also runs tests!\n";
+
+    #[test]
+    fn test_every_strategy_produces_chunks() {
+        for strategy in [
+            ChunkingStrategy::Fixed,
+            ChunkingStrategy::Recursive,
+            ChunkingStrategy::Sentence,
+            ChunkingStrategy::Token,
+            ChunkingStrategy::Semantic,
+            ChunkingStrategy::Markdown,
+            ChunkingStrategy::Window,
+        ] {
+            let chunks = Chunker::new(strategy, 40, 5).chunk(TEXT);
+            assert!(!chunks.is_empty(), "{:?} produced no chunks", strategy);
+            for (i, chunk) in chunks.iter().enumerate() {
+                assert_eq!(chunk.chunk_id, i);
+                assert!(!chunk.text.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_fixed_chunks_respect_size() {
+        let chunks = Chunker::new(ChunkingStrategy::Fixed, 10, 2).chunk("abcdefghijklmnopqrstuvwxyz");
+        assert!(chunks.iter().all(|c| c.text.chars().count() <= 10));
+        assert_eq!(&*chunks[1].text, "ijklmnopqr");
+    }
+
+    #[test]
+    fn test_window_chunks_use_consistent_stride() {
+        let text = "aaaa bbbb cccc dddd eeee ffff gggg hhhh";
+        let chunks = Chunker::new(ChunkingStrategy::Window, 14, 0).with_stride(10).chunk(text);
+        let texts: Vec<&str> = chunks.iter().map(|c| &*c.text).collect();
+        assert_eq!(
+            texts,
+            vec!["aaaa bbbb cccc", "cccc dddd eeee", "eeee ffff gggg", "gggg hhhh"]
+        );
+    }
+
+    #[test]
+    fn test_markdown_splits_on_headings() {
+        let chunks = Chunker::new(ChunkingStrategy::Markdown, 1000, 0).chunk(TEXT);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].text.starts_with("# Intro"));
+        assert!(chunks[1].text.starts_with("# Usage"));
+    }
+
+    fn tokens(text: &str) -> Vec<&str> {
+        text.split_whitespace().collect()
+    }
+
+    #[test]
+    fn test_token_overlap_is_exact_and_size_bounded() {
+        let text = (1..=200)
+            .map(|i| format!("Sentence number {} has some words", i))
+            .collect::<Vec<_>>()
+            .join(". ");
+
+        for strategy in [ChunkingStrategy::Sentence, ChunkingStrategy::Recursive] {
+            let chunks = Chunker::new(strategy, 120, 4).chunk(&text);
+            assert!(chunks.len() > 10);
+
+            for pair in chunks.windows(2) {
+                assert!(pair[0].text.len() <= 120, "{:?} chunk too long", strategy);
+                let previous = tokens(&pair[0].text);
+                let next = tokens(&pair[1].text);
+                assert_eq!(
+                    &previous[previous.len() - 4..],
+                    &next[..4],
+                    "{:?} overlap is not exactly 4 tokens",
+                    strategy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_overlap_never_exceeds_configured_tokens() {
+        // Long sentences leave little room, so the carry-over must shrink
+        // rather than push the chunk over its size
+        let text = (0..30)
+            .map(|s| (0..8).map(|w| format!("w{}x{}", s, w)).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join(". ");
+
+        for overlap in [0, 1, 3, 10] {
+            let chunks = Chunker::new(ChunkingStrategy::Sentence, 60, overlap).chunk(&text);
+            for pair in chunks.windows(2) {
+                assert!(pair[1].text.len() <= 60);
+                let previous = tokens(&pair[0].text);
+                let next = tokens(&pair[1].text);
+                let shared = previous
+                    .iter()
+                    .position(|t| *t == next[0])
+                    .map_or(0, |pos| previous.len() - pos);
+                assert!(shared <= overlap, "overlap {} carried {}", overlap, shared);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sentence_overlap_carries_whole_sentences() {
+        let text = "One fish. Two fish. Red fish. Blue fish. Old fish. New fish.";
+        let chunks = Chunker::new(ChunkingStrategy::Sentence, 30, 1)
+            .with_overlap_unit(OverlapUnit::Sentences)
+            .chunk(text);
+
+        for pair in chunks.windows(2) {
+            let last_sentence = pair[0].text.rsplit("fish").nth(1).unwrap();
+            assert!(pair[1].text.starts_with(last_sentence.trim()));
+        }
+    }
+
+    #[test]
+    fn test_sentence_spans_round_trip() {
+        assert_eq!(Chunker::sentence_spans(TEXT).concat(), TEXT);
+    }
+
+    #[test]
+    fn test_multibyte_text_does_not_panic() {
+        let text = "Überprüfung läuft. Ärger über Öl. ".repeat(20);
+        for strategy in [ChunkingStrategy::Fixed, ChunkingStrategy::Recursive, ChunkingStrategy::Sentence] {
+            assert!(!Chunker::new(strategy, 17, 7).chunk(&text).is_empty());
+        }
+    }
+}
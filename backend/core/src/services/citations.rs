@@ -0,0 +1,72 @@
+/// Instruction appended to prompts so answers cite the numbered context blocks
+pub const CITATION_INSTRUCTION: &str = "Cite the sources you use inline with their bracketed numbers, e.g. [1] or [2][3], placed right after the statement they support.";
+
+/// Distinct source numbers cited in `answer` as `[n]` or `[n, m]`, in order of
+/// first appearance. Numbers outside `1..=num_sources` are ignored.
+pub fn extract_citation_markers(answer: &str, num_sources: usize) -> Vec<usize> {
+    let mut markers = Vec::new();
+    let mut rest = answer;
+
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else {
+            break;
+        };
+
+        let inner = &rest[..close];
+        let numbers: Option<Vec<usize>> = inner
+            .split(',')
+            .map(|part| part.trim().parse::<usize>().ok())
+            .collect();
+
+        if let Some(numbers) = numbers {
+            for n in numbers {
+                if (1..=num_sources).contains(&n) && !markers.contains(&n) {
+                    markers.push(n);
+                }
+            }
+            rest = &rest[close + 1..];
+        }
+    }
+
+    markers
+}
+
+/// `citations` entries linking each cited marker to its source
+pub fn build_citations(answer: &str, sources: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    extract_citation_markers(answer, sources.len())
+        .into_iter()
+        .map(|marker| {
+            let mut citation = sources[marker - 1].clone();
+            citation["marker"] = serde_json::json!(marker);
+            citation
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_citation_markers() {
+        let answer = "Rust is fast [2]. It is safe [1, 3][2]. See [the docs] and [9]. Unclosed [1";
+        assert_eq!(extract_citation_markers(answer, 3), vec![2, 1, 3]);
+        assert!(extract_citation_markers("No citations here.", 3).is_empty());
+        assert!(extract_citation_markers("[0] [4]", 3).is_empty());
+    }
+
+    #[test]
+    fn test_build_citations_links_sources() {
+        let sources = vec![
+            serde_json::json!({"file_name": "a.txt", "chunk_id": 0}),
+            serde_json::json!({"file_name": "b.txt", "chunk_id": 7}),
+        ];
+
+        let citations = build_citations("Answer [2].", &sources);
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0]["marker"], 2);
+        assert_eq!(citations[0]["file_name"], "b.txt");
+        assert_eq!(citations[0]["chunk_id"], 7);
+    }
+}
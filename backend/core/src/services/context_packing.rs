@@ -0,0 +1,176 @@
+use crate::models::SearchResult;
+use crate::services::dedup::{overlap, shingles};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Shingle overlap with an already packed chunk at which a chunk adds
+/// nothing new
+const REDUNDANT_OVERLAP: f32 = 0.8;
+
+/// Why a retrieved chunk was left out of a packed context
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// Mostly repeats text already in the context
+    Redundant,
+    /// Didn't fit in what was left of the token budget
+    OverBudget,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DroppedChunk {
+    pub file_name: String,
+    pub chunk_id: usize,
+    pub score: f32,
+    pub tokens: usize,
+    pub reason: DropReason,
+    /// Highest overlap with a packed chunk
+    pub overlap: f32,
+}
+
+/// Chunks chosen for a context and the ones left out
+#[derive(Debug, Clone)]
+pub struct Packing {
+    pub chunks: Vec<SearchResult>,
+    pub budget: usize,
+    pub used_tokens: usize,
+    pub dropped: Vec<DroppedChunk>,
+}
+
+impl Packing {
+    /// Summary returned with the answer
+    pub fn report(&self) -> serde_json::Value {
+        serde_json::json!({
+            "budget_tokens": self.budget,
+            "used_tokens": self.used_tokens,
+            "packed": self.chunks.len(),
+            "dropped": self.dropped
+        })
+    }
+}
+
+struct Candidate {
+    chunk: SearchResult,
+    shingles: HashSet<String>,
+    tokens: usize,
+    overlap: f32,
+}
+
+impl Candidate {
+    fn score(&self) -> f32 {
+        self.chunk.rerank_score.unwrap_or(self.chunk.similarity_score)
+    }
+
+    /// Score discounted by how much of the chunk is already packed
+    fn gain(&self) -> f32 {
+        self.score() * (1.0 - self.overlap)
+    }
+
+    fn dropped(self, reason: DropReason) -> DroppedChunk {
+        DroppedChunk {
+            score: self.score(),
+            file_name: self.chunk.file_name,
+            chunk_id: self.chunk.chunk_id,
+            tokens: self.tokens,
+            reason,
+            overlap: self.overlap,
+        }
+    }
+}
+
+/// Fill `budget` tokens with the chunks that add the most: repeatedly take
+/// the one with the best score discounted by its overlap with those already
+/// taken, skipping chunks that repeat packed text or no longer fit.
+/// `count_tokens` sizes a chunk's context text.
+pub fn pack_context(chunks: &[SearchResult], budget: usize, count_tokens: impl Fn(&str) -> usize) -> Packing {
+    let mut candidates: Vec<Candidate> = chunks
+        .iter()
+        .map(|chunk| {
+            let text = chunk.parent_text.as_deref().unwrap_or(&chunk.text);
+            Candidate {
+                chunk: chunk.clone(),
+                shingles: shingles(text),
+                tokens: count_tokens(text),
+                overlap: 0.0,
+            }
+        })
+        .collect();
+    let mut packed: Vec<Candidate> = Vec::new();
+    let mut dropped = Vec::new();
+    let mut used_tokens = 0;
+
+    loop {
+        let remaining = budget - used_tokens;
+        let (keep, left_out): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|c| c.overlap < REDUNDANT_OVERLAP && c.tokens <= remaining);
+        dropped.extend(left_out.into_iter().map(|c| {
+            let reason = if c.overlap >= REDUNDANT_OVERLAP {
+                DropReason::Redundant
+            } else {
+                DropReason::OverBudget
+            };
+            c.dropped(reason)
+        }));
+        candidates = keep;
+
+        let Some(best) = (0..candidates.len()).max_by(|&a, &b| candidates[a].gain().total_cmp(&candidates[b].gain()))
+        else {
+            break;
+        };
+        let chosen = candidates.swap_remove(best);
+        used_tokens += chosen.tokens;
+        for candidate in &mut candidates {
+            candidate.overlap = candidate.overlap.max(overlap(&candidate.shingles, &chosen.shingles));
+        }
+        packed.push(chosen);
+    }
+
+    Packing {
+        chunks: packed.into_iter().map(|c| c.chunk).collect(),
+        budget,
+        used_tokens,
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_id: usize, score: f32, text: &str) -> SearchResult {
+        SearchResult {
+            file_path: "handbook.txt".to_string(),
+            file_name: "handbook.txt".to_string(),
+            file_type: ".txt".to_string(),
+            chunk_id,
+            chunk_size: text.len(),
+            text: text.to_string(),
+            similarity_score: score,
+            parent_id: None,
+            parent_text: None,
+            location: Default::default(),
+            rerank_score: None,
+            highlights: None,
+            document_date: None,
+        }
+    }
+
+    #[test]
+    fn test_packing_prefers_new_information_within_budget() {
+        let pto = "Employees accrue 25 days of paid time off per year.";
+        let chunks = [
+            chunk(0, 0.9, pto),
+            chunk(1, 0.85, &format!("{} Unused days carry over.", pto)),
+            chunk(2, 0.6, "Parking is free at the main office."),
+            chunk(3, 0.5, &"Expense reports are due monthly. ".repeat(20)),
+        ];
+        let words = |text: &str| text.split_whitespace().count();
+
+        let packing = pack_context(&chunks, 30, words);
+        assert_eq!(packing.chunks.iter().map(|c| c.chunk_id).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(packing.used_tokens, 17);
+        let reasons: Vec<_> = packing.dropped.iter().map(|d| (d.chunk_id, d.reason)).collect();
+        assert_eq!(reasons, vec![(3, DropReason::OverBudget), (1, DropReason::Redundant)]);
+    }
+}
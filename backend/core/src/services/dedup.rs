@@ -0,0 +1,95 @@
+use crate::models::SearchResult;
+use std::collections::HashSet;
+
+/// Shingle overlap at which a chunk duplicates a higher-ranked one
+pub const DEFAULT_DEDUP_THRESHOLD: f32 = 0.8;
+
+/// Words per shingle when comparing chunk texts
+const SHINGLE_WORDS: usize = 3;
+
+/// Lowercased runs of `SHINGLE_WORDS` consecutive words; texts shorter than
+/// that are one shingle
+pub(crate) fn shingles(text: &str) -> HashSet<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() <= SHINGLE_WORDS {
+        return HashSet::from([words.join(" ")]);
+    }
+    words.windows(SHINGLE_WORDS).map(|w| w.join(" ")).collect()
+}
+
+/// Share of the smaller set of shingles also found in the other, so a
+/// chunk contained in a longer one counts as a duplicate of it
+pub(crate) fn overlap(a: &HashSet<String>, b: &HashSet<String>) -> f32 {
+    let smaller = a.len().min(b.len());
+    if smaller == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f32 / smaller as f32
+}
+
+/// `chunks` without those whose context text is at least `threshold`
+/// similar to a higher-ranked chunk's. Returns the kept chunks and the
+/// number dropped.
+pub fn dedup_chunks(chunks: &[SearchResult], threshold: f32) -> (Vec<SearchResult>, usize) {
+    let mut kept: Vec<(SearchResult, HashSet<String>)> = Vec::new();
+    let mut dropped = 0;
+
+    for chunk in chunks {
+        let text_shingles = shingles(chunk.parent_text.as_deref().unwrap_or(&chunk.text));
+        if kept.iter().any(|(_, other)| overlap(&text_shingles, other) >= threshold) {
+            dropped += 1;
+        } else {
+            kept.push((chunk.clone(), text_shingles));
+        }
+    }
+
+    (kept.into_iter().map(|(chunk, _)| chunk).collect(), dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_id: usize, text: &str) -> SearchResult {
+        SearchResult {
+            file_path: "policy.txt".to_string(),
+            file_name: "policy.txt".to_string(),
+            file_type: ".txt".to_string(),
+            chunk_id,
+            chunk_size: text.len(),
+            text: text.to_string(),
+            similarity_score: 0.5,
+            parent_id: None,
+            parent_text: None,
+            location: Default::default(),
+            rerank_score: None,
+            highlights: None,
+            document_date: None,
+        }
+    }
+
+    fn similarity(a: &str, b: &str) -> f32 {
+        overlap(&shingles(a), &shingles(b))
+    }
+
+    #[test]
+    fn test_near_duplicates_are_dropped() {
+        let pto = "Employees accrue 25 days of paid time off per year, prorated in the first year.";
+        assert_eq!(similarity(pto, pto), 1.0);
+        assert_eq!(similarity(pto, &format!("{} Requests go to your manager.", pto)), 1.0);
+        assert!(similarity(pto, "Parking is free for all employees at the main office.") < 0.1);
+
+        let chunks = [
+            chunk(0, pto),
+            chunk(1, &format!("PTO: {}", pto)),
+            chunk(2, "Parking is free for all employees at the main office."),
+        ];
+        let (kept, dropped) = dedup_chunks(&chunks, 0.8);
+        assert_eq!(dropped, 1);
+        assert_eq!(kept.iter().map(|c| c.chunk_id).collect::<Vec<_>>(), vec![0, 2]);
+    }
+}
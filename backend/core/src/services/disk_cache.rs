@@ -0,0 +1,201 @@
+use crate::services::cache_manager::{unix_millis, Cache, CacheCounters, CacheStats, SavedEntry};
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// A cache kept as one JSON file per entry in a directory, so it survives
+/// restarts without being saved. Files are touched when read, and the least
+/// recently touched go first when the cache is full.
+pub struct DiskCache<V> {
+    dir: PathBuf,
+    max_size: usize,
+    ttl: Option<Duration>,
+    counters: CacheCounters,
+    /// Held while writing or evicting, so the count stays within bounds
+    write_lock: Mutex<()>,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V: Serialize + DeserializeOwned> DiskCache<V> {
+    /// Holds up to `max_size` entries in `dir`, created if missing
+    pub fn open(dir: &Path, max_size: usize) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        Ok(DiskCache {
+            dir: dir.to_path_buf(),
+            max_size,
+            ttl: None,
+            counters: CacheCounters::default(),
+            write_lock: Mutex::new(()),
+            value: PhantomData,
+        })
+    }
+
+    /// Expire entries `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hex::encode(Sha256::digest(key))))
+    }
+
+    /// Paths of the entry files
+    fn entry_paths(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect()
+    }
+
+    /// The entry in `path`, or `None` if it's missing, unreadable, or
+    /// expired; expired and unreadable files are removed
+    fn read_entry(&self, path: &Path) -> Option<SavedEntry<V>> {
+        let json = fs::read(path).ok()?;
+        let entry: SavedEntry<V> = match serde_json::from_slice(&json) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Removing unreadable cache entry {}: {}", path.display(), e);
+                let _ = fs::remove_file(path);
+                return None;
+            }
+        };
+        if entry.expires_at.is_some_and(|expires_at| expires_at <= unix_millis(SystemTime::now())) {
+            CacheCounters::count(&self.counters.expirations, 1);
+            let _ = fs::remove_file(path);
+            return None;
+        }
+        Some(entry)
+    }
+
+    fn write_entry(&self, key: &str, value: V) -> Result<()> {
+        let entry = SavedEntry {
+            key: key.to_string(),
+            value,
+            expires_at: self.ttl.map(|ttl| unix_millis(SystemTime::now() + ttl)),
+        };
+        let path = self.entry_path(key);
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(&entry)?)
+            .with_context(|| format!("Failed to write cache entry {}", temp.display()))?;
+        fs::rename(&temp, &path)?;
+        Ok(())
+    }
+
+    /// Remove the least recently touched entries beyond `max_size`
+    fn evict(&self) {
+        let mut paths: Vec<(SystemTime, PathBuf)> = self
+            .entry_paths()
+            .into_iter()
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect();
+        if paths.len() <= self.max_size {
+            return;
+        }
+        paths.sort();
+        let excess = paths.len() - self.max_size;
+        for (_, path) in paths.into_iter().take(excess) {
+            let _ = fs::remove_file(path);
+        }
+        CacheCounters::count(&self.counters.evictions, excess);
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> Cache<str, V> for DiskCache<V> {
+    fn get(&self, key: &str) -> Option<V> {
+        let path = self.entry_path(key);
+        match self.read_entry(&path).filter(|entry| entry.key == key) {
+            Some(entry) => {
+                CacheCounters::count(&self.counters.hits, 1);
+                // Mark as recently used
+                if let Ok(file) = File::options().write(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                Some(entry.value)
+            }
+            None => {
+                CacheCounters::count(&self.counters.misses, 1);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, value: V) {
+        if self.max_size == 0 {
+            return;
+        }
+        let _lock = self.write_lock.lock().unwrap();
+        match self.write_entry(key, value) {
+            Ok(()) => self.evict(),
+            Err(e) => log::warn!("Failed to cache entry on disk: {}", e),
+        }
+    }
+
+    fn invalidate(&self, key: &str) -> bool {
+        fs::remove_file(self.entry_path(key)).is_ok()
+    }
+
+    fn invalidate_where(&self, stale: &dyn Fn(&V) -> bool) -> usize {
+        let _lock = self.write_lock.lock().unwrap();
+        self.entry_paths()
+            .into_iter()
+            .filter(|path| {
+                self.read_entry(path)
+                    .is_some_and(|entry| stale(&entry.value) && fs::remove_file(path).is_ok())
+            })
+            .count()
+    }
+
+    fn purge_expired(&self) -> usize {
+        let _lock = self.write_lock.lock().unwrap();
+        // Reading an expired entry removes it
+        self.entry_paths()
+            .into_iter()
+            .filter(|path| path.exists() && self.read_entry(path).is_none())
+            .count()
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.counters.stats(self.entry_paths().len(), self.max_size, self.ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_survive_reopening_and_are_evicted_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DiskCache::open(dir.path(), 2).unwrap();
+        cache.put("a", "first".to_string());
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("b", "second".to_string());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get("a"), Some("first".to_string()));
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put("c", "third".to_string());
+
+        let reopened: DiskCache<String> = DiskCache::open(dir.path(), 2).unwrap();
+        assert_eq!(reopened.get("b"), None);
+        assert_eq!(reopened.get("a"), Some("first".to_string()));
+        assert_eq!(reopened.get("c"), Some("third".to_string()));
+
+        assert_eq!(reopened.invalidate_where(&|value| value.starts_with('t')), 1);
+        assert_eq!(reopened.stats().size, 1);
+
+        let expiring = DiskCache::open(dir.path(), 2).unwrap().with_ttl(Duration::ZERO);
+        expiring.put("d", "gone".to_string());
+        assert_eq!(expiring.get("d"), None);
+        assert_eq!(expiring.stats().expirations, 1);
+    }
+}
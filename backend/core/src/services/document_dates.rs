@@ -0,0 +1,135 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Characters at the start of a document searched for a date in its text
+const TEXT_DATE_CHARS: usize = 2000;
+
+/// Front-matter keys holding a document's date, most specific first
+const FRONT_MATTER_KEYS: &[&str] = &[
+    "updated",
+    "last_updated",
+    "last_modified",
+    "modified",
+    "effective_date",
+    "effective",
+    "date",
+];
+
+/// Where a document's date was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DateSource {
+    /// A `date:` or `updated:` field in YAML front matter
+    FrontMatter,
+    /// The latest date written near the start of the text
+    Text,
+    /// The file's modification time
+    FileModified,
+}
+
+/// The day a document applies from, as YYYY-MM-DD
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentDate {
+    pub date: String,
+    pub source: DateSource,
+}
+
+impl DocumentDate {
+    fn new(date: NaiveDate, source: DateSource) -> Self {
+        DocumentDate {
+            date: date.format("%Y-%m-%d").to_string(),
+            source,
+        }
+    }
+
+    pub fn day(&self) -> Option<NaiveDate> {
+        parse_date(&self.date)
+    }
+}
+
+/// A date written as 2024-03-15, 2024/03/15, March 15, 2024, or 15 March 2024
+pub fn parse_date(text: &str) -> Option<NaiveDate> {
+    let text = text.trim().trim_matches(|c: char| c == '"' || c == '\'');
+    ["%Y-%m-%d", "%Y/%m/%d", "%B %d, %Y", "%B %d %Y", "%d %B %Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+}
+
+/// Date from YAML front matter (`---` delimited lines at the very start)
+fn front_matter_date(text: &str) -> Option<NaiveDate> {
+    let mut lines = text.trim_start().lines();
+    if lines.next()?.trim() != "---" {
+        return None;
+    }
+    let fields: Vec<(String, &str)> = lines
+        .take_while(|line| line.trim() != "---")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_lowercase(), value))
+        .collect();
+    FRONT_MATTER_KEYS.iter().find_map(|wanted| {
+        fields
+            .iter()
+            .find(|(key, _)| key == wanted)
+            .and_then(|(_, value)| parse_date(value))
+    })
+}
+
+/// The latest date written in the first `TEXT_DATE_CHARS` characters,
+/// where revision and effective dates usually are
+fn text_date(text: &str) -> Option<NaiveDate> {
+    let head: String = text.chars().take(TEXT_DATE_CHARS).collect();
+    let words: Vec<&str> = head
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric() && c != ',' && c != '-' && c != '/'))
+        .collect();
+
+    let mut latest = None;
+    for i in 0..words.len() {
+        let candidates = [
+            words[i].trim_end_matches(',').to_string(),
+            words[i..words.len().min(i + 3)].join(" ").trim_end_matches(',').to_string(),
+        ];
+        if let Some(date) = candidates.iter().find_map(|c| parse_date(c)) {
+            latest = latest.max(Some(date));
+        }
+    }
+    latest
+}
+
+/// A document's date from its front matter, then its text, then the file's
+/// modification time
+pub fn detect_date(text: &str, modified: Option<SystemTime>) -> Option<DocumentDate> {
+    if let Some(date) = front_matter_date(text) {
+        return Some(DocumentDate::new(date, DateSource::FrontMatter));
+    }
+    if let Some(date) = text_date(text) {
+        return Some(DocumentDate::new(date, DateSource::Text));
+    }
+    modified.map(|time| DocumentDate::new(chrono::DateTime::<chrono::Utc>::from(time).date_naive(), DateSource::FileModified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_date() {
+        let front_matter = "---\ntitle: PTO policy\ndate: 2023-01-10\nupdated: \"2024-02-01\"\n---\nEmployees accrue...";
+        assert_eq!(
+            detect_date(front_matter, None),
+            Some(DocumentDate {
+                date: "2024-02-01".to_string(),
+                source: DateSource::FrontMatter
+            })
+        );
+
+        let text = "Travel Policy\nEffective March 5, 2024 (replaces the policy of 2021/06/30).\nBook flights...";
+        assert_eq!(detect_date(text, None).unwrap().date, "2024-03-05");
+        assert_eq!(parse_date("15 March 2024"), NaiveDate::from_ymd_opt(2024, 3, 15));
+
+        let undated = detect_date("No dates here.", Some(SystemTime::UNIX_EPOCH)).unwrap();
+        assert_eq!((undated.date.as_str(), undated.source), ("1970-01-01", DateSource::FileModified));
+        assert_eq!(detect_date("No dates here.", None), None);
+    }
+}
@@ -0,0 +1,1064 @@
+use crate::models::{
+    ChunkingParams, ChunkingProfile, ChunkingStrategy, DocumentChunk, ProcessedDocument, SourceLocation,
+    MAX_DOCUMENT_TEXT,
+};
+use crate::services::chunker::Chunker;
+use crate::services::document_dates::detect_date;
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Extracted text is handed to the chunker in segments of about this many
+/// bytes, so a large file is never held in memory as one string
+const SEGMENT_BYTES: usize = 64 * 1024;
+
+/// A run of extracted text that shares one position in the source file
+#[derive(Debug, Clone)]
+pub struct TextSegment {
+    pub text: String,
+    pub location: SourceLocation,
+}
+
+impl TextSegment {
+    fn unlocated(text: String) -> Self {
+        TextSegment {
+            text,
+            location: SourceLocation::default(),
+        }
+    }
+}
+
+/// Extracted lines gathered into segments of about `SEGMENT_BYTES`, each
+/// handed on as soon as it fills
+struct SegmentWriter<'a> {
+    location: SourceLocation,
+    text: String,
+    on_segment: &'a mut dyn FnMut(TextSegment) -> Result<()>,
+}
+
+impl<'a> SegmentWriter<'a> {
+    fn new(location: SourceLocation, on_segment: &'a mut dyn FnMut(TextSegment) -> Result<()>) -> Self {
+        SegmentWriter {
+            location,
+            text: String::new(),
+            on_segment,
+        }
+    }
+
+    fn push_line(&mut self, line: &str) -> Result<()> {
+        if !self.text.is_empty() {
+            self.text.push('\n');
+        }
+        self.text.push_str(line);
+        if self.text.len() >= SEGMENT_BYTES {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.text.is_empty() {
+            return Ok(());
+        }
+        let text = std::mem::take(&mut self.text);
+        (self.on_segment)(TextSegment {
+            text,
+            location: self.location.clone(),
+        })
+    }
+}
+
+/// A chunk cut by `SegmentChunks`
+enum SegmentChunk {
+    /// A chunk to index
+    Chunk(DocumentChunk),
+    /// A parent section that chunks point into
+    Parent(DocumentChunk),
+}
+
+/// Chunks cut from segments as they're extracted and passed on as soon as
+/// they're final, numbered across the whole document. Consecutive segments
+/// of one location are chunked as one text, so the chunk overlap holds
+/// across them, but no chunk spans two pages, sheets, slides, or sections.
+struct SegmentChunks<'a> {
+    processor: &'a DocumentProcessor,
+    chunker: &'a Chunker,
+    on_chunk: &'a mut dyn FnMut(SegmentChunk),
+    /// Text of the current location not yet cut into final chunks
+    pending: String,
+    location: SourceLocation,
+    chunks: usize,
+    parents: usize,
+}
+
+impl<'a> SegmentChunks<'a> {
+    fn new(processor: &'a DocumentProcessor, chunker: &'a Chunker, on_chunk: &'a mut dyn FnMut(SegmentChunk)) -> Self {
+        SegmentChunks {
+            processor,
+            chunker,
+            on_chunk,
+            pending: String::new(),
+            location: SourceLocation::default(),
+            chunks: 0,
+            parents: 0,
+        }
+    }
+
+    fn add(&mut self, segment: &TextSegment) {
+        if segment.location != self.location {
+            self.cut(true);
+            self.location = segment.location.clone();
+        }
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(&segment.text);
+        self.cut(false);
+    }
+
+    fn finish(mut self) {
+        self.cut(true);
+    }
+
+    /// Cut the pending text and pass the chunks on. Unless `all`, the last
+    /// chunk, or the last parent section, could still grow with the next
+    /// segment, so its text stays pending and is cut again with that
+    /// segment's.
+    fn cut(&mut self, all: bool) {
+        let with_parents = self.processor.parent_chunk_size > self.chunker.chunk_size();
+        let (mut chunks, mut parents) = if with_parents {
+            self.processor.create_parent_child_chunks(&self.pending, self.chunker)
+        } else {
+            (self.chunker.chunk(&self.pending), Vec::new())
+        };
+
+        let last = if with_parents { parents.last() } else { chunks.last() };
+        let kept_from = last.filter(|_| !all).and_then(|last| chunk_start(&self.pending, &last.text));
+        if kept_from.is_some() {
+            if with_parents {
+                let last = parents.pop().map(|parent| parent.chunk_id);
+                chunks.retain(|chunk| chunk.parent_id != last);
+            } else {
+                chunks.pop();
+            }
+        }
+
+        let parent_offset = self.parents;
+        for mut parent in parents {
+            parent.chunk_id += parent_offset;
+            parent.location = self.location.clone();
+            self.parents += 1;
+            (self.on_chunk)(SegmentChunk::Parent(parent));
+        }
+        for mut chunk in chunks {
+            chunk.chunk_id = self.chunks;
+            chunk.parent_id = chunk.parent_id.map(|id| id + parent_offset);
+            chunk.location = self.location.clone();
+            self.chunks += 1;
+            (self.on_chunk)(SegmentChunk::Chunk(chunk));
+        }
+        match kept_from {
+            Some(start) => drop(self.pending.drain(..start)),
+            None => self.pending.clear(),
+        }
+    }
+}
+
+/// Byte offset in `text` where the last occurrence of `chunk` starts.
+/// Only letters and digits are compared, since chunkers may drop
+/// punctuation and rejoin words.
+fn chunk_start(text: &str, chunk: &str) -> Option<usize> {
+    let (offsets, letters): (Vec<usize>, String) = text.char_indices().filter(|(_, c)| c.is_alphanumeric()).unzip();
+    let chunk: String = chunk.chars().filter(|c| c.is_alphanumeric()).collect();
+    if chunk.is_empty() {
+        return None;
+    }
+    let at = letters.rfind(&chunk)?;
+    offsets.get(letters[..at].chars().count()).copied()
+}
+
+/// Append as much of `more` to `text` as fits in `MAX_DOCUMENT_TEXT`
+fn push_capped(text: &mut String, more: &str) {
+    let mut end = more.len().min(MAX_DOCUMENT_TEXT.saturating_sub(text.len()));
+    while !more.is_char_boundary(end) {
+        end -= 1;
+    }
+    text.push_str(&more[..end]);
+}
+
+#[derive(Clone)]
+pub struct DocumentProcessor {
+    chunk_size: usize,
+    chunk_overlap: usize,
+    parent_chunk_size: usize,
+    default_strategy: ChunkingStrategy,
+    profiles: HashMap<String, ChunkingProfile>,
+    workers: usize,
+}
+
+impl DocumentProcessor {
+    pub fn new(chunk_size: usize, chunk_overlap: usize) -> Self {
+        DocumentProcessor {
+            chunk_size,
+            chunk_overlap,
+            parent_chunk_size: 0,
+            default_strategy: ChunkingStrategy::default(),
+            profiles: HashMap::new(),
+            workers: 1,
+        }
+    }
+
+    /// Per-file-type chunking defaults keyed by extension (e.g. ".pdf"),
+    /// used in place of the global settings for matching files
+    pub fn with_profiles(mut self, profiles: HashMap<String, ChunkingProfile>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Enable small-to-big chunking: chunks are cut from parent sections of
+    /// this size. A value not larger than the chunk size disables it.
+    pub fn with_parent_chunk_size(mut self, parent_chunk_size: usize) -> Self {
+        self.parent_chunk_size = parent_chunk_size;
+        self
+    }
+
+    /// Process up to this many files at once in `process_files`
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Process `files`, given as paths with optional original names, on a
+    /// pool of worker threads. Each result is passed to `on_processed`
+    /// with the file's index as soon as it's ready, on the calling thread,
+    /// so callers can index documents one at a time while others are
+    /// still being extracted. At most one finished document per worker
+    /// waits to be taken.
+    pub fn process_files(
+        &self,
+        files: &[(String, Option<String>)],
+        params: &ChunkingParams,
+        mut on_processed: impl FnMut(usize, Result<ProcessedDocument>),
+    ) {
+        let workers = self.workers.min(files.len());
+        if workers <= 1 {
+            for (i, (file_path, original_name)) in files.iter().enumerate() {
+                on_processed(i, self.process_file_with_params(file_path, original_name.as_deref(), params));
+            }
+            return;
+        }
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = mpsc::sync_channel(workers);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                let sender = sender.clone();
+                let next = &next;
+                scope.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((file_path, original_name)) = files.get(i) else {
+                        break;
+                    };
+                    let processed = self.process_file_with_params(file_path, original_name.as_deref(), params);
+                    if sender.send((i, processed)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+            for (i, processed) in receiver {
+                on_processed(i, processed);
+            }
+        });
+    }
+
+    pub fn process_file(&self, file_path: &str) -> Result<ProcessedDocument> {
+        self.process_file_with_name(file_path, None)
+    }
+
+    pub fn process_file_with_name(&self, file_path: &str, original_name: Option<&str>) -> Result<ProcessedDocument> {
+        self.process_file_with_params(file_path, original_name, &ChunkingParams::default())
+    }
+
+    /// Process a file, overriding the default chunking settings with any
+    /// values set in `params`
+    #[tracing::instrument(name = "process_file", skip(self, params), fields(chunks))]
+    pub fn process_file_with_params(
+        &self,
+        file_path: &str,
+        original_name: Option<&str>,
+        params: &ChunkingParams,
+    ) -> Result<ProcessedDocument> {
+        let path = Path::new(file_path);
+
+        if !path.exists() {
+            return Err(anyhow!("File not found: {}", file_path));
+        }
+
+        // Try to get extension from original filename first, then from file path
+        let extension = if let Some(name) = original_name {
+            Path::new(name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|s| format!(".{}", s.to_lowercase()))
+        } else {
+            None
+        }.or_else(|| {
+            path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|s| format!(".{}", s.to_lowercase()))
+        }).ok_or_else(|| {
+            let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            anyhow!("Cannot determine file extension for file: {} (path: {})", file_name, file_path)
+        })?;
+
+        let supported_extensions = [".pdf", ".txt", ".doc", ".docx", ".csv", ".xlsx", ".xls", ".md", ".pptx", ".json"];
+        if !supported_extensions.contains(&extension.as_str()) {
+            return Err(anyhow!("Unsupported file format: {}. Supported formats: {:?}", extension, supported_extensions));
+        }
+
+        // Segments are chunked as they're extracted and then dropped; only
+        // the start of the text is kept whole
+        let chunker = self.chunker(params, Some(&extension));
+        let (mut chunks, mut parent_chunks) = (Vec::new(), Vec::new());
+        let mut on_chunk = |chunk| match chunk {
+            SegmentChunk::Chunk(chunk) => chunks.push(chunk),
+            SegmentChunk::Parent(parent) => parent_chunks.push(parent),
+        };
+        let mut segment_chunks = SegmentChunks::new(self, &chunker, &mut on_chunk);
+        let mut text = String::new();
+        let mut text_length = 0;
+        let mut segments = 0;
+        let mut has_text = false;
+        tracing::info_span!("extract", file_type = %extension, strategy = ?chunker.strategy()).in_scope(|| {
+            self.stream_segments(path, &extension, &mut |segment| {
+                if segments > 0 {
+                    text_length += 1;
+                    push_capped(&mut text, "\n");
+                }
+                segments += 1;
+                text_length += segment.text.len();
+                push_capped(&mut text, &segment.text);
+                has_text |= !segment.text.trim().is_empty();
+                segment_chunks.add(&segment);
+                Ok(())
+            })
+        })?;
+        segment_chunks.finish();
+
+        if !has_text {
+            return Err(anyhow!("No text content could be extracted from file"));
+        }
+
+        tracing::Span::current().record("chunks", chunks.len());
+
+        let file_metadata = fs::metadata(path)?;
+        let file_size = file_metadata.len();
+        let date = detect_date(&text, file_metadata.modified().ok());
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown")
+            .to_string();
+
+        info!("Successfully processed file: {} ({} bytes, {} chunks)", file_name, file_size, chunks.len());
+
+        Ok(ProcessedDocument {
+            document_id: crate::models::document_id(file_path),
+            file_path: file_path.to_string(),
+            file_name,
+            file_type: extension,
+            text,
+            text_length,
+            num_chunks: chunks.len(),
+            chunks,
+            file_size,
+            parent_chunks,
+            chunking_strategy: chunker.strategy(),
+            date,
+            labels: Default::default(),
+        })
+    }
+
+    /// Extract text as segments tagged with their page, sheet, slide, or
+    /// section, passing each to `on_segment` as soon as it's read. Text,
+    /// CSV, and spreadsheet rows are read incrementally and split into
+    /// segments of bounded size, so large files never sit in memory whole.
+    pub fn stream_segments(
+        &self,
+        path: &Path,
+        extension: &str,
+        on_segment: &mut dyn FnMut(TextSegment) -> Result<()>,
+    ) -> Result<()> {
+        let mut each = |segments: Vec<TextSegment>| segments.into_iter().try_for_each(&mut *on_segment);
+
+        match extension {
+            ".txt" => self.extract_txt_text(path, on_segment),
+            ".md" => each(self.extract_markdown_text(path)?),
+            ".json" => on_segment(TextSegment::unlocated(self.extract_json_text(path)?)),
+            ".csv" => self.extract_csv_text(path, on_segment),
+            ".xlsx" | ".xls" => self.extract_excel_text(path, on_segment),
+            ".pdf" => each(self.extract_pdf_text(path)?),
+            ".docx" => on_segment(TextSegment::unlocated(self.extract_docx_text(path)?)),
+            ".doc" => on_segment(TextSegment::unlocated(self.extract_doc_text(path)?)),
+            ".pptx" => self.extract_pptx_text(path, on_segment),
+            _ => Err(anyhow!("No extractor available for {}", extension)),
+        }
+    }
+
+    fn extract_txt_text(&self, path: &Path, on_segment: &mut dyn FnMut(TextSegment) -> Result<()>) -> Result<()> {
+        let file = fs::File::open(path)
+            .map_err(|e| anyhow!("Error reading text file: {}", e))?;
+
+        let mut writer = SegmentWriter::new(SourceLocation::default(), on_segment);
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| anyhow!("Error reading text file: {}", e))?;
+            writer.push_line(&line)?;
+        }
+        writer.flush()?;
+
+        info!("Successfully extracted text from {:?}", path);
+        Ok(())
+    }
+
+    fn extract_markdown_text(&self, path: &Path) -> Result<Vec<TextSegment>> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Error reading markdown file: {}", e))?;
+
+        // Heading markers are kept so the markdown chunking strategy can
+        // split on sections; list bullets and emphasis are stripped.
+        let mut segments: Vec<TextSegment> = Vec::new();
+
+        for line in content.lines() {
+            let line = line.trim_start();
+            let cleaned = if line.starts_with('#') {
+                let heading = line.trim_start_matches('#').trim();
+                segments.push(TextSegment {
+                    text: String::new(),
+                    location: SourceLocation {
+                        section: Some(heading.to_string()).filter(|h| !h.is_empty()),
+                        ..Default::default()
+                    },
+                });
+                line
+            } else {
+                let trimmed = line.trim_start_matches([' ', '-', '*']);
+                trimmed.trim_start_matches('*').trim_start_matches('_')
+            };
+
+            if cleaned.is_empty() {
+                continue;
+            }
+            if segments.is_empty() {
+                segments.push(TextSegment::unlocated(String::new()));
+            }
+            if let Some(segment) = segments.last_mut() {
+                if !segment.text.is_empty() {
+                    segment.text.push('\n');
+                }
+                segment.text.push_str(cleaned);
+            }
+        }
+
+        info!("Extracted markdown from {:?}", path);
+        Ok(segments)
+    }
+
+    fn extract_json_text(&self, path: &Path) -> Result<String> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Error reading JSON file: {}", e))?;
+
+        match serde_json::from_str::<serde_json::Value>(&content) {
+            Ok(json) => {
+                let text = self.json_to_text(&json);
+                info!("Extracted JSON from {:?}", path);
+                Ok(text)
+            }
+            Err(e) => Err(anyhow!("Failed to parse JSON: {}", e))
+        }
+    }
+
+    fn json_to_text(&self, value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::Object(obj) => {
+                obj.iter()
+                    .map(|(k, v)| format!("{}: {}", k, self.json_to_text(v)))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            serde_json::Value::Array(arr) => {
+                arr.iter()
+                    .map(|v| self.json_to_text(v))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            serde_json::Value::Null => String::new(),
+        }
+    }
+
+    fn extract_csv_text(&self, path: &Path, on_segment: &mut dyn FnMut(TextSegment) -> Result<()>) -> Result<()> {
+        let mut reader = csv::Reader::from_path(path)
+            .map_err(|e| anyhow!("Error reading CSV file: {}", e))?;
+
+        let mut writer = SegmentWriter::new(SourceLocation::default(), on_segment);
+        writer.push_line(&format!("Document: {}\n", path.file_name().unwrap_or_default().to_string_lossy()))?;
+
+        if let Ok(headers) = reader.headers() {
+            writer.push_line(&headers.iter().collect::<Vec<_>>().join(" | "))?;
+            writer.push_line("---")?;
+        }
+
+        for record in reader.records().flatten() {
+            writer.push_line(&record.iter().collect::<Vec<_>>().join(" | "))?;
+        }
+        writer.flush()?;
+
+        info!("Extracted CSV from {:?}", path);
+        Ok(())
+    }
+
+    fn extract_excel_text(&self, path: &Path, on_segment: &mut dyn FnMut(TextSegment) -> Result<()>) -> Result<()> {
+        use calamine::{Reader, Xlsx};
+
+        let file = fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open Excel file: {}", e))?;
+
+        let mut workbook: Xlsx<_> = Xlsx::new(file)
+            .map_err(|e| anyhow!("Failed to open Excel file: {}", e))?;
+
+        let sheet_names: Vec<_> = workbook.sheet_names().to_vec();
+
+        for sheet_name in sheet_names {
+            let location = SourceLocation {
+                sheet: Some(sheet_name.clone()),
+                ..Default::default()
+            };
+            let mut writer = SegmentWriter::new(location, &mut *on_segment);
+            writer.push_line(&format!("Sheet: {}", sheet_name))?;
+            writer.push_line(&"─".repeat(50))?;
+
+            if let Some(Ok(range)) = workbook.worksheet_range(&sheet_name) {
+                for row in range.rows() {
+                    let row_text: Vec<String> = row
+                        .iter()
+                        .map(|cell| cell.to_string())
+                        .collect();
+                    writer.push_line(&row_text.join(" | "))?;
+                }
+            } else {
+                warn!("Could not read sheet {}", sheet_name);
+            }
+            writer.flush()?;
+        }
+
+        info!("Extracted Excel from {:?}", path);
+        Ok(())
+    }
+
+    fn extract_pdf_text(&self, path: &Path) -> Result<Vec<TextSegment>> {
+        // Try using pdf-extract library first, one segment per page
+        match pdf_extract::extract_text_by_pages(path.to_str().ok_or_else(|| anyhow!("Invalid path"))?) {
+            Ok(pages) => {
+                if pages.iter().any(|page| !page.trim().is_empty()) {
+                    info!("Extracted PDF from {:?} using pdf-extract", path);
+                    return Ok(pages
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, text)| TextSegment {
+                            text,
+                            location: SourceLocation {
+                                page: Some(i + 1),
+                                ..Default::default()
+                            },
+                        })
+                        .collect());
+                }
+            }
+            Err(e) => {
+                warn!("pdf-extract failed: {}, trying fallback method", e);
+            }
+        }
+
+        // Fallback to byte-level extraction
+        let mut file = fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open PDF file: {}", e))?;
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)
+            .map_err(|e| anyhow!("Failed to read PDF file: {}", e))?;
+
+        let text = self.extract_text_from_pdf_bytes(&content);
+
+        if text.is_empty() {
+            warn!("No text extracted from PDF");
+            return Err(anyhow!("Could not extract text from PDF - file may be image-based or encrypted"));
+        }
+
+        info!("Extracted PDF from {:?} using fallback method", path);
+        Ok(vec![TextSegment::unlocated(text)])
+    }
+
+    fn extract_text_from_pdf_bytes(&self, content: &[u8]) -> String {
+        let mut text = String::new();
+        let mut in_text_object = false;
+        let mut current_text = String::new();
+
+        // More robust extraction looking for text between BT/ET markers
+        let mut i = 0;
+        while i < content.len() {
+            // Look for BT marker
+            if i + 2 <= content.len() && &content[i..i + 2] == b"BT"
+                && (i == 0 || content[i - 1].is_ascii_whitespace()) {
+                in_text_object = true;
+                i += 2;
+                continue;
+            }
+
+            // Look for ET marker
+            if in_text_object && i + 2 <= content.len() && &content[i..i + 2] == b"ET"
+                && (i + 2 >= content.len() || content[i + 2].is_ascii_whitespace()) {
+                in_text_object = false;
+                if !current_text.trim().is_empty() {
+                    text.push_str(&current_text);
+                    text.push('\n');
+                    current_text.clear();
+                }
+                i += 2;
+                continue;
+            }
+
+            // Extract strings within parentheses when in text object
+            if in_text_object && content[i] == b'(' {
+                let mut j = i + 1;
+                let mut paren_depth = 1;
+                while j < content.len() && paren_depth > 0 {
+                    if content[j] == b'\\' && j + 1 < content.len() {
+                        j += 2;
+                        continue;
+                    }
+                    if content[j] == b'(' {
+                        paren_depth += 1;
+                    } else if content[j] == b')' {
+                        paren_depth -= 1;
+                    }
+                    j += 1;
+                }
+                if j > i + 1 {
+                    for &c in &content[(i + 1)..(j - 1)] {
+                        if (32..=126).contains(&c) {
+                            current_text.push(c as char);
+                        } else if c == b'\n' || c == b'\r' {
+                            current_text.push(' ');
+                        }
+                    }
+                    current_text.push(' ');
+                }
+                i = j;
+                continue;
+            }
+
+            i += 1;
+        }
+
+        // Clean up the extracted text
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn extract_docx_text(&self, path: &Path) -> Result<String> {
+        use zip::ZipArchive;
+
+        let file = fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open DOCX file: {}", e))?;
+
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| anyhow!("Failed to read DOCX archive: {}", e))?;
+
+        let mut document_xml = archive
+            .by_name("word/document.xml")
+            .map_err(|e| anyhow!("Failed to find document.xml in DOCX: {}", e))?;
+
+        let mut xml_content = String::new();
+        document_xml
+            .read_to_string(&mut xml_content)
+            .map_err(|e| anyhow!("Failed to read document.xml: {}", e))?;
+
+        let text = self.extract_text_from_xml(&xml_content);
+
+        info!("Extracted DOCX from {:?}", path);
+        Ok(text)
+    }
+
+    fn extract_text_from_xml(&self, xml: &str) -> String {
+        let mut text = String::new();
+        let mut inside_tag = false;
+        let mut current_text = String::new();
+
+        for ch in xml.chars() {
+            match ch {
+                '<' => {
+                    inside_tag = true;
+                    if !current_text.is_empty() {
+                        text.push_str(&current_text);
+                        text.push(' ');
+                        current_text.clear();
+                    }
+                }
+                '>' => {
+                    inside_tag = false;
+                }
+                _ if !inside_tag => {
+                    current_text.push(ch);
+                }
+                _ => {}
+            }
+        }
+
+        if !current_text.is_empty() {
+            text.push_str(&current_text);
+        }
+
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn extract_doc_text(&self, _path: &Path) -> Result<String> {
+        warn!("DOC extraction not fully implemented");
+        Err(anyhow!("DOC files require conversion to DOCX or TXT. Please convert your file using Microsoft Word or LibreOffice."))
+    }
+
+    fn extract_pptx_text(&self, path: &Path, on_segment: &mut dyn FnMut(TextSegment) -> Result<()>) -> Result<()> {
+        use zip::ZipArchive;
+
+        let file = fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open PPTX file: {}", e))?;
+
+        let mut archive = ZipArchive::new(file)
+            .map_err(|e| anyhow!("Failed to read PPTX archive: {}", e))?;
+
+        // Archive order is arbitrary, so collect slides by number first
+        let mut slides: Vec<(usize, usize)> = Vec::new();
+        for i in 0..archive.len() {
+            let file_name = match archive.by_index(i) {
+                Ok(f) => f.name().to_string(),
+                Err(_) => continue,
+            };
+
+            let slide_number = file_name
+                .strip_prefix("ppt/slides/slide")
+                .and_then(|rest| rest.strip_suffix(".xml"))
+                .and_then(|n| n.parse::<usize>().ok());
+
+            if let Some(slide_number) = slide_number {
+                slides.push((slide_number, i));
+            }
+        }
+        slides.sort_unstable();
+
+        let mut found_text = false;
+        for (slide_number, index) in slides {
+            if let Ok(mut slide_file) = archive.by_index(index) {
+                let mut slide_content = String::new();
+                let _ = slide_file.read_to_string(&mut slide_content);
+                let slide_text = self.extract_text_from_xml(&slide_content);
+                if !slide_text.is_empty() {
+                    found_text = true;
+                    on_segment(TextSegment {
+                        text: slide_text,
+                        location: SourceLocation {
+                            slide: Some(slide_number),
+                            ..Default::default()
+                        },
+                    })?;
+                }
+            }
+        }
+
+        if !found_text {
+            return Err(anyhow!("No text content found in PPTX file"));
+        }
+
+        info!("Extracted PPTX from {:?}", path);
+        Ok(())
+    }
+
+    /// Resolve chunking settings: request overrides first, then the profile
+    /// for the file type, then the global defaults
+    fn chunker(&self, params: &ChunkingParams, extension: Option<&str>) -> Chunker {
+        let profile = extension
+            .and_then(|ext| self.profiles.get(ext))
+            .copied()
+            .unwrap_or(ChunkingProfile {
+                strategy: self.default_strategy,
+                chunk_size: self.chunk_size,
+                chunk_overlap: self.chunk_overlap,
+            });
+
+        let chunker = Chunker::new(
+            params.chunking_strategy.unwrap_or(profile.strategy),
+            params.chunk_size.unwrap_or(profile.chunk_size),
+            params.chunk_overlap.unwrap_or(profile.chunk_overlap),
+        );
+
+        let chunker = match params.overlap_unit {
+            Some(unit) => chunker.with_overlap_unit(unit),
+            None => chunker,
+        };
+
+        match params.chunk_stride {
+            Some(stride) => chunker.with_stride(stride),
+            None => chunker,
+        }
+    }
+
+    /// Chunk `text` with the default settings
+    pub fn create_chunks(&self, text: &str) -> Vec<DocumentChunk> {
+        let chunks = self.chunker(&ChunkingParams::default(), None).chunk(text);
+        info!("Created {} chunks from text", chunks.len());
+        chunks
+    }
+
+    /// Group sentences into contiguous parent sections of up to
+    /// `parent_chunk_size` bytes, then chunk each section with `chunker` so
+    /// every child lies inside its parent.
+    fn create_parent_child_chunks(&self, text: &str, chunker: &Chunker) -> (Vec<DocumentChunk>, Vec<DocumentChunk>) {
+        let mut sections: Vec<&str> = Vec::new();
+        let mut section_start = 0;
+        let mut section_end = 0;
+
+        for span in Chunker::sentence_spans(text) {
+            if section_end > section_start && section_end - section_start + span.len() > self.parent_chunk_size {
+                sections.push(&text[section_start..section_end]);
+                section_start = section_end;
+            }
+            section_end += span.len();
+        }
+        if section_end > section_start {
+            sections.push(&text[section_start..section_end]);
+        }
+
+        let mut parents = Vec::new();
+        let mut children = Vec::new();
+
+        for section in sections {
+            let section = section.trim();
+            if section.is_empty() {
+                continue;
+            }
+
+            let parent_id = parents.len();
+            for mut child in chunker.chunk(section) {
+                child.chunk_id = children.len();
+                child.parent_id = Some(parent_id);
+                children.push(child);
+            }
+
+            parents.push(DocumentChunk {
+                text: section.into(),
+                size: section.len(),
+                chunk_id: parent_id,
+                parent_id: None,
+                location: SourceLocation::default(),
+            });
+        }
+
+        info!("Created {} chunks from {} parent sections", children.len(), parents.len());
+        (children, parents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_chunks() {
+        let processor = DocumentProcessor::new(100, 20);
+        let text = "This is sentence one. This is sentence two. This is sentence three.";
+        let chunks = processor.create_chunks(text);
+        assert!(!chunks.is_empty());
+    }
+
+    #[test]
+    fn test_empty_text() {
+        let processor = DocumentProcessor::new(100, 20);
+        let chunks = processor.create_chunks("");
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn test_json_extraction() {
+        let processor = DocumentProcessor::new(1000, 100);
+        let json = serde_json::json!({
+            "name": "test",
+            "items": [1, 2, 3]
+        });
+        let text = processor.json_to_text(&json);
+        assert!(!text.is_empty());
+        assert!(text.contains("test"));
+    }
+
+    #[test]
+    fn test_chunks_carry_segment_location() {
+        let processor = DocumentProcessor::new(1000, 0);
+        let chunker = Chunker::new(ChunkingStrategy::Sentence, 1000, 0);
+        let mut chunks = Vec::new();
+        let mut on_chunk = |chunk| {
+            if let SegmentChunk::Chunk(chunk) = chunk {
+                chunks.push(chunk);
+            }
+        };
+        let mut segment_chunks = SegmentChunks::new(&processor, &chunker, &mut on_chunk);
+        segment_chunks.add(&TextSegment {
+            text: "First page text.".to_string(),
+            location: SourceLocation { page: Some(1), ..Default::default() },
+        });
+        segment_chunks.add(&TextSegment {
+            text: "Second page text.".to_string(),
+            location: SourceLocation { page: Some(2), ..Default::default() },
+        });
+        segment_chunks.finish();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].chunk_id, 1);
+        assert_eq!(chunks[1].location.page, Some(2));
+    }
+
+    #[test]
+    fn test_segments_of_one_location_chunk_as_one_text() {
+        let sentences: Vec<String> = (0..40).map(|i| format!("Sentence number {} is here.", i)).collect();
+        let (first, second) = (sentences[..17].join(" "), sentences[17..].join(" "));
+        let processor = DocumentProcessor::new(120, 4);
+
+        for strategy in [
+            ChunkingStrategy::Fixed,
+            ChunkingStrategy::Recursive,
+            ChunkingStrategy::Sentence,
+            ChunkingStrategy::Token,
+            ChunkingStrategy::Semantic,
+            ChunkingStrategy::Markdown,
+            ChunkingStrategy::Window,
+        ] {
+            let chunker = Chunker::new(strategy, 120, 4);
+            let mut chunks = Vec::new();
+            let mut on_chunk = |chunk| {
+                if let SegmentChunk::Chunk(chunk) = chunk {
+                    chunks.push(chunk);
+                }
+            };
+            let mut segment_chunks = SegmentChunks::new(&processor, &chunker, &mut on_chunk);
+            for text in [&first, &second] {
+                segment_chunks.add(&TextSegment::unlocated(text.clone()));
+            }
+            segment_chunks.finish();
+
+            // The same chunks, overlaps included, as the text cut whole
+            let whole = chunker.chunk(&format!("{}\n{}", first, second));
+            let texts = |chunks: &[DocumentChunk]| chunks.iter().map(|c| c.text.to_string()).collect::<Vec<_>>();
+            assert_eq!(texts(&chunks), texts(&whole), "{:?}", strategy);
+            assert!(chunks.iter().enumerate().all(|(i, chunk)| chunk.chunk_id == i));
+        }
+    }
+
+    #[test]
+    fn test_process_files_reports_every_file_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = Vec::new();
+        for i in 0..12 {
+            let path = dir.path().join(format!("doc-{}.txt", i));
+            fs::write(&path, format!("Document number {} is here.", i)).unwrap();
+            files.push((path.to_string_lossy().into_owned(), None));
+        }
+        files.push(("missing.txt".to_string(), None));
+
+        let processor = DocumentProcessor::new(1000, 0).with_workers(4);
+        let mut seen = vec![0; files.len()];
+        processor.process_files(&files, &ChunkingParams::default(), |i, processed| {
+            seen[i] += 1;
+            match processed {
+                Ok(document) => assert!(document.text.contains(&format!("number {} ", i))),
+                Err(_) => assert_eq!(files[i].0, "missing.txt"),
+            }
+        });
+        assert!(seen.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_large_files_stream_in_bounded_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large.csv");
+        let rows: String = (0..40_000).map(|i| format!("{},item number {}\n", i, i)).collect();
+        fs::write(&path, format!("id,name\n{}", rows)).unwrap();
+
+        let processor = DocumentProcessor::new(1000, 0);
+        let mut segments = Vec::new();
+        processor
+            .stream_segments(&path, ".csv", &mut |segment| {
+                segments.push(segment.text.len());
+                Ok(())
+            })
+            .unwrap();
+        assert!(segments.len() > 1);
+        assert!(segments.iter().all(|&len| len < 2 * SEGMENT_BYTES));
+
+        let document = processor.process_file(path.to_str().unwrap()).unwrap();
+        assert_eq!(document.text.len(), MAX_DOCUMENT_TEXT.min(document.text_length));
+        assert!(document.chunks.last().unwrap().text.contains("39999 | item number 39999"));
+    }
+
+    #[test]
+    fn test_profile_selected_by_extension() {
+        let profiles = HashMap::from([(
+            ".csv".to_string(),
+            ChunkingProfile {
+                strategy: ChunkingStrategy::Token,
+                chunk_size: 300,
+                chunk_overlap: 30,
+            },
+        )]);
+        let processor = DocumentProcessor::new(1000, 200).with_profiles(profiles);
+
+        let csv = processor.chunker(&ChunkingParams::default(), Some(".csv"));
+        assert_eq!(csv.strategy(), ChunkingStrategy::Token);
+        assert_eq!(csv.chunk_size(), 300);
+
+        let txt = processor.chunker(&ChunkingParams::default(), Some(".txt"));
+        assert_eq!(txt.strategy(), ChunkingStrategy::Sentence);
+        assert_eq!(txt.chunk_size(), 1000);
+
+        let overridden = processor.chunker(
+            &ChunkingParams {
+                chunk_size: Some(50),
+                ..Default::default()
+            },
+            Some(".csv"),
+        );
+        assert_eq!(overridden.strategy(), ChunkingStrategy::Token);
+        assert_eq!(overridden.chunk_size(), 50);
+    }
+
+    #[test]
+    fn test_parent_child_chunks() {
+        let processor = DocumentProcessor::new(40, 0).with_parent_chunk_size(120);
+        let chunker = Chunker::new(ChunkingStrategy::Recursive, 40, 0);
+        let text = "Alpha beta gamma delta. Epsilon zeta eta theta. Iota kappa lambda mu. \
+                    Nu xi omicron pi. Rho sigma tau upsilon. Phi chi psi omega.";
+        let (children, parents) = processor.create_parent_child_chunks(text, &chunker);
+
+        assert!(parents.len() > 1);
+        assert!(children.len() > parents.len());
+        for (i, child) in children.iter().enumerate() {
+            assert_eq!(child.chunk_id, i);
+            let parent = &parents[child.parent_id.unwrap()];
+            assert!(parent.text.contains(&*child.text));
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use crate::models::{ChatMessage, DocumentChunk};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// How well one query's ranking found its expected documents
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct RankingMetrics {
+    /// Share of the expected documents in the top k
+    pub recall: f32,
+    /// 1 / rank of the first expected document, 0 if none was found
+    pub reciprocal_rank: f32,
+    /// Discounted cumulative gain of the ranking over that of a perfect one
+    pub ndcg: f32,
+}
+
+/// Score `ranked`, the distinct documents of a top-`k` search best first,
+/// against the `expected` ones. `is_match(ranked, expected)` says whether a
+/// ranked document is an expected one.
+pub fn ranking_metrics<R, E>(
+    ranked: &[R],
+    expected: &[E],
+    k: usize,
+    is_match: impl Fn(&R, &E) -> bool,
+) -> RankingMetrics {
+    if expected.is_empty() {
+        return RankingMetrics {
+            recall: 0.0,
+            reciprocal_rank: 0.0,
+            ndcg: 0.0,
+        };
+    }
+
+    let relevant: Vec<bool> = ranked
+        .iter()
+        .map(|document| expected.iter().any(|e| is_match(document, e)))
+        .collect();
+    let found = expected
+        .iter()
+        .filter(|e| ranked.iter().any(|document| is_match(document, e)))
+        .count();
+
+    let gain = |rank: usize| 1.0 / ((rank + 2) as f32).log2();
+    let dcg: f32 = relevant
+        .iter()
+        .enumerate()
+        .filter(|(_, relevant)| **relevant)
+        .map(|(rank, _)| gain(rank))
+        .sum();
+    let ideal: f32 = (0..expected.len().min(k)).map(gain).sum();
+
+    RankingMetrics {
+        recall: found as f32 / expected.len() as f32,
+        reciprocal_rank: relevant
+            .iter()
+            .position(|relevant| *relevant)
+            .map_or(0.0, |rank| 1.0 / (rank + 1) as f32),
+        ndcg: if ideal > 0.0 { dcg / ideal } else { 0.0 },
+    }
+}
+
+/// Mean of each metric over several queries
+pub fn mean_metrics(metrics: &[RankingMetrics]) -> RankingMetrics {
+    let count = metrics.len().max(1) as f32;
+    RankingMetrics {
+        recall: metrics.iter().map(|m| m.recall).sum::<f32>() / count,
+        reciprocal_rank: metrics.iter().map(|m| m.reciprocal_rank).sum::<f32>() / count,
+        ndcg: metrics.iter().map(|m| m.ndcg).sum::<f32>() / count,
+    }
+}
+
+/// LLM-as-judge scores for one answered question, each 0.0 to 1.0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RagScores {
+    /// How much of the answer the retrieved context supports
+    pub faithfulness: f32,
+    /// How directly the answer addresses the question
+    pub answer_relevance: f32,
+    /// Whether relevant chunks were ranked above irrelevant ones
+    pub context_precision: f32,
+}
+
+/// Mean of each score over several questions
+pub fn mean_scores(scores: &[RagScores]) -> RagScores {
+    let count = scores.len().max(1) as f32;
+    RagScores {
+        faithfulness: scores.iter().map(|s| s.faithfulness).sum::<f32>() / count,
+        answer_relevance: scores.iter().map(|s| s.answer_relevance).sum::<f32>() / count,
+        context_precision: scores.iter().map(|s| s.context_precision).sum::<f32>() / count,
+    }
+}
+
+/// Mean precision at each rank holding a relevant chunk, so relevant chunks
+/// ranked first score higher than the same chunks ranked last
+pub fn context_precision(relevant: &[bool]) -> f32 {
+    let mut hits = 0;
+    let mut total = 0.0;
+    for (rank, relevant) in relevant.iter().enumerate() {
+        if *relevant {
+            hits += 1;
+            total += hits as f32 / (rank + 1) as f32;
+        }
+    }
+    if hits == 0 {
+        0.0
+    } else {
+        total / hits as f32
+    }
+}
+
+/// Prompt for a judge to grade an answer and its numbered contexts
+pub fn rag_judge_messages(
+    question: &str,
+    contexts: &[&str],
+    answer: &str,
+    reference_answer: Option<&str>,
+) -> Vec<ChatMessage> {
+    let numbered: Vec<String> = contexts
+        .iter()
+        .enumerate()
+        .map(|(i, context)| format!("[{}] {}", i + 1, context))
+        .collect();
+    let reference = reference_answer
+        .map(|reference| format!("Reference answer: {}\n\n", reference))
+        .unwrap_or_default();
+
+    vec![
+        ChatMessage::system(
+            "You grade answers of a document question-answering system. Be strict and reply with JSON only.",
+        ),
+        ChatMessage::user(format!(
+            "Question: {}\n\nRetrieved contexts:\n{}\n\n{}Answer: {}\n\nGrade:\n- faithfulness: 0-10, how much of the answer is stated in or directly implied by the contexts\n- answer_relevance: 0-10, how directly and completely the answer addresses the question\n- relevant_contexts: numbers of the contexts that help answer the question\n\nRespond with a JSON object: {{\"faithfulness\": <0-10>, \"answer_relevance\": <0-10>, \"relevant_contexts\": [<number>, ...]}}",
+            question,
+            numbered.join("\n\n"),
+            reference,
+            answer
+        )),
+    ]
+}
+
+/// Scores from a judge response for an answer built from `context_count` contexts
+pub fn parse_rag_judgement(response: &str, context_count: usize) -> Result<RagScores> {
+    let start = response.find('{').ok_or_else(|| anyhow!("No JSON object in judge response"))?;
+    let end = response.rfind('}').ok_or_else(|| anyhow!("No JSON object in judge response"))?;
+    let judgement: serde_json::Value = serde_json::from_str(&response[start..=end])?;
+
+    let grade = |field: &str| {
+        judgement[field]
+            .as_f64()
+            .map(|score| (score as f32 / 10.0).clamp(0.0, 1.0))
+            .ok_or_else(|| anyhow!("Judge response has no {}", field))
+    };
+    let mut relevant = vec![false; context_count];
+    for n in judgement["relevant_contexts"].as_array().into_iter().flatten() {
+        if let Some(slot) = n.as_u64().and_then(|n| (n as usize).checked_sub(1)).and_then(|i| relevant.get_mut(i)) {
+            *slot = true;
+        }
+    }
+
+    Ok(RagScores {
+        faithfulness: grade("faithfulness")?,
+        answer_relevance: grade("answer_relevance")?,
+        context_precision: context_precision(&relevant),
+    })
+}
+
+/// Chunks shorter than this rarely hold a fact worth asking about
+const MIN_TESTSET_CHUNK_CHARS: usize = 200;
+
+/// Characters of a chunk shown when generating a question from it
+const TESTSET_CHUNK_CHARS: usize = 4000;
+
+/// Up to `n` chunks to generate test questions from, shared evenly across
+/// `documents` and spread evenly through each. Returns (document index,
+/// chunk) pairs.
+pub fn testset_chunks(documents: &[Vec<DocumentChunk>], n: usize) -> Vec<(usize, &DocumentChunk)> {
+    let usable: Vec<Vec<&DocumentChunk>> = documents
+        .iter()
+        .map(|chunks| {
+            chunks
+                .iter()
+                .filter(|c| c.text.trim().chars().count() >= MIN_TESTSET_CHUNK_CHARS)
+                .collect()
+        })
+        .collect();
+
+    // Hand out the n slots one document at a time until chunks run out
+    let mut quotas = vec![0; usable.len()];
+    let mut remaining = n;
+    while remaining > 0 {
+        let before = remaining;
+        for (quota, chunks) in quotas.iter_mut().zip(&usable) {
+            if remaining > 0 && *quota < chunks.len() {
+                *quota += 1;
+                remaining -= 1;
+            }
+        }
+        if remaining == before {
+            break;
+        }
+    }
+
+    usable
+        .iter()
+        .zip(quotas)
+        .enumerate()
+        .flat_map(|(doc, (chunks, quota))| (0..quota).map(move |i| (doc, chunks[i * chunks.len() / quota])))
+        .collect()
+}
+
+/// Prompt for one question a chunk answers, with that answer
+pub fn testset_messages(file_name: &str, chunk: &str) -> Vec<ChatMessage> {
+    let chunk: String = chunk.chars().take(TESTSET_CHUNK_CHARS).collect();
+    vec![
+        ChatMessage::system(
+            "You write evaluation questions for a document question-answering system. Reply with JSON only.",
+        ),
+        ChatMessage::user(format!(
+            "Document: {}\n\nPassage:\n{}\n\nWrite one question a user of the document might ask that this passage fully answers, and the answer as stated in the passage. The question must make sense without seeing the passage.\n\nRespond with a JSON object: {{\"question\": \"...\", \"answer\": \"...\"}}",
+            file_name, chunk
+        )),
+    ]
+}
+
+/// (question, answer) from a generation response
+pub fn parse_testset_pair(response: &str) -> Result<(String, String)> {
+    let start = response.find('{').ok_or_else(|| anyhow!("No JSON object in generated pair"))?;
+    let end = response.rfind('}').ok_or_else(|| anyhow!("No JSON object in generated pair"))?;
+    let pair: serde_json::Value = serde_json::from_str(&response[start..=end])?;
+
+    let field = |name: &str| {
+        pair[name]
+            .as_str()
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Generated pair has no {}", name))
+    };
+    Ok((field("question")?, field("answer")?))
+}
+
+/// Results of one evaluation run, kept to compare runs over time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalRun {
+    pub id: String,
+    pub name: Option<String>,
+    /// RFC 3339 time the run finished
+    pub created_at: String,
+    /// Retrieval and generation settings the run used
+    pub settings: serde_json::Value,
+    /// Mean scores over the questions that were judged
+    pub scores: RagScores,
+    pub count: usize,
+    pub failed: usize,
+    pub cases: Vec<serde_json::Value>,
+}
+
+/// Evaluation runs saved as one JSON file each
+pub struct EvalRunStore {
+    dir: PathBuf,
+}
+
+impl EvalRunStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(EvalRunStore { dir })
+    }
+
+    pub fn save(&self, run: &EvalRun) -> Result<()> {
+        fs::write(self.dir.join(format!("{}.json", run.id)), serde_json::to_string_pretty(run)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<EvalRun> {
+        // Ids are generated UUIDs; anything else can't name a run
+        if !id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return None;
+        }
+        let json = fs::read_to_string(self.dir.join(format!("{}.json", id))).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Every run without its per-question results, newest first
+    pub fn list(&self) -> Result<Vec<EvalRun>> {
+        let mut runs: Vec<EvalRun> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|entry| {
+                let json = fs::read_to_string(entry.path()).ok()?;
+                serde_json::from_str::<EvalRun>(&json).ok()
+            })
+            .map(|run| EvalRun { cases: Vec::new(), ..run })
+            .collect();
+        runs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(ranked: &[&str], expected: &[&str]) -> RankingMetrics {
+        ranking_metrics(ranked, expected, 3, |r, e| r == e)
+    }
+
+    #[test]
+    fn test_ranking_metrics() {
+        let perfect = metrics(&["a", "b", "c"], &["a", "b"]);
+        assert_eq!(perfect.recall, 1.0);
+        assert_eq!(perfect.reciprocal_rank, 1.0);
+        assert!((perfect.ndcg - 1.0).abs() < 1e-6);
+
+        let late = metrics(&["x", "y", "a"], &["a", "b"]);
+        assert_eq!(late.recall, 0.5);
+        assert!((late.reciprocal_rank - 1.0 / 3.0).abs() < 1e-6);
+        // 1/log2(4) over 1/log2(2) + 1/log2(3)
+        assert!((late.ndcg - 0.5 / (1.0 + 1.0 / 3f32.log2())).abs() < 1e-6);
+
+        let missed = metrics(&["x"], &["a"]);
+        assert_eq!(missed, metrics(&[], &["a"]));
+        assert_eq!(missed.ndcg, 0.0);
+
+        let mean = mean_metrics(&[perfect, missed]);
+        assert_eq!(mean.recall, 0.5);
+        assert_eq!(mean.reciprocal_rank, 0.5);
+    }
+
+    #[test]
+    fn test_testset_chunks_and_pairs() {
+        let chunk = |chunk_id: usize, len: usize| DocumentChunk {
+            text: "x".repeat(len).into(),
+            size: len,
+            chunk_id,
+            parent_id: None,
+            location: Default::default(),
+        };
+        let documents = vec![
+            (0..6).map(|i| chunk(i, 300)).collect::<Vec<_>>(),
+            vec![chunk(0, 300), chunk(1, 20)],
+        ];
+        let picked: Vec<(usize, usize)> = testset_chunks(&documents, 4)
+            .into_iter()
+            .map(|(doc, chunk)| (doc, chunk.chunk_id))
+            .collect();
+        assert_eq!(picked, vec![(0, 0), (0, 2), (0, 4), (1, 0)]);
+        assert_eq!(testset_chunks(&documents, 50).len(), 7);
+
+        let (question, answer) =
+            parse_testset_pair("```json\n{\"question\": \"How many PTO days?\", \"answer\": \" 25 \"}\n```").unwrap();
+        assert_eq!((question.as_str(), answer.as_str()), ("How many PTO days?", "25"));
+        assert!(parse_testset_pair("{\"question\": \"\"}").is_err());
+    }
+
+    #[test]
+    fn test_rag_judgement_and_runs() {
+        let scores = parse_rag_judgement(
+            "```json\n{\"faithfulness\": 8, \"answer_relevance\": 10, \"relevant_contexts\": [2, 9]}\n```",
+            3,
+        )
+        .unwrap();
+        assert_eq!(scores.faithfulness, 0.8);
+        assert_eq!(scores.answer_relevance, 1.0);
+        assert_eq!(scores.context_precision, 0.5);
+        assert!(parse_rag_judgement("{\"faithfulness\": 8}", 3).is_err());
+        assert_eq!(context_precision(&[true, false, true]), (1.0 + 2.0 / 3.0) / 2.0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = EvalRunStore::new(dir.path()).unwrap();
+        let run = EvalRun {
+            id: "0b6f5d3e-1c2a-4f5e-9d8c-7b6a5f4e3d2c".to_string(),
+            name: Some("baseline".to_string()),
+            created_at: "2026-01-01T00:00:00+00:00".to_string(),
+            settings: serde_json::json!({"k": 5}),
+            scores,
+            count: 1,
+            failed: 0,
+            cases: vec![serde_json::json!({"question": "What is the PTO policy?"})],
+        };
+        store.save(&run).unwrap();
+
+        assert_eq!(store.get(&run.id).unwrap().cases.len(), 1);
+        assert!(store.get("../secrets").is_none());
+        let runs = store.list().unwrap();
+        assert_eq!(runs[0].name.as_deref(), Some("baseline"));
+        assert!(runs[0].cases.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use crate::services::pipeline::PipelineStore;
+use anyhow::{anyhow, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Answered requests remembered for feedback; older ones can't be rated
+const MAX_TRACKED_REQUESTS: usize = 10_000;
+
+/// One arm of an experiment: a named pipeline and its share of traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variant {
+    pub name: String,
+    pub pipeline: String,
+    /// Relative share of requests; defaults to 1
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// Retrieval configurations compared on live `/api/query` traffic
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Experiment {
+    pub name: String,
+    pub variants: Vec<Variant>,
+}
+
+impl Experiment {
+    /// The experiment in `path`, or `None` if there's no such file
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(|e| anyhow!("Invalid experiment in {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// At least two uniquely named variants with traffic, each running a
+    /// defined pipeline
+    pub fn validate(&self, pipelines: &PipelineStore) -> Result<()> {
+        if self.variants.len() < 2 {
+            return Err(anyhow!("an experiment needs at least two variants"));
+        }
+        let mut names = HashSet::new();
+        for variant in &self.variants {
+            if !names.insert(variant.name.as_str()) {
+                return Err(anyhow!("variant '{}' is defined twice", variant.name));
+            }
+            if variant.weight == 0 {
+                return Err(anyhow!("variant '{}' has no traffic weight", variant.name));
+            }
+            pipelines
+                .resolve(Some(&variant.pipeline))
+                .map_err(|e| anyhow!("variant '{}': {}", variant.name, e))?;
+        }
+        Ok(())
+    }
+
+    /// The variant covering `roll`, a number below the total weight
+    fn variant_at(&self, mut roll: u32) -> &Variant {
+        for variant in &self.variants {
+            if roll < variant.weight {
+                return variant;
+            }
+            roll -= variant.weight;
+        }
+        self.variants.last().expect("validated experiments have variants")
+    }
+
+    /// A variant drawn at random in proportion to the weights
+    pub fn assign(&self) -> &Variant {
+        let total: u32 = self.variants.iter().map(|v| v.weight).sum();
+        self.variant_at(rand::thread_rng().gen_range(0..total))
+    }
+}
+
+/// Latency and feedback accumulated for one variant
+#[derive(Debug, Clone, Copy, Default)]
+struct VariantStats {
+    requests: u64,
+    total_latency_ms: u64,
+    max_latency_ms: u64,
+    helpful: u64,
+    unhelpful: u64,
+}
+
+#[derive(Default)]
+struct TrackerState {
+    stats: HashMap<String, VariantStats>,
+    /// Request id → variant for answers that haven't been rated yet
+    assignments: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+/// The running experiment, if any, with in-memory per-variant results
+#[derive(Default)]
+pub struct ExperimentTracker {
+    experiment: Option<Experiment>,
+    state: Mutex<TrackerState>,
+}
+
+impl ExperimentTracker {
+    pub fn new(experiment: Option<Experiment>) -> Self {
+        ExperimentTracker {
+            experiment,
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    pub fn experiment(&self) -> Option<&Experiment> {
+        self.experiment.as_ref()
+    }
+
+    /// Count an answered request under `variant` so feedback on
+    /// `request_id` can be attributed to it
+    pub fn record_request(&self, request_id: &str, variant: &str, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let latency_ms = latency.as_millis() as u64;
+        let stats = state.stats.entry(variant.to_string()).or_default();
+        stats.requests += 1;
+        stats.total_latency_ms += latency_ms;
+        stats.max_latency_ms = stats.max_latency_ms.max(latency_ms);
+
+        state.assignments.insert(request_id.to_string(), variant.to_string());
+        state.order.push_back(request_id.to_string());
+        while state.order.len() > MAX_TRACKED_REQUESTS {
+            if let Some(oldest) = state.order.pop_front() {
+                state.assignments.remove(&oldest);
+            }
+        }
+    }
+
+    /// Record whether the answer to `request_id` helped; each answer can be
+    /// rated once. Returns the variant credited.
+    pub fn record_feedback(&self, request_id: &str, helpful: bool) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let variant = state
+            .assignments
+            .remove(request_id)
+            .ok_or_else(|| anyhow!("No unrated experiment request with id {}", request_id))?;
+        let stats = state.stats.entry(variant.clone()).or_default();
+        if helpful {
+            stats.helpful += 1;
+        } else {
+            stats.unhelpful += 1;
+        }
+        Ok(variant)
+    }
+
+    /// The experiment's variants with their request counts, latency, and
+    /// share of helpful ratings
+    pub fn report(&self) -> serde_json::Value {
+        let Some(experiment) = &self.experiment else {
+            return json!({ "experiment": null });
+        };
+        let state = self.state.lock().unwrap();
+        let variants: Vec<serde_json::Value> = experiment
+            .variants
+            .iter()
+            .map(|variant| {
+                let stats = state.stats.get(&variant.name).copied().unwrap_or_default();
+                let rated = stats.helpful + stats.unhelpful;
+                json!({
+                    "name": variant.name,
+                    "pipeline": variant.pipeline,
+                    "weight": variant.weight,
+                    "requests": stats.requests,
+                    "mean_latency_ms": (stats.requests > 0)
+                        .then(|| stats.total_latency_ms as f64 / stats.requests as f64),
+                    "max_latency_ms": stats.max_latency_ms,
+                    "feedback": {
+                        "helpful": stats.helpful,
+                        "unhelpful": stats.unhelpful,
+                        "helpful_rate": (rated > 0).then(|| stats.helpful as f64 / rated as f64)
+                    }
+                })
+            })
+            .collect();
+
+        json!({
+            "experiment": experiment.name,
+            "variants": variants
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn experiment() -> Experiment {
+        serde_json::from_value(json!({
+            "name": "rerank-vs-plain",
+            "variants": [
+                {"name": "control", "pipeline": "fast", "weight": 3},
+                {"name": "reranked", "pipeline": "precise"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_assignment_and_aggregation() {
+        let experiment = experiment();
+        assert_eq!(experiment.variant_at(2).name, "control");
+        assert_eq!(experiment.variant_at(3).name, "reranked");
+        assert!(experiment.validate(&PipelineStore::default()).is_err());
+
+        let tracker = ExperimentTracker::new(Some(experiment));
+        tracker.record_request("a", "control", Duration::from_millis(100));
+        tracker.record_request("b", "control", Duration::from_millis(300));
+        tracker.record_request("c", "reranked", Duration::from_millis(500));
+        assert_eq!(tracker.record_feedback("a", true).unwrap(), "control");
+        assert!(tracker.record_feedback("a", false).is_err());
+        tracker.record_feedback("b", false).unwrap();
+
+        let report = tracker.report();
+        let control = &report["variants"][0];
+        assert_eq!(control["requests"], 2);
+        assert_eq!(control["mean_latency_ms"], 200.0);
+        assert_eq!(control["max_latency_ms"], 300);
+        assert_eq!(control["feedback"]["helpful_rate"], 0.5);
+        assert_eq!(report["variants"][1]["feedback"]["helpful_rate"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::models::ChatMessage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Examples beyond this would crowd the context out of the prompt
+pub const MAX_EXAMPLES: usize = 20;
+
+/// A question and the answer the deployment would like to see for it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FewShotExample {
+    pub question: String,
+    pub answer: String,
+}
+
+/// The examples as prior conversation turns, ahead of the real question
+pub fn example_messages(examples: &[FewShotExample]) -> Vec<ChatMessage> {
+    examples
+        .iter()
+        .flat_map(|example| {
+            [
+                ChatMessage::user(example.question.as_str()),
+                ChatMessage::assistant(example.answer.as_str()),
+            ]
+        })
+        .collect()
+}
+
+/// Few-shot examples kept in a JSON file and prepended to every answer prompt
+pub struct FewShotStore {
+    path: PathBuf,
+    examples: Vec<FewShotExample>,
+}
+
+impl FewShotStore {
+    /// Load the examples in `path`; a missing file means no examples
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let examples = match fs::read_to_string(&path) {
+            Ok(json) => {
+                let examples: Vec<FewShotExample> = serde_json::from_str(&json)
+                    .map_err(|e| anyhow!("Invalid few-shot examples in {}: {}", path.display(), e))?;
+                validate(&examples)?;
+                examples
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(FewShotStore { path, examples })
+    }
+
+    pub fn examples(&self) -> &[FewShotExample] {
+        &self.examples
+    }
+
+    /// Replace every example and write them to disk
+    pub fn replace(&mut self, examples: Vec<FewShotExample>) -> Result<&[FewShotExample]> {
+        validate(&examples)?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&examples)?)?;
+        self.examples = examples;
+        Ok(&self.examples)
+    }
+}
+
+fn validate(examples: &[FewShotExample]) -> Result<()> {
+    if examples.len() > MAX_EXAMPLES {
+        return Err(anyhow!("At most {} few-shot examples are allowed", MAX_EXAMPLES));
+    }
+    if let Some(i) = examples
+        .iter()
+        .position(|e| e.question.trim().is_empty() || e.answer.trim().is_empty())
+    {
+        return Err(anyhow!("Few-shot example {} needs both a question and an answer", i + 1));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("few_shot.json");
+        let mut store = FewShotStore::new(&path).unwrap();
+        assert!(store.examples().is_empty());
+
+        let example = FewShotExample {
+            question: "What is the PTO policy?".to_string(),
+            answer: "- 25 days per year [1]".to_string(),
+        };
+        store.replace(vec![example.clone()]).unwrap();
+        assert!(store
+            .replace(vec![FewShotExample {
+                question: "Blank answer?".to_string(),
+                answer: " ".to_string(),
+            }])
+            .is_err());
+
+        let reloaded = FewShotStore::new(&path).unwrap();
+        assert_eq!(reloaded.examples(), &[example]);
+        let messages = example_messages(reloaded.examples());
+        assert_eq!(messages[0].role, "user");
+        assert_eq!(messages[1].role, "assistant");
+    }
+}
@@ -0,0 +1,47 @@
+use crate::models::ChatMessage;
+use crate::services::query_transform::parse_expansions;
+
+/// Follow-up questions suggested with an answer
+pub const FOLLOW_UP_COUNT: usize = 3;
+
+/// Characters of the answer's context shown when suggesting follow-ups
+const FOLLOW_UP_CONTEXT_CHARS: usize = 6000;
+
+/// Prompt for questions a reader might ask next that `context` can answer
+pub fn follow_up_messages(query: &str, answer: &str, context: &str) -> Vec<ChatMessage> {
+    let context: String = context.chars().take(FOLLOW_UP_CONTEXT_CHARS).collect();
+    vec![
+        ChatMessage::system(format!(
+            "Suggest {} short follow-up questions the user might ask next. Each must be answerable from the context and not already answered by the answer. Write them in the language of the answer. Reply with one question per line and nothing else.",
+            FOLLOW_UP_COUNT
+        )),
+        ChatMessage::user(format!(
+            "Context:\n{}\n\nQuestion: {}\n\nAnswer: {}",
+            context, query, answer
+        )),
+    ]
+}
+
+/// Questions from a suggestion response, without numbering, repeats, or
+/// the original question
+pub fn parse_follow_ups(response: &str, query: &str) -> Vec<String> {
+    let mut questions = parse_expansions(response, query);
+    questions.truncate(FOLLOW_UP_COUNT);
+    questions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_follow_ups() {
+        let response = "1. How do I request PTO?\n2. Does unused PTO carry over?\n3. What is the PTO policy?\n4. Who approves PTO?\n5. Is PTO paid out?";
+        assert_eq!(
+            parse_follow_ups(response, "What is the PTO policy?"),
+            vec!["How do I request PTO?", "Does unused PTO carry over?", "Who approves PTO?"]
+        );
+        let messages = follow_up_messages("q", "a", &"x".repeat(10_000));
+        assert!(messages[1].content.len() < 6100);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::models::ChatMessage;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A claim counts as supported when at least this share of its content
+/// terms appears in a single context block
+const SUPPORT_THRESHOLD: f32 = 0.5;
+
+/// Returned in place of an answer that strict mode rejects
+pub const INSUFFICIENT_INFORMATION: &str =
+    "I don't have enough information in the provided documents to answer that reliably.";
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "had", "her", "was", "one",
+    "our", "out", "has", "have", "his", "how", "its", "may", "who", "did", "does", "this", "that",
+    "with", "from", "they", "them", "then", "than", "there", "their", "these", "those", "what",
+    "when", "where", "which", "while", "will", "would", "could", "should", "been", "being", "into",
+    "also", "such", "some", "more", "most", "other", "only", "about", "over", "very", "each", "both",
+];
+
+/// How generated answers are checked against their context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerificationMode {
+    Off,
+    /// Term overlap between each answer sentence and the context blocks
+    #[default]
+    Lexical,
+    /// A second LLM call judges each sentence, falling back to lexical
+    Llm,
+}
+
+impl std::str::FromStr for VerificationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "none" => Ok(VerificationMode::Off),
+            "lexical" => Ok(VerificationMode::Lexical),
+            "llm" | "judge" => Ok(VerificationMode::Llm),
+            other => Err(format!("Unknown verification mode: {}", other)),
+        }
+    }
+}
+
+/// Support found for one sentence of an answer
+#[derive(Debug, Clone, Serialize)]
+pub struct ClaimSupport {
+    pub text: String,
+    pub supported: bool,
+    pub score: f32,
+    /// Context block numbers that support the claim
+    pub sources: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Groundedness {
+    /// Mean support score over all claims, 0.0 to 1.0
+    pub confidence: f32,
+    pub claims: Vec<ClaimSupport>,
+}
+
+impl Groundedness {
+    fn from_claims(claims: Vec<ClaimSupport>) -> Self {
+        let confidence = if claims.is_empty() {
+            0.0
+        } else {
+            claims.iter().map(|c| c.score).sum::<f32>() / claims.len() as f32
+        };
+        Groundedness { confidence, claims }
+    }
+
+    pub fn unsupported_claims(&self) -> impl Iterator<Item = &ClaimSupport> {
+        self.claims.iter().filter(|c| !c.supported)
+    }
+}
+
+/// Content terms of `text`: lowercase words of three or more letters that
+/// aren't stopwords, plus anything containing a digit
+pub fn content_terms(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|w| {
+            w.chars().any(|c| c.is_ascii_digit())
+                || (w.chars().count() >= 3 && !STOPWORDS.contains(&w.as_str()))
+        })
+        .collect()
+}
+
+/// Remove `[n]` citation markers so they don't count as terms
+fn strip_citations(sentence: &str) -> String {
+    let mut stripped = String::with_capacity(sentence.len());
+    let mut rest = sentence;
+    while let Some(open) = rest.find('[') {
+        stripped.push_str(&rest[..open]);
+        match rest[open..].find(']') {
+            Some(close)
+                if rest[open + 1..open + close]
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == ' ') =>
+            {
+                rest = &rest[open + close + 1..];
+            }
+            _ => {
+                stripped.push('[');
+                rest = &rest[open + 1..];
+            }
+        }
+    }
+    stripped.push_str(rest);
+    stripped.trim().to_string()
+}
+
+/// Sentences of an answer that make checkable claims
+pub fn split_claims(answer: &str) -> Vec<String> {
+    answer
+        .split_inclusive(['.', '!', '?', '\n'])
+        .map(strip_citations)
+        .map(|s| s.trim_start_matches(['-', '*', ' ']).trim().to_string())
+        .filter(|s| !content_terms(s).is_empty())
+        .collect()
+}
+
+/// Score each claim by its best term overlap with a single context block
+pub fn lexical_groundedness(answer: &str, context_blocks: &[&str]) -> Groundedness {
+    let block_terms: Vec<HashSet<String>> = context_blocks.iter().map(|b| content_terms(b)).collect();
+
+    let claims = split_claims(answer)
+        .into_iter()
+        .map(|text| {
+            let terms = content_terms(&text);
+            let overlaps: Vec<f32> = block_terms
+                .iter()
+                .map(|block| terms.intersection(block).count() as f32 / terms.len() as f32)
+                .collect();
+            let score = overlaps.iter().copied().fold(0.0, f32::max);
+            let sources = overlaps
+                .iter()
+                .enumerate()
+                .filter(|(_, overlap)| **overlap >= SUPPORT_THRESHOLD)
+                .map(|(i, _)| i + 1)
+                .collect();
+
+            ClaimSupport {
+                text,
+                supported: score >= SUPPORT_THRESHOLD,
+                score,
+                sources,
+            }
+        })
+        .collect();
+
+    Groundedness::from_claims(claims)
+}
+
+/// Messages asking an LLM to judge each claim against the context
+pub fn judge_messages(claims: &[ClaimSupport], context: &str) -> Vec<ChatMessage> {
+    let numbered: Vec<String> = claims
+        .iter()
+        .enumerate()
+        .map(|(i, claim)| format!("{}. {}", i + 1, claim.text))
+        .collect();
+
+    vec![
+        ChatMessage::system(
+            "You verify whether claims are supported by the given context. A claim is supported only if the context states or directly implies it. Reply with JSON only.",
+        ),
+        ChatMessage::user(format!(
+            "Context:\n{}\n\nClaims:\n{}\n\nRespond with a JSON array containing one object per claim: [{{\"claim\": <number>, \"supported\": true|false}}]",
+            context,
+            numbered.join("\n")
+        )),
+    ]
+}
+
+/// Follow-up asking the model to drop the claims that failed verification
+pub fn strict_instruction(groundedness: &Groundedness) -> String {
+    let unsupported: Vec<String> = groundedness
+        .unsupported_claims()
+        .map(|claim| format!("- {}", claim.text))
+        .collect();
+
+    format!(
+        "These statements in your answer are not supported by the context:\n{}\n\nAnswer again using only facts stated in the numbered context, citing each one. If the context does not contain the answer, reply exactly: {}",
+        unsupported.join("\n"),
+        INSUFFICIENT_INFORMATION
+    )
+}
+
+/// Apply an LLM judgement to the lexical result, keeping its sources
+pub fn apply_judgement(lexical: Groundedness, response: &str) -> Result<Groundedness> {
+    let start = response.find('[').ok_or_else(|| anyhow!("No JSON array in judge response"))?;
+    let end = response.rfind(']').ok_or_else(|| anyhow!("No JSON array in judge response"))?;
+    let verdicts: Vec<serde_json::Value> = serde_json::from_str(&response[start..=end])?;
+
+    let mut claims = lexical.claims;
+    for verdict in verdicts {
+        let (Some(n), Some(supported)) = (verdict["claim"].as_u64(), verdict["supported"].as_bool()) else {
+            continue;
+        };
+        if let Some(claim) = (n as usize).checked_sub(1).and_then(|i| claims.get_mut(i)) {
+            claim.supported = supported;
+            claim.score = if supported { 1.0 } else { 0.0 };
+        }
+    }
+
+    Ok(Groundedness::from_claims(claims))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexical_groundedness_flags_unsupported_claims() {
+        let blocks = [
+            "Rust was first released in 2015 by Mozilla Research.",
+            "The borrow checker enforces memory safety without garbage collection.",
+        ];
+        let answer = "Rust was released in 2015 [1]. Its borrow checker enforces memory safety [2]. It was designed by aliens on Mars.";
+
+        let result = lexical_groundedness(answer, &blocks);
+        assert_eq!(result.claims.len(), 3);
+        assert!(result.claims[0].supported);
+        assert_eq!(result.claims[0].sources, vec![1]);
+        assert!(result.claims[1].supported);
+        assert_eq!(result.claims[1].sources, vec![2]);
+        assert!(!result.claims[2].supported);
+        assert_eq!(result.unsupported_claims().count(), 1);
+        assert!(result.confidence > 0.5 && result.confidence < 1.0);
+    }
+
+    #[test]
+    fn test_apply_judgement_overrides_scores() {
+        let lexical = lexical_groundedness("Rust is fast. Rust is old.", &["Rust is fast."]);
+        let judged = apply_judgement(
+            lexical,
+            "Here you go: [{\"claim\": 1, \"supported\": true}, {\"claim\": 2, \"supported\": false}]",
+        )
+        .unwrap();
+
+        assert!(judged.claims[0].supported);
+        assert!(!judged.claims[1].supported);
+        assert_eq!(judged.confidence, 0.5);
+        assert!(apply_judgement(judged, "not json").is_err());
+    }
+
+    #[test]
+    fn test_strip_citations_keeps_other_brackets() {
+        assert_eq!(strip_citations("Fast [1, 2] and safe [see docs]."), "Fast  and safe [see docs].");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::models::{Highlights, TextSpan};
+use crate::services::groundedness::content_terms;
+use std::collections::HashSet;
+
+/// Spans of `text` that match `query`: each occurrence of one of its content
+/// terms, and the sentence holding the most distinct ones. Offsets count
+/// characters so they index the text the same way on any client.
+pub fn highlight(text: &str, query: &str) -> Highlights {
+    let terms = content_terms(query);
+    if terms.is_empty() {
+        return Highlights::default();
+    }
+
+    let length = text.chars().count();
+    let mut highlights = Highlights::default();
+    let mut best_sentence: Option<(usize, TextSpan)> = None;
+    let mut sentence_start = 0;
+    let mut sentence_terms: HashSet<String> = HashSet::new();
+    let mut word = String::new();
+    let mut word_start = 0;
+
+    // A trailing sentinel flushes the last word and sentence
+    for (position, c) in text.chars().chain(['\n']).enumerate() {
+        if c.is_alphanumeric() {
+            if word.is_empty() {
+                word_start = position;
+            }
+            word.extend(c.to_lowercase());
+            continue;
+        }
+
+        if !word.is_empty() {
+            if terms.contains(&word) {
+                highlights.terms.push(TextSpan {
+                    start: word_start,
+                    end: position,
+                });
+                sentence_terms.insert(std::mem::take(&mut word));
+            }
+            word.clear();
+        }
+
+        if matches!(c, '.' | '!' | '?' | '\n') {
+            let matched = sentence_terms.len();
+            if matched > 0 && best_sentence.is_none_or(|(best, _)| matched > best) {
+                let span = trimmed_span(text, sentence_start, (position + 1).min(length));
+                best_sentence = Some((matched, span));
+            }
+            sentence_terms.clear();
+            sentence_start = position + 1;
+        }
+    }
+
+    highlights.passage = best_sentence.map(|(_, span)| span);
+    highlights
+}
+
+/// `start..end` of `text` without its leading and trailing whitespace
+fn trimmed_span(text: &str, start: usize, end: usize) -> TextSpan {
+    let sentence: Vec<char> = text.chars().skip(start).take(end - start).collect();
+    let leading = sentence.iter().take_while(|c| c.is_whitespace()).count();
+    let trailing = sentence.iter().rev().take_while(|c| c.is_whitespace()).count();
+    TextSpan {
+        start: start + leading,
+        end: (end - trailing).max(start + leading),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spanned(text: &str, span: TextSpan) -> String {
+        text.chars().skip(span.start).take(span.end - span.start).collect()
+    }
+
+    #[test]
+    fn test_highlight_terms_and_passage() {
+        let text = "Café hours vary. Employees get 25 days of paid leave. Leave requests need approval!";
+        let highlights = highlight(text, "How many days of leave do employees get?");
+
+        let terms: Vec<String> = highlights.terms.iter().map(|s| spanned(text, *s)).collect();
+        assert_eq!(terms, vec!["Employees", "get", "days", "leave", "Leave"]);
+        assert_eq!(
+            spanned(text, highlights.passage.unwrap()),
+            "Employees get 25 days of paid leave."
+        );
+
+        assert_eq!(highlight(text, "the and"), Highlights::default());
+        assert_eq!(highlight(text, "parking").passage, None);
+    }
+}
@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+/// How the outbound HTTP client pools and connects. One client built from
+/// these is shared by every LLM provider and webhook delivery, so
+/// connections to the same host are reused instead of each caller keeping
+/// its own pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientSettings {
+    /// Idle connections kept open per host
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle pooled connection is kept; `None` keeps it forever
+    pub pool_idle_timeout: Option<Duration>,
+    /// TCP keep-alive probe interval; `None` turns probes off
+    pub tcp_keepalive: Option<Duration>,
+    /// Negotiate HTTP/2 with servers that offer it; otherwise HTTP/1.1 only
+    pub http2: bool,
+    /// Proxy for every request, overriding `HTTP_PROXY`/`HTTPS_PROXY`.
+    /// Hosts in `NO_PROXY` still bypass it.
+    pub proxy: Option<String>,
+    pub connect_timeout: Duration,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        HttpClientSettings {
+            pool_max_idle_per_host: 32,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            tcp_keepalive: Some(Duration::from_secs(60)),
+            http2: true,
+            proxy: None,
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl HttpClientSettings {
+    pub fn build(&self) -> Result<reqwest::Client> {
+        let mut builder = reqwest::Client::builder()
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .connect_timeout(self.connect_timeout);
+        if !self.http2 {
+            builder = builder.http1_only();
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .with_context(|| format!("Invalid proxy URL {}", proxy))?
+                .no_proxy(reqwest::NoProxy::from_env());
+            builder = builder.proxy(proxy);
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_checks_the_proxy() {
+        assert!(HttpClientSettings::default().build().is_ok());
+        let settings = HttpClientSettings {
+            http2: false,
+            tcp_keepalive: None,
+            proxy: Some("http://proxy.internal:3128".to_string()),
+            ..Default::default()
+        };
+        assert!(settings.build().is_ok());
+
+        let error = HttpClientSettings {
+            proxy: Some("not a url".to_string()),
+            ..Default::default()
+        }
+        .build()
+        .unwrap_err();
+        assert!(error.to_string().contains("Invalid proxy URL"));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::models::ChatMessage;
+use serde::Serialize;
+
+/// Queries this long or longer are always treated as knowledge-base questions
+const MAX_SMALL_TALK_WORDS: usize = 8;
+
+const GREETINGS: &[&str] = &[
+    "hi", "hello", "hey", "hi there", "hello there", "hey there", "hiya", "yo", "howdy", "greetings",
+    "good morning", "good afternoon", "good evening",
+];
+
+const SMALL_TALK: &[&str] = &[
+    "thanks", "thank you", "thanks a lot", "thanks so much", "thank you so much", "thank you very much", "thx", "ty", "cheers", "bye", "goodbye", "see you", "ok", "okay", "cool",
+    "great", "nice", "awesome", "how are you", "who are you", "what are you", "are you there",
+    "lol", "good job", "well done",
+];
+
+const COMMANDS: &[&str] = &[
+    "help", "what can you do", "how do i use this", "clear", "reset", "start over", "new chat",
+];
+
+/// What an incoming query is asking for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryIntent {
+    /// Hello, good morning
+    Greeting,
+    /// Thanks, bye, how are you
+    SmallTalk,
+    /// Help or chat controls rather than content
+    Command,
+    /// Needs retrieval from the indexed documents
+    KnowledgeBase,
+}
+
+impl QueryIntent {
+    pub fn needs_retrieval(self) -> bool {
+        self == QueryIntent::KnowledgeBase
+    }
+}
+
+fn normalize(query: &str) -> String {
+    query
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '\'' { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether `text` is made up only of `phrases` (any number, any order)
+fn only_phrases(text: &str, phrases: &[&str]) -> bool {
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let Some(phrase) = phrases
+            .iter()
+            .filter(|p| rest == **p || rest.starts_with(&format!("{} ", p)))
+            .max_by_key(|p| p.len())
+        else {
+            return false;
+        };
+        rest = rest[phrase.len()..].trim_start();
+    }
+    true
+}
+
+/// Classify a query with cheap keyword heuristics. Only messages made up
+/// entirely of known phrases count as greetings, small talk, or commands;
+/// anything else ("hi, what is our refund policy") is a knowledge-base question.
+pub fn classify_intent(query: &str) -> QueryIntent {
+    let trimmed = query.trim();
+    if trimmed.starts_with('/') {
+        return QueryIntent::Command;
+    }
+
+    let text = normalize(trimmed);
+    if text.is_empty() {
+        return QueryIntent::SmallTalk;
+    }
+    if text.split(' ').count() >= MAX_SMALL_TALK_WORDS {
+        return QueryIntent::KnowledgeBase;
+    }
+
+    if only_phrases(&text, COMMANDS) {
+        return QueryIntent::Command;
+    }
+    if only_phrases(&text, GREETINGS) {
+        return QueryIntent::Greeting;
+    }
+
+    let mut small_talk: Vec<&str> = GREETINGS.to_vec();
+    small_talk.extend_from_slice(SMALL_TALK);
+    if only_phrases(&text, &small_talk) {
+        return QueryIntent::SmallTalk;
+    }
+
+    QueryIntent::KnowledgeBase
+}
+
+/// Reply to a command without calling the model
+pub fn command_reply(query: &str) -> String {
+    let text = normalize(query.trim_start_matches('/'));
+    if only_phrases(&text, &["clear", "reset", "start over", "new chat"]) {
+        return "To start over, clear the conversation in your chat window. I don't keep history between requests on my side.".to_string();
+    }
+
+    "I answer questions about the documents in your knowledge base. Upload PDF, Word, Excel, PowerPoint, CSV, Markdown, or text files, then ask about their contents. I cite the passages each answer comes from, and can summarize whole documents.".to_string()
+}
+
+/// Messages for a short conversational reply that needs no documents
+pub fn small_talk_messages(history: &[ChatMessage], query: &str) -> Vec<ChatMessage> {
+    let mut messages = vec![ChatMessage::system(
+        "You are the assistant of a document question-answering app. Reply to greetings and small talk briefly and warmly in one or two sentences, and invite the user to ask about their documents. Do not make up facts.",
+    )];
+    messages.extend(history.iter().cloned());
+    messages.push(ChatMessage::user(query));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_intent() {
+        assert_eq!(classify_intent("Hi!"), QueryIntent::Greeting);
+        assert_eq!(classify_intent("hello, good morning"), QueryIntent::Greeting);
+        assert_eq!(classify_intent("thanks a lot"), QueryIntent::SmallTalk);
+        assert_eq!(classify_intent("Thank you, bye"), QueryIntent::SmallTalk);
+        assert_eq!(classify_intent("how are you?"), QueryIntent::SmallTalk);
+        assert_eq!(classify_intent("/help"), QueryIntent::Command);
+        assert_eq!(classify_intent("What can you do?"), QueryIntent::Command);
+        assert_eq!(classify_intent("hi, what is the refund policy?"), QueryIntent::KnowledgeBase);
+        assert_eq!(classify_intent("Who approved the 2024 budget?"), QueryIntent::KnowledgeBase);
+        assert_eq!(classify_intent("help me understand clause 4"), QueryIntent::KnowledgeBase);
+    }
+}
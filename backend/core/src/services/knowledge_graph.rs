@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Most words between two entities for the text linking them to count as
+/// a relation
+const MAX_PREDICATE_WORDS: usize = 4;
+
+/// Capitalized words that start sentences or questions rather than name
+/// something
+const STOP_WORDS: &[&str] = &[
+    "a", "after", "all", "also", "an", "and", "as", "at", "before", "but", "by", "each", "for", "from", "he", "her",
+    "here", "his", "how", "i", "if", "in", "it", "its", "no", "not", "of", "on", "or", "our", "she", "so", "some",
+    "that", "the", "their", "then", "there", "these", "they", "this", "those", "to", "we", "what", "when", "where",
+    "which", "who", "why", "with", "yes", "you", "your",
+];
+
+/// Words between two entities that join them in a list rather than relate
+/// them
+const CONJUNCTIONS: &[&str] = &["and", "or", "nor", "&"];
+
+/// A chunk of an indexed document
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub file_path: String,
+    pub chunk_id: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entity {
+    /// Name as first written in the documents
+    pub name: String,
+    pub mentions: Vec<ChunkRef>,
+}
+
+/// "`subject` `predicate` `object`" as stated in one chunk, with entities
+/// given by key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relation {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    pub source: ChunkRef,
+}
+
+/// An entity named in a sentence and the span of words it covers
+#[derive(Debug, Clone, PartialEq)]
+struct Mention {
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+/// Lowercased name an entity is looked up by
+fn entity_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+fn is_stop_word(word: &str) -> bool {
+    STOP_WORDS.contains(&word.to_lowercase().as_str())
+}
+
+/// Proper names in `sentence`: runs of capitalized words or acronyms, which
+/// may be joined by "of" ("Bank of England"), without a leading stop word
+fn sentence_mentions(words: &[&str]) -> Vec<Mention> {
+    let bare: Vec<&str> = words
+        .iter()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .collect();
+    let capitalized = |i: usize| bare[i].chars().next().is_some_and(char::is_uppercase);
+    // A comma or other trailing punctuation ends a name
+    let ends_name = |i: usize| words[i].ends_with(|c: char| !c.is_alphanumeric());
+
+    let mut mentions = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if !capitalized(i) {
+            i += 1;
+            continue;
+        }
+        let mut start = i;
+        let mut end = i + 1;
+        while end < words.len() && !ends_name(end - 1) {
+            if capitalized(end) {
+                end += 1;
+            } else if bare[end] == "of" && end + 1 < words.len() && capitalized(end + 1) && !ends_name(end) {
+                end += 2;
+            } else {
+                break;
+            }
+        }
+        while start < end && is_stop_word(bare[start]) {
+            start += 1;
+        }
+        if start < end {
+            let name = bare[start..end].join(" ");
+            if name.chars().count() > 1 {
+                mentions.push(Mention { name, start, end });
+            }
+        }
+        i = end;
+    }
+    mentions
+}
+
+fn sentences(text: &str) -> impl Iterator<Item = Vec<&str>> {
+    text.split_inclusive(['.', '!', '?', '\n'])
+        .map(|sentence| sentence.split_whitespace().collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+}
+
+/// Names of the entities mentioned in `text`, each once
+pub fn extract_entities(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    sentences(text)
+        .flat_map(|words| sentence_mentions(&words))
+        .map(|m| m.name)
+        .filter(|name| seen.insert(entity_key(name)))
+        .collect()
+}
+
+/// (subject, predicate, object) for entities next to each other in a
+/// sentence with a few words between them, e.g. "Maria Chen approved
+/// Project Atlas" gives ("Maria Chen", "approved", "Project Atlas")
+pub fn extract_relations(text: &str) -> Vec<(String, String, String)> {
+    let mut relations = Vec::new();
+    for words in sentences(text) {
+        let mentions = sentence_mentions(&words);
+        for pair in mentions.windows(2) {
+            let between: Vec<String> = words[pair[0].end..pair[1].start]
+                .iter()
+                .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+                .filter(|w| !w.is_empty())
+                .collect();
+            if between.is_empty()
+                || between.len() > MAX_PREDICATE_WORDS
+                || between.iter().all(|w| CONJUNCTIONS.contains(&w.as_str()))
+            {
+                continue;
+            }
+            relations.push((pair[0].name.clone(), between.join(" "), pair[1].name.clone()));
+        }
+    }
+    relations
+}
+
+/// Entities and relations extracted from indexed chunks, for expanding a
+/// search from the entities a query names to the chunks around them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeGraph {
+    /// Entity key → entity
+    entities: HashMap<String, Entity>,
+    relations: Vec<Relation>,
+}
+
+impl KnowledgeGraph {
+    pub fn num_entities(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn num_relations(&self) -> usize {
+        self.relations.len()
+    }
+
+    /// Record the entities and relations in one chunk's text
+    pub fn add_chunk(&mut self, file_path: &str, chunk_id: usize, text: &str) {
+        let source = ChunkRef {
+            file_path: file_path.to_string(),
+            chunk_id,
+        };
+        for name in extract_entities(text) {
+            let entity = self.entities.entry(entity_key(&name)).or_insert_with(|| Entity {
+                name,
+                mentions: Vec::new(),
+            });
+            entity.mentions.push(source.clone());
+        }
+        for (subject, predicate, object) in extract_relations(text) {
+            self.relations.push(Relation {
+                subject: entity_key(&subject),
+                predicate,
+                object: entity_key(&object),
+                source: source.clone(),
+            });
+        }
+    }
+
+    /// Forget what was extracted from a document's chunks
+    pub fn remove_document(&mut self, file_path: &str) {
+        self.entities.retain(|_, entity| {
+            entity.mentions.retain(|m| m.file_path != file_path);
+            !entity.mentions.is_empty()
+        });
+        self.relations.retain(|r| r.source.file_path != file_path);
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+        self.relations.clear();
+    }
+
+    /// Keys of the known entities named in `query`, matched as whole words
+    /// regardless of case
+    pub fn match_entities(&self, query: &str) -> Vec<&str> {
+        let words: Vec<String> = query
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        let query = format!(" {} ", words.join(" "));
+        let mut keys: Vec<&str> = self
+            .entities
+            .keys()
+            .filter(|key| !is_stop_word(key))
+            .filter(|key| {
+                let key_words: Vec<&str> = key.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+                !key_words.is_empty() && query.contains(&format!(" {} ", key_words.join(" ")))
+            })
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Chunks that mention `keys` (0 hops), then chunks that mention an
+    /// entity related to one of them (1 hop), each once at its fewest hops
+    pub fn neighborhood(&self, keys: &[&str]) -> Vec<(&ChunkRef, usize)> {
+        let mut hops: HashMap<&ChunkRef, usize> = HashMap::new();
+        for key in keys {
+            for mention in self.entities.get(*key).into_iter().flat_map(|e| &e.mentions) {
+                hops.insert(mention, 0);
+            }
+        }
+        for relation in &self.relations {
+            let neighbor = if keys.contains(&relation.subject.as_str()) {
+                &relation.object
+            } else if keys.contains(&relation.object.as_str()) {
+                &relation.subject
+            } else {
+                continue;
+            };
+            hops.entry(&relation.source).or_insert(0);
+            for mention in self.entities.get(neighbor).into_iter().flat_map(|e| &e.mentions) {
+                hops.entry(mention).or_insert(1);
+            }
+        }
+
+        let mut chunks: Vec<(&ChunkRef, usize)> = hops.into_iter().collect();
+        chunks.sort_by(|a, b| (a.1, &a.0.file_path, a.0.chunk_id).cmp(&(b.1, &b.0.file_path, b.0.chunk_id)));
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_entities_and_relations() {
+        let text = "The budget for Project Atlas was approved by Maria Chen in March. \
+                    Maria Chen reports to the Bank of England, and Tom and Ana joined.";
+        assert_eq!(
+            extract_entities(text),
+            vec!["Project Atlas", "Maria Chen", "March", "Bank of England", "Tom", "Ana"]
+        );
+        let relations = extract_relations(text);
+        assert!(relations.contains(&(
+            "Project Atlas".to_string(),
+            "was approved by".to_string(),
+            "Maria Chen".to_string()
+        )));
+        assert!(relations.contains(&(
+            "Maria Chen".to_string(),
+            "reports to the".to_string(),
+            "Bank of England".to_string()
+        )));
+        assert!(!relations.iter().any(|(s, _, o)| s == "Tom" && o == "Ana"));
+    }
+
+    #[test]
+    fn test_neighborhood_follows_relations() {
+        let mut graph = KnowledgeGraph::default();
+        graph.add_chunk("a.txt", 0, "Project Atlas was approved by Maria Chen.");
+        graph.add_chunk("a.txt", 1, "Maria Chen leads the Finance team.");
+        graph.add_chunk("b.txt", 0, "The cafeteria opens at noon.");
+
+        let keys = graph.match_entities("who approved project atlas?");
+        assert_eq!(keys, vec!["project atlas"]);
+        let chunks: Vec<(&str, usize, usize)> = graph
+            .neighborhood(&keys)
+            .into_iter()
+            .map(|(c, hops)| (c.file_path.as_str(), c.chunk_id, hops))
+            .collect();
+        assert_eq!(chunks, vec![("a.txt", 0, 0), ("a.txt", 1, 1)]);
+
+        graph.remove_document("a.txt");
+        assert!(graph.match_entities("project atlas").is_empty());
+        assert_eq!(graph.num_relations(), 0);
+    }
+}
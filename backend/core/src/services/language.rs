@@ -0,0 +1,132 @@
+/// Value of `AnswerRequest.language` that asks for the query's language
+pub const AUTO: &str = "auto";
+
+/// Common function words of Latin-script languages, used to tell them apart
+const LATIN_STOPWORDS: &[(&str, &[&str])] = &[
+    (
+        "English",
+        &["the", "is", "are", "what", "how", "why", "which", "does", "and", "of", "to", "in", "with"],
+    ),
+    (
+        "Spanish",
+        &["el", "la", "los", "las", "es", "qué", "que", "cómo", "por", "para", "una", "del", "con"],
+    ),
+    (
+        "French",
+        &["le", "la", "les", "est", "quel", "quelle", "comment", "pourquoi", "des", "une", "du", "avec"],
+    ),
+    (
+        "German",
+        &["der", "die", "das", "ist", "sind", "was", "wie", "warum", "und", "ein", "eine", "mit", "nicht"],
+    ),
+    (
+        "Portuguese",
+        &["o", "os", "as", "é", "são", "qual", "como", "por", "que", "uma", "do", "da", "não"],
+    ),
+    (
+        "Italian",
+        &["il", "lo", "gli", "è", "sono", "che", "come", "perché", "della", "una", "non", "con"],
+    ),
+    (
+        "Dutch",
+        &["de", "het", "een", "is", "zijn", "wat", "hoe", "waarom", "en", "van", "niet", "met"],
+    ),
+];
+
+/// Best guess at the language `text` is written in, by script and, for
+/// Latin script, by function words. `None` when there's too little to go on.
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+    let mut latin = 0;
+
+    for c in text.chars() {
+        let script = match c as u32 {
+            0x3040..=0x30FF => "Japanese",
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => "Korean",
+            0x4E00..=0x9FFF => "Chinese",
+            0x0400..=0x04FF => "Russian",
+            0x0370..=0x03FF => "Greek",
+            0x0590..=0x05FF => "Hebrew",
+            0x0600..=0x06FF => "Arabic",
+            0x0900..=0x097F => "Hindi",
+            0x0E00..=0x0E7F => "Thai",
+            _ => {
+                if c.is_alphabetic() {
+                    latin += 1;
+                }
+                continue;
+            }
+        };
+        match counts.iter_mut().find(|(name, _)| *name == script) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script, 1)),
+        }
+    }
+
+    // Kana marks Japanese even though most of the text may be kanji
+    if counts.iter().any(|(name, _)| *name == "Japanese") {
+        return Some("Japanese");
+    }
+    if let Some((script, count)) = counts.iter().max_by_key(|(_, count)| *count) {
+        if *count >= latin {
+            return Some(script);
+        }
+    }
+
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let (language, hits) = LATIN_STOPWORDS
+        .iter()
+        .map(|(language, stopwords)| {
+            let hits = words.iter().filter(|w| stopwords.contains(&w.as_str())).count();
+            (*language, hits)
+        })
+        .max_by_key(|(_, hits)| *hits)?;
+
+    (hits > 0).then_some(language)
+}
+
+/// The language to answer in: `requested`, or the query's language when
+/// `requested` is `auto`
+pub fn resolve_language(requested: Option<&str>, query: &str) -> Option<String> {
+    match requested.map(str::trim) {
+        None | Some("") => None,
+        Some(language) if language.eq_ignore_ascii_case(AUTO) => detect_language(query).map(str::to_string),
+        Some(language) => Some(language.to_string()),
+    }
+}
+
+/// System prompt addition that fixes the answer language
+pub fn language_instruction(language: &str) -> String {
+    format!(
+        "Always answer in {}, even when the context or earlier messages are in another language. Quote source text in its original language only when the exact wording matters.",
+        language
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(detect_language("What is the refund policy?"), Some("English"));
+        assert_eq!(detect_language("¿Cuál es la política de reembolso para los clientes?"), Some("Spanish"));
+        assert_eq!(detect_language("Wie ist die Rückgaberegel für Kunden?"), Some("German"));
+        assert_eq!(detect_language("返金ポリシーは何ですか？"), Some("Japanese"));
+        assert_eq!(detect_language("退款政策是什么？"), Some("Chinese"));
+        assert_eq!(detect_language("Какова политика возврата?"), Some("Russian"));
+        assert_eq!(detect_language("42"), None);
+    }
+
+    #[test]
+    fn test_resolve_language() {
+        assert_eq!(resolve_language(None, "What is it?"), None);
+        assert_eq!(resolve_language(Some("French"), "What is it?").as_deref(), Some("French"));
+        assert_eq!(resolve_language(Some("auto"), "Was ist das?").as_deref(), Some("German"));
+    }
+}
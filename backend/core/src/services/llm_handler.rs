@@ -0,0 +1,2558 @@
+use crate::models::{
+    ChatMessage, DocumentChunk, RetrievalMode, SamplingParams, SearchResult, StrictMode, SummaryStyle,
+    SynthesisStrategy,
+};
+use crate::services::cache_manager::{Cache, LruCache};
+use crate::services::citations::{build_citations, CITATION_INSTRUCTION};
+use crate::services::evaluation::{parse_rag_judgement, parse_testset_pair, rag_judge_messages, testset_messages, RagScores};
+use crate::services::context_packing::pack_context;
+use crate::services::dedup::{dedup_chunks, DEFAULT_DEDUP_THRESHOLD};
+use crate::services::few_shot::{example_messages, FewShotExample};
+use crate::services::follow_ups::{follow_up_messages, parse_follow_ups};
+use crate::services::groundedness::{
+    apply_judgement, judge_messages, lexical_groundedness, strict_instruction, Groundedness, VerificationMode,
+    INSUFFICIENT_INFORMATION,
+};
+use crate::services::intent::{classify_intent, command_reply, small_talk_messages, QueryIntent};
+use crate::services::language::{language_instruction, resolve_language};
+use crate::services::llm_providers::{
+    Generation, GenerationRequest, LLMProvider, ProviderError, TokenStream, TokenUsage, SYSTEM_PROMPT,
+};
+use crate::services::moderation::{apply_verdict, guard_messages, Moderation, Moderator, BLOCKED_ANSWER, BLOCKED_QUERY};
+use crate::services::pipeline::compress_chunks;
+use crate::services::prompt_templates::{PromptTemplate, DEFAULT_TEMPLATE};
+use crate::services::query_transform::{
+    decomposition_messages, expansion_messages, hyde_messages, hyde_search_text, parse_expansions, parse_rewrite,
+    parse_sub_questions, rewrite_messages,
+};
+use crate::services::rate_limiter::RateLimiter;
+use crate::services::rerank::{apply_scores, parse_scores, rerank_messages, RerankMode, RERANK_BATCH_SIZE, RERANK_CANDIDATES};
+use crate::services::synthesis::{
+    combine_summary_messages, comparison_context, comparison_messages, group_sections, is_relevant, map_messages, refine_messages,
+    section_summary_messages, step_synthesis_messages, sub_question_messages, MAX_SYNTHESIS_CHUNKS, STUFF_CHUNKS,
+};
+use crate::services::tools::ToolRegistry;
+use crate::services::usage::{ModelPrice, UsageTracker};
+use crate::services::vector_store::IndexChange;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use validator::Validate;
+
+/// Model turns allowed to request tool calls before a final answer is forced
+const MAX_TOOL_ROUNDS: usize = 4;
+
+/// Reply when no retrieved chunk scores above the answer score floor
+pub const NO_ANSWER: &str =
+    "I don't know. The knowledge base has nothing relevant enough to this question to answer it.";
+
+/// Best-scoring chunks reported with a no-answer response
+const NO_ANSWER_TOP_SCORES: usize = 5;
+
+/// Generated answers kept for repeated questions
+pub const DEFAULT_RESPONSE_CACHE_SIZE: usize = 1000;
+
+/// How long a request the provider refused is answered with the same error
+/// instead of being sent again
+pub const DEFAULT_FAILURE_TTL: Duration = Duration::from_secs(60);
+
+/// Refused requests remembered at once
+const MAX_CACHED_FAILURES: usize = 500;
+
+/// A request the provider refused moments ago, answered with the same
+/// error without being sent again
+#[derive(Debug, thiserror::Error)]
+#[error("{error} (the same request was refused recently; not sent again for {retry_after_secs}s)")]
+pub struct RecentFailure {
+    #[source]
+    pub error: ProviderError,
+    pub retry_after_secs: u64,
+}
+
+/// A generated answer and the documents it was drawn from
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CachedAnswer {
+    pub answer: String,
+    /// File paths of the retrieved chunks; `None` when tools could have
+    /// read any document
+    pub documents: Option<Vec<String>>,
+}
+
+impl CachedAnswer {
+    /// Whether `change` could make the answer stale
+    fn affected_by(&self, change: &IndexChange) -> bool {
+        match (change, &self.documents) {
+            (IndexChange::Documents(changed), Some(documents)) => documents.iter().any(|d| changed.contains(d)),
+            _ => true,
+        }
+    }
+}
+
+/// Per-call generation settings; `None` fields fall back to the handler's
+/// configured defaults
+#[derive(Debug, Clone)]
+pub struct AnswerOptions {
+    pub max_tokens: usize,
+    pub temperature: f32,
+    pub sampling: SamplingParams,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub system_prompt: Option<String>,
+    /// Builds the user message instead of the built-in template
+    pub template: Option<PromptTemplate>,
+    /// Example questions and answers shown to the model before the history
+    pub examples: Vec<FewShotExample>,
+    pub history: Vec<ChatMessage>,
+    /// Let tool-capable providers call the registered tools
+    pub use_tools: bool,
+    pub strict_mode: Option<StrictMode>,
+    /// Language to answer in, or `auto` for the query's language
+    pub language: Option<String>,
+    pub strategy: SynthesisStrategy,
+    /// How the store is searched for the query; `None` uses the configured mode
+    pub retrieval: Option<RetrievalMode>,
+    /// Whether follow-ups are rewritten before searching; `None` uses the
+    /// configured setting
+    pub rewrite: Option<bool>,
+    pub rerank: Option<RerankMode>,
+    pub rerank_model: Option<String>,
+    /// Cut each retrieved chunk down to this many query-matching sentences
+    pub compress: Option<usize>,
+    /// Tokens of retrieved text to pack into the context; `None` uses the
+    /// configured budget
+    pub context_budget: Option<usize>,
+    pub verification: Option<VerificationMode>,
+    /// Suggest follow-up questions grounded in the answer's context
+    pub follow_ups: bool,
+    /// File names of the only documents the answer may use, stated in the
+    /// system prompt
+    pub scope: Vec<String>,
+    /// Day the answer should hold on (YYYY-MM-DD), for preferring the
+    /// latest source dated by then
+    pub as_of: Option<String>,
+}
+
+impl Default for AnswerOptions {
+    fn default() -> Self {
+        AnswerOptions {
+            max_tokens: 8192,
+            temperature: 1.0,
+            sampling: SamplingParams::default(),
+            provider: None,
+            model: None,
+            system_prompt: None,
+            template: None,
+            examples: Vec::new(),
+            history: Vec::new(),
+            use_tools: false,
+            strict_mode: None,
+            language: None,
+            strategy: SynthesisStrategy::default(),
+            retrieval: None,
+            rewrite: None,
+            rerank: None,
+            rerank_model: None,
+            compress: None,
+            context_budget: None,
+            verification: None,
+            follow_ups: false,
+            scope: Vec::new(),
+            as_of: None,
+        }
+    }
+}
+
+impl From<&crate::models::AnswerRequest> for AnswerOptions {
+    fn from(req: &crate::models::AnswerRequest) -> Self {
+        let defaults = AnswerOptions::default();
+        AnswerOptions {
+            max_tokens: req.max_tokens.unwrap_or(defaults.max_tokens),
+            temperature: req.temperature.unwrap_or(defaults.temperature),
+            sampling: req.sampling.clone(),
+            provider: req.provider.clone(),
+            model: req.model.clone(),
+            system_prompt: req.system_prompt.clone(),
+            template: None,
+            examples: Vec::new(),
+            history: req.history.clone(),
+            use_tools: req.use_tools,
+            strict_mode: req.strict_mode,
+            language: req.language.clone(),
+            strategy: req.strategy,
+            retrieval: None,
+            rewrite: None,
+            rerank: None,
+            rerank_model: None,
+            compress: None,
+            context_budget: req.context_budget,
+            verification: None,
+            follow_ups: req.follow_ups,
+            scope: Vec::new(),
+            as_of: None,
+        }
+    }
+}
+
+impl From<&crate::models::QueryRequest> for AnswerOptions {
+    fn from(req: &crate::models::QueryRequest) -> Self {
+        let defaults = AnswerOptions::default();
+        AnswerOptions {
+            max_tokens: req.max_tokens.unwrap_or(defaults.max_tokens),
+            temperature: req.temperature.unwrap_or(defaults.temperature),
+            sampling: req.sampling.clone(),
+            provider: req.provider.clone(),
+            model: req.model.clone(),
+            system_prompt: req.system_prompt.clone(),
+            template: None,
+            examples: Vec::new(),
+            history: req.history.clone(),
+            use_tools: req.use_tools,
+            strict_mode: req.strict_mode,
+            language: req.language.clone(),
+            strategy: req.strategy,
+            retrieval: req.retrieval,
+            rewrite: None,
+            rerank: None,
+            rerank_model: None,
+            compress: None,
+            context_budget: req.context_budget,
+            verification: None,
+            follow_ups: req.follow_ups,
+            scope: Vec::new(),
+            as_of: req.filters.as_of.clone(),
+        }
+    }
+}
+
+impl AnswerOptions {
+    /// Reject generation settings outside what providers accept
+    pub fn validate(&self) -> Result<()> {
+        if self.max_tokens == 0 {
+            return Err(anyhow!("max_tokens must be at least 1"));
+        }
+        if !(0.0..=2.0).contains(&self.temperature) {
+            return Err(anyhow!("temperature must be between 0 and 2"));
+        }
+        Ok(self.sampling.validate()?)
+    }
+}
+
+/// A streamed answer's request and context, kept until its text is complete
+pub struct PendingAnswer {
+    llm: Arc<dyn LLMProvider>,
+    request: GenerationRequest,
+    context: String,
+    context_blocks: Vec<String>,
+    sources: Vec<serde_json::Value>,
+    language: Option<String>,
+    verification: VerificationMode,
+    /// Suggest follow-ups to `query` once the answer is complete
+    follow_ups: bool,
+    query: String,
+    packing: Option<serde_json::Value>,
+}
+
+impl PendingAnswer {
+    pub fn sources(&self) -> &[serde_json::Value] {
+        &self.sources
+    }
+}
+
+/// Answers queries with one of the registered LLM providers
+pub struct LLMHandler {
+    providers: std::sync::RwLock<HashMap<String, Arc<dyn LLMProvider>>>,
+    default_provider: String,
+    system_prompt: String,
+    tools: ToolRegistry,
+    verification: VerificationMode,
+    strict_mode: StrictMode,
+    /// Answer greetings, small talk, and commands without the documents
+    intent_routing: bool,
+    /// Rewrite follow-up questions into standalone ones before searching
+    query_rewriting: bool,
+    /// Similarity the best retrieved chunk must reach for an answer to be
+    /// generated (0 = always answer)
+    answer_score_floor: f32,
+    /// Text overlap at which a chunk counts as a duplicate of a higher-ranked
+    /// one and is left out of the context (0 = keep all)
+    dedup_threshold: f32,
+    /// Tokens of retrieved text packed into the context by score and
+    /// novelty (0 = take the top chunks as ranked)
+    context_budget: usize,
+    moderator: Moderator,
+    retrieval_mode: RetrievalMode,
+    rerank_mode: RerankMode,
+    /// Scores chunks when reranking; the answering model when unset
+    rerank_model: Option<String>,
+    rate_limiter: RateLimiter,
+    usage: UsageTracker,
+    /// Generated answers by model, prompt, and sampling settings
+    response_cache: Arc<dyn Cache<str, CachedAnswer>>,
+    /// Requests the provider refused in a way resending won't fix, and when
+    failures: LruCache<(ProviderError, Instant)>,
+    failure_ttl: Duration,
+}
+
+impl LLMHandler {
+    /// A handler whose default is `provider`
+    pub fn new(provider: Arc<dyn LLMProvider>) -> Self {
+        let default_provider = provider.name().to_string();
+        let mut providers = HashMap::new();
+        providers.insert(default_provider.clone(), provider);
+
+        LLMHandler {
+            providers: std::sync::RwLock::new(providers),
+            default_provider,
+            system_prompt: SYSTEM_PROMPT.to_string(),
+            tools: ToolRegistry::default(),
+            verification: VerificationMode::default(),
+            strict_mode: StrictMode::default(),
+            intent_routing: true,
+            query_rewriting: true,
+            answer_score_floor: 0.0,
+            dedup_threshold: DEFAULT_DEDUP_THRESHOLD,
+            context_budget: 0,
+            moderator: Moderator::default(),
+            retrieval_mode: RetrievalMode::default(),
+            rerank_mode: RerankMode::default(),
+            rerank_model: None,
+            rate_limiter: RateLimiter::new(0, 0),
+            usage: UsageTracker::default(),
+            response_cache: Arc::new(LruCache::new(DEFAULT_RESPONSE_CACHE_SIZE)),
+            failures: LruCache::new(MAX_CACHED_FAILURES).with_ttl(DEFAULT_FAILURE_TTL),
+            failure_ttl: DEFAULT_FAILURE_TTL,
+        }
+    }
+
+    /// Replace the default in-memory cache of generated answers
+    pub fn with_response_cache(mut self, cache: Arc<dyn Cache<str, CachedAnswer>>) -> Self {
+        self.response_cache = cache;
+        self
+    }
+
+    /// Answer requests the provider refused with the same error for `ttl`
+    /// instead of sending them again (zero = always send)
+    pub fn with_failure_ttl(mut self, ttl: Duration) -> Self {
+        let capacity = if ttl.is_zero() { 0 } else { MAX_CACHED_FAILURES };
+        self.failures = LruCache::new(capacity).with_ttl(ttl);
+        self.failure_ttl = ttl;
+        self
+    }
+
+    /// Replace the built-in system prompt for every answer
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = system_prompt;
+        self
+    }
+
+    /// Tools the model may call when a request sets `use_tools`
+    pub fn with_tools(mut self, tools: ToolRegistry) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// How answers are checked against their context
+    pub fn with_verification(mut self, verification: VerificationMode) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Default handling of answers with unsupported claims
+    pub fn with_strict_mode(mut self, strict_mode: StrictMode) -> Self {
+        self.strict_mode = strict_mode;
+        self
+    }
+
+    /// Queue LLM calls to stay within requests and tokens per minute (0 = unlimited)
+    pub fn with_rate_limits(mut self, requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        self.rate_limiter = RateLimiter::new(requests_per_minute, tokens_per_minute);
+        self
+    }
+
+    /// Whether non-knowledge-base queries skip the document context
+    pub fn with_intent_routing(mut self, intent_routing: bool) -> Self {
+        self.intent_routing = intent_routing;
+        self
+    }
+
+    /// Whether follow-up questions are rewritten using the history before
+    /// searching the store
+    pub fn with_query_rewriting(mut self, query_rewriting: bool) -> Self {
+        self.query_rewriting = query_rewriting;
+        self
+    }
+
+    /// Skip the LLM and answer "I don't know" when no retrieved chunk is at
+    /// least this similar to the query (0 = always answer)
+    pub fn with_answer_score_floor(mut self, floor: f32) -> Self {
+        self.answer_score_floor = floor;
+        self
+    }
+
+    /// Drop retrieved chunks whose text overlaps a higher-ranked chunk's by
+    /// at least `threshold` before building the context (0 = keep all)
+    pub fn with_dedup_threshold(mut self, threshold: f32) -> Self {
+        self.dedup_threshold = threshold;
+        self
+    }
+
+    /// Fill the context with up to `budget` tokens of retrieved text, chosen
+    /// greedily by score with overlapping chunks penalized (0 = take the
+    /// top chunks as ranked)
+    pub fn with_context_budget(mut self, budget: usize) -> Self {
+        self.context_budget = budget;
+        self
+    }
+
+    /// Rules and guard model that queries and answers are checked against
+    pub fn with_moderation(mut self, moderator: Moderator) -> Self {
+        self.moderator = moderator;
+        self
+    }
+
+    /// How the store is searched unless a request says otherwise
+    pub fn with_retrieval_mode(mut self, mode: RetrievalMode) -> Self {
+        self.retrieval_mode = mode;
+        self
+    }
+
+    /// Reorder retrieved chunks by relevance before building the context,
+    /// scoring them with `model` (a cheap, fast one) when given
+    pub fn with_reranking(mut self, mode: RerankMode, model: Option<String>) -> Self {
+        self.rerank_mode = mode;
+        self.rerank_model = model;
+        self
+    }
+
+    /// Per-model prices used to estimate spend in usage reports
+    pub fn with_prices(mut self, prices: HashMap<String, ModelPrice>) -> Self {
+        self.usage = UsageTracker::new(prices);
+        self
+    }
+
+    /// Make another provider selectable per request
+    pub fn register_provider(&mut self, provider: Arc<dyn LLMProvider>) {
+        self.providers.get_mut().unwrap().insert(provider.name().to_string(), provider);
+    }
+
+    pub fn default_provider(&self) -> &str {
+        &self.default_provider
+    }
+
+    /// Swap in providers rebuilt from new config, e.g. with other default
+    /// models. Requests already running finish on the old ones. The default
+    /// provider can't be dropped, so a set without it is refused.
+    pub fn replace_providers(&self, providers: Vec<Arc<dyn LLMProvider>>) -> Result<()> {
+        let providers: HashMap<String, Arc<dyn LLMProvider>> = providers
+            .into_iter()
+            .map(|provider| (provider.name().to_string(), provider))
+            .collect();
+        if !providers.contains_key(&self.default_provider) {
+            return Err(anyhow!("The default LLM provider '{}' is missing", self.default_provider));
+        }
+        *self.providers.write().unwrap() = providers;
+        Ok(())
+    }
+
+    /// The named provider, or the default when `name` is `None`
+    pub fn provider(&self, name: Option<&str>) -> Result<Arc<dyn LLMProvider>> {
+        let name = name.unwrap_or(&self.default_provider);
+        let providers = self.providers.read().unwrap();
+        providers.get(name).cloned().ok_or_else(|| {
+            let mut available: Vec<&str> = providers.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            anyhow!(
+                "LLM provider '{}' is not configured (available: {})",
+                name,
+                available.join(", ")
+            )
+        })
+    }
+
+    /// Answer `query` from `retrieved_chunks`. With moderation on, a
+    /// blocked query is refused, a blocked answer withheld, and redactions
+    /// applied to both; the decisions are returned under `moderation`.
+    pub async fn generate_answer(
+        &self,
+        query: &str,
+        retrieved_chunks: &[SearchResult],
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let mut response = self.answer(query, retrieved_chunks, options).await?;
+        if options.follow_ups {
+            let llm = self.provider(options.provider.as_deref())?;
+            self.add_follow_ups(llm.as_ref(), options.model.as_deref(), query, &mut response)
+                .await;
+        }
+        Ok(response)
+    }
+
+    async fn answer(
+        &self,
+        query: &str,
+        retrieved_chunks: &[SearchResult],
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let llm = self.provider(options.provider.as_deref())?;
+        if self.below_score_floor(query, retrieved_chunks) {
+            let best = retrieved_chunks.iter().map(|c| c.similarity_score).fold(f32::MIN, f32::max);
+            log::info!(
+                "Best retrieval score {:.3} is below the floor of {:.3}, not answering",
+                best,
+                self.answer_score_floor
+            );
+            let language = resolve_language(options.language.as_deref(), query);
+            let model_used = options.model.as_deref().unwrap_or(llm.model());
+            let mut response = direct_response(
+                NO_ANSWER,
+                QueryIntent::KnowledgeBase,
+                language,
+                llm.name(),
+                model_used,
+            );
+            response["no_answer"] = no_answer_diagnostics(retrieved_chunks, self.answer_score_floor);
+            return Ok(response);
+        }
+        if !self.moderator.is_enabled() {
+            return self.compose_answer(query, retrieved_chunks, options).await;
+        }
+
+        let query_moderation = self.moderate(llm.as_ref(), query, None).await?;
+
+        if query_moderation.is_blocked() {
+            log::warn!("Moderation blocked query ({})", query_moderation.categories.join(", "));
+            let language = resolve_language(options.language.as_deref(), query);
+            let model_used = options.model.as_deref().unwrap_or(llm.model());
+            let mut response =
+                direct_response(BLOCKED_QUERY, classify_intent(query), language, llm.name(), model_used);
+            response["moderation"] = json!({"query": query_moderation, "answer": null});
+            return Ok(response);
+        }
+
+        let query = query_moderation.text.as_str();
+        let mut response = self.compose_answer(query, retrieved_chunks, options).await?;
+        let answer = response["answer"].as_str().unwrap_or_default().to_string();
+        let answer_moderation = self.moderate(llm.as_ref(), query, Some(&answer)).await?;
+
+        if answer_moderation.is_blocked() {
+            log::warn!("Moderation withheld answer ({})", answer_moderation.categories.join(", "));
+            response["answer"] = json!(BLOCKED_ANSWER);
+            response["citations"] = json!([]);
+        } else if answer_moderation.text != answer {
+            let sources = response["sources"].as_array().cloned().unwrap_or_default();
+            response["citations"] = json!(build_citations(&answer_moderation.text, &sources));
+            response["answer"] = json!(answer_moderation.text);
+        }
+        response["moderation"] = json!({"query": query_moderation, "answer": answer_moderation});
+
+        Ok(response)
+    }
+
+    /// Whether queries and answers go through moderation. Streamed answers
+    /// can't be withdrawn once sent, so callers should not stream them then.
+    pub fn moderation_enabled(&self) -> bool {
+        self.moderator.is_enabled()
+    }
+
+    /// Check `answer` (or `query` when there's no answer yet) against the
+    /// rules and then the guard model
+    async fn moderate(&self, llm: &dyn LLMProvider, query: &str, answer: Option<&str>) -> Result<Moderation> {
+        let mut moderation = self.moderator.apply_rules(answer.unwrap_or(query));
+        if let (Some(model), false) = (self.moderator.model(), moderation.is_blocked()) {
+            let messages = match answer {
+                Some(_) => guard_messages(query, Some(&moderation.text)),
+                None => guard_messages(&moderation.text, None),
+            };
+            let request = GenerationRequest {
+                messages,
+                model: Some(model.to_string()),
+                max_tokens: 32,
+                temperature: 0.0,
+                sampling: SamplingParams::default(),
+                tools: Vec::new(),
+            };
+            let verdict = self.complete_text(llm, &request).await?;
+            apply_verdict(&mut moderation, &verdict)?;
+        }
+
+        Ok(moderation)
+    }
+
+    async fn compose_answer(
+        &self,
+        query: &str,
+        retrieved_chunks: &[SearchResult],
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let language = resolve_language(options.language.as_deref(), query);
+        let system_prompt = self.answer_system_prompt(options, language.as_deref());
+        let model = options.model.as_deref();
+        let max_tokens = options.max_tokens;
+        let model_used = model.unwrap_or(llm.model()).to_string();
+
+        let intent = classify_intent(query);
+        if self.intent_routing && !intent.needs_retrieval() {
+            let answer = match intent {
+                QueryIntent::Command => command_reply(query),
+                _ => {
+                    let mut messages = small_talk_messages(&options.history, query);
+                    if let Some(language) = &language {
+                        messages[0].content = format!("{}\n\n{}", messages[0].content, language_instruction(language));
+                    }
+                    let request = GenerationRequest {
+                        messages,
+                        model: model.map(str::to_string),
+                        max_tokens: 256,
+                        temperature: options.temperature,
+                        sampling: options.sampling.clone(),
+                        tools: Vec::new(),
+                    };
+                    self.complete_text(llm.as_ref(), &request).await?
+                }
+            };
+            log::info!("Answered {:?} query without retrieval", intent);
+
+            return Ok(direct_response(&answer, intent, language, llm.name(), &model_used));
+        }
+
+        if retrieved_chunks.is_empty() {
+            return Ok(direct_response(
+                "I couldn't find any relevant information in the knowledge base to answer your question.",
+                intent,
+                language,
+                llm.name(),
+                &model_used,
+            ));
+        }
+
+        let retrieved_chunks = self.rerank(llm.as_ref(), query, retrieved_chunks, options).await;
+        let retrieved_chunks = compress(&retrieved_chunks, query, options);
+
+        // Prepare context from top chunks; strategies that read chunks one at
+        // a time can afford more of them
+        let chunk_limit = match options.strategy {
+            SynthesisStrategy::Stuff => STUFF_CHUNKS,
+            SynthesisStrategy::MapReduce | SynthesisStrategy::Refine => MAX_SYNTHESIS_CHUNKS,
+        };
+        let (retrieved_chunks, packing) = self.select_chunks(llm.as_ref(), &retrieved_chunks, chunk_limit, options);
+        let (context_parts, context_blocks, sources) = build_context(&retrieved_chunks, retrieved_chunks.len());
+        let system_prompt = with_recency(system_prompt, &retrieved_chunks, options.as_of.as_deref());
+
+        let context = match options.strategy {
+            SynthesisStrategy::MapReduce => self.map_context(llm.as_ref(), model, query, &context_parts).await?,
+            _ => context_parts.join("\n\n"),
+        };
+        let messages = build_messages(
+            &system_prompt,
+            options.template.as_ref(),
+            &options.examples,
+            &options.history,
+            query,
+            &context,
+        );
+
+        let use_tools = options.use_tools
+            && options.strategy != SynthesisStrategy::Refine
+            && llm.supports_tools()
+            && !self.tools.is_empty();
+
+        // Check cache; sampling settings such as the seed or stop sequences
+        // change the answer, so they're part of the key
+        let cache_key = format!(
+            "{}_{}_{}_{:?}_{}_{:x}_{:x}",
+            llm.name(),
+            model_used,
+            use_tools,
+            options.strategy,
+            query,
+            calculate_hash(&serde_json::to_string(&messages)?),
+            calculate_hash(&format!("{}{:?}", options.temperature, options.sampling))
+        );
+        let cached_answer = self.response_cache.get(&cache_key);
+
+        let (mut answer, tool_calls) = match cached_answer {
+            Some(cached) => (cached.answer, Vec::new()),
+            None => {
+                // Generate answer
+                let request = GenerationRequest {
+                    messages: messages.clone(),
+                    model: model.map(str::to_string),
+                    max_tokens,
+                    temperature: options.temperature,
+                    sampling: options.sampling.clone(),
+                    tools: Vec::new(),
+                };
+                let (answer, tool_calls) = if options.strategy == SynthesisStrategy::Refine {
+                    let request = GenerationRequest {
+                        messages: build_messages(
+                            &system_prompt,
+                            options.template.as_ref(),
+                            &options.examples,
+                            &options.history,
+                            query,
+                            &context_parts[0],
+                        ),
+                        ..request
+                    };
+                    let answer = self
+                        .refine(llm.as_ref(), request, &system_prompt, query, &context_parts[1..])
+                        .await?;
+                    (answer, Vec::new())
+                } else if use_tools {
+                    self.generate_with_tools(llm.as_ref(), request).await?
+                } else {
+                    (self.complete_text(llm.as_ref(), &request).await?, Vec::new())
+                };
+
+                // Cache result
+                let documents = (!use_tools).then(|| {
+                    let mut documents: Vec<String> = retrieved_chunks.iter().map(|c| c.file_path.clone()).collect();
+                    documents.sort();
+                    documents.dedup();
+                    documents
+                });
+                self.response_cache.put(
+                    &cache_key,
+                    CachedAnswer {
+                        answer: answer.clone(),
+                        documents,
+                    },
+                );
+                (answer, tool_calls)
+            }
+        };
+
+        // Strict mode needs a verdict even when verification is otherwise off
+        let strict_mode = options.strict_mode.unwrap_or(self.strict_mode);
+        let verification = match (strict_mode, options.verification.unwrap_or(self.verification)) {
+            (StrictMode::Off, verification) => verification,
+            (_, VerificationMode::Off) => VerificationMode::Lexical,
+            (_, verification) => verification,
+        };
+        let groundedness = self
+            .verify(verification, llm.as_ref(), model, &answer, &context_blocks, &context)
+            .await;
+
+        let guard = match &groundedness {
+            Some(checked) if strict_mode != StrictMode::Off && checked.unsupported_claims().next().is_some() => {
+                let mut action = "refused";
+                if strict_mode == StrictMode::Regenerate {
+                    let mut retry_messages = messages;
+                    retry_messages.push(ChatMessage::assistant(answer.clone()));
+                    retry_messages.push(ChatMessage::user(strict_instruction(checked)));
+                    let request = GenerationRequest {
+                        messages: retry_messages,
+                        model: model.map(str::to_string),
+                        max_tokens,
+                        temperature: 0.0,
+                        sampling: options.sampling.clone(),
+                        tools: Vec::new(),
+                    };
+                    let retry = self.complete_text(llm.as_ref(), &request).await?;
+
+                    if !retry.contains(INSUFFICIENT_INFORMATION) {
+                        let rechecked = self
+                            .verify(verification, llm.as_ref(), model, &retry, &context_blocks, &context)
+                            .await;
+                        if rechecked.is_some_and(|g| g.unsupported_claims().next().is_none()) {
+                            answer = retry;
+                            action = "regenerated";
+                        }
+                    }
+                }
+                if action == "refused" {
+                    answer = INSUFFICIENT_INFORMATION.to_string();
+                }
+
+                let unsupported: Vec<&str> = checked.unsupported_claims().map(|c| c.text.as_str()).collect();
+                json!({"action": action, "unsupported_claims": unsupported})
+            }
+            _ if strict_mode != StrictMode::Off => json!({"action": "none", "unsupported_claims": []}),
+            _ => serde_json::Value::Null,
+        };
+
+        let citations = build_citations(&answer, &sources);
+
+        Ok(json!({
+            "answer": answer,
+            "intent": intent,
+            "sources": sources,
+            "citations": citations,
+            "confidence": groundedness.as_ref().map(|g| g.confidence),
+            "claims": groundedness.map(|g| g.claims),
+            "guard": guard,
+            "language": language,
+            "strategy": options.strategy,
+            "context_used": context,
+            "context_packing": packing,
+            "num_sources": sources.len(),
+            "tool_calls": tool_calls,
+            "llm_type": llm.name(),
+            "model_used": model_used
+        }))
+    }
+
+    /// The configured or per-request system prompt, told to answer in
+    /// `language` if one is set
+    fn answer_system_prompt(&self, options: &AnswerOptions, language: Option<&str>) -> String {
+        let mut system_prompt = options.system_prompt.as_deref().unwrap_or(&self.system_prompt).to_string();
+        if !options.scope.is_empty() {
+            system_prompt = format!("{}\n\n{}", system_prompt, scope_instruction(&options.scope));
+        }
+        match language {
+            Some(language) => format!("{}\n\n{}", system_prompt, language_instruction(language)),
+            None => system_prompt,
+        }
+    }
+
+    /// Summarize a whole document: each section of consecutive chunks on
+    /// its own, then the section summaries combined in `style`
+    pub async fn summarize_document(
+        &self,
+        file_name: &str,
+        chunks: &[DocumentChunk],
+        style: SummaryStyle,
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let model = options.model.as_deref();
+        let model_used = model.unwrap_or(llm.model()).to_string();
+        if chunks.is_empty() {
+            return Err(anyhow!("Document {} has no text to summarize", file_name));
+        }
+
+        let sections = group_sections(chunks);
+        let llm_ref = llm.as_ref();
+        let section_summaries = futures::future::try_join_all(sections.iter().map(|section| {
+            let text = section.iter().map(|c| &*c.text).collect::<Vec<_>>().join("\n");
+            let request = GenerationRequest {
+                messages: section_summary_messages(file_name, &text),
+                model: model.map(str::to_string),
+                max_tokens: 400,
+                temperature: 0.0,
+                sampling: SamplingParams::default(),
+                tools: Vec::new(),
+            };
+            async move { self.complete_text(llm_ref, &request).await }
+        }))
+        .await?;
+
+        let language = resolve_language(options.language.as_deref(), &chunks[0].text);
+        let system_prompt = self.answer_system_prompt(options, language.as_deref());
+        let numbered: Vec<String> = section_summaries
+            .iter()
+            .enumerate()
+            .map(|(i, summary)| format!("[{}] {}", i + 1, summary))
+            .collect();
+
+        let request = GenerationRequest {
+            messages: combine_summary_messages(&system_prompt, style, file_name, &numbered.join("\n\n")),
+            model: model.map(str::to_string),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            sampling: options.sampling.clone(),
+            tools: Vec::new(),
+        };
+        let summary = self.complete_text(llm.as_ref(), &request).await?;
+
+        let sources: Vec<serde_json::Value> = sections
+            .iter()
+            .zip(&section_summaries)
+            .map(|(section, section_summary)| {
+                json!({
+                    "file_name": file_name,
+                    "chunk_ids": section.iter().map(|c| c.chunk_id).collect::<Vec<_>>(),
+                    "location": section[0].location,
+                    "summary": section_summary
+                })
+            })
+            .collect();
+        let citations = build_citations(&summary, &sources);
+
+        Ok(json!({
+            "summary": summary,
+            "style": style,
+            "file_name": file_name,
+            "num_chunks": chunks.len(),
+            "sources": sources,
+            "citations": citations,
+            "llm_type": llm.name(),
+            "model_used": model_used
+        }))
+    }
+
+    /// Compare or contrast several documents on `query`, each given by file
+    /// name with the chunks retrieved from it alone. Sources come back
+    /// grouped by document.
+    pub async fn compare_documents(
+        &self,
+        query: &str,
+        documents: &[(String, Vec<SearchResult>)],
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let language = resolve_language(options.language.as_deref(), query);
+        let system_prompt = self.answer_system_prompt(options, language.as_deref());
+        let model_used = options.model.as_deref().unwrap_or(llm.model()).to_string();
+
+        let contexts: Vec<_> = documents
+            .iter()
+            .map(|(_, chunks)| build_context(chunks, STUFF_CHUNKS))
+            .collect();
+        let grouped: Vec<(&str, Vec<&str>)> = documents
+            .iter()
+            .zip(&contexts)
+            .map(|((file_name, _), (_, blocks, _))| (file_name.as_str(), blocks.clone()))
+            .collect();
+        let context = comparison_context(&grouped);
+
+        let request = GenerationRequest {
+            messages: comparison_messages(&system_prompt, query, &context),
+            model: options.model.clone(),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            sampling: options.sampling.clone(),
+            tools: Vec::new(),
+        };
+        let answer = self.complete_text(llm.as_ref(), &request).await?;
+
+        // Citation numbers run across documents in the order they're listed
+        let sources: Vec<serde_json::Value> = contexts.iter().flat_map(|(_, _, sources)| sources.clone()).collect();
+        let grouped_sources: Vec<serde_json::Value> = documents
+            .iter()
+            .zip(&contexts)
+            .map(|((file_name, _), (_, _, sources))| {
+                json!({
+                    "file_name": file_name,
+                    "sources": sources
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "answer": answer,
+            "documents": grouped_sources,
+            "citations": build_citations(&answer, &sources),
+            "language": language,
+            "context_used": context,
+            "num_sources": sources.len(),
+            "llm_type": llm.name(),
+            "model_used": model_used
+        }))
+    }
+
+    /// Map step of map-reduce: the facts each numbered block holds about
+    /// `query`, keeping the block numbers for citations
+    async fn map_context(
+        &self,
+        llm: &dyn LLMProvider,
+        model: Option<&str>,
+        query: &str,
+        context_parts: &[String],
+    ) -> Result<String> {
+        let notes = futures::future::try_join_all(context_parts.iter().map(|block| {
+            let request = GenerationRequest {
+                messages: map_messages(query, block),
+                model: model.map(str::to_string),
+                max_tokens: 512,
+                temperature: 0.0,
+                sampling: SamplingParams::default(),
+                tools: Vec::new(),
+            };
+            async move { self.complete_text(llm, &request).await }
+        }))
+        .await?;
+
+        let relevant: Vec<String> = notes
+            .iter()
+            .enumerate()
+            .filter(|(_, note)| is_relevant(note))
+            .map(|(i, note)| format!("[{}] {}", i + 1, note.trim()))
+            .collect();
+        log::info!("Map step kept notes from {} of {} chunks", relevant.len(), context_parts.len());
+
+        Ok(relevant.join("\n\n"))
+    }
+
+    /// Refine strategy: answer `request`, built from the first block, then
+    /// revise the answer with each of the remaining blocks in turn
+    async fn refine(
+        &self,
+        llm: &dyn LLMProvider,
+        mut request: GenerationRequest,
+        system_prompt: &str,
+        query: &str,
+        remaining_parts: &[String],
+    ) -> Result<String> {
+        let mut answer = self.complete_text(llm, &request).await?;
+
+        for block in remaining_parts {
+            request.messages = refine_messages(system_prompt, query, &answer, block);
+            answer = self.complete_text(llm, &request).await?;
+        }
+
+        Ok(answer)
+    }
+
+    /// Check the answer's claims against the context. Returns `None` when
+    /// verification is off; a failed LLM judge falls back to the lexical check.
+    async fn verify(
+        &self,
+        verification: VerificationMode,
+        llm: &dyn LLMProvider,
+        model: Option<&str>,
+        answer: &str,
+        context_blocks: &[&str],
+        context: &str,
+    ) -> Option<Groundedness> {
+        if verification == VerificationMode::Off {
+            return None;
+        }
+
+        let lexical = lexical_groundedness(answer, context_blocks);
+        if verification == VerificationMode::Lexical || lexical.claims.is_empty() {
+            return Some(lexical);
+        }
+
+        let request = GenerationRequest {
+            messages: judge_messages(&lexical.claims, context),
+            model: model.map(str::to_string),
+            max_tokens: 1024,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        let judged = match self.complete_text(llm, &request).await {
+            Ok(response) => apply_judgement(lexical.clone(), &response),
+            Err(e) => Err(e),
+        };
+
+        match judged {
+            Ok(groundedness) => Some(groundedness),
+            Err(e) => {
+                log::warn!("LLM verification failed, using lexical check: {}", e);
+                Some(lexical)
+            }
+        }
+    }
+
+    /// `chunks` ordered by an LLM's relevance scores when reranking is on.
+    /// The first `RERANK_CANDIDATES` are scored in batches; if any batch
+    /// fails the retrieval order is kept.
+    #[tracing::instrument(skip_all, fields(chunks = chunks.len()))]
+    async fn rerank<'a>(
+        &self,
+        llm: &dyn LLMProvider,
+        query: &str,
+        chunks: &'a [SearchResult],
+        options: &AnswerOptions,
+    ) -> Cow<'a, [SearchResult]> {
+        if options.rerank.unwrap_or(self.rerank_mode) == RerankMode::Off || chunks.len() < 2 {
+            return Cow::Borrowed(chunks);
+        }
+
+        let candidates = &chunks[..chunks.len().min(RERANK_CANDIDATES)];
+        let model = options
+            .rerank_model
+            .as_deref()
+            .or(self.rerank_model.as_deref())
+            .or(options.model.as_deref());
+        let scores = futures::future::try_join_all(candidates.chunks(RERANK_BATCH_SIZE).map(|batch| {
+            let passages: Vec<&str> = batch.iter().map(|c| c.text.as_str()).collect();
+            let request = GenerationRequest {
+                messages: rerank_messages(query, &passages),
+                model: model.map(str::to_string),
+                max_tokens: 512,
+                temperature: 0.0,
+                sampling: SamplingParams::default(),
+                tools: Vec::new(),
+            };
+            async move { parse_scores(&self.complete_text(llm, &request).await?, batch.len()) }
+        }))
+        .await;
+
+        match scores {
+            Ok(scores) => Cow::Owned(apply_scores(chunks, &scores.concat())),
+            Err(e) => {
+                log::warn!("Reranking failed, keeping retrieval order: {}", e);
+                Cow::Borrowed(chunks)
+            }
+        }
+    }
+
+    /// The chunks to build the context from: with a token budget, those
+    /// packed into it, reporting the ones left out and why; otherwise
+    /// the first `limit` distinct ones
+    fn select_chunks<'a>(
+        &self,
+        llm: &dyn LLMProvider,
+        chunks: &'a [SearchResult],
+        limit: usize,
+        options: &AnswerOptions,
+    ) -> (Cow<'a, [SearchResult]>, Option<serde_json::Value>) {
+        let budget = options.context_budget.unwrap_or(self.context_budget);
+        if budget == 0 {
+            let chunks = self.dedup(chunks);
+            if chunks.len() > limit {
+                return (Cow::Owned(chunks[..limit].to_vec()), None);
+            }
+            return (chunks, None);
+        }
+        let packing = pack_context(chunks, budget, |text| llm.count_tokens(text));
+        log::debug!(
+            "Packed {} chunks into {} of {} context tokens, dropped {}",
+            packing.chunks.len(),
+            packing.used_tokens,
+            budget,
+            packing.dropped.len()
+        );
+        let report = packing.report();
+        (Cow::Owned(packing.chunks), Some(report))
+    }
+
+    /// `chunks` without near-duplicates, so each context slot carries
+    /// different text
+    fn dedup<'a>(&self, chunks: &'a [SearchResult]) -> Cow<'a, [SearchResult]> {
+        if self.dedup_threshold <= 0.0 || chunks.len() < 2 {
+            return Cow::Borrowed(chunks);
+        }
+        let (kept, dropped) = dedup_chunks(chunks, self.dedup_threshold);
+        if dropped == 0 {
+            return Cow::Borrowed(chunks);
+        }
+        log::debug!("Dropped {} near-duplicate chunks from the context", dropped);
+        Cow::Owned(kept)
+    }
+
+    /// Send `request` within the rate limits and record its token usage,
+    /// estimating it when the provider doesn't report any
+    #[tracing::instrument(
+        name = "generate",
+        skip_all,
+        fields(provider = llm.name(), model = request.model_or(llm.model()), prompt_tokens, completion_tokens)
+    )]
+    async fn complete(&self, llm: &dyn LLMProvider, request: &GenerationRequest) -> Result<Generation> {
+        let failure_key = failure_key(llm, request)?;
+        if let Some((error, failed_at)) = self.failures.get(&failure_key) {
+            let retry_after = self.failure_ttl.saturating_sub(failed_at.elapsed());
+            return Err(RecentFailure {
+                error,
+                retry_after_secs: retry_after.as_secs().max(1),
+            }
+            .into());
+        }
+
+        self.throttle(llm, request).await;
+        let generation = match llm.complete(request).await {
+            Ok(generation) => generation,
+            Err(e) => {
+                let refused = e.chain().find_map(|cause| cause.downcast_ref::<ProviderError>());
+                if let Some(error) = refused.filter(|error| error.is_permanent()) {
+                    self.failures.put(&failure_key, (error.clone(), Instant::now()));
+                }
+                return Err(e);
+            }
+        };
+
+        let model = request.model_or(llm.model());
+        let (usage, estimated) = match generation.usage {
+            Some(usage) => (usage, false),
+            None => {
+                let estimate = TokenUsage {
+                    prompt_tokens: prompt_tokens(llm, request) as u64,
+                    completion_tokens: llm.count_tokens(&generation.content) as u64,
+                };
+                (estimate, true)
+            }
+        };
+        let span = tracing::Span::current();
+        span.record("prompt_tokens", usage.prompt_tokens);
+        span.record("completion_tokens", usage.completion_tokens);
+        self.usage.record(llm.name(), model, usage, estimated);
+
+        Ok(generation)
+    }
+
+    /// Text of a completion made without tools
+    async fn complete_text(&self, llm: &dyn LLMProvider, request: &GenerationRequest) -> Result<String> {
+        let generation = self.complete(llm, request).await?;
+        if generation.content.is_empty() {
+            return Err(anyhow!("No response from LLM"));
+        }
+        Ok(generation.content)
+    }
+
+    /// Wait for rate-limit budget before sending `request`
+    async fn throttle(&self, llm: &dyn LLMProvider, request: &GenerationRequest) {
+        let prompt_tokens = prompt_tokens(llm, request);
+        log::debug!("Sending ~{} prompt tokens to {}", prompt_tokens, llm.name());
+
+        // Providers count the requested completion budget against the limit too
+        let waited = self.rate_limiter.acquire(prompt_tokens + request.max_tokens).await;
+        if !waited.is_zero() {
+            log::info!("Rate limit reached, queued LLM request for {:?}", waited);
+        }
+    }
+
+    /// Let the model call tools until it answers, for at most
+    /// `MAX_TOOL_ROUNDS` rounds. Returns the answer and a trace of the calls.
+    async fn generate_with_tools(
+        &self,
+        llm: &dyn LLMProvider,
+        mut request: GenerationRequest,
+    ) -> Result<(String, Vec<serde_json::Value>)> {
+        request.tools = self.tools.specs();
+        let mut trace = Vec::new();
+
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let generation = self.complete(llm, &request).await?;
+            if generation.tool_calls.is_empty() {
+                if generation.content.is_empty() {
+                    return Err(anyhow!("No response from LLM"));
+                }
+                return Ok((generation.content, trace));
+            }
+
+            request
+                .messages
+                .push(ChatMessage::assistant_tool_calls(generation.content, generation.tool_calls.clone()));
+            for call in &generation.tool_calls {
+                let output = self.tools.execute(call).await;
+                log::info!("Tool call {}({}) returned {} chars", call.function.name, call.function.arguments, output.len());
+                trace.push(json!({
+                    "name": call.function.name,
+                    "arguments": call.function.arguments,
+                    "output": output
+                }));
+                request.messages.push(ChatMessage::tool_result(&call.id, output));
+            }
+        }
+
+        // Out of rounds: answer with what has been gathered so far
+        request.tools.clear();
+        Ok((self.complete_text(llm, &request).await?, trace))
+    }
+
+    /// Make a tiny completion with the default provider and model, so a bad
+    /// API key or model name shows up at startup rather than on first use
+    pub async fn check(&self) -> Result<()> {
+        let llm = self.provider(None)?;
+        let request = GenerationRequest {
+            messages: vec![ChatMessage::user("Reply with OK.")],
+            model: None,
+            max_tokens: 16,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        self.complete(llm.as_ref(), &request).await?;
+        Ok(())
+    }
+
+    /// Intent of `query` and whether it should be answered from the documents
+    pub fn classify(&self, query: &str) -> serde_json::Value {
+        json!({
+            "intent": classify_intent(query),
+            "needs_retrieval": self.needs_retrieval(query)
+        })
+    }
+
+    /// Whether answering `query` needs retrieved chunks
+    pub fn needs_retrieval(&self, query: &str) -> bool {
+        !self.intent_routing || classify_intent(query).needs_retrieval()
+    }
+
+    /// Whether `query` would be answered from chunks none of which reach the
+    /// answer score floor, so no answer should be generated
+    pub fn below_score_floor(&self, query: &str, retrieved_chunks: &[SearchResult]) -> bool {
+        self.answer_score_floor > 0.0
+            && !retrieved_chunks.is_empty()
+            && self.needs_retrieval(query)
+            && retrieved_chunks.iter().all(|c| c.similarity_score < self.answer_score_floor)
+    }
+
+    /// Start answering `query` from `retrieved_chunks` with the answer text
+    /// streamed as it's generated. Pass the collected text to
+    /// `finish_answer` for citations and verification; tools, synthesis
+    /// strategies, and strict mode don't apply to streamed answers.
+    pub async fn stream_answer(
+        &self,
+        query: &str,
+        retrieved_chunks: &[SearchResult],
+        options: &AnswerOptions,
+    ) -> Result<(TokenStream, PendingAnswer)> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let language = resolve_language(options.language.as_deref(), query);
+        let system_prompt = self.answer_system_prompt(options, language.as_deref());
+
+        let retrieved_chunks = self.rerank(llm.as_ref(), query, retrieved_chunks, options).await;
+        let retrieved_chunks = compress(&retrieved_chunks, query, options);
+        let (retrieved_chunks, packing) = self.select_chunks(llm.as_ref(), &retrieved_chunks, STUFF_CHUNKS, options);
+        let (context_parts, context_blocks, sources) = build_context(&retrieved_chunks, retrieved_chunks.len());
+        let system_prompt = with_recency(system_prompt, &retrieved_chunks, options.as_of.as_deref());
+        let context = context_parts.join("\n\n");
+        let request = GenerationRequest {
+            messages: build_messages(
+                &system_prompt,
+                options.template.as_ref(),
+                &options.examples,
+                &options.history,
+                query,
+                &context,
+            ),
+            model: options.model.clone(),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            sampling: options.sampling.clone(),
+            tools: Vec::new(),
+        };
+
+        self.throttle(llm.as_ref(), &request).await;
+        let tokens = llm.stream(&request).await?;
+
+        Ok((
+            tokens,
+            PendingAnswer {
+                llm,
+                request,
+                context,
+                context_blocks: context_blocks.into_iter().map(str::to_string).collect(),
+                sources,
+                language,
+                verification: options.verification.unwrap_or(self.verification),
+                follow_ups: options.follow_ups,
+                query: query.to_string(),
+                packing,
+            },
+        ))
+    }
+
+    /// How the store is searched for a request: its own mode or the
+    /// configured one
+    pub fn retrieval_mode(&self, options: &AnswerOptions) -> RetrievalMode {
+        options.retrieval.unwrap_or(self.retrieval_mode)
+    }
+
+    /// Text to search the store with for `query`: the query itself, or in
+    /// HyDE mode the query with a drafted answer. A failed draft falls back
+    /// to the query.
+    pub async fn search_text(&self, query: &str, options: &AnswerOptions) -> Result<String> {
+        if self.retrieval_mode(options) != RetrievalMode::Hyde {
+            return Ok(query.to_string());
+        }
+
+        let llm = self.provider(options.provider.as_deref())?;
+        let request = GenerationRequest {
+            messages: hyde_messages(query),
+            model: options.model.clone(),
+            max_tokens: 256,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        match self.complete_text(llm.as_ref(), &request).await {
+            Ok(draft) => Ok(hyde_search_text(query, &draft)),
+            Err(e) => {
+                log::warn!("HyDE draft failed, searching with the query: {}", e);
+                Ok(query.to_string())
+            }
+        }
+    }
+
+    /// `query` rewritten to stand on its own given `options.history`, so a
+    /// follow-up like "what about the second option?" retrieves the right
+    /// chunks. Without history, or if rewriting fails, it is the query as is.
+    pub async fn standalone_query(&self, query: &str, options: &AnswerOptions) -> Result<String> {
+        if !options.rewrite.unwrap_or(self.query_rewriting) || options.history.is_empty() {
+            return Ok(query.to_string());
+        }
+
+        let llm = self.provider(options.provider.as_deref())?;
+        let request = GenerationRequest {
+            messages: rewrite_messages(&options.history, query),
+            model: options.model.clone(),
+            max_tokens: 128,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        match self.complete_text(llm.as_ref(), &request).await {
+            Ok(response) => Ok(parse_rewrite(&response).unwrap_or_else(|| query.to_string())),
+            Err(e) => {
+                log::warn!("Query rewriting failed, searching with the question as asked: {}", e);
+                Ok(query.to_string())
+            }
+        }
+    }
+
+    /// Simpler questions that together answer `query`, for multi-step
+    /// answering. A single fact question, or a failed decomposition, gives
+    /// just the question itself.
+    pub async fn decompose_query(&self, query: &str, options: &AnswerOptions) -> Result<Vec<String>> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let request = GenerationRequest {
+            messages: decomposition_messages(query),
+            model: options.model.clone(),
+            max_tokens: 256,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        match self.complete_text(llm.as_ref(), &request).await {
+            Ok(response) => {
+                let sub_questions = parse_sub_questions(&response);
+                if sub_questions.len() < 2 {
+                    return Ok(vec![query.to_string()]);
+                }
+                Ok(sub_questions)
+            }
+            Err(e) => {
+                log::warn!("Query decomposition failed, answering in one step: {}", e);
+                Ok(vec![query.to_string()])
+            }
+        }
+    }
+
+    /// Answer `query` in steps: each sub-question from its own retrieved
+    /// chunks, then a final answer combining the step answers and citing the
+    /// chunks of every step
+    pub async fn answer_in_steps(
+        &self,
+        query: &str,
+        steps: &[(String, Vec<SearchResult>)],
+        options: &AnswerOptions,
+    ) -> Result<serde_json::Value> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let language = resolve_language(options.language.as_deref(), query);
+        let system_prompt = self.answer_system_prompt(options, language.as_deref());
+        let model = options.model.as_deref();
+
+        let step_answers = futures::future::try_join_all(steps.iter().map(|(sub_question, chunks)| {
+            let llm = llm.as_ref();
+            async move {
+                let chunks = self.dedup(chunks);
+                let (context_parts, _, _) = build_context(&chunks, STUFF_CHUNKS);
+                if context_parts.is_empty() {
+                    return Ok("Nothing relevant was found.".to_string());
+                }
+                let request = GenerationRequest {
+                    messages: sub_question_messages(sub_question, &context_parts.join("\n\n")),
+                    model: model.map(str::to_string),
+                    max_tokens: 512,
+                    temperature: 0.0,
+                    sampling: options.sampling.clone(),
+                    tools: Vec::new(),
+                };
+                self.complete_text(llm, &request).await
+            }
+        }))
+        .await?;
+
+        // Every step's chunks, each once, numbered for the final answer
+        let mut combined: Vec<SearchResult> = Vec::new();
+        for chunk in steps.iter().flat_map(|(_, chunks)| chunks) {
+            if !combined.iter().any(|c| c.file_path == chunk.file_path && c.chunk_id == chunk.chunk_id) {
+                combined.push(chunk.clone());
+            }
+        }
+        let combined = self.dedup(&combined);
+        let (context_parts, context_blocks, sources) = build_context(&combined, MAX_SYNTHESIS_CHUNKS);
+        let context = context_parts.join("\n\n");
+        let steps_text: Vec<String> = steps
+            .iter()
+            .zip(&step_answers)
+            .enumerate()
+            .map(|(i, ((sub_question, _), answer))| format!("Step {}: {}\n{}", i + 1, sub_question, answer.trim()))
+            .collect();
+
+        let request = GenerationRequest {
+            messages: step_synthesis_messages(&system_prompt, query, &steps_text.join("\n\n"), &context),
+            model: model.map(str::to_string),
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            sampling: options.sampling.clone(),
+            tools: Vec::new(),
+        };
+        let answer = self.complete_text(llm.as_ref(), &request).await?;
+        let groundedness = self
+            .verify(
+                options.verification.unwrap_or(self.verification),
+                llm.as_ref(),
+                model,
+                &answer,
+                &context_blocks,
+                &context,
+            )
+            .await;
+
+        Ok(json!({
+            "answer": answer,
+            "intent": QueryIntent::KnowledgeBase,
+            "steps": steps.iter().zip(&step_answers).map(|((sub_question, chunks), answer)| json!({
+                "question": sub_question,
+                "answer": answer.trim(),
+                "num_chunks": chunks.len()
+            })).collect::<Vec<_>>(),
+            "sources": sources,
+            "citations": build_citations(&answer, &sources),
+            "confidence": groundedness.as_ref().map(|g| g.confidence),
+            "claims": groundedness.map(|g| g.claims),
+            "language": language,
+            "context_used": context,
+            "num_sources": sources.len(),
+            "llm_type": llm.name(),
+            "model_used": model.unwrap_or(llm.model())
+        }))
+    }
+
+    /// Paraphrases and sub-questions of `query` to search alongside it. A
+    /// failed expansion returns none, so only the query is searched.
+    pub async fn expand_query(&self, query: &str, options: &AnswerOptions) -> Result<Vec<String>> {
+        let llm = self.provider(options.provider.as_deref())?;
+        let request = GenerationRequest {
+            messages: expansion_messages(query),
+            model: options.model.clone(),
+            max_tokens: 256,
+            temperature: 0.7,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        match self.complete_text(llm.as_ref(), &request).await {
+            Ok(response) => Ok(parse_expansions(&response, query)),
+            Err(e) => {
+                log::warn!("Query expansion failed, searching with the query only: {}", e);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Grade `answer` to `question` and the `contexts` it was built from
+    /// with an LLM judge
+    pub async fn judge_rag(
+        &self,
+        provider: Option<&str>,
+        model: Option<&str>,
+        question: &str,
+        contexts: &[&str],
+        answer: &str,
+        reference_answer: Option<&str>,
+    ) -> Result<RagScores> {
+        let llm = self.provider(provider)?;
+        let request = GenerationRequest {
+            messages: rag_judge_messages(question, contexts, answer, reference_answer),
+            model: model.map(str::to_string),
+            max_tokens: 256,
+            temperature: 0.0,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        parse_rag_judgement(&self.complete_text(llm.as_ref(), &request).await?, contexts.len())
+    }
+
+    /// A question `chunk` answers and its answer, for building test sets
+    pub async fn generate_test_case(
+        &self,
+        provider: Option<&str>,
+        model: Option<&str>,
+        file_name: &str,
+        chunk: &str,
+    ) -> Result<(String, String)> {
+        let llm = self.provider(provider)?;
+        let request = GenerationRequest {
+            messages: testset_messages(file_name, chunk),
+            model: model.map(str::to_string),
+            max_tokens: 512,
+            temperature: 0.3,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        parse_testset_pair(&self.complete_text(llm.as_ref(), &request).await?)
+    }
+
+    /// Final response for a streamed answer whose full text is `answer`
+    pub async fn finish_answer(&self, pending: PendingAnswer, answer: &str) -> serde_json::Value {
+        let llm = pending.llm.as_ref();
+        let model = pending.request.model.as_deref();
+
+        // Streams don't report usage, so count it from the text
+        let usage = TokenUsage {
+            prompt_tokens: prompt_tokens(llm, &pending.request) as u64,
+            completion_tokens: llm.count_tokens(answer) as u64,
+        };
+        self.usage.record(llm.name(), pending.request.model_or(llm.model()), usage, true);
+
+        let context_blocks: Vec<&str> = pending.context_blocks.iter().map(String::as_str).collect();
+        let groundedness = self
+            .verify(pending.verification, llm, model, answer, &context_blocks, &pending.context)
+            .await;
+
+        let mut response = json!({
+            "answer": answer,
+            "intent": QueryIntent::KnowledgeBase,
+            "sources": pending.sources,
+            "citations": build_citations(answer, &pending.sources),
+            "confidence": groundedness.as_ref().map(|g| g.confidence),
+            "claims": groundedness.map(|g| g.claims),
+            "language": pending.language,
+            "context_used": pending.context,
+            "context_packing": pending.packing,
+            "num_sources": pending.sources.len(),
+            "llm_type": llm.name(),
+            "model_used": pending.request.model_or(llm.model())
+        });
+        if pending.follow_ups {
+            self.add_follow_ups(llm, model, &pending.query, &mut response).await;
+        }
+        response
+    }
+
+    /// Add `follow_up_questions` to an answer built from document context;
+    /// answers without context, refused ones, and failed suggestions get none
+    async fn add_follow_ups(
+        &self,
+        llm: &dyn LLMProvider,
+        model: Option<&str>,
+        query: &str,
+        response: &mut serde_json::Value,
+    ) {
+        let context = response["context_used"].as_str().unwrap_or_default();
+        let answer = response["answer"].as_str().unwrap_or_default();
+        if context.is_empty() || response["guard"]["action"] == "refused" {
+            response["follow_up_questions"] = json!([]);
+            return;
+        }
+
+        let request = GenerationRequest {
+            messages: follow_up_messages(query, answer, context),
+            model: model.map(str::to_string),
+            max_tokens: 256,
+            temperature: 0.7,
+            sampling: SamplingParams::default(),
+            tools: Vec::new(),
+        };
+        let questions = match self.complete_text(llm, &request).await {
+            Ok(suggestions) => parse_follow_ups(&suggestions, query),
+            Err(e) => {
+                log::warn!("Suggesting follow-up questions failed: {}", e);
+                Vec::new()
+            }
+        };
+        response["follow_up_questions"] = json!(questions);
+    }
+
+    /// Drop the cached answers `change` could have made stale: those drawn
+    /// from the changed documents, or all of them when the store was
+    /// cleared. Returns how many were dropped.
+    pub fn invalidate_answers(&self, change: &IndexChange) -> usize {
+        self.response_cache.invalidate_where(&|cached| cached.affected_by(change))
+    }
+
+    /// Token usage and estimated spend, optionally for the last `days` days
+    pub fn usage_report(&self, days: Option<u32>) -> serde_json::Value {
+        self.usage.report(days)
+    }
+
+    pub fn get_model_info(&self) -> serde_json::Value {
+        let providers = self.providers.read().unwrap();
+        let mut info = providers[&self.default_provider].model_info();
+        let mut available: Vec<&String> = providers.keys().collect();
+        available.sort_unstable();
+        info["available_providers"] = json!(available);
+        info
+    }
+}
+
+/// Response for an answer given without document context
+fn direct_response(
+    answer: &str,
+    intent: QueryIntent,
+    language: Option<String>,
+    llm_type: &str,
+    model_used: &str,
+) -> serde_json::Value {
+    json!({
+        "answer": answer,
+        "intent": intent,
+        "sources": [],
+        "citations": [],
+        "confidence": null,
+        "claims": [],
+        "guard": null,
+        "language": language,
+        "context_used": "",
+        "num_sources": 0,
+        "llm_type": llm_type,
+        "model_used": model_used
+    })
+}
+
+/// System prompt addition limiting an answer to the named documents
+/// `system_prompt` told how to weigh dated sources, when any of `chunks`
+/// has a date
+fn with_recency(system_prompt: String, chunks: &[SearchResult], as_of: Option<&str>) -> String {
+    if chunks.iter().all(|c| c.document_date.is_none()) {
+        return system_prompt;
+    }
+    let when = match as_of {
+        Some(as_of) => format!("as of {}", as_of),
+        None => "now".to_string(),
+    };
+    format!(
+        "{}\n\nSources marked with a date come from documents dated that day. If sources disagree, answer with what applies {} according to the most recent of them, cite it, and mention that older sources said otherwise.",
+        system_prompt, when
+    )
+}
+
+fn scope_instruction(documents: &[String]) -> String {
+    let names = match documents {
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+        [] => String::new(),
+    };
+    format!(
+        "Answer using only {}. If {} not contain the answer, say so instead of drawing on other sources or general knowledge.",
+        names,
+        if documents.len() == 1 { "it does" } else { "they do" }
+    )
+}
+
+/// Why a question went unanswered: the floor and the best retrieval scores
+/// that missed it
+fn no_answer_diagnostics(retrieved_chunks: &[SearchResult], score_floor: f32) -> serde_json::Value {
+    let mut top: Vec<&SearchResult> = retrieved_chunks.iter().collect();
+    top.sort_by(|a, b| b.similarity_score.partial_cmp(&a.similarity_score).unwrap());
+    json!({
+        "reason": "low_retrieval_score",
+        "score_floor": score_floor,
+        "best_score": top.first().map(|c| c.similarity_score),
+        "top_scores": top.iter().take(NO_ANSWER_TOP_SCORES).map(|c| json!({
+            "file_name": c.file_name,
+            "chunk_id": c.chunk_id,
+            "similarity_score": c.similarity_score
+        })).collect::<Vec<_>>()
+    })
+}
+
+/// `chunks` compressed to their query-matching sentences when the options
+/// ask for it
+fn compress<'a>(chunks: &'a [SearchResult], query: &str, options: &AnswerOptions) -> Cow<'a, [SearchResult]> {
+    match options.compress {
+        Some(max_sentences) => Cow::Owned(compress_chunks(chunks, query, max_sentences)),
+        None => Cow::Borrowed(chunks),
+    }
+}
+
+/// Numbered context blocks, their raw text, and the matching sources for
+/// up to `limit` chunks. A chunk with a parent section contributes the whole
+/// section, and each section is included only once.
+fn build_context(
+    retrieved_chunks: &[SearchResult],
+    limit: usize,
+) -> (Vec<String>, Vec<&str>, Vec<serde_json::Value>) {
+    let mut context_parts = Vec::new();
+    let mut context_blocks = Vec::new();
+    let mut sources = Vec::new();
+
+    let mut used_parents = std::collections::HashSet::new();
+
+    for chunk in retrieved_chunks.iter().take(limit) {
+        let context_text = match (&chunk.parent_text, chunk.parent_id) {
+            (Some(parent_text), Some(parent_id)) => {
+                if !used_parents.insert((chunk.file_path.as_str(), parent_id)) {
+                    continue;
+                }
+                parent_text.as_str()
+            }
+            _ => chunk.text.as_str(),
+        };
+
+        // Dated sources say so, letting the model prefer the newest
+        let context_part = match &chunk.document_date {
+            Some(date) => format!("[{}] (dated {}) {}", context_parts.len() + 1, date, context_text),
+            None => format!("[{}] {}", context_parts.len() + 1, context_text),
+        };
+        context_parts.push(context_part);
+        context_blocks.push(context_text);
+        let mut source = json!({
+            "file_name": chunk.file_name,
+            "file_path": chunk.file_path,
+            "similarity_score": chunk.similarity_score,
+            "chunk_id": chunk.chunk_id,
+            "parent_id": chunk.parent_id,
+            "location": chunk.location
+        });
+        if let Some(score) = chunk.rerank_score {
+            source["rerank_score"] = json!(score);
+        }
+        if let Some(date) = &chunk.document_date {
+            source["document_date"] = json!(date);
+        }
+        sources.push(source);
+    }
+
+    (context_parts, context_blocks, sources)
+}
+
+/// Approximate prompt size of `request` in `llm`'s tokens
+fn prompt_tokens(llm: &dyn LLMProvider, request: &GenerationRequest) -> usize {
+    request.messages.iter().map(|m| llm.count_tokens(&m.content)).sum()
+}
+
+/// Chat messages for a RAG query. History goes in as earlier turns unless the
+/// template places it itself via `{history}`.
+fn build_messages(
+    system_prompt: &str,
+    template: Option<&PromptTemplate>,
+    examples: &[FewShotExample],
+    history: &[ChatMessage],
+    query: &str,
+    context: &str,
+) -> Vec<ChatMessage> {
+    let default_template;
+    let template = match template {
+        Some(template) => template,
+        None => {
+            default_template = PromptTemplate {
+                name: "default".to_string(),
+                template: DEFAULT_TEMPLATE.to_string(),
+            };
+            &default_template
+        }
+    };
+
+    let history_text = history
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let variables = HashMap::from([
+        ("query", query.to_string()),
+        ("context", context.to_string()),
+        ("history", history_text),
+    ]);
+
+    let mut messages = vec![ChatMessage::system(system_prompt)];
+    messages.extend(example_messages(examples));
+    if !template.uses("history") {
+        messages.extend(history.iter().cloned());
+    }
+    messages.push(ChatMessage::user(format!(
+        "{}\n\n{}",
+        template.render(&variables),
+        CITATION_INSTRUCTION
+    )));
+    messages
+}
+
+/// Identifies a request to a provider, to recognize it when it's sent again
+fn failure_key(llm: &dyn LLMProvider, request: &GenerationRequest) -> Result<String> {
+    let tools: Vec<&str> = request.tools.iter().map(|tool| tool.name.as_str()).collect();
+    Ok(format!(
+        "{}_{}_{}_{:x}",
+        llm.name(),
+        request.model_or(llm.model()),
+        request.max_tokens,
+        calculate_hash(&serde_json::to_string(&(
+            &request.messages,
+            request.temperature,
+            &request.sampling,
+            tools
+        ))?)
+    ))
+}
+
+/// Same for the same input across runs and Rust versions, as cache keys
+/// are saved to disk
+fn calculate_hash(input: &str) -> u64 {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(input.as_bytes());
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FunctionCall, LLMModel, ToolCall};
+    use crate::services::llm_providers::{Generation, TokenStream};
+    use crate::services::tools::Calculator;
+    use async_trait::async_trait;
+    use futures::stream::{self, StreamExt};
+
+    /// Echoes the model it was asked to use
+    struct EchoLLM(&'static str);
+
+    #[async_trait]
+    impl LLMProvider for EchoLLM {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn model(&self) -> &str {
+            "echo-default"
+        }
+
+        async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+            Ok(format!("{}:{}", self.0, request.model_or(self.model())))
+        }
+
+        async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+            let answer = self.generate(request).await?;
+            Ok(stream::once(async move { Ok(answer) }).boxed())
+        }
+
+        fn model_info(&self) -> serde_json::Value {
+            json!({"provider": self.0})
+        }
+
+        async fn list_models(&self) -> Result<Vec<LLMModel>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn chunk() -> SearchResult {
+        SearchResult {
+            file_path: "/tmp/rust.txt".to_string(),
+            file_name: "rust.txt".to_string(),
+            file_type: ".txt".to_string(),
+            chunk_id: 0,
+            chunk_size: 27,
+            text: "Rust is a systems language.".to_string(),
+            similarity_score: 0.9,
+            parent_id: None,
+            parent_text: None,
+            location: Default::default(),
+            rerank_score: None,
+            highlights: None,
+            document_date: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_and_model_selection() {
+        let mut handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        handler.register_provider(Arc::new(EchoLLM("second")));
+
+        let default = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(default["answer"], "first:echo-default");
+
+        let chosen = handler
+            .generate_answer(
+                "What is Rust?",
+                &[chunk()],
+                &AnswerOptions {
+                    provider: Some("second".to_string()),
+                    model: Some("big".to_string()),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(chosen["answer"], "second:big");
+        assert_eq!(chosen["llm_type"], "second");
+        assert_eq!(chosen["model_used"], "big");
+
+        assert!(handler.provider(Some("missing")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_override_bypasses_cache() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")))
+            .with_system_prompt("Answer like a pirate.".to_string());
+        assert_eq!(handler.system_prompt, "Answer like a pirate.");
+
+        let options = AnswerOptions {
+            system_prompt: Some("Answer in French.".to_string()),
+            ..Default::default()
+        };
+        handler.generate_answer("What is Rust?", &[chunk()], &options).await.unwrap();
+        handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+
+        // Different system prompts must not share a cached answer
+        assert_eq!(handler.response_cache.stats().size, 2);
+
+        // Only changes to the documents an answer drew on drop it
+        let other = IndexChange::Documents(vec!["/tmp/other.txt".to_string()]);
+        assert_eq!(handler.invalidate_answers(&other), 0);
+        let changed = IndexChange::Documents(vec!["/tmp/rust.txt".to_string()]);
+        assert_eq!(handler.invalidate_answers(&changed), 2);
+        assert_eq!(handler.response_cache.stats().size, 0);
+    }
+
+    /// Refuses every request with `status`, counting them
+    struct RefusingLLM {
+        status: u16,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LLMProvider for RefusingLLM {
+        fn name(&self) -> &'static str {
+            "refusing"
+        }
+
+        fn model(&self) -> &str {
+            "small"
+        }
+
+        async fn generate(&self, _request: &GenerationRequest) -> Result<String> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(ProviderError {
+                provider: "Refusing".to_string(),
+                status: self.status,
+                message: "context_length_exceeded".to_string(),
+            }
+            .into())
+        }
+
+        async fn stream(&self, _request: &GenerationRequest) -> Result<TokenStream> {
+            Err(anyhow!("not streamed"))
+        }
+
+        fn model_info(&self) -> serde_json::Value {
+            json!({})
+        }
+
+        async fn list_models(&self) -> Result<Vec<LLMModel>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refused_requests_are_not_sent_again() {
+        let llm = Arc::new(RefusingLLM {
+            status: 400,
+            calls: Default::default(),
+        });
+        let handler = LLMHandler::new(llm.clone());
+        let options = AnswerOptions::default();
+
+        let first = handler.generate_answer("What is Rust?", &[chunk()], &options).await.unwrap_err();
+        assert!(first.downcast_ref::<ProviderError>().is_some());
+        let second = handler.generate_answer("What is Rust?", &[chunk()], &options).await.unwrap_err();
+        let recent = second.downcast_ref::<RecentFailure>().unwrap();
+        assert_eq!(recent.error.status, 400);
+        assert_eq!(llm.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // A different question is sent
+        handler.generate_answer("What is Go?", &[chunk()], &options).await.unwrap_err();
+        assert_eq!(llm.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        // Rate limits pass, so they're retried
+        let llm = Arc::new(RefusingLLM {
+            status: 429,
+            calls: Default::default(),
+        });
+        let handler = LLMHandler::new(llm.clone());
+        for _ in 0..2 {
+            handler.generate_answer("What is Rust?", &[chunk()], &options).await.unwrap_err();
+        }
+        assert_eq!(llm.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Asks for the calculator once, then answers with its output
+    struct CalculatingLLM;
+
+    #[async_trait]
+    impl LLMProvider for CalculatingLLM {
+        fn name(&self) -> &'static str {
+            "calculating"
+        }
+
+        fn model(&self) -> &str {
+            "calc"
+        }
+
+        async fn generate(&self, _request: &GenerationRequest) -> Result<String> {
+            Ok("no tools".to_string())
+        }
+
+        fn supports_tools(&self) -> bool {
+            true
+        }
+
+        async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+            if request.tools.is_empty() {
+                return Ok(Generation {
+                    content: self.generate(request).await?,
+                    ..Default::default()
+                });
+            }
+            assert!(request.tools.iter().any(|t| t.name == "calculator"));
+            match request.messages.iter().find(|m| m.role == "tool") {
+                Some(result) => Ok(Generation {
+                    content: format!("The total is {} [1].", result.content),
+                    tool_calls: Vec::new(),
+                    usage: Some(TokenUsage {
+                        prompt_tokens: 40,
+                        completion_tokens: 8,
+                    }),
+                }),
+                None => Ok(Generation {
+                    content: String::new(),
+                    tool_calls: vec![ToolCall {
+                        id: "call_1".to_string(),
+                        kind: "function".to_string(),
+                        function: FunctionCall {
+                            name: "calculator".to_string(),
+                            arguments: r#"{"expression": "19 * 3"}"#.to_string(),
+                        },
+                    }],
+                    usage: None,
+                }),
+            }
+        }
+
+        async fn stream(&self, _request: &GenerationRequest) -> Result<TokenStream> {
+            Ok(stream::empty().boxed())
+        }
+
+        fn model_info(&self) -> serde_json::Value {
+            json!({})
+        }
+
+        async fn list_models(&self) -> Result<Vec<LLMModel>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_loop_until_answer() {
+        let mut tools = ToolRegistry::default();
+        tools.register(Arc::new(Calculator));
+        let handler = LLMHandler::new(Arc::new(CalculatingLLM)).with_tools(tools);
+
+        let with_tools = AnswerOptions {
+            use_tools: true,
+            ..Default::default()
+        };
+        let response = handler.generate_answer("Total?", &[chunk()], &with_tools).await.unwrap();
+        assert_eq!(response["answer"], "The total is 57 [1].");
+        assert_eq!(response["tool_calls"][0]["name"], "calculator");
+        assert_eq!(response["tool_calls"][0]["output"], "57");
+        assert_eq!(response["citations"][0]["marker"], 1);
+
+        let without = handler
+            .generate_answer("Total?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(without["answer"], "no tools");
+
+        // Two tool-loop completions plus the plain one, which reported no usage
+        let usage = handler.usage_report(None);
+        assert_eq!(usage["totals"]["requests"], 3);
+        assert_eq!(usage["totals"]["estimated_requests"], 2);
+        assert_eq!(usage["by_model"][0]["model"], "calc");
+    }
+
+    #[tokio::test]
+    async fn test_answers_carry_groundedness() {
+        let handler = LLMHandler::new(Arc::new(CalculatingLLM));
+        let response = handler
+            .generate_answer("Total?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response["claims"][0]["text"], "no tools");
+        assert_eq!(response["claims"][0]["supported"], false);
+        assert_eq!(response["confidence"], 0.0);
+
+        let handler = LLMHandler::new(Arc::new(CalculatingLLM)).with_verification(VerificationMode::Off);
+        let response = handler
+            .generate_answer("Total?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert!(response["confidence"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_map_reduce_and_refine_read_every_chunk() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first"))).with_verification(VerificationMode::Off);
+        let mut second = chunk();
+        second.chunk_id = 1;
+        second.text = "Rust has no garbage collector.".to_string();
+        let chunks = [chunk(), second];
+
+        let map_reduce = AnswerOptions {
+            strategy: SynthesisStrategy::MapReduce,
+            ..Default::default()
+        };
+        let response = handler.generate_answer("What is Rust?", &chunks, &map_reduce).await.unwrap();
+        assert_eq!(response["context_used"], "[1] first:echo-default\n\n[2] first:echo-default");
+        assert_eq!(response["strategy"], "map_reduce");
+
+        let refine = AnswerOptions {
+            strategy: SynthesisStrategy::Refine,
+            ..Default::default()
+        };
+        handler.generate_answer("What is Rust?", &chunks, &refine).await.unwrap();
+
+        // Two map calls and a reduce, then an answer and one refinement
+        assert_eq!(handler.usage_report(None)["totals"]["requests"], 5);
+    }
+
+    #[tokio::test]
+    async fn test_summarize_document_cites_sections() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let chunks: Vec<DocumentChunk> = (0..3)
+            .map(|chunk_id| DocumentChunk {
+                text: "Rust is a systems language. ".repeat(100).into(),
+                size: 2800,
+                chunk_id,
+                parent_id: None,
+                location: Default::default(),
+            })
+            .collect();
+
+        let response = handler
+            .summarize_document("rust.txt", &chunks, SummaryStyle::Brief, &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response["summary"], "first:echo-default");
+        assert_eq!(response["style"], "brief");
+        assert_eq!(response["sources"][0]["chunk_ids"], json!([0, 1]));
+        assert_eq!(response["sources"][1]["chunk_ids"], json!([2]));
+        assert_eq!(handler.usage_report(None)["totals"]["requests"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_answer_finishes_with_citations() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let (tokens, pending) = handler
+            .stream_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(pending.sources().len(), 1);
+
+        let answer: Vec<String> = tokens.map(|token| token.unwrap()).collect().await;
+        let response = handler.finish_answer(pending, &format!("{} [1]", answer.concat())).await;
+        assert_eq!(response["answer"], "first:echo-default [1]");
+        assert_eq!(response["citations"][0]["marker"], 1);
+        assert_eq!(handler.usage_report(None)["totals"]["estimated_requests"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_small_talk_skips_context() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+
+        let greeting = handler
+            .generate_answer("Hello there!", &[], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(greeting["intent"], "greeting");
+        assert_eq!(greeting["answer"], "first:echo-default");
+        assert_eq!(greeting["num_sources"], 0);
+
+        let help = handler.generate_answer("/help", &[chunk()], &AnswerOptions::default()).await.unwrap();
+        assert_eq!(help["intent"], "command");
+        assert_eq!(handler.usage_report(None)["totals"]["requests"], 1);
+
+        let routed_off = LLMHandler::new(Arc::new(EchoLLM("first"))).with_intent_routing(false);
+        assert_eq!(routed_off.classify("hi")["needs_retrieval"], true);
+        let question = routed_off.generate_answer("hi", &[chunk()], &AnswerOptions::default()).await.unwrap();
+        assert_eq!(question["num_sources"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_low_scores_skip_the_llm() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first"))).with_answer_score_floor(0.95);
+        let weak = SearchResult {
+            similarity_score: 0.4,
+            ..chunk()
+        };
+
+        let response = handler
+            .generate_answer("What is Rust?", &[weak, chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response["answer"], NO_ANSWER);
+        assert_eq!(response["no_answer"]["top_scores"][0]["similarity_score"], 0.9f32);
+        assert_eq!(response["no_answer"]["top_scores"].as_array().unwrap().len(), 2);
+        assert_eq!(handler.usage_report(None)["totals"]["requests"], 0);
+
+        assert!(!handler.below_score_floor("Hello there!", &[chunk()]));
+        let handler = handler.with_answer_score_floor(0.5);
+        let answered = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(answered["answer"], "first:echo-default");
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_questions_need_context() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let options = AnswerOptions {
+            follow_ups: true,
+            ..Default::default()
+        };
+
+        let answered = handler.generate_answer("What is Rust?", &[chunk()], &options).await.unwrap();
+        assert_eq!(answered["follow_up_questions"], json!(["first:echo-default"]));
+
+        let greeting = handler.generate_answer("Hello there!", &[], &options).await.unwrap();
+        assert_eq!(greeting["follow_up_questions"], json!([]));
+
+        let plain = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert!(plain.get("follow_up_questions").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compare_groups_sources_by_document() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let other = SearchResult {
+            file_path: "/tmp/go.txt".to_string(),
+            file_name: "go.txt".to_string(),
+            ..chunk()
+        };
+        let documents = vec![
+            ("rust.txt".to_string(), vec![chunk()]),
+            ("go.txt".to_string(), vec![other.clone(), SearchResult { chunk_id: 1, ..other }]),
+        ];
+
+        let response = handler
+            .compare_documents("Which is faster?", &documents, &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response["answer"], "first:echo-default");
+        assert_eq!(response["documents"][1]["file_name"], "go.txt");
+        assert_eq!(response["documents"][1]["sources"].as_array().unwrap().len(), 2);
+        assert_eq!(response["num_sources"], 3);
+        assert!(response["context_used"].as_str().unwrap().contains("Document: go.txt\n[2]"));
+    }
+
+    #[tokio::test]
+    async fn test_steps_are_answered_then_combined() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("combined")));
+        let other = SearchResult {
+            file_path: "/tmp/go.txt".to_string(),
+            file_name: "go.txt".to_string(),
+            text: "Go compiles quickly and ships a garbage collector.".to_string(),
+            ..chunk()
+        };
+        let steps = vec![
+            ("How fast is Rust?".to_string(), vec![chunk()]),
+            ("How fast is Go?".to_string(), vec![other, chunk()]),
+        ];
+
+        let response = handler
+            .answer_in_steps("Is Rust faster than Go?", &steps, &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(response["answer"], "combined:echo-default");
+        assert_eq!(response["steps"][1]["question"], "How fast is Go?");
+        assert_eq!(response["steps"][1]["num_chunks"], 2);
+        assert_eq!(response["num_sources"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_hyde_searches_with_draft() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let options = AnswerOptions::default();
+        assert_eq!(handler.search_text("PTO?", &options).await.unwrap(), "PTO?");
+
+        let handler = handler.with_retrieval_mode(RetrievalMode::Hyde);
+        assert_eq!(handler.search_text("PTO?", &options).await.unwrap(), "PTO?\n\nfirst:echo-default");
+
+        let standard = AnswerOptions {
+            retrieval: Some(RetrievalMode::Standard),
+            ..Default::default()
+        };
+        assert_eq!(handler.search_text("PTO?", &standard).await.unwrap(), "PTO?");
+    }
+
+    #[tokio::test]
+    async fn test_follow_ups_are_rewritten_with_history() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let mut options = AnswerOptions::default();
+        assert_eq!(handler.standalone_query("and Pro?", &options).await.unwrap(), "and Pro?");
+
+        options.history = vec![
+            ChatMessage::user("How much is the Basic plan?"),
+            ChatMessage::assistant("$10 per month [1]"),
+        ];
+        assert_eq!(handler.standalone_query("and Pro?", &options).await.unwrap(), "first:echo-default");
+
+        let handler = handler.with_query_rewriting(false);
+        assert_eq!(handler.standalone_query("and Pro?", &options).await.unwrap(), "and Pro?");
+    }
+
+    #[test]
+    fn test_sampling_options_from_request() {
+        let request: crate::models::AnswerRequest = serde_json::from_value(json!({
+            "query": "What is Rust?",
+            "retrieved_chunks": [],
+            "temperature": 0.3,
+            "top_p": 0.9,
+            "stop": ["\n\n"],
+            "seed": 7
+        }))
+        .unwrap();
+        let options = AnswerOptions::from(&request);
+        assert_eq!(options.sampling.top_p, Some(0.9));
+        assert_eq!(options.sampling.seed, Some(7));
+        assert!(options.validate().is_ok());
+
+        let invalid = |sampling: SamplingParams| AnswerOptions {
+            sampling,
+            ..Default::default()
+        };
+        assert!(invalid(SamplingParams { top_p: Some(0.0), ..Default::default() }).validate().is_err());
+        assert!(invalid(SamplingParams { presence_penalty: Some(2.5), ..Default::default() }).validate().is_err());
+        assert!(invalid(SamplingParams { stop: vec!["x".to_string(); 5], ..Default::default() }).validate().is_err());
+        assert!(AnswerOptions { temperature: 3.0, ..Default::default() }.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_moderation_blocks_queries_and_redacts_answers() {
+        let rules = crate::services::moderation::parse_rules("block: \\bbomb\\b\nredact model: echo").unwrap();
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first"))).with_moderation(Moderator::new(rules, None));
+
+        let blocked = handler
+            .generate_answer("How do I make a bomb?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(blocked["answer"], BLOCKED_QUERY);
+        assert_eq!(blocked["moderation"]["query"]["action"], "block");
+        assert_eq!(handler.usage_report(None)["totals"]["requests"], 0);
+
+        let redacted = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(redacted["answer"], "first:[redacted]-default");
+        assert_eq!(redacted["moderation"]["query"]["action"], "allow");
+        assert_eq!(redacted["moderation"]["answer"]["categories"], json!(["model"]));
+    }
+
+    /// Hallucinates until told which claims were unsupported
+    struct CorrectableLLM;
+
+    #[async_trait]
+    impl LLMProvider for CorrectableLLM {
+        fn name(&self) -> &'static str {
+            "correctable"
+        }
+
+        fn model(&self) -> &str {
+            "correctable"
+        }
+
+        async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+            let last = request.messages.last().map(|m| m.content.as_str()).unwrap_or_default();
+            if last.contains(INSUFFICIENT_INFORMATION) {
+                Ok("Rust is a systems language [1].".to_string())
+            } else {
+                Ok("Rust was invented on Mars.".to_string())
+            }
+        }
+
+        async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+            let answer = self.generate(request).await?;
+            Ok(stream::once(async move { Ok(answer) }).boxed())
+        }
+
+        fn model_info(&self) -> serde_json::Value {
+            json!({})
+        }
+
+        async fn list_models(&self) -> Result<Vec<LLMModel>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_regenerates_or_refuses() {
+        let handler = LLMHandler::new(Arc::new(CorrectableLLM)).with_verification(VerificationMode::Off);
+
+        let lenient = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(lenient["answer"], "Rust was invented on Mars.");
+        assert!(lenient["guard"].is_null());
+
+        let regenerate = AnswerOptions {
+            strict_mode: Some(StrictMode::Regenerate),
+            ..Default::default()
+        };
+        let corrected = handler.generate_answer("What is Rust?", &[chunk()], &regenerate).await.unwrap();
+        assert_eq!(corrected["answer"], "Rust is a systems language [1].");
+        assert_eq!(corrected["guard"]["action"], "regenerated");
+        assert_eq!(corrected["guard"]["unsupported_claims"][0], "Rust was invented on Mars.");
+
+        let handler = handler.with_strict_mode(StrictMode::Refuse);
+        let refused = handler
+            .generate_answer("What is Rust?", &[chunk()], &AnswerOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(refused["answer"], INSUFFICIENT_INFORMATION);
+        assert_eq!(refused["guard"]["action"], "refused");
+        assert_eq!(refused["citations"], json!([]));
+    }
+
+    #[test]
+    fn test_scope_is_stated_in_system_prompt() {
+        let handler = LLMHandler::new(Arc::new(EchoLLM("first")));
+        let options = AnswerOptions {
+            scope: vec!["contract.pdf".to_string()],
+            ..Default::default()
+        };
+        assert!(handler
+            .answer_system_prompt(&options, None)
+            .ends_with("Answer using only contract.pdf. If it does not contain the answer, say so instead of drawing on other sources or general knowledge."));
+
+        let names = ["a.pdf", "b.pdf", "c.pdf"].map(String::from);
+        assert!(scope_instruction(&names).starts_with("Answer using only a.pdf, b.pdf and c.pdf. If they do"));
+        assert!(!handler.answer_system_prompt(&AnswerOptions::default(), None).contains("Answer using only"));
+    }
+
+    #[test]
+    fn test_history_placement() {
+        let history = vec![
+            ChatMessage::user("What is Rust?"),
+            ChatMessage {
+                role: "assistant".to_string(),
+                content: "A systems language.".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let messages = build_messages("sys", None, &[], &history, "Is it fast?", "ctx");
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[2].role, "assistant");
+        assert!(messages[3].content.contains("Is it fast?"));
+
+        let examples = [FewShotExample {
+            question: "Is it safe?".to_string(),
+            answer: "Yes, memory-safe by default [1].".to_string(),
+        }];
+        let messages = build_messages("sys", None, &examples, &history, "Is it fast?", "ctx");
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[1].content, "Is it safe?");
+        assert_eq!(messages[3].content, "What is Rust?");
+
+        let template = PromptTemplate {
+            name: "chat".to_string(),
+            template: "{history}\n---\n{context}\nuser: {query}".to_string(),
+        };
+        let messages = build_messages("sys", Some(&template), &[], &history, "Is it fast?", "ctx");
+        assert_eq!(messages.len(), 2);
+        assert!(messages[1].content.starts_with(
+            "user: What is Rust?\nassistant: A systems language.\n---\nctx\nuser: Is it fast?"
+        ));
+        assert!(messages[1].content.ends_with(CITATION_INSTRUCTION));
+    }
+}
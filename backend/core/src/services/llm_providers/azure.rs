@@ -0,0 +1,192 @@
+use super::{
+    error_for_status, json_event_stream, openai_delta_content, openai_generation, openai_message_content,
+    openai_sampling, openai_tools, Generation, GenerationRequest, HttpTimeouts, LLMProvider, TokenStream,
+};
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+/// How requests to Azure OpenAI are authenticated
+#[derive(Clone)]
+pub enum AzureAuth {
+    /// Resource key sent in the `api-key` header
+    ApiKey(String),
+    /// Pre-issued Azure AD bearer token
+    AadToken(String),
+    /// Azure AD app registration; tokens are fetched and refreshed on demand
+    ClientCredentials {
+        tenant_id: String,
+        client_id: String,
+        client_secret: String,
+    },
+}
+
+/// Azure OpenAI, addressed by deployment name and API version
+#[derive(Clone)]
+pub struct AzureOpenAILLM {
+    endpoint: String,
+    deployment: String,
+    api_version: String,
+    auth: AzureAuth,
+    timeouts: HttpTimeouts,
+    client: reqwest::Client,
+    /// Cached AAD token and its expiry for client-credentials auth
+    token_cache: std::sync::Arc<std::sync::Mutex<Option<(String, std::time::Instant)>>>,
+}
+
+impl AzureOpenAILLM {
+    pub fn new(endpoint: String, deployment: String, api_version: String, auth: AzureAuth) -> Result<Self> {
+        if endpoint.is_empty() || deployment.is_empty() {
+            return Err(anyhow!(
+                "Azure OpenAI requires AZURE_OPENAI_ENDPOINT and AZURE_OPENAI_DEPLOYMENT."
+            ));
+        }
+
+        Ok(AzureOpenAILLM {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            deployment,
+            api_version,
+            auth,
+            timeouts: HttpTimeouts::default(),
+            client: HttpTimeouts::default().client(),
+            token_cache: std::sync::Arc::new(std::sync::Mutex::new(None)),
+        })
+    }
+
+    /// Connect and read timeouts for calls to Azure OpenAI and Azure AD
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.client();
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send requests through `client`, typically one shared with the other
+    /// providers; call after `with_timeouts`, which builds a client of its own
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fetch (or reuse) an AAD token for the Cognitive Services scope
+    async fn aad_token(&self, tenant_id: &str, client_id: &str, client_secret: &str) -> Result<String> {
+        if let Some((token, expires_at)) = self.token_cache.lock().unwrap().as_ref() {
+            if std::time::Instant::now() < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let response = self
+            .client
+            .post(format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", tenant_id))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", client_id),
+                ("client_secret", client_secret),
+                ("scope", "https://cognitiveservices.azure.com/.default"),
+            ])
+            .timeout(self.timeouts.read)
+            .send()
+            .await?;
+
+        let result: serde_json::Value = error_for_status(response, "Azure AD token").await?.json().await?;
+        let token = result["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Azure AD token response missing access_token"))?
+            .to_string();
+        let expires_in = result["expires_in"].as_u64().unwrap_or(3600);
+
+        // Refresh a minute early to avoid using a token as it expires
+        let expires_at = std::time::Instant::now()
+            + std::time::Duration::from_secs(expires_in.saturating_sub(60));
+        *self.token_cache.lock().unwrap() = Some((token.clone(), expires_at));
+
+        Ok(token)
+    }
+
+    async fn send(&self, request: &GenerationRequest, stream: bool) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "messages": request.messages,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "top_p": 1.0,
+            "stream": stream
+        });
+        openai_sampling(&mut body, &request.sampling);
+        if !request.tools.is_empty() {
+            body["tools"] = openai_tools(&request.tools);
+        }
+
+        // A requested model names another deployment on the same resource
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint,
+            request.model_or(&self.deployment),
+            self.api_version
+        );
+
+        let http_request = self.client.post(url).json(&body);
+        let http_request = match &self.auth {
+            AzureAuth::ApiKey(key) => http_request.header("api-key", key),
+            AzureAuth::AadToken(token) => http_request.bearer_auth(token),
+            AzureAuth::ClientCredentials {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => http_request.bearer_auth(self.aad_token(tenant_id, client_id, client_secret).await?),
+        };
+
+        let response = self.timeouts.apply(http_request, stream).send().await?;
+        error_for_status(response, "Azure OpenAI").await
+    }
+}
+
+#[async_trait]
+impl LLMProvider for AzureOpenAILLM {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn model(&self) -> &str {
+        &self.deployment
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_message_content(&result)
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_generation(&result)
+    }
+
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+        let response = self.timeouts.started("Azure OpenAI", self.send(request, true)).await?;
+        Ok(json_event_stream(response, "Azure OpenAI", self.timeouts.read, true, openai_delta_content))
+    }
+
+    fn model_info(&self) -> serde_json::Value {
+        json!({
+            "provider": "azure",
+            "model": self.deployment,
+            "endpoint": self.endpoint,
+            "api_version": self.api_version,
+            "supports_streaming": true,
+            "max_tokens": 8192
+        })
+    }
+
+    /// Azure serves exactly the configured deployment
+    async fn list_models(&self) -> Result<Vec<LLMModel>> {
+        Ok(vec![LLMModel {
+            id: self.deployment.clone(),
+            name: format!("Azure OpenAI deployment {}", self.deployment),
+            max_tokens: 8192,
+        }])
+    }
+}
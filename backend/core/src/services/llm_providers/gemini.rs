@@ -0,0 +1,317 @@
+use super::{
+    error_for_status, json_event_stream, sampling_fields, token_usage, Generation, GenerationRequest, HttpTimeouts, LLMProvider,
+    TokenStream,
+};
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Gemini harm categories, keyed by the short names accepted in config
+const GEMINI_HARM_CATEGORIES: &[(&str, &str)] = &[
+    ("harassment", "HARM_CATEGORY_HARASSMENT"),
+    ("hate_speech", "HARM_CATEGORY_HATE_SPEECH"),
+    ("sexually_explicit", "HARM_CATEGORY_SEXUALLY_EXPLICIT"),
+    ("dangerous_content", "HARM_CATEGORY_DANGEROUS_CONTENT"),
+];
+
+/// Map a blocking level (`none`, `low`, `medium`, `high`) to Gemini's
+/// threshold name. `low` blocks the most content, `none` blocks nothing.
+fn gemini_threshold(level: &str) -> Result<&'static str> {
+    match level.trim().to_lowercase().as_str() {
+        "none" | "off" => Ok("BLOCK_NONE"),
+        "low" => Ok("BLOCK_LOW_AND_ABOVE"),
+        "medium" => Ok("BLOCK_MEDIUM_AND_ABOVE"),
+        "high" => Ok("BLOCK_ONLY_HIGH"),
+        other => Err(anyhow!("Unknown Gemini safety level: {}", other)),
+    }
+}
+
+/// Build Gemini `safetySettings` from a default level applied to every harm
+/// category plus comma-separated `<category>=<level>` overrides, e.g.
+/// `harassment=low,dangerous_content=high`.
+pub fn gemini_safety_settings(default_level: &str, overrides: &str) -> Result<serde_json::Value> {
+    let default_threshold = gemini_threshold(default_level)?;
+    let mut thresholds: HashMap<&str, &str> = GEMINI_HARM_CATEGORIES
+        .iter()
+        .map(|(_, category)| (*category, default_threshold))
+        .collect();
+
+    for entry in overrides.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, level) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected <category>=<level> in '{}'", entry))?;
+        let category = GEMINI_HARM_CATEGORIES
+            .iter()
+            .find(|(short, _)| *short == name.trim().to_lowercase())
+            .map(|(_, category)| *category)
+            .ok_or_else(|| anyhow!("Unknown Gemini harm category: {}", name))?;
+        thresholds.insert(category, gemini_threshold(level)?);
+    }
+
+    let settings: Vec<serde_json::Value> = GEMINI_HARM_CATEGORIES
+        .iter()
+        .map(|(_, category)| json!({"category": category, "threshold": thresholds[category]}))
+        .collect();
+
+    Ok(json!(settings))
+}
+
+/// Concatenated text parts of a Gemini response candidate
+fn candidate_text(event: &serde_json::Value) -> Option<String> {
+    event["candidates"][0]["content"]["parts"].as_array().map(|parts| {
+        parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<String>()
+    })
+}
+
+/// Google Gemini via the generateContent API
+#[derive(Clone)]
+pub struct GeminiLLM {
+    api_key: String,
+    model: String,
+    safety_settings: serde_json::Value,
+    timeouts: HttpTimeouts,
+    client: reqwest::Client,
+}
+
+impl GeminiLLM {
+    const BASE_URL: &'static str = "https://generativelanguage.googleapis.com/v1beta";
+
+    pub fn new(api_key: String, model: String, safety_settings: serde_json::Value) -> Result<Self> {
+        if api_key.is_empty() {
+            return Err(anyhow!(
+                "Gemini API key required. Set GEMINI_API_KEY environment variable."
+            ));
+        }
+
+        Ok(GeminiLLM {
+            api_key,
+            model,
+            safety_settings,
+            timeouts: HttpTimeouts::default(),
+            client: HttpTimeouts::default().client(),
+        })
+    }
+
+    /// Connect and read timeouts for calls to Gemini
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.client();
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send requests through `client`, typically one shared with the other
+    /// providers; call after `with_timeouts`, which builds a client of its own
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Gemini takes system messages as `systemInstruction` and calls the
+    /// assistant role `model`
+    fn request_body(&self, request: &GenerationRequest) -> serde_json::Value {
+        let system: Vec<serde_json::Value> = request
+            .messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| json!({"text": m.content}))
+            .collect();
+
+        let contents: Vec<serde_json::Value> = request
+            .messages
+            .iter()
+            .filter(|m| m.role != "system")
+            .map(|m| {
+                let role = if m.role == "assistant" { "model" } else { "user" };
+                json!({"role": role, "parts": [{"text": m.content}]})
+            })
+            .collect();
+
+        let mut body = json!({
+            "contents": contents,
+            "generationConfig": {
+                "temperature": request.temperature,
+                "maxOutputTokens": request.max_tokens,
+                "topP": 1.0
+            },
+            "safetySettings": self.safety_settings
+        });
+        for (name, value) in sampling_fields(&request.sampling) {
+            let name = match name {
+                "top_p" => "topP",
+                "top_k" => "topK",
+                "stop" => "stopSequences",
+                "frequency_penalty" => "frequencyPenalty",
+                "presence_penalty" => "presencePenalty",
+                other => other,
+            };
+            body["generationConfig"][name] = value;
+        }
+        if !system.is_empty() {
+            body["systemInstruction"] = json!({"parts": system});
+        }
+
+        body
+    }
+
+    async fn send(&self, request: &GenerationRequest, method: &str) -> Result<reqwest::Response> {
+        let http_request = self
+            .client
+            .post(format!("{}/models/{}:{}", Self::BASE_URL, request.model_or(&self.model), method))
+            .header("x-goog-api-key", &self.api_key)
+            .json(&self.request_body(request));
+        let stream = method.starts_with("streamGenerateContent");
+        let response = self.timeouts.apply(http_request, stream).send().await?;
+
+        error_for_status(response, "Gemini").await
+    }
+}
+
+#[async_trait]
+impl LLMProvider for GeminiLLM {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+        Ok(self.complete(request).await?.content)
+    }
+
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        let result: serde_json::Value = self.send(request, "generateContent").await?.json().await?;
+
+        if let Some(reason) = result["promptFeedback"]["blockReason"].as_str() {
+            return Err(anyhow!("Gemini blocked the prompt: {}", reason));
+        }
+
+        if result["candidates"][0]["finishReason"].as_str() == Some("SAFETY") {
+            return Err(anyhow!("Gemini blocked the response due to safety settings"));
+        }
+
+        let answer = candidate_text(&result)
+            .filter(|text| !text.trim().is_empty())
+            .ok_or_else(|| anyhow!("No response from LLM"))?
+            .trim()
+            .to_string();
+
+        Ok(Generation {
+            content: answer,
+            tool_calls: Vec::new(),
+            usage: token_usage(&result["usageMetadata"], "promptTokenCount", "candidatesTokenCount"),
+        })
+    }
+
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+        let response = self
+            .timeouts
+            .started("Gemini", self.send(request, "streamGenerateContent?alt=sse"))
+            .await?;
+        Ok(json_event_stream(response, "Gemini", self.timeouts.read, true, candidate_text))
+    }
+
+    fn model_info(&self) -> serde_json::Value {
+        json!({
+            "provider": "gemini",
+            "model": self.model,
+            "supports_streaming": true,
+            "max_tokens": 8192,
+            "safety_settings": self.safety_settings
+        })
+    }
+
+    /// Gemini models that support generateContent
+    async fn list_models(&self) -> Result<Vec<LLMModel>> {
+        let response = self
+            .client
+            .get(format!("{}/models", Self::BASE_URL))
+            .header("x-goog-api-key", &self.api_key)
+            .timeout(self.timeouts.read)
+            .send()
+            .await?;
+
+        let result: serde_json::Value = error_for_status(response, "Gemini").await?.json().await?;
+
+        let models = result["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter(|m| {
+                        m["supportedGenerationMethods"]
+                            .as_array()
+                            .is_some_and(|methods| methods.iter().any(|v| v == "generateContent"))
+                    })
+                    .filter_map(|m| {
+                        let id = m["name"].as_str()?.trim_start_matches("models/").to_string();
+                        Some(LLMModel {
+                            name: m["displayName"].as_str().unwrap_or(&id).to_string(),
+                            max_tokens: m["outputTokenLimit"].as_u64().unwrap_or(8192) as usize,
+                            id,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::llm_providers::{ChatMessage, SamplingParams, SYSTEM_PROMPT};
+
+    #[test]
+    fn test_gemini_safety_settings_mapping() {
+        let settings = gemini_safety_settings("medium", "harassment=low, dangerous_content=none").unwrap();
+        let threshold = |category: &str| {
+            settings
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|s| s["category"] == category)
+                .map(|s| s["threshold"].as_str().unwrap().to_string())
+                .unwrap()
+        };
+
+        assert_eq!(threshold("HARM_CATEGORY_HARASSMENT"), "BLOCK_LOW_AND_ABOVE");
+        assert_eq!(threshold("HARM_CATEGORY_DANGEROUS_CONTENT"), "BLOCK_NONE");
+        assert_eq!(threshold("HARM_CATEGORY_HATE_SPEECH"), "BLOCK_MEDIUM_AND_ABOVE");
+        assert!(gemini_safety_settings("medium", "violence=low").is_err());
+        assert!(gemini_safety_settings("extreme", "").is_err());
+    }
+
+    #[test]
+    fn test_gemini_request_body_maps_roles() {
+        let llm = GeminiLLM::new("key".to_string(), "gemini-1.5-flash".to_string(), json!([])).unwrap();
+        let request = GenerationRequest {
+            messages: vec![ChatMessage::system(SYSTEM_PROMPT), ChatMessage::user("What is Rust?")],
+            model: None,
+            max_tokens: 100,
+            temperature: 0.2,
+            sampling: SamplingParams {
+                top_k: Some(40),
+                stop: vec!["END".to_string()],
+                ..Default::default()
+            },
+            tools: Vec::new(),
+        };
+
+        let body = llm.request_body(&request);
+        assert_eq!(body["generationConfig"]["topP"], 1.0);
+        assert_eq!(body["generationConfig"]["topK"], 40);
+        assert_eq!(body["generationConfig"]["stopSequences"], json!(["END"]));
+        assert!(body["systemInstruction"]["parts"][0]["text"].is_string());
+        assert_eq!(body["contents"].as_array().unwrap().len(), 1);
+        assert_eq!(body["contents"][0]["role"], "user");
+    }
+}
@@ -0,0 +1,269 @@
+use super::retry::{retry_after, RetryPolicy};
+use super::{
+    error_for_status, json_event_stream, openai_delta_content, openai_generation, openai_message_content,
+    openai_sampling, openai_tools, Generation, GenerationRequest, HttpTimeouts, LLMProvider, TokenStream,
+};
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Live model list and when it was fetched
+type ModelCatalog = Arc<Mutex<Option<(Instant, Vec<LLMModel>)>>>;
+
+/// Groq model families that are not chat-completion models
+const NON_CHAT_MODEL_MARKERS: &[&str] = &["whisper", "tts", "guard"];
+
+/// Chat-capable, active models from a Groq `/models` response
+fn chat_models(result: &serde_json::Value) -> Vec<LLMModel> {
+    let mut models: Vec<LLMModel> = result["data"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter(|m| m["active"].as_bool().unwrap_or(true))
+                .filter_map(|m| {
+                    let id = m["id"].as_str()?;
+                    let lower = id.to_lowercase();
+                    if NON_CHAT_MODEL_MARKERS.iter().any(|marker| lower.contains(marker)) {
+                        return None;
+                    }
+                    let max_tokens = m["max_completion_tokens"]
+                        .as_u64()
+                        .or_else(|| m["context_window"].as_u64())
+                        .unwrap_or(8192);
+                    Some(LLMModel {
+                        id: id.to_string(),
+                        name: id.to_string(),
+                        max_tokens: max_tokens as usize,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models
+}
+
+#[derive(Clone)]
+pub struct GroqLLM {
+    api_key: String,
+    model: String,
+    retry_policy: RetryPolicy,
+    timeouts: HttpTimeouts,
+    client: reqwest::Client,
+    model_catalog: ModelCatalog,
+}
+
+impl GroqLLM {
+    const CHAT_URL: &'static str = "https://api.groq.com/openai/v1/chat/completions";
+    const MODELS_URL: &'static str = "https://api.groq.com/openai/v1/models";
+    /// How long a fetched model list is reused
+    const CATALOG_TTL: Duration = Duration::from_secs(3600);
+
+    pub fn new(api_key: String, model: String) -> Result<Self> {
+        if api_key.is_empty() {
+            return Err(anyhow!(
+                "Groq API key required. Set GROQ_API_KEY environment variable."
+            ));
+        }
+
+        let model = if model.trim().is_empty() {
+            "openai/gpt-oss-120b".to_string()
+        } else {
+            model
+        };
+
+        Ok(GroqLLM {
+            api_key,
+            model,
+            retry_policy: RetryPolicy::default(),
+            timeouts: HttpTimeouts::default(),
+            client: HttpTimeouts::default().client(),
+            model_catalog: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Connect and read timeouts for calls to Groq
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.client();
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send requests through `client`, typically one shared with the other
+    /// providers; call after `with_timeouts`, which builds a client of its own
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    async fn send(&self, request: &GenerationRequest, stream: bool) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "model": request.model_or(&self.model),
+            "messages": request.messages,
+            "temperature": request.temperature,
+            "max_completion_tokens": request.max_tokens,
+            "top_p": 1.0,
+            "stream": stream
+        });
+        openai_sampling(&mut body, &request.sampling);
+        if !request.tools.is_empty() {
+            body["tools"] = openai_tools(&request.tools);
+        }
+
+        let mut attempt = 0;
+        loop {
+            let http_request = self
+                .client
+                .post(Self::CHAT_URL)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&body);
+            let result = self.timeouts.apply(http_request, stream).send().await;
+
+            let retry_delay = match &result {
+                Ok(response) if RetryPolicy::is_retryable_status(response.status()) => {
+                    Some(self.retry_policy.delay(attempt, retry_after(response)))
+                }
+                Err(e) if RetryPolicy::is_retryable_error(e) => Some(self.retry_policy.delay(attempt, None)),
+                _ => None,
+            };
+
+            match retry_delay {
+                Some(delay) if attempt < self.retry_policy.max_retries => {
+                    log::warn!(
+                        "Groq request failed ({}), retrying in {:?} (attempt {}/{})",
+                        match &result {
+                            Ok(response) => response.status().to_string(),
+                            Err(e) => e.to_string(),
+                        },
+                        delay,
+                        attempt + 1,
+                        self.retry_policy.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return error_for_status(result?, "Groq").await,
+            }
+        }
+    }
+}
+
+impl GroqLLM {
+    async fn fetch_models(&self) -> Result<Vec<LLMModel>> {
+        let response = self
+            .client
+            .get(Self::MODELS_URL)
+            .bearer_auth(&self.api_key)
+            .timeout(self.timeouts.read)
+            .send()
+            .await?;
+
+        let result: serde_json::Value = error_for_status(response, "Groq").await?.json().await?;
+        let models = chat_models(&result);
+        if models.is_empty() {
+            return Err(anyhow!("Groq returned no chat models"));
+        }
+
+        Ok(models)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for GroqLLM {
+    fn name(&self) -> &'static str {
+        "groq"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_message_content(&result)
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_generation(&result)
+    }
+
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+        let response = self.timeouts.started("Groq", self.send(request, true)).await?;
+        Ok(json_event_stream(response, "Groq", self.timeouts.read, true, openai_delta_content))
+    }
+
+    fn model_info(&self) -> serde_json::Value {
+        json!({
+            "provider": "groq",
+            "model": self.model,
+            "supports_streaming": true,
+            "max_tokens": 8192
+        })
+    }
+
+    /// Live chat models, cached for an hour. If Groq can't be reached the
+    /// last fetched list is served, or the built-in list before any fetch.
+    async fn list_models(&self) -> Result<Vec<LLMModel>> {
+        let cached = self.model_catalog.lock().unwrap().clone();
+        if let Some((fetched_at, models)) = &cached {
+            if fetched_at.elapsed() < Self::CATALOG_TTL {
+                return Ok(models.clone());
+            }
+        }
+
+        match self.fetch_models().await {
+            Ok(models) => {
+                *self.model_catalog.lock().unwrap() = Some((Instant::now(), models.clone()));
+                Ok(models)
+            }
+            Err(e) => {
+                log::warn!("Failed to fetch Groq model catalog: {}", e);
+                Ok(cached
+                    .map(|(_, models)| models)
+                    .unwrap_or_else(crate::models::get_supported_models))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_models_filters_catalog() {
+        let result = json!({
+            "object": "list",
+            "data": [
+                {"id": "llama-3.3-70b-versatile", "active": true, "context_window": 131072, "max_completion_tokens": 32768},
+                {"id": "whisper-large-v3", "active": true, "context_window": 448},
+                {"id": "meta-llama/llama-guard-4-12b", "active": true, "context_window": 131072},
+                {"id": "playai-tts", "active": true, "context_window": 8192},
+                {"id": "llama-3.1-70b-versatile", "active": false, "context_window": 131072},
+                {"id": "openai/gpt-oss-120b", "context_window": 131072}
+            ]
+        });
+
+        let models = chat_models(&result);
+        let ids: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["llama-3.3-70b-versatile", "openai/gpt-oss-120b"]);
+        assert_eq!(models[0].max_tokens, 32768);
+        assert_eq!(models[1].max_tokens, 131072);
+    }
+}
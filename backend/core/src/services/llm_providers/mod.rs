@@ -0,0 +1,353 @@
+mod azure;
+mod gemini;
+mod groq;
+mod ollama;
+mod openai_compatible;
+mod retry;
+mod timeouts;
+
+pub use azure::{AzureAuth, AzureOpenAILLM};
+pub use gemini::{gemini_safety_settings, GeminiLLM};
+pub use groq::GroqLLM;
+pub use ollama::OllamaLLM;
+pub use openai_compatible::OpenAICompatibleLLM;
+pub use retry::RetryPolicy;
+pub use timeouts::{is_timeout, HttpTimeouts, LLMTimeout};
+
+pub use crate::models::{ChatMessage, SamplingParams, ToolCall};
+
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::time::Duration;
+
+/// Incremental answer text produced by a streaming generation
+pub type TokenStream = BoxStream<'static, Result<String>>;
+
+/// Default system prompt, used unless config or the request overrides it
+pub const SYSTEM_PROMPT: &str = "You are an expert AI assistant specializing in document analysis and knowledge extraction.\n\nYour responsibilities:\n- Provide accurate, well-structured answers based solely on the provided context\n- Cite specific information from the context when possible\n- Clearly state when information is insufficient to answer the question\n- Maintain professional, concise communication\n- Focus on factual accuracy over speculation";
+
+/// A tool the model may call, described by a JSON Schema for its arguments
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// Everything a provider needs to produce one completion
+#[derive(Debug, Clone)]
+pub struct GenerationRequest {
+    pub messages: Vec<ChatMessage>,
+    /// Overrides the provider's default model for this request
+    pub model: Option<String>,
+    pub max_tokens: usize,
+    pub temperature: f32,
+    pub sampling: SamplingParams,
+    /// Tools offered to providers that support calling them
+    pub tools: Vec<ToolSpec>,
+}
+
+/// Tokens a provider reported for one completion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// A completion that may ask for tool calls instead of, or besides, text
+#[derive(Debug, Clone, Default)]
+pub struct Generation {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    /// `None` when the provider doesn't report usage
+    pub usage: Option<TokenUsage>,
+}
+
+impl GenerationRequest {
+    pub fn model_or<'a>(&'a self, default: &'a str) -> &'a str {
+        self.model.as_deref().unwrap_or(default)
+    }
+}
+
+/// A chat-completion backend
+#[async_trait]
+pub trait LLMProvider: Send + Sync {
+    /// Short identifier used for per-request selection, e.g. "groq"
+    fn name(&self) -> &'static str;
+
+    /// Model used when a request does not name one
+    fn model(&self) -> &str;
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String>;
+
+    /// Generate incrementally, yielding text as the provider produces it
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream>;
+
+    /// Whether `complete` can return tool calls
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Generate the full completion: text, any tool calls the model makes
+    /// from `request.tools`, and token usage. Providers without tool support
+    /// answer directly; the default reports no usage.
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        Ok(Generation {
+            content: self.generate(request).await?,
+            ..Default::default()
+        })
+    }
+
+    fn model_info(&self) -> serde_json::Value;
+
+    /// Models this provider can serve
+    async fn list_models(&self) -> Result<Vec<LLMModel>>;
+
+    /// Approximate token count; providers without a local tokenizer use the
+    /// common ~4 characters per token estimate
+    fn count_tokens(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+}
+
+/// A provider answered with an error status
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{provider} API error: {message}")]
+pub struct ProviderError {
+    pub provider: String,
+    pub status: u16,
+    pub message: String,
+}
+
+impl ProviderError {
+    /// Whether the same request would be refused the same way if sent
+    /// again, as when it's too long for the model: client errors other
+    /// than rate limits, timeouts, and bad credentials
+    pub fn is_permanent(&self) -> bool {
+        reqwest::StatusCode::from_u16(self.status).is_ok_and(|status| {
+            status.is_client_error()
+                && !RetryPolicy::is_retryable_status(status)
+                && status != reqwest::StatusCode::UNAUTHORIZED
+                && status != reqwest::StatusCode::FORBIDDEN
+        })
+    }
+}
+
+/// Turn a non-success response into an error carrying the provider's message
+async fn error_for_status(response: reqwest::Response, provider: &str) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    Err(ProviderError {
+        provider: provider.to_string(),
+        status: status.as_u16(),
+        message: response.text().await?,
+    }
+    .into())
+}
+
+/// Answer text of an OpenAI-style chat completion
+fn openai_message_content(result: &serde_json::Value) -> Result<String> {
+    Ok(result["choices"][0]["message"]["content"]
+        .as_str()
+        .ok_or_else(|| anyhow!("No response from LLM"))?
+        .trim()
+        .to_string())
+}
+
+/// The sampling settings that are set, under their OpenAI (and Ollama) names
+fn sampling_fields(sampling: &SamplingParams) -> Vec<(&'static str, serde_json::Value)> {
+    let mut fields = Vec::new();
+    if let Some(top_p) = sampling.top_p {
+        fields.push(("top_p", serde_json::json!(top_p)));
+    }
+    if let Some(top_k) = sampling.top_k {
+        fields.push(("top_k", serde_json::json!(top_k)));
+    }
+    if !sampling.stop.is_empty() {
+        fields.push(("stop", serde_json::json!(sampling.stop)));
+    }
+    if let Some(seed) = sampling.seed {
+        fields.push(("seed", serde_json::json!(seed)));
+    }
+    if let Some(penalty) = sampling.frequency_penalty {
+        fields.push(("frequency_penalty", serde_json::json!(penalty)));
+    }
+    if let Some(penalty) = sampling.presence_penalty {
+        fields.push(("presence_penalty", serde_json::json!(penalty)));
+    }
+    fields
+}
+
+/// Sampling settings on an OpenAI-style chat-completions body, which has
+/// no top_k
+fn openai_sampling(body: &mut serde_json::Value, sampling: &SamplingParams) {
+    for (name, value) in sampling_fields(sampling) {
+        if name != "top_k" {
+            body[name] = value;
+        }
+    }
+}
+
+/// `tools` in OpenAI function-calling form
+fn openai_tools(tools: &[ToolSpec]) -> serde_json::Value {
+    tools
+        .iter()
+        .map(|tool| {
+            serde_json::json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters
+                }
+            })
+        })
+        .collect()
+}
+
+/// Text and tool calls of an OpenAI-style chat completion
+fn openai_generation(result: &serde_json::Value) -> Result<Generation> {
+    let message = &result["choices"][0]["message"];
+    if message.is_null() {
+        return Err(anyhow!("No response from LLM"));
+    }
+
+    let tool_calls = match message.get("tool_calls") {
+        Some(calls) if !calls.is_null() => serde_json::from_value(calls.clone())
+            .map_err(|e| anyhow!("Invalid tool calls from LLM: {}", e))?,
+        _ => Vec::new(),
+    };
+
+    Ok(Generation {
+        content: message["content"].as_str().unwrap_or("").trim().to_string(),
+        tool_calls,
+        usage: token_usage(&result["usage"], "prompt_tokens", "completion_tokens"),
+    })
+}
+
+/// Usage from a response's counters, named differently by each provider
+fn token_usage(usage: &serde_json::Value, prompt_field: &str, completion_field: &str) -> Option<TokenUsage> {
+    Some(TokenUsage {
+        prompt_tokens: usage[prompt_field].as_u64()?,
+        completion_tokens: usage[completion_field].as_u64().unwrap_or(0),
+    })
+}
+
+/// Text delta of an OpenAI-style streaming chunk
+fn openai_delta_content(event: &serde_json::Value) -> Option<String> {
+    event["choices"][0]["delta"]["content"].as_str().map(str::to_string)
+}
+
+/// Split a streaming response body into lines, failing with `LLMTimeout`
+/// when no data arrives for `idle`
+fn line_stream(response: reqwest::Response, provider: &'static str, idle: Duration) -> BoxStream<'static, Result<String>> {
+    let bytes = Box::pin(response.bytes_stream());
+
+    stream::unfold((bytes, Vec::new(), false), move |(mut bytes, mut buffer, mut finished)| async move {
+        loop {
+            if let Some(pos) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line).trim_end().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                return Some((Ok(line), (bytes, buffer, finished)));
+            }
+
+            if finished {
+                if buffer.iter().all(u8::is_ascii_whitespace) {
+                    return None;
+                }
+                let line = String::from_utf8_lossy(&buffer).trim_end().to_string();
+                buffer.clear();
+                return Some((Ok(line), (bytes, buffer, finished)));
+            }
+
+            match tokio::time::timeout(idle, bytes.next()).await {
+                Ok(Some(Ok(chunk))) => buffer.extend_from_slice(&chunk),
+                Ok(Some(Err(e))) => return Some((Err(anyhow!("Stream error: {}", e)), (bytes, buffer, true))),
+                Ok(None) => finished = true,
+                Err(_) => return Some((Err(LLMTimeout::new(provider, idle).into()), (bytes, buffer, true))),
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Stream of text extracted from JSON events, either server-sent events
+/// (`data: {...}` lines, ending at `data: [DONE]`) or newline-delimited JSON
+fn json_event_stream(
+    response: reqwest::Response,
+    provider: &'static str,
+    idle: Duration,
+    server_sent_events: bool,
+    extract: fn(&serde_json::Value) -> Option<String>,
+) -> TokenStream {
+    line_stream(response, provider, idle)
+        .map(move |line| -> Result<Option<String>> {
+            let line = line?;
+            let payload = if server_sent_events {
+                match line.strip_prefix("data:") {
+                    Some(data) => data.trim(),
+                    None => return Ok(None),
+                }
+            } else {
+                line.as_str()
+            };
+
+            if payload == "[DONE]" {
+                return Ok(None);
+            }
+
+            let event: serde_json::Value = serde_json::from_str(payload)
+                .map_err(|e| anyhow!("Invalid stream event: {}", e))?;
+            Ok(extract(&event).filter(|text| !text.is_empty()))
+        })
+        .filter_map(|item| async move { item.transpose() })
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openai_generation_parses_tool_calls() {
+        let result = serde_json::json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": {"name": "calculator", "arguments": "{\"expression\": \"2+2\"}"}
+                    }]
+                }
+            }],
+            "usage": {"prompt_tokens": 120, "completion_tokens": 18, "total_tokens": 138}
+        });
+
+        let generation = openai_generation(&result).unwrap();
+        assert!(generation.content.is_empty());
+        assert_eq!(
+            generation.usage,
+            Some(TokenUsage {
+                prompt_tokens: 120,
+                completion_tokens: 18
+            })
+        );
+        assert_eq!(generation.tool_calls.len(), 1);
+        assert_eq!(generation.tool_calls[0].function.name, "calculator");
+
+        // The call round-trips into the assistant message sent back to the model
+        let message = serde_json::to_value(ChatMessage::assistant_tool_calls("", generation.tool_calls)).unwrap();
+        assert_eq!(message["tool_calls"][0]["type"], "function");
+        assert!(message.get("tool_call_id").is_none());
+    }
+}
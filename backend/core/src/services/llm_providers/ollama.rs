@@ -0,0 +1,152 @@
+use super::{
+    error_for_status, json_event_stream, sampling_fields, token_usage, Generation, GenerationRequest, HttpTimeouts, LLMProvider,
+    TokenStream,
+};
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+/// LLM served by a local Ollama instance, for fully offline operation
+#[derive(Clone)]
+pub struct OllamaLLM {
+    base_url: String,
+    model: String,
+    timeouts: HttpTimeouts,
+    client: reqwest::Client,
+}
+
+impl OllamaLLM {
+    pub fn new(base_url: String, model: String) -> Self {
+        OllamaLLM {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            timeouts: HttpTimeouts::default(),
+            client: HttpTimeouts::default().client(),
+        }
+    }
+
+    /// Connect and read timeouts for calls to Ollama
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.client();
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send requests through `client`, typically one shared with the other
+    /// providers; call after `with_timeouts`, which builds a client of its own
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Keeps the underlying error so timeouts can still be told apart
+    fn unreachable(&self, error: reqwest::Error) -> anyhow::Error {
+        let message = format!("Failed to reach Ollama at {}: {}", self.base_url, error);
+        anyhow::Error::new(error).context(message)
+    }
+
+    async fn send(&self, request: &GenerationRequest, stream: bool) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "model": request.model_or(&self.model),
+            "messages": request.messages,
+            "stream": stream,
+            "options": {
+                "temperature": request.temperature,
+                "num_predict": request.max_tokens
+            }
+        });
+        for (name, value) in sampling_fields(&request.sampling) {
+            body["options"][name] = value;
+        }
+
+        let http_request = self.client.post(format!("{}/api/chat", self.base_url)).json(&body);
+        let response = self
+            .timeouts
+            .apply(http_request, stream)
+            .send()
+            .await
+            .map_err(|e| self.unreachable(e))?;
+
+        error_for_status(response, "Ollama").await
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OllamaLLM {
+    fn name(&self) -> &'static str {
+        "ollama"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+        Ok(self.complete(request).await?.content)
+    }
+
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+
+        let answer = result["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow!("No response from LLM"))?
+            .trim()
+            .to_string();
+
+        Ok(Generation {
+            content: answer,
+            tool_calls: Vec::new(),
+            usage: token_usage(&result, "prompt_eval_count", "eval_count"),
+        })
+    }
+
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+        // Ollama streams newline-delimited JSON objects rather than SSE
+        let response = self.timeouts.started("Ollama", self.send(request, true)).await?;
+        Ok(json_event_stream(response, "Ollama", self.timeouts.read, false, |event| {
+            event["message"]["content"].as_str().map(str::to_string)
+        }))
+    }
+
+    fn model_info(&self) -> serde_json::Value {
+        json!({
+            "provider": "ollama",
+            "model": self.model,
+            "base_url": self.base_url,
+            "supports_streaming": true,
+            "max_tokens": 8192
+        })
+    }
+
+    /// Models pulled into the local Ollama instance
+    async fn list_models(&self) -> Result<Vec<LLMModel>> {
+        let response = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .timeout(self.timeouts.read)
+            .send()
+            .await
+            .map_err(|e| self.unreachable(e))?;
+
+        let result: serde_json::Value = error_for_status(response, "Ollama").await?.json().await?;
+
+        let models = result["models"]
+            .as_array()
+            .map(|models| {
+                models
+                    .iter()
+                    .filter_map(|m| m["name"].as_str())
+                    .map(|name| LLMModel {
+                        id: name.to_string(),
+                        name: name.to_string(),
+                        max_tokens: 8192,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(models)
+    }
+}
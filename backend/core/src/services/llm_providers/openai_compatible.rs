@@ -0,0 +1,236 @@
+use super::{
+    error_for_status, json_event_stream, openai_delta_content, openai_generation, openai_message_content,
+    openai_sampling, openai_tools, Generation, GenerationRequest, HttpTimeouts, LLMProvider, TokenStream,
+};
+use crate::models::LLMModel;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+
+/// Models listed in an OpenAI-style `/models` response
+fn listed_models(result: &serde_json::Value) -> Vec<LLMModel> {
+    let mut models: Vec<LLMModel> = result["data"]
+        .as_array()
+        .map(|models| {
+            models
+                .iter()
+                .filter_map(|m| {
+                    let id = m["id"].as_str()?;
+                    let max_tokens = m["context_length"]
+                        .as_u64()
+                        .or_else(|| m["max_model_len"].as_u64())
+                        .unwrap_or(8192);
+                    Some(LLMModel {
+                        id: id.to_string(),
+                        name: m["name"].as_str().unwrap_or(id).to_string(),
+                        max_tokens: max_tokens as usize,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    models
+}
+
+/// Any server speaking the OpenAI chat-completions API at `base_url`,
+/// e.g. vLLM, LM Studio, Together, or OpenRouter
+#[derive(Clone)]
+pub struct OpenAICompatibleLLM {
+    base_url: String,
+    /// Local servers usually need none
+    api_key: Option<String>,
+    model: String,
+    supports_tools: bool,
+    timeouts: HttpTimeouts,
+    client: reqwest::Client,
+}
+
+impl OpenAICompatibleLLM {
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Result<Self> {
+        if base_url.trim().is_empty() {
+            return Err(anyhow!(
+                "OpenAI-compatible provider requires LLM_BASE_URL, e.g. http://localhost:8000/v1"
+            ));
+        }
+        if model.trim().is_empty() {
+            return Err(anyhow!("OpenAI-compatible provider requires LLM_MODEL"));
+        }
+
+        Ok(OpenAICompatibleLLM {
+            base_url: base_url.trim().trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|key| !key.is_empty()),
+            model,
+            supports_tools: false,
+            timeouts: HttpTimeouts::default(),
+            client: HttpTimeouts::default().client(),
+        })
+    }
+
+    /// Offer tools to the model; only for servers that accept `tools`
+    pub fn with_tools(mut self, supports_tools: bool) -> Self {
+        self.supports_tools = supports_tools;
+        self
+    }
+
+    /// Connect and read timeouts for calls to the server
+    pub fn with_timeouts(mut self, timeouts: HttpTimeouts) -> Self {
+        self.client = timeouts.client();
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Send requests through `client`, typically one shared with the other
+    /// providers; call after `with_timeouts`, which builds a client of its own
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn authorized(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    fn configured_model(&self) -> LLMModel {
+        LLMModel {
+            id: self.model.clone(),
+            name: self.model.clone(),
+            max_tokens: 8192,
+        }
+    }
+
+    /// Keeps the underlying error so timeouts can still be told apart
+    fn unreachable(&self, error: reqwest::Error) -> anyhow::Error {
+        let message = format!("Failed to reach {}: {}", self.base_url, error);
+        anyhow::Error::new(error).context(message)
+    }
+
+    async fn send(&self, request: &GenerationRequest, stream: bool) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "model": request.model_or(&self.model),
+            "messages": request.messages,
+            "temperature": request.temperature,
+            "max_tokens": request.max_tokens,
+            "stream": stream
+        });
+        openai_sampling(&mut body, &request.sampling);
+        if self.supports_tools && !request.tools.is_empty() {
+            body["tools"] = openai_tools(&request.tools);
+        }
+
+        let http_request = self
+            .authorized(self.client.post(format!("{}/chat/completions", self.base_url)))
+            .json(&body);
+        let response = self
+            .timeouts
+            .apply(http_request, stream)
+            .send()
+            .await
+            .map_err(|e| self.unreachable(e))?;
+
+        error_for_status(response, "OpenAI-compatible").await
+    }
+}
+
+#[async_trait]
+impl LLMProvider for OpenAICompatibleLLM {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<String> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_message_content(&result)
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.supports_tools
+    }
+
+    async fn complete(&self, request: &GenerationRequest) -> Result<Generation> {
+        let result: serde_json::Value = self.send(request, false).await?.json().await?;
+        openai_generation(&result)
+    }
+
+    async fn stream(&self, request: &GenerationRequest) -> Result<TokenStream> {
+        let response = self.timeouts.started("OpenAI-compatible", self.send(request, true)).await?;
+        Ok(json_event_stream(
+            response,
+            "OpenAI-compatible",
+            self.timeouts.read,
+            true,
+            openai_delta_content,
+        ))
+    }
+
+    fn model_info(&self) -> serde_json::Value {
+        json!({
+            "provider": "openai",
+            "model": self.model,
+            "base_url": self.base_url,
+            "supports_streaming": true,
+            "max_tokens": 8192
+        })
+    }
+
+    /// Models the server lists, or just the configured one when it has no
+    /// usable `/models` endpoint
+    async fn list_models(&self) -> Result<Vec<LLMModel>> {
+        let fetched = async {
+            let response = self
+                .authorized(self.client.get(format!("{}/models", self.base_url)))
+                .timeout(self.timeouts.read)
+                .send()
+                .await
+                .map_err(|e| self.unreachable(e))?;
+            let result: serde_json::Value = error_for_status(response, "OpenAI-compatible").await?.json().await?;
+            Ok::<_, anyhow::Error>(listed_models(&result))
+        };
+
+        match fetched.await {
+            Ok(models) if !models.is_empty() => Ok(models),
+            Ok(_) => Ok(vec![self.configured_model()]),
+            Err(e) => {
+                log::warn!("Failed to list models at {}: {}", self.base_url, e);
+                Ok(vec![self.configured_model()])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listed_models() {
+        let result = json!({
+            "object": "list",
+            "data": [
+                {"id": "meta-llama/Llama-3.1-8B-Instruct", "object": "model", "max_model_len": 32768},
+                {"id": "mistralai/mixtral-8x7b-instruct", "name": "Mixtral 8x7B Instruct", "context_length": 32768},
+                {"object": "model"}
+            ]
+        });
+
+        let models = listed_models(&result);
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].id, "meta-llama/Llama-3.1-8B-Instruct");
+        assert_eq!(models[0].max_tokens, 32768);
+        assert_eq!(models[1].name, "Mixtral 8x7B Instruct");
+
+        assert!(OpenAICompatibleLLM::new(String::new(), None, "m".to_string()).is_err());
+        let provider = OpenAICompatibleLLM::new("http://localhost:1234/v1/".to_string(), Some(String::new()), "m".to_string())
+            .unwrap();
+        assert_eq!(provider.base_url, "http://localhost:1234/v1");
+        assert!(provider.api_key.is_none());
+    }
+}
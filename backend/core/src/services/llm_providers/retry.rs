@@ -0,0 +1,91 @@
+use rand::Rng;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Exponential backoff with jitter for transient provider failures
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Rate limiting and server-side failures are worth another attempt
+    pub fn is_retryable_status(status: StatusCode) -> bool {
+        status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status.is_server_error()
+    }
+
+    /// Timeouts and dropped connections are worth another attempt
+    pub fn is_retryable_error(error: &reqwest::Error) -> bool {
+        error.is_timeout() || error.is_connect() || error.is_request()
+    }
+
+    /// Delay before retry number `attempt` (0-based). A server-provided
+    /// `Retry-After` wins; otherwise the exponential delay is jittered to
+    /// between half and all of itself so clients don't retry in lockstep.
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let half = exponential / 2;
+        half + exponential.saturating_sub(half).mul_f64(rand::thread_rng().gen::<f64>())
+    }
+}
+
+/// `Retry-After` given in seconds; HTTP-date values are ignored
+pub fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_grows_within_bounds() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+
+        for attempt in 0..6 {
+            let full = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max_delay);
+            let delay = policy.delay(attempt, None);
+            assert!(delay >= full / 2 && delay <= full, "attempt {}: {:?}", attempt, delay);
+        }
+
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(60))), policy.max_delay);
+        assert_eq!(policy.delay(3, Some(Duration::from_millis(200))), Duration::from_millis(200));
+        assert!(RetryPolicy::is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(RetryPolicy::is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!RetryPolicy::is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+}
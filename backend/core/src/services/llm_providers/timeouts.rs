@@ -0,0 +1,106 @@
+use crate::services::http_client::HttpClientSettings;
+use anyhow::Result;
+use std::future::Future;
+use std::time::Duration;
+
+/// How long to wait on a provider before giving up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpTimeouts {
+    /// Establishing the connection
+    pub connect: Duration,
+    /// The whole response of a regular request, or the start of a streamed
+    /// response and each gap between its chunks
+    pub read: Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        HttpTimeouts {
+            connect: Duration::from_secs(10),
+            read: Duration::from_secs(120),
+        }
+    }
+}
+
+impl HttpTimeouts {
+    /// Client with the default pooling that gives up connecting after
+    /// `connect`, for providers not given a shared client
+    pub fn client(&self) -> reqwest::Client {
+        HttpClientSettings {
+            connect_timeout: self.connect,
+            ..Default::default()
+        }
+        .build()
+        .unwrap_or_default()
+    }
+
+    /// `request` bounded by the read timeout. Streamed requests are left
+    /// unbounded here, since a long answer may stream for longer than that;
+    /// `started` and the stream's idle timeout cover them instead.
+    pub fn apply(&self, request: reqwest::RequestBuilder, stream: bool) -> reqwest::RequestBuilder {
+        if stream {
+            request
+        } else {
+            request.timeout(self.read)
+        }
+    }
+
+    /// Wait at most the read timeout for a streamed response to start
+    pub async fn started<F>(&self, provider: &str, response: F) -> Result<reqwest::Response>
+    where
+        F: Future<Output = Result<reqwest::Response>>,
+    {
+        tokio::time::timeout(self.read, response)
+            .await
+            .map_err(|_| LLMTimeout::new(provider, self.read))?
+    }
+}
+
+/// A provider stopped responding for longer than its read timeout
+#[derive(Debug, thiserror::Error)]
+#[error("{provider} did not respond within {} seconds", .after.as_secs())]
+pub struct LLMTimeout {
+    pub provider: String,
+    pub after: Duration,
+}
+
+impl LLMTimeout {
+    pub fn new(provider: &str, after: Duration) -> Self {
+        LLMTimeout {
+            provider: provider.to_string(),
+            after,
+        }
+    }
+}
+
+/// Whether `error` comes from a provider timing out, so callers can report
+/// it apart from other failures
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<LLMTimeout>()
+            || cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_timeout())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_started_times_out() {
+        let timeouts = HttpTimeouts {
+            connect: Duration::from_millis(10),
+            read: Duration::from_millis(10),
+        };
+        let error = timeouts
+            .started("Groq", std::future::pending::<Result<reqwest::Response>>())
+            .await
+            .unwrap_err();
+
+        assert!(is_timeout(&error));
+        assert!(is_timeout(&error.context("Error generating answer")));
+        assert!(!is_timeout(&anyhow::anyhow!("Groq API error: bad request")));
+    }
+}
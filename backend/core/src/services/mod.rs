@@ -0,0 +1,43 @@
+pub mod cache_manager;
+pub mod chunker;
+pub mod citations;
+pub mod context_packing;
+pub mod dedup;
+pub mod disk_cache;
+pub mod document_dates;
+pub mod document_processor;
+pub mod evaluation;
+pub mod experiment;
+pub mod few_shot;
+pub mod follow_ups;
+pub mod groundedness;
+pub mod highlight;
+pub mod http_client;
+pub mod intent;
+pub mod knowledge_graph;
+pub mod language;
+pub mod llm_handler;
+pub mod llm_providers;
+pub mod moderation;
+pub mod pipeline;
+pub mod prompt_templates;
+pub mod query_transform;
+pub mod rate_limiter;
+pub mod redis_cache;
+pub mod rerank;
+pub mod semantic_cache;
+pub mod synthesis;
+pub mod tools;
+pub mod usage;
+pub mod vocabulary;
+pub mod vector_store;
+
+pub use document_processor::DocumentProcessor;
+pub use http_client::HttpClientSettings;
+pub use llm_handler::{AnswerOptions, LLMHandler};
+pub use llm_providers::{
+    gemini_safety_settings, is_timeout, AzureAuth, AzureOpenAILLM, GeminiLLM, GroqLLM, HttpTimeouts, LLMProvider,
+    OllamaLLM, OpenAICompatibleLLM, RetryPolicy,
+};
+pub use prompt_templates::PromptTemplateStore;
+pub use vector_store::VectorStore;
@@ -0,0 +1,232 @@
+use crate::models::ChatMessage;
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::path::Path;
+
+/// Reply to a query that moderation blocked
+pub const BLOCKED_QUERY: &str = "I can't help with that request.";
+
+/// Replaces an answer that moderation blocked
+pub const BLOCKED_ANSWER: &str = "The answer was withheld because it conflicts with this deployment's content policy.";
+
+/// Replaces text matched by a redact rule
+const REDACTED: &str = "[redacted]";
+
+/// Outcome of moderating one text, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    Allow,
+    Redact,
+    Block,
+}
+
+/// A pattern that redacts or blocks the text it matches
+#[derive(Debug, Clone)]
+pub struct ModerationRule {
+    pub action: ModerationAction,
+    /// Reported as the decision's category
+    pub label: String,
+    pub pattern: Regex,
+}
+
+/// Parse one rule per line: `block: <regex>` or `redact <label>: <regex>`.
+/// Patterns are case-insensitive; blank lines and `#` comments are skipped.
+pub fn parse_rules(spec: &str) -> Result<Vec<ModerationRule>> {
+    let mut rules = Vec::new();
+
+    for (number, line) in spec.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (head, pattern) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("line {}: expected '<action> [label]: <pattern>'", number + 1))?;
+        let mut head = head.split_whitespace();
+        let action = match head.next() {
+            Some("block") => ModerationAction::Block,
+            Some("redact") => ModerationAction::Redact,
+            other => {
+                return Err(anyhow!(
+                    "line {}: unknown action '{}', expected block or redact",
+                    number + 1,
+                    other.unwrap_or_default()
+                ))
+            }
+        };
+        let label = head.next().unwrap_or(if action == ModerationAction::Block { "blocked" } else { "redacted" });
+
+        let pattern = RegexBuilder::new(pattern.trim())
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| anyhow!("line {}: invalid pattern: {}", number + 1, e))?;
+        rules.push(ModerationRule {
+            action,
+            label: label.to_string(),
+            pattern,
+        });
+    }
+
+    Ok(rules)
+}
+
+/// Moderation decision for one text
+#[derive(Debug, Clone, Serialize)]
+pub struct Moderation {
+    pub action: ModerationAction,
+    pub categories: Vec<String>,
+    /// The text with redactions applied
+    #[serde(skip)]
+    pub text: String,
+}
+
+impl Moderation {
+    pub fn is_blocked(&self) -> bool {
+        self.action == ModerationAction::Block
+    }
+
+    fn flag(&mut self, action: ModerationAction, category: &str) {
+        self.action = self.action.max(action);
+        if !self.categories.iter().any(|c| c == category) {
+            self.categories.push(category.to_string());
+        }
+    }
+}
+
+/// Checks queries and answers against keyword/regex rules and, optionally,
+/// a guard model (e.g. Llama Guard) served by the answering provider
+#[derive(Debug, Clone, Default)]
+pub struct Moderator {
+    rules: Vec<ModerationRule>,
+    model: Option<String>,
+}
+
+impl Moderator {
+    pub fn new(rules: Vec<ModerationRule>, model: Option<String>) -> Self {
+        Moderator { rules, model }
+    }
+
+    /// Load rules from `path`, if given, and use `model` as the guard model
+    pub fn load(path: Option<&Path>, model: Option<String>) -> Result<Self> {
+        let rules = match path {
+            Some(path) => {
+                let spec = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read moderation rules {}: {}", path.display(), e))?;
+                parse_rules(&spec).map_err(|e| anyhow!("Invalid moderation rules {}: {}", path.display(), e))?
+            }
+            None => Vec::new(),
+        };
+        Ok(Moderator::new(rules, model))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty() || self.model.is_some()
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Apply the rules to `text`: any block rule blocks it, redact rules
+    /// replace what they match
+    pub fn apply_rules(&self, text: &str) -> Moderation {
+        let mut moderation = Moderation {
+            action: ModerationAction::Allow,
+            categories: Vec::new(),
+            text: text.to_string(),
+        };
+
+        for rule in &self.rules {
+            if !rule.pattern.is_match(&moderation.text) {
+                continue;
+            }
+            moderation.flag(rule.action, &rule.label);
+            if rule.action == ModerationAction::Redact {
+                moderation.text = rule.pattern.replace_all(&moderation.text, REDACTED).into_owned();
+            }
+        }
+
+        moderation
+    }
+}
+
+/// Conversation for the guard model to classify: the query alone, or the
+/// query and the answer to it
+pub fn guard_messages(query: &str, answer: Option<&str>) -> Vec<ChatMessage> {
+    let mut messages = vec![ChatMessage::user(query)];
+    if let Some(answer) = answer {
+        messages.push(ChatMessage::assistant(answer));
+    }
+    messages
+}
+
+/// Fold a guard model verdict (`safe`, or `unsafe` followed by category
+/// codes such as `S1,S10`) into `moderation`
+pub fn apply_verdict(moderation: &mut Moderation, verdict: &str) -> Result<()> {
+    let mut lines = verdict.trim().lines().map(str::trim);
+    match lines.next().map(str::to_lowercase).as_deref() {
+        Some("safe") => Ok(()),
+        Some("unsafe") => {
+            let categories: Vec<&str> = lines
+                .flat_map(|line| line.split(','))
+                .map(str::trim)
+                .filter(|c| !c.is_empty())
+                .collect();
+            if categories.is_empty() {
+                moderation.flag(ModerationAction::Block, "unsafe");
+            }
+            for category in categories {
+                moderation.flag(ModerationAction::Block, category);
+            }
+            Ok(())
+        }
+        _ => Err(anyhow!("Unexpected moderation verdict: {}", verdict.trim())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_redact_and_block() {
+        let rules = parse_rules(
+            "# contact details\nredact email: [\\w.+-]+@[\\w-]+\\.[\\w.]+\nblock weapons: \\b(bomb|explosives?)\\b\n",
+        )
+        .unwrap();
+        let moderator = Moderator::new(rules, None);
+
+        let redacted = moderator.apply_rules("Mail jane.doe@example.com for access");
+        assert_eq!(redacted.action, ModerationAction::Redact);
+        assert_eq!(redacted.text, "Mail [redacted] for access");
+        assert_eq!(redacted.categories, vec!["email"]);
+
+        let blocked = moderator.apply_rules("How do I build a BOMB? Ask a@b.io");
+        assert!(blocked.is_blocked());
+        assert_eq!(blocked.categories, vec!["email", "weapons"]);
+
+        assert_eq!(moderator.apply_rules("What is the refund policy?").action, ModerationAction::Allow);
+        assert!(parse_rules("warn: x").is_err());
+        assert!(parse_rules("block: (").is_err());
+    }
+
+    #[test]
+    fn test_apply_verdict() {
+        let moderator = Moderator::default();
+        let mut moderation = moderator.apply_rules("anything");
+        apply_verdict(&mut moderation, "safe").unwrap();
+        assert_eq!(moderation.action, ModerationAction::Allow);
+
+        apply_verdict(&mut moderation, "unsafe\nS1,S10").unwrap();
+        assert!(moderation.is_blocked());
+        assert_eq!(moderation.categories, vec!["S1", "S10"]);
+        assert!(apply_verdict(&mut moderation, "I cannot tell").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::models::{QueryRequest, RetrievalMode, SearchResult, StrictMode, SynthesisStrategy};
+use crate::services::groundedness::{content_terms, VerificationMode};
+use crate::services::llm_handler::AnswerOptions;
+use crate::services::rerank::RerankMode;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Sentences kept per chunk by a compress stage that doesn't say
+const DEFAULT_COMPRESS_SENTENCES: usize = 3;
+
+/// One step of a query pipeline and its parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Stage {
+    /// Turn follow-up questions into standalone ones using the history
+    Rewrite,
+    Retrieve {
+        #[serde(default)]
+        k: Option<usize>,
+        #[serde(default)]
+        score_threshold: Option<f32>,
+        #[serde(default)]
+        mode: Option<RetrievalMode>,
+        #[serde(default)]
+        expand_query: bool,
+    },
+    /// Order the retrieved chunks by an LLM's relevance scores
+    Rerank {
+        #[serde(default)]
+        model: Option<String>,
+    },
+    /// Keep only the sentences of each chunk that share terms with the query
+    Compress {
+        #[serde(default)]
+        max_sentences: Option<usize>,
+    },
+    Generate {
+        #[serde(default)]
+        provider: Option<String>,
+        #[serde(default)]
+        model: Option<String>,
+        #[serde(default)]
+        template: Option<String>,
+        #[serde(default)]
+        max_tokens: Option<usize>,
+        #[serde(default)]
+        temperature: Option<f32>,
+        #[serde(default)]
+        strategy: Option<SynthesisStrategy>,
+    },
+    Verify {
+        #[serde(default)]
+        mode: Option<VerificationMode>,
+        #[serde(default)]
+        strict_mode: Option<StrictMode>,
+    },
+}
+
+impl Stage {
+    /// Position in rewrite → retrieve → rerank → compress → generate → verify
+    fn order(&self) -> usize {
+        match self {
+            Stage::Rewrite => 0,
+            Stage::Retrieve { .. } => 1,
+            Stage::Rerank { .. } => 2,
+            Stage::Compress { .. } => 3,
+            Stage::Generate { .. } => 4,
+            Stage::Verify { .. } => 5,
+        }
+    }
+}
+
+/// Stages a query runs through; stages left out are skipped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    #[serde(default)]
+    pub description: Option<String>,
+    pub stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Stages must appear at most once each, in pipeline order, and every
+    /// pipeline retrieves and generates
+    pub fn validate(&self) -> Result<()> {
+        for pair in self.stages.windows(2) {
+            if pair[0].order() >= pair[1].order() {
+                return Err(anyhow!(
+                    "stages must appear once each, in the order rewrite, retrieve, rerank, compress, generate, verify"
+                ));
+            }
+        }
+        if !self.stages.iter().any(|s| matches!(s, Stage::Retrieve { .. })) {
+            return Err(anyhow!("a pipeline needs a retrieve stage"));
+        }
+        if !self.stages.iter().any(|s| matches!(s, Stage::Generate { .. })) {
+            return Err(anyhow!("a pipeline needs a generate stage"));
+        }
+        Ok(())
+    }
+
+    /// Fill the retrieval and generation settings `req` leaves unset from
+    /// the pipeline's stages; what the request sets wins
+    pub fn apply(&self, req: &mut QueryRequest) {
+        for stage in &self.stages {
+            match stage {
+                Stage::Retrieve { k, score_threshold, mode, expand_query } => {
+                    req.k = req.k.or(*k);
+                    req.score_threshold = req.score_threshold.or(*score_threshold);
+                    req.retrieval = req.retrieval.or(*mode);
+                    req.expand_query |= *expand_query;
+                }
+                Stage::Generate { provider, model, template, max_tokens, temperature, strategy } => {
+                    req.provider = req.provider.take().or_else(|| provider.clone());
+                    req.model = req.model.take().or_else(|| model.clone());
+                    req.template = req.template.take().or_else(|| template.clone());
+                    req.max_tokens = req.max_tokens.or(*max_tokens);
+                    req.temperature = req.temperature.or(*temperature);
+                    if req.strategy == SynthesisStrategy::default() {
+                        req.strategy = strategy.unwrap_or_default();
+                    }
+                }
+                Stage::Verify { strict_mode, .. } => {
+                    req.strict_mode = req.strict_mode.or(*strict_mode);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Switch the optional stages on or off in `options`, overriding the
+    /// handler's configured defaults
+    pub fn configure(&self, options: &mut AnswerOptions) {
+        options.rewrite = Some(false);
+        options.rerank = Some(RerankMode::Off);
+        options.verification = Some(VerificationMode::Off);
+        for stage in &self.stages {
+            match stage {
+                Stage::Rewrite => options.rewrite = Some(true),
+                Stage::Rerank { model } => {
+                    options.rerank = Some(RerankMode::Llm);
+                    options.rerank_model = model.clone();
+                }
+                Stage::Compress { max_sentences } => {
+                    options.compress = Some(max_sentences.unwrap_or(DEFAULT_COMPRESS_SENTENCES).max(1));
+                }
+                Stage::Verify { mode, .. } => {
+                    options.verification = Some(mode.unwrap_or(VerificationMode::Lexical));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Named pipelines loaded from a JSON object of name → pipeline at startup
+#[derive(Debug, Default)]
+pub struct PipelineStore {
+    pipelines: BTreeMap<String, Pipeline>,
+    default: Option<String>,
+}
+
+impl PipelineStore {
+    /// Load the pipelines in `path`; a missing file means none. `default`
+    /// runs for requests that don't name a pipeline and must be defined.
+    pub fn new(path: impl AsRef<Path>, default: Option<String>) -> Result<Self> {
+        let path = path.as_ref();
+        let pipelines: BTreeMap<String, Pipeline> = match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| anyhow!("Invalid pipelines in {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        for (name, pipeline) in &pipelines {
+            pipeline
+                .validate()
+                .map_err(|e| anyhow!("Invalid pipeline '{}': {}", name, e))?;
+        }
+        if let Some(name) = &default {
+            if !pipelines.contains_key(name) {
+                return Err(anyhow!("Default pipeline '{}' is not defined in {}", name, path.display()));
+            }
+        }
+
+        Ok(PipelineStore { pipelines, default })
+    }
+
+    pub fn list(&self) -> &BTreeMap<String, Pipeline> {
+        &self.pipelines
+    }
+
+    pub fn default_name(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// The named pipeline, or the default one when `name` is `None`
+    pub fn resolve(&self, name: Option<&str>) -> Result<Option<&Pipeline>> {
+        match name.or(self.default.as_deref()) {
+            Some(name) => self
+                .pipelines
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| anyhow!("Pipeline not found: {}", name)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// `chunks` with the context text of each cut down to its (at most)
+/// `max_sentences` sentences sharing the most terms with `query`, kept in
+/// document order. Chunks without such a sentence are left whole, since
+/// the embedding matched them on meaning rather than wording.
+pub fn compress_chunks(chunks: &[SearchResult], query: &str, max_sentences: usize) -> Vec<SearchResult> {
+    let terms = content_terms(query);
+    chunks
+        .iter()
+        .map(|chunk| {
+            let mut chunk = chunk.clone();
+            let text = chunk.parent_text.as_mut().unwrap_or(&mut chunk.text);
+            if let Some(compressed) = compress_text(text, &terms, max_sentences) {
+                *text = compressed;
+            }
+            chunk
+        })
+        .collect()
+}
+
+fn compress_text(text: &str, terms: &HashSet<String>, max_sentences: usize) -> Option<String> {
+    let sentences: Vec<&str> = text
+        .split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    let mut scored: Vec<(usize, usize)> = sentences
+        .iter()
+        .enumerate()
+        .map(|(i, sentence)| (content_terms(sentence).intersection(terms).count(), i))
+        .filter(|(matched, _)| *matched > 0)
+        .collect();
+    if scored.is_empty() {
+        return None;
+    }
+
+    // Most matching terms first, earlier sentences on ties
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let mut kept: Vec<usize> = scored.into_iter().take(max_sentences).map(|(_, i)| i).collect();
+    kept.sort_unstable();
+    Some(kept.into_iter().map(|i| sentences[i]).collect::<Vec<_>>().join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipelines_load_and_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pipelines.json");
+        fs::write(
+            &path,
+            r#"{
+                "precise": {
+                    "description": "Rewritten, reranked, compressed, verified",
+                    "stages": [
+                        {"stage": "rewrite"},
+                        {"stage": "retrieve", "k": 10, "mode": "hyde"},
+                        {"stage": "rerank", "model": "small"},
+                        {"stage": "compress"},
+                        {"stage": "generate", "temperature": 0.2, "strategy": "refine"},
+                        {"stage": "verify", "mode": "llm", "strict_mode": "refuse"}
+                    ]
+                },
+                "fast": {"stages": [{"stage": "retrieve"}, {"stage": "generate"}]}
+            }"#,
+        )
+        .unwrap();
+        let store = PipelineStore::new(&path, Some("fast".to_string())).unwrap();
+        assert!(PipelineStore::new(&path, Some("missing".to_string())).is_err());
+        assert!(store.resolve(Some("missing")).is_err());
+        assert_eq!(store.resolve(None).unwrap(), store.list().get("fast"));
+
+        let mut req: QueryRequest = serde_json::from_value(serde_json::json!({
+            "query": "PTO?",
+            "k": 3
+        }))
+        .unwrap();
+        let precise = store.resolve(Some("precise")).unwrap().unwrap();
+        precise.apply(&mut req);
+        assert_eq!(req.k, Some(3));
+        assert_eq!(req.retrieval, Some(RetrievalMode::Hyde));
+        assert_eq!(req.temperature, Some(0.2));
+        assert_eq!(req.strategy, SynthesisStrategy::Refine);
+        assert_eq!(req.strict_mode, Some(StrictMode::Refuse));
+
+        let mut options = AnswerOptions::default();
+        precise.configure(&mut options);
+        assert_eq!(options.rewrite, Some(true));
+        assert_eq!(options.rerank_model.as_deref(), Some("small"));
+        assert_eq!(options.compress, Some(DEFAULT_COMPRESS_SENTENCES));
+        assert_eq!(options.verification, Some(VerificationMode::Llm));
+        store.resolve(None).unwrap().unwrap().configure(&mut options);
+        assert_eq!(options.rerank, Some(RerankMode::Off));
+        assert_eq!(options.verification, Some(VerificationMode::Off));
+
+        let out_of_order: Pipeline = serde_json::from_str(
+            r#"{"stages": [{"stage": "generate"}, {"stage": "retrieve"}]}"#,
+        )
+        .unwrap();
+        assert!(out_of_order.validate().is_err());
+        assert!(serde_json::from_str::<Pipeline>(r#"{"stages": [{"stage": "retrieve", "top": 3}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_compress_keeps_matching_sentences() {
+        let terms = content_terms("How many vacation days do employees get?");
+        let text = "The office opens at nine. Employees get 25 vacation days. Vacation requests need approval. Parking is free.";
+        assert_eq!(
+            compress_text(text, &terms, 2).as_deref(),
+            Some("Employees get 25 vacation days. Vacation requests need approval.")
+        );
+        assert_eq!(
+            compress_text(text, &terms, 1).as_deref(),
+            Some("Employees get 25 vacation days.")
+        );
+        assert_eq!(compress_text("Parking is free.", &terms, 2), None);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Placeholders a template may use, as `{name}` or `{{name}}`
+pub const TEMPLATE_VARIABLES: &[&str] = &["context", "query", "history"];
+
+const TEMPLATE_EXTENSION: &str = "txt";
+
+/// User message used when a request names no template
+pub const DEFAULT_TEMPLATE: &str = "Context Information:\n{context}\n\nUser Question: {query}\n\nPlease provide a comprehensive answer based on the context above. If the context doesn't contain sufficient information, clearly state this limitation.";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    pub template: String,
+}
+
+impl PromptTemplate {
+    /// Whether the template places `variable` itself
+    pub fn uses(&self, variable: &str) -> bool {
+        let mut rest = self.template.as_str();
+        while let Some(pos) = rest.find('{') {
+            if let Some((name, _)) = placeholder_at(&rest[pos..]) {
+                if name == variable {
+                    return true;
+                }
+            }
+            rest = &rest[pos + 1..];
+        }
+        false
+    }
+
+    /// Substitute known placeholders in one pass, so substituted text is
+    /// never expanded again; other braces are left untouched
+    pub fn render(&self, variables: &HashMap<&str, String>) -> String {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+
+        while let Some(pos) = rest.find('{') {
+            rendered.push_str(&rest[..pos]);
+            match placeholder_at(&rest[pos..]) {
+                Some((name, len)) => {
+                    rendered.push_str(variables.get(name).map(String::as_str).unwrap_or(""));
+                    rest = &rest[pos + len..];
+                }
+                None => {
+                    rendered.push('{');
+                    rest = &rest[pos + 1..];
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+/// A `{{ name }}` or `{name}` placeholder for a known variable at the start
+/// of `text`, with its length in bytes
+fn placeholder_at(text: &str) -> Option<(&'static str, usize)> {
+    let (open, close) = if text.starts_with("{{") { (2, "}}") } else { (1, "}") };
+    let inner_len = text[open..].find(close)?;
+    let inner = text[open..open + inner_len].trim();
+    let name = TEMPLATE_VARIABLES.iter().find(|v| **v == inner)?;
+    Some((name, open + inner_len + close.len()))
+}
+
+/// Named prompt templates stored as `<name>.txt` files in a directory
+pub struct PromptTemplateStore {
+    dir: PathBuf,
+    templates: HashMap<String, PromptTemplate>,
+}
+
+impl PromptTemplateStore {
+    /// Load every template in `dir`, creating the directory if needed
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut templates = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(TEMPLATE_EXTENSION) {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if validate_name(name).is_err() {
+                log::warn!("Skipping prompt template with invalid name: {}", path.display());
+                continue;
+            }
+
+            let template = fs::read_to_string(&path)?;
+            templates.insert(
+                name.to_string(),
+                PromptTemplate {
+                    name: name.to_string(),
+                    template,
+                },
+            );
+        }
+
+        Ok(PromptTemplateStore { dir, templates })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.templates.get(name)
+    }
+
+    /// All templates, sorted by name
+    pub fn list(&self) -> Vec<&PromptTemplate> {
+        let mut templates: Vec<&PromptTemplate> = self.templates.values().collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        templates
+    }
+
+    /// Create or replace a template and write it to disk
+    pub fn save(&mut self, name: &str, template: String) -> Result<&PromptTemplate> {
+        validate_name(name)?;
+        let prompt = PromptTemplate {
+            name: name.to_string(),
+            template,
+        };
+        for required in ["context", "query"] {
+            if !prompt.uses(required) {
+                return Err(anyhow!("Template must include a {{{}}} placeholder", required));
+            }
+        }
+
+        fs::write(self.path_for(name), &prompt.template)?;
+        self.templates.insert(name.to_string(), prompt);
+        Ok(&self.templates[name])
+    }
+
+    /// Remove a template; returns whether it existed
+    pub fn delete(&mut self, name: &str) -> Result<bool> {
+        if self.templates.remove(name).is_none() {
+            return Ok(false);
+        }
+        fs::remove_file(self.path_for(name))?;
+        Ok(true)
+    }
+
+    fn path_for(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION))
+    }
+}
+
+/// Names become file names, so only allow a safe character set
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Invalid template name '{}': use 1-64 letters, digits, '-' or '_'",
+            name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_both_placeholder_styles() {
+        let template = PromptTemplate {
+            name: "test".to_string(),
+            template: "Q: {query}\nDocs: {{ context }}\nKeep {braces} and JSON {\"a\": 1}\n{history}".to_string(),
+        };
+        let variables = HashMap::from([
+            ("query", "What is Rust?".to_string()),
+            ("context", "Rust is a language. {query}".to_string()),
+        ]);
+
+        assert!(template.uses("history"));
+        assert_eq!(
+            template.render(&variables),
+            "Q: What is Rust?\nDocs: Rust is a language. {query}\nKeep {braces} and JSON {\"a\": 1}\n"
+        );
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = PromptTemplateStore::new(dir.path()).unwrap();
+
+        store.save("concise", "Answer briefly.\n{context}\n{query}".to_string()).unwrap();
+        assert!(store.save("no_context", "{query}".to_string()).is_err());
+        assert!(store.save("../escape", "{context}{query}".to_string()).is_err());
+
+        let reloaded = PromptTemplateStore::new(dir.path()).unwrap();
+        assert_eq!(reloaded.list().len(), 1);
+        assert!(reloaded.get("concise").unwrap().uses("context"));
+
+        assert!(store.delete("concise").unwrap());
+        assert!(!store.delete("concise").unwrap());
+        assert!(PromptTemplateStore::new(dir.path()).unwrap().list().is_empty());
+    }
+}
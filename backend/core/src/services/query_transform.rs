@@ -0,0 +1,227 @@
+use crate::models::{ChatMessage, SearchResult};
+use std::collections::HashMap;
+
+/// Most paraphrases and sub-questions searched besides the query itself
+pub const MAX_EXPANSIONS: usize = 5;
+
+/// Most sub-questions a complex question is broken into
+pub const MAX_SUB_QUESTIONS: usize = 4;
+
+/// Recent messages shown when rewriting a follow-up question
+const REWRITE_HISTORY_MESSAGES: usize = 6;
+
+/// Prompt to turn a follow-up question into one that stands on its own,
+/// given the last few turns of the conversation
+pub fn rewrite_messages(history: &[ChatMessage], query: &str) -> Vec<ChatMessage> {
+    let recent = &history[history.len().saturating_sub(REWRITE_HISTORY_MESSAGES)..];
+    let transcript: Vec<String> = recent
+        .iter()
+        .map(|message| format!("{}: {}", message.role, message.content))
+        .collect();
+
+    vec![
+        ChatMessage::system(
+            "Rewrite the user's latest question so it can be understood without the conversation: resolve pronouns and references such as \"it\", \"that\" or \"the second option\" to what they refer to. Keep the question's meaning and language. If it already stands on its own, repeat it unchanged. Reply with the question only.",
+        ),
+        ChatMessage::user(format!(
+            "Conversation:\n{}\n\nLatest question: {}",
+            transcript.join("\n"),
+            query
+        )),
+    ]
+}
+
+/// The rewritten question from a rewrite response, or `None` if it's empty
+pub fn parse_rewrite(response: &str) -> Option<String> {
+    let rewritten = response.lines().find(|line| !line.trim().is_empty())?.trim();
+    let rewritten = rewritten.strip_prefix("Question:").unwrap_or(rewritten).trim().trim_matches('"');
+    (!rewritten.is_empty()).then(|| rewritten.to_string())
+}
+
+/// Rank offset of reciprocal rank fusion; 60 is the usual choice and keeps a
+/// single top rank from outweighing agreement between lists
+const RRF_K: f32 = 60.0;
+
+/// Prompt for a short passage that would answer `query`. Its facts may be
+/// wrong; only its wording matters, since it is embedded, never shown.
+pub fn hyde_messages(query: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(
+            "Write a short passage, as it might appear in a company document, that answers the question. Use the terminology such a document would use. Do not hedge or mention that you are unsure; reply with the passage only.",
+        ),
+        ChatMessage::user(query),
+    ]
+}
+
+/// What HyDE embeds: the query followed by the drafted passage, so terms of
+/// the question itself still count
+pub fn hyde_search_text(query: &str, draft: &str) -> String {
+    format!("{}\n\n{}", query.trim(), draft.trim())
+}
+
+/// Prompt for paraphrases and sub-questions of `query`, one per line
+pub fn expansion_messages(query: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(format!(
+            "You help search a document collection. Rewrite the user's question as 3 to {} different search queries: paraphrases using other likely wording, and the sub-questions it depends on. Reply with one query per line and nothing else.",
+            MAX_EXPANSIONS
+        )),
+        ChatMessage::user(query),
+    ]
+}
+
+/// Prompt to break a complex question into simpler ones, one per line, or
+/// to repeat a simple question unchanged
+pub fn decomposition_messages(query: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(format!(
+            "You plan searches over a document collection. If the question needs several separate facts (for example it compares things, spans several documents, or has multiple parts), break it into at most {} self-contained sub-questions that together answer it. If it asks for a single fact, reply with the question unchanged. Reply with one question per line and nothing else.",
+            MAX_SUB_QUESTIONS
+        )),
+        ChatMessage::user(query),
+    ]
+}
+
+/// Sub-questions from a decomposition response; fewer than two means the
+/// question is answered in one step
+pub fn parse_sub_questions(response: &str) -> Vec<String> {
+    let mut questions = parse_expansions(response, "");
+    questions.truncate(MAX_SUB_QUESTIONS);
+    questions
+}
+
+/// Queries from an expansion response, without numbering, blank lines, or
+/// repeats of `query`
+pub fn parse_expansions(response: &str, query: &str) -> Vec<String> {
+    let mut queries: Vec<String> = Vec::new();
+    for line in response.lines() {
+        let line = line
+            .trim()
+            .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '.' | ')' | '-' | '*'))
+            .trim()
+            .trim_matches('"');
+        let seen = |q: &str| q.eq_ignore_ascii_case(line);
+        if line.is_empty() || seen(query.trim()) || queries.iter().any(|q| seen(q)) {
+            continue;
+        }
+        queries.push(line.to_string());
+    }
+    queries.truncate(MAX_EXPANSIONS);
+    queries
+}
+
+/// Merge ranked result lists with reciprocal rank fusion: a chunk scores
+/// the sum of 1 / (RRF_K + rank) over the lists it appears in. Returns the
+/// top `k`, each keeping its best similarity score.
+pub fn fuse_results(lists: Vec<Vec<SearchResult>>, k: usize) -> Vec<SearchResult> {
+    let mut fused: HashMap<(String, usize), (f32, usize, SearchResult)> = HashMap::new();
+    let mut order = 0;
+
+    for list in lists {
+        for (rank, result) in list.into_iter().enumerate() {
+            let contribution = 1.0 / (RRF_K + rank as f32 + 1.0);
+            let key = (result.file_path.clone(), result.chunk_id);
+            let entry = fused.entry(key).or_insert_with(|| {
+                order += 1;
+                (0.0, order, result.clone())
+            });
+            entry.0 += contribution;
+            if result.similarity_score > entry.2.similarity_score {
+                entry.2.similarity_score = result.similarity_score;
+            }
+        }
+    }
+
+    // Ties keep the order chunks were first seen in
+    let mut fused: Vec<(f32, usize, SearchResult)> = fused.into_values().collect();
+    fused.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)));
+    fused.into_iter().take(k).map(|(_, _, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RetrievalMode;
+
+    #[test]
+    fn test_hyde_search_text() {
+        let messages = hyde_messages("PTO?");
+        assert_eq!(messages[1].content, "PTO?");
+        assert_eq!(
+            hyde_search_text(" PTO? ", "Employees accrue 25 days of paid time off.\n"),
+            "PTO?\n\nEmployees accrue 25 days of paid time off."
+        );
+        assert_eq!("HyDE".parse::<RetrievalMode>().unwrap(), RetrievalMode::Hyde);
+        assert_eq!("graph".parse::<RetrievalMode>().unwrap(), RetrievalMode::Graph);
+        assert!("fuzzy".parse::<RetrievalMode>().is_err());
+    }
+
+    #[test]
+    fn test_rewrite_follow_up() {
+        let history: Vec<ChatMessage> = (0..10)
+            .map(|i| ChatMessage::user(format!("turn {}", i)))
+            .collect();
+        let messages = rewrite_messages(&history, "what about the second option?");
+        assert!(!messages[1].content.contains("turn 3"));
+        assert!(messages[1].content.contains("turn 4\nuser: turn 5"));
+        assert!(messages[1].content.ends_with("Latest question: what about the second option?"));
+
+        assert_eq!(
+            parse_rewrite("\n\"What is the price of the Pro plan?\"\n").as_deref(),
+            Some("What is the price of the Pro plan?")
+        );
+        assert_eq!(parse_rewrite("  \n"), None);
+    }
+
+    fn result(file_path: &str, chunk_id: usize, similarity_score: f32) -> SearchResult {
+        SearchResult {
+            file_path: file_path.to_string(),
+            file_name: file_path.to_string(),
+            file_type: ".txt".to_string(),
+            chunk_id,
+            chunk_size: 10,
+            text: format!("{} #{}", file_path, chunk_id),
+            similarity_score,
+            parent_id: None,
+            parent_text: None,
+            location: Default::default(),
+            rerank_score: None,
+            highlights: None,
+            document_date: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_sub_questions() {
+        let response = "1. What was revenue in the 2022 report?\n2. What was revenue in the 2023 report?\n3. What was revenue in the 2024 report?\n4. a\n5. b";
+        let questions = parse_sub_questions(response);
+        assert_eq!(questions.len(), MAX_SUB_QUESTIONS);
+        assert_eq!(questions[1], "What was revenue in the 2023 report?");
+        assert_eq!(parse_sub_questions("What is the PTO policy?").len(), 1);
+    }
+
+    #[test]
+    fn test_expansions_and_fusion() {
+        let response = "1. How many vacation days do employees get?\n2) PTO allowance\n\n- how many vacation days do employees get?\nWhat is the PTO policy?";
+        assert_eq!(
+            parse_expansions(response, "What is the PTO policy?"),
+            vec!["How many vacation days do employees get?", "PTO allowance"]
+        );
+
+        let fused = fuse_results(
+            vec![
+                vec![result("a.txt", 0, 0.9), result("b.txt", 0, 0.5)],
+                vec![result("b.txt", 0, 0.7), result("c.txt", 1, 0.6)],
+                vec![result("c.txt", 1, 0.4), result("b.txt", 0, 0.3)],
+            ],
+            2,
+        );
+        assert_eq!(fused.len(), 2);
+        assert_eq!(fused[0].file_path, "b.txt");
+        assert_eq!(fused[0].similarity_score, 0.7);
+        assert_eq!(fused[1].file_path, "c.txt");
+
+        let single = fuse_results(vec![vec![result("a.txt", 0, 0.9), result("b.txt", 0, 0.5)]], 5);
+        assert_eq!(single[0].file_path, "a.txt");
+    }
+}
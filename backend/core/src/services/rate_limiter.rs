@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Clients tracked before buckets refilled to capacity are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token bucket refilled continuously at `capacity` per minute
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn per_minute(capacity: u32) -> Self {
+        TokenBucket {
+            capacity: capacity as f64,
+            available: capacity as f64,
+            refill_per_sec: capacity as f64 / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.available = (self.available + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    /// Time until `amount` is available. Amounts above capacity wait for a
+    /// full bucket so oversized requests are delayed, not rejected.
+    fn wait_time(&self, amount: f64) -> Duration {
+        let needed = amount.min(self.capacity) - self.available;
+        if needed <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(needed / self.refill_per_sec)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+/// Client-side limits on LLM requests and tokens per minute. Callers wait
+/// their turn in arrival order instead of tripping the provider's limits.
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<(Option<TokenBucket>, Option<TokenBucket>)>,
+}
+
+impl RateLimiter {
+    /// A limit of 0 disables that dimension
+    pub fn new(requests_per_minute: u32, tokens_per_minute: u32) -> Self {
+        let bucket = |limit: u32| (limit > 0).then(|| TokenBucket::per_minute(limit));
+        RateLimiter {
+            buckets: Mutex::new((bucket(requests_per_minute), bucket(tokens_per_minute))),
+        }
+    }
+
+    /// Wait until one request costing `tokens` fits in both budgets, then
+    /// deduct it. The lock is held while waiting so callers are served FIFO.
+    pub async fn acquire(&self, tokens: usize) -> Duration {
+        let mut buckets = self.buckets.lock().await;
+        let (requests, token_budget) = &mut *buckets;
+        let mut waited = Duration::ZERO;
+
+        loop {
+            let now = Instant::now();
+            let mut wait = Duration::ZERO;
+            if let Some(bucket) = requests.as_mut() {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_time(1.0));
+            }
+            if let Some(bucket) = token_budget.as_mut() {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_time(tokens as f64));
+            }
+
+            if wait.is_zero() {
+                break;
+            }
+            tokio::time::sleep(wait).await;
+            waited += wait;
+        }
+
+        if let Some(bucket) = requests.as_mut() {
+            bucket.take(1.0);
+        }
+        if let Some(bucket) = token_budget.as_mut() {
+            bucket.take(tokens as f64);
+        }
+
+        waited
+    }
+}
+
+/// Server-side limit on requests per minute from each client. Unlike
+/// `RateLimiter`, requests over the limit are refused rather than queued.
+#[derive(Debug)]
+pub struct ClientRateLimiter {
+    per_minute: AtomicU32,
+    buckets: std::sync::Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl ClientRateLimiter {
+    /// A limit of 0 allows every request
+    pub fn new(per_minute: u32) -> Self {
+        ClientRateLimiter {
+            per_minute: AtomicU32::new(per_minute),
+            buckets: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.per_minute() > 0
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute.load(Ordering::Relaxed)
+    }
+
+    /// Change the limit; clients start again with a full bucket
+    pub fn set_per_minute(&self, per_minute: u32) {
+        if self.per_minute.swap(per_minute, Ordering::Relaxed) != per_minute {
+            self.buckets.lock().unwrap().clear();
+        }
+    }
+
+    /// Count a request from `client`, or return how long until it would
+    /// be allowed
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        Self::check_all(&[self], client)
+    }
+
+    /// Count a request from `client` against every one of `limiters`, or
+    /// against none when any would refuse it, returning the longest wait
+    pub fn check_all(limiters: &[&ClientRateLimiter], client: &str) -> Result<(), Duration> {
+        Self::check_all_at(limiters, client, Instant::now())
+    }
+
+    /// Give back a request counted by `check`
+    pub fn refund(&self, client: &str) {
+        if let Some(bucket) = self.buckets.lock().unwrap().get_mut(client) {
+            bucket.available = (bucket.available + 1.0).min(bucket.capacity);
+        }
+    }
+
+    #[cfg(test)]
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        Self::check_all_at(&[self], client, now)
+    }
+
+    fn check_all_at(limiters: &[&ClientRateLimiter], client: &str, now: Instant) -> Result<(), Duration> {
+        // Locked in the order given, which callers keep fixed
+        let mut locked: Vec<_> = limiters
+            .iter()
+            .filter(|limiter| limiter.is_enabled())
+            .map(|limiter| (limiter.per_minute(), limiter.buckets.lock().unwrap()))
+            .collect();
+
+        let mut wait = Duration::ZERO;
+        for (per_minute, buckets) in &mut locked {
+            if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+                buckets.retain(|_, bucket| {
+                    bucket.refill(now);
+                    bucket.available < bucket.capacity
+                });
+            }
+            let bucket = buckets
+                .entry(client.to_string())
+                .or_insert_with(|| TokenBucket::per_minute(*per_minute));
+            bucket.refill(now);
+            wait = wait.max(bucket.wait_time(1.0));
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (_, buckets) in &mut locked {
+            if let Some(bucket) = buckets.get_mut(client) {
+                bucket.take(1.0);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_waits_for_refill() {
+        let mut bucket = TokenBucket::per_minute(60);
+        let start = bucket.last_refill;
+
+        bucket.take(60.0);
+        assert_eq!(bucket.wait_time(1.0).as_millis(), 1000);
+
+        bucket.refill(start + Duration::from_secs(30));
+        assert_eq!(bucket.wait_time(30.0), Duration::ZERO);
+        assert_eq!(bucket.wait_time(40.0).as_millis(), 10_000);
+
+        // Oversized requests wait for a full bucket rather than forever
+        assert_eq!(bucket.wait_time(1000.0).as_millis(), 30_000);
+
+        bucket.refill(start + Duration::from_secs(600));
+        assert_eq!(bucket.available, 60.0);
+    }
+
+    #[test]
+    fn test_clients_are_limited_separately() {
+        let limiter = ClientRateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.check_at("key:alice", start).is_ok());
+        assert!(limiter.check_at("key:alice", start).is_ok());
+        assert_eq!(limiter.check_at("key:alice", start), Err(Duration::from_secs(30)));
+        assert!(limiter.check_at("ip:10.0.0.1", start).is_ok());
+        assert!(limiter.check_at("key:alice", start + Duration::from_secs(30)).is_ok());
+
+        let unlimited = ClientRateLimiter::new(0);
+        assert!((0..100).all(|_| unlimited.check("ip:10.0.0.1").is_ok()));
+
+        limiter.set_per_minute(0);
+        assert!(limiter.check_at("key:alice", start).is_ok());
+    }
+
+    #[test]
+    fn test_refused_requests_take_from_no_limiter() {
+        let (all, llm) = (ClientRateLimiter::new(3), ClientRateLimiter::new(1));
+        let start = Instant::now();
+        let check = |limiters: &[&ClientRateLimiter]| ClientRateLimiter::check_all_at(limiters, "key:alice", start);
+        assert!(check(&[&all, &llm]).is_ok());
+        assert_eq!(check(&[&all, &llm]), Err(Duration::from_secs(60)));
+        assert_eq!(check(&[&all, &llm]), Err(Duration::from_secs(60)));
+
+        // The refused LLM requests left the overall budget alone
+        assert!(check(&[&all]).is_ok());
+        assert!(check(&[&all]).is_ok());
+        assert!(check(&[&all]).is_err());
+        all.refund("key:alice");
+        assert!(check(&[&all]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_limits_never_wait() {
+        let limiter = RateLimiter::new(0, 0);
+        for _ in 0..1000 {
+            assert_eq!(limiter.acquire(10_000).await, Duration::ZERO);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::services::cache_manager::{Cache, CacheCounters, CacheStats};
+use anyhow::{Context, Result};
+use redis::{Commands, Connection, RedisResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How long connecting, or waiting on a reply, may take before the lookup
+/// counts as a miss
+const REDIS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A cache kept in Redis under keys starting with a prefix, shared by every
+/// instance pointed at the same server. Redis's own memory policy bounds
+/// its size.
+pub struct RedisCache<V> {
+    client: redis::Client,
+    /// Dropped after an error, so the next call reconnects
+    connection: Mutex<Option<Connection>>,
+    prefix: String,
+    ttl: Option<Duration>,
+    counters: CacheCounters,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V: Serialize + DeserializeOwned> RedisCache<V> {
+    /// Connect to the server at `url`, keeping entries under `prefix`
+    pub fn open(url: &str, prefix: &str) -> Result<Self> {
+        let client = redis::Client::open(url).with_context(|| format!("Invalid Redis URL {}", url))?;
+        let cache = RedisCache {
+            client,
+            connection: Mutex::new(None),
+            prefix: prefix.to_string(),
+            ttl: None,
+            counters: CacheCounters::default(),
+            value: PhantomData,
+        };
+        cache
+            .run(|connection| redis::cmd("PING").query::<String>(connection))
+            .with_context(|| format!("Failed to connect to Redis at {}", url))?;
+        Ok(cache)
+    }
+
+    /// Expire entries `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, hex::encode(Sha256::digest(key)))
+    }
+
+    /// Run `command` on the connection, connecting first if needed
+    fn run<T>(&self, command: impl FnOnce(&mut Connection) -> RedisResult<T>) -> Result<T> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            let new = self.client.get_connection_with_timeout(REDIS_TIMEOUT)?;
+            new.set_read_timeout(Some(REDIS_TIMEOUT))?;
+            new.set_write_timeout(Some(REDIS_TIMEOUT))?;
+            *connection = Some(new);
+        }
+        let result = command(connection.as_mut().unwrap());
+        if result.is_err() {
+            *connection = None;
+        }
+        Ok(result?)
+    }
+
+    /// Keys of every entry
+    fn keys(&self) -> Result<Vec<String>> {
+        let pattern = format!("{}*", self.prefix);
+        self.run(|connection| connection.scan_match::<_, String>(&pattern).map(Iterator::collect))
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> Cache<str, V> for RedisCache<V> {
+    fn get(&self, key: &str) -> Option<V> {
+        let json = self
+            .run(|connection| connection.get::<_, Option<String>>(self.redis_key(key)))
+            .unwrap_or_else(|e| {
+                log::warn!("Redis cache lookup failed: {}", e);
+                None
+            });
+        match json.and_then(|json| serde_json::from_str(&json).ok()) {
+            Some(value) => {
+                CacheCounters::count(&self.counters.hits, 1);
+                Some(value)
+            }
+            None => {
+                CacheCounters::count(&self.counters.misses, 1);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, value: V) {
+        let stored = serde_json::to_string(&value).map_err(anyhow::Error::from).and_then(|json| {
+            let key = self.redis_key(key);
+            self.run(|connection| match self.ttl {
+                Some(ttl) => connection.pset_ex::<_, _, ()>(key, json, ttl.as_millis().max(1) as u64),
+                None => connection.set::<_, _, ()>(key, json),
+            })
+        });
+        if let Err(e) = stored {
+            log::warn!("Failed to cache entry in Redis: {}", e);
+        }
+    }
+
+    fn invalidate(&self, key: &str) -> bool {
+        self.run(|connection| connection.del::<_, usize>(self.redis_key(key)))
+            .is_ok_and(|removed| removed > 0)
+    }
+
+    fn invalidate_where(&self, stale: &dyn Fn(&V) -> bool) -> usize {
+        let invalidated = self.keys().and_then(|keys| {
+            let mut removed = 0;
+            for key in keys {
+                let json: Option<String> = self.run(|connection| connection.get(&key))?;
+                let value = json.and_then(|json| serde_json::from_str(&json).ok());
+                if value.is_some_and(|value| stale(&value)) {
+                    removed += self.run(|connection| connection.del::<_, usize>(&key))?;
+                }
+            }
+            Ok(removed)
+        });
+        invalidated.unwrap_or_else(|e| {
+            log::warn!("Failed to invalidate Redis cache entries: {}", e);
+            0
+        })
+    }
+
+    fn stats(&self) -> CacheStats {
+        let size = self.keys().map(|keys| keys.len()).unwrap_or_default();
+        self.counters.stats(size, 0, self.ttl)
+    }
+}
@@ -0,0 +1,143 @@
+use crate::models::{ChatMessage, SearchResult};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Retrieved chunks scored per answer; the rest keep their retrieval order
+pub const RERANK_CANDIDATES: usize = 20;
+
+/// Chunks scored in one LLM call
+pub const RERANK_BATCH_SIZE: usize = 10;
+
+/// Characters of each chunk shown to the scoring model
+const PASSAGE_CHARS: usize = 1200;
+
+/// How retrieved chunks are ordered before context assembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RerankMode {
+    /// Keep the vector store's similarity order
+    #[default]
+    Off,
+    /// A (preferably small and fast) model scores each chunk's relevance
+    Llm,
+}
+
+impl std::str::FromStr for RerankMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "none" => Ok(RerankMode::Off),
+            "llm" => Ok(RerankMode::Llm),
+            other => Err(format!("Unknown rerank mode: {}", other)),
+        }
+    }
+}
+
+/// Prompt asking for a 0-10 relevance score for each numbered passage
+pub fn rerank_messages(query: &str, passages: &[&str]) -> Vec<ChatMessage> {
+    let numbered: Vec<String> = passages
+        .iter()
+        .enumerate()
+        .map(|(i, passage)| {
+            let passage: String = passage.chars().take(PASSAGE_CHARS).collect();
+            format!("[{}] {}", i + 1, passage.replace('\n', " "))
+        })
+        .collect();
+
+    vec![
+        ChatMessage::system(
+            "You rate how useful passages are for answering a question. 10 means the passage answers it directly, 0 means it is unrelated. Reply with JSON only.",
+        ),
+        ChatMessage::user(format!(
+            "Question: {}\n\nPassages:\n{}\n\nRespond with a JSON array containing one object per passage: [{{\"passage\": <number>, \"score\": <0-10>}}]",
+            query,
+            numbered.join("\n\n")
+        )),
+    ]
+}
+
+/// Relevance of each of `count` passages from a scoring response, scaled to
+/// 0.0-1.0. Passages the model skipped score 0.
+pub fn parse_scores(response: &str, count: usize) -> Result<Vec<f32>> {
+    let start = response.find('[').ok_or_else(|| anyhow!("No JSON array in rerank response"))?;
+    let end = response.rfind(']').ok_or_else(|| anyhow!("No JSON array in rerank response"))?;
+    let ratings: Vec<serde_json::Value> = serde_json::from_str(&response[start..=end])?;
+
+    let mut scores = vec![0.0; count];
+    let mut rated = 0;
+    for rating in ratings {
+        let (Some(n), Some(score)) = (rating["passage"].as_u64(), rating["score"].as_f64()) else {
+            continue;
+        };
+        if let Some(slot) = (n as usize).checked_sub(1).and_then(|i| scores.get_mut(i)) {
+            *slot = (score as f32 / 10.0).clamp(0.0, 1.0);
+            rated += 1;
+        }
+    }
+
+    if rated == 0 {
+        return Err(anyhow!("Rerank response scored no passages"));
+    }
+    Ok(scores)
+}
+
+/// `chunks` with the first `scores.len()` ordered by score, highest first,
+/// followed by any unscored chunks in their original order
+pub fn apply_scores(chunks: &[SearchResult], scores: &[f32]) -> Vec<SearchResult> {
+    let mut scored: Vec<SearchResult> = chunks
+        .iter()
+        .zip(scores)
+        .map(|(chunk, score)| SearchResult {
+            rerank_score: Some(*score),
+            ..chunk.clone()
+        })
+        .collect();
+    // Stable, so equal scores keep the retrieval order
+    scored.sort_by(|a, b| b.rerank_score.partial_cmp(&a.rerank_score).unwrap());
+
+    scored.extend(chunks.iter().skip(scores.len()).cloned());
+    scored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(text: &str) -> SearchResult {
+        SearchResult {
+            file_path: "policy.txt".to_string(),
+            file_name: "policy.txt".to_string(),
+            file_type: ".txt".to_string(),
+            chunk_id: 0,
+            chunk_size: text.len(),
+            text: text.to_string(),
+            similarity_score: 0.5,
+            parent_id: None,
+            parent_text: None,
+            location: Default::default(),
+            rerank_score: None,
+            highlights: None,
+            document_date: None,
+        }
+    }
+
+    #[test]
+    fn test_scores_reorder_chunks() {
+        let response = "Here you go:\n[{\"passage\": 1, \"score\": 2}, {\"passage\": 2, \"score\": 9}, {\"passage\": 7, \"score\": 10}]";
+        let scores = parse_scores(response, 3).unwrap();
+        assert_eq!(scores, vec![0.2, 0.9, 0.0]);
+        assert!(parse_scores("no idea", 3).is_err());
+        assert!(parse_scores("[{\"passage\": 9, \"score\": 5}]", 3).is_err());
+
+        let chunks = [chunk("office hours"), chunk("25 days of PTO"), chunk("parking"), chunk("holidays")];
+        let reranked = apply_scores(&chunks, &scores);
+        let texts: Vec<&str> = reranked.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(texts, vec!["25 days of PTO", "office hours", "parking", "holidays"]);
+        assert_eq!(reranked[0].rerank_score, Some(0.9));
+        assert_eq!(reranked[3].rerank_score, None);
+
+        assert_eq!("LLM".parse::<RerankMode>().unwrap(), RerankMode::Llm);
+        assert!("cross-encoder".parse::<RerankMode>().is_err());
+    }
+}
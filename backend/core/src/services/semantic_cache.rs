@@ -0,0 +1,168 @@
+use crate::services::cache_manager::{CacheCounters, CacheStats};
+use crate::services::vector_store::{cosine_similarity, IndexChange};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Answers cached per query embedding, kept up to this many by default
+pub const DEFAULT_SEMANTIC_CACHE_SIZE: usize = 1000;
+
+struct SemanticEntry {
+    /// Options, filters, and caller the response was generated for; only
+    /// queries asked the same way share an answer
+    scope: String,
+    query: String,
+    embedding: Vec<f32>,
+    response: serde_json::Value,
+    stored_at: Instant,
+}
+
+/// A cached response to a query close enough to the one asked
+#[derive(Debug, Clone)]
+pub struct SemanticHit {
+    /// The query the response was generated for
+    pub query: String,
+    pub similarity: f32,
+    pub response: serde_json::Value,
+}
+
+/// Responses served again for queries whose embedding is within
+/// `threshold` cosine similarity of one already answered, so rephrasings
+/// of a frequent question skip the LLM. Entries are dropped whenever the
+/// indexed documents change: answers without sources may now have one, and
+/// query embeddings depend on every document's term frequencies.
+pub struct SemanticCache {
+    threshold: f32,
+    max_size: usize,
+    ttl: Option<Duration>,
+    /// Least recently used first
+    entries: Mutex<VecDeque<SemanticEntry>>,
+    counters: CacheCounters,
+}
+
+impl SemanticCache {
+    pub fn new(threshold: f32, max_size: usize) -> Self {
+        SemanticCache {
+            threshold,
+            max_size,
+            ttl: None,
+            entries: Mutex::new(VecDeque::new()),
+            counters: CacheCounters::default(),
+        }
+    }
+
+    /// Expire responses `ttl` after they're stored
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Drop every expired response, returning how many there were
+    pub fn purge_expired(&self) -> usize {
+        self.remove_expired(&mut self.entries.lock().unwrap())
+    }
+
+    fn remove_expired(&self, entries: &mut VecDeque<SemanticEntry>) -> usize {
+        let Some(ttl) = self.ttl else {
+            return 0;
+        };
+        let before = entries.len();
+        entries.retain(|entry| entry.stored_at.elapsed() < ttl);
+        CacheCounters::count(&self.counters.expirations, before - entries.len());
+        before - entries.len()
+    }
+
+    /// The response to the most similar query asked in `scope`, if it's
+    /// within the threshold
+    pub fn get(&self, scope: &str, embedding: &[f32]) -> Option<SemanticHit> {
+        let mut entries = self.entries.lock().unwrap();
+        self.remove_expired(&mut entries);
+
+        let best = entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.scope == scope)
+            .map(|(i, entry)| (i, cosine_similarity(embedding, &entry.embedding)))
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let Some((i, similarity)) = best else {
+            CacheCounters::count(&self.counters.misses, 1);
+            return None;
+        };
+        CacheCounters::count(&self.counters.hits, 1);
+        let entry = entries.remove(i)?;
+        let hit = SemanticHit {
+            query: entry.query.clone(),
+            similarity,
+            response: entry.response.clone(),
+        };
+        entries.push_back(entry);
+        Some(hit)
+    }
+
+    /// Cache `response` to `query`, asked in `scope`
+    pub fn put(&self, scope: &str, query: &str, embedding: Vec<f32>, response: &serde_json::Value) {
+        if self.max_size == 0 || embedding.iter().all(|x| *x == 0.0) {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.scope != scope || entry.query != query);
+        while entries.len() >= self.max_size {
+            entries.pop_front();
+            CacheCounters::count(&self.counters.evictions, 1);
+        }
+        entries.push_back(SemanticEntry {
+            scope: scope.to_string(),
+            query: query.to_string(),
+            embedding,
+            response: response.clone(),
+            stored_at: Instant::now(),
+        });
+    }
+
+    /// Drop the responses `change` could have made stale, which is all of
+    /// them. Returns how many were dropped.
+    pub fn invalidate(&self, _change: &IndexChange) -> usize {
+        let mut entries = self.entries.lock().unwrap();
+        let dropped = entries.len();
+        entries.clear();
+        dropped
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let size = self.entries.lock().unwrap().len();
+        self.counters.stats(size, self.max_size, self.ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_similar_queries_share_answers_until_documents_change() {
+        let cache = SemanticCache::new(0.9, 10);
+        let answer = json!({"answer": "30 days", "sources": [{"file_path": "uploads/policy.pdf"}]});
+        cache.put("default", "How long do refunds take?", vec![1.0, 0.2, 0.0], &answer);
+
+        let hit = cache.get("default", &[1.0, 0.25, 0.0]).unwrap();
+        assert_eq!(hit.query, "How long do refunds take?");
+        assert_eq!(hit.response["answer"], "30 days");
+        assert!(hit.similarity > 0.99);
+
+        // Not for other scopes or distant queries
+        assert!(cache.get("model=big", &[1.0, 0.2, 0.0]).is_none());
+        assert!(cache.get("default", &[0.0, 0.2, 1.0]).is_none());
+
+        // A document the answer didn't draw on could still answer the query
+        // now, and an answer with no sources is no better
+        cache.put("default", "What's the warranty?", vec![0.0, 1.0, 0.0], &json!({"answer": "", "sources": []}));
+        assert_eq!(cache.invalidate(&IndexChange::Documents(vec!["uploads/faq.md".to_string()])), 2);
+        assert!(cache.get("default", &[1.0, 0.2, 0.0]).is_none());
+        assert!(cache.get("default", &[0.0, 1.0, 0.0]).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.size), (1, 4, 0));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::models::{ChatMessage, DocumentChunk, SummaryStyle};
+
+/// Chunks used by the stuff strategy, which puts them all in one prompt
+pub const STUFF_CHUNKS: usize = 5;
+
+/// Chunks used by map-reduce and refine, which read them one at a time
+pub const MAX_SYNTHESIS_CHUNKS: usize = 20;
+
+/// Characters of consecutive chunks summarized together
+pub const SECTION_CHARS: usize = 6000;
+
+/// Map-step reply for a passage with nothing relevant to the question
+const NOTHING_RELEVANT: &str = "NONE";
+
+/// Messages extracting what one numbered passage says about the question
+pub fn map_messages(query: &str, block: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(
+            "You extract facts from a single passage. Be brief and use only what the passage says.",
+        ),
+        ChatMessage::user(format!(
+            "Question: {}\n\nPassage:\n{}\n\nList the facts in this passage that help answer the question, in a few sentences. If nothing is relevant, reply exactly: {}",
+            query, block, NOTHING_RELEVANT
+        )),
+    ]
+}
+
+/// Whether a map-step reply found anything
+pub fn is_relevant(note: &str) -> bool {
+    let note = note.trim().trim_end_matches('.');
+    !note.is_empty() && !note.eq_ignore_ascii_case(NOTHING_RELEVANT)
+}
+
+/// Messages improving `answer` with one more numbered passage
+pub fn refine_messages(system_prompt: &str, query: &str, answer: &str, block: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(format!(
+            "Question: {}\n\nCurrent answer:\n{}\n\nAdditional context:\n{}\n\nRefine the current answer with the additional context if it is relevant, citing it by its number in square brackets and keeping the existing citations. If it adds nothing, repeat the current answer unchanged. Reply with the answer only.",
+            query, answer, block
+        )),
+    ]
+}
+
+/// Consecutive chunks grouped into sections of at most `SECTION_CHARS`
+/// characters; an oversized chunk forms its own section
+pub fn group_sections(chunks: &[DocumentChunk]) -> Vec<&[DocumentChunk]> {
+    let mut sections = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let len = chunk.text.chars().count();
+        if i > start && chars + len > SECTION_CHARS {
+            sections.push(&chunks[start..i]);
+            start = i;
+            chars = 0;
+        }
+        chars += len;
+    }
+    if start < chunks.len() {
+        sections.push(&chunks[start..]);
+    }
+
+    sections
+}
+
+fn style_instruction(style: SummaryStyle) -> &'static str {
+    match style {
+        SummaryStyle::Executive => {
+            "Write an executive summary: one short overview paragraph, then the key points, decisions, and figures as bullet points."
+        }
+        SummaryStyle::Bullets => "Write the summary as concise bullet points only.",
+        SummaryStyle::Detailed => {
+            "Write a detailed summary that covers each major part of the document in its own paragraph."
+        }
+        SummaryStyle::Brief => "Write a brief summary of two or three sentences.",
+    }
+}
+
+/// Messages summarizing one section of a document
+pub fn section_summary_messages(file_name: &str, section: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(
+            "You summarize sections of documents accurately, keeping names, numbers, and conclusions. Use only what the section says.",
+        ),
+        ChatMessage::user(format!(
+            "Summarize this section of {} in a short paragraph.\n\nSection:\n{}",
+            file_name, section
+        )),
+    ]
+}
+
+/// Messages combining numbered section summaries into one document summary
+pub fn combine_summary_messages(
+    system_prompt: &str,
+    style: SummaryStyle,
+    file_name: &str,
+    section_summaries: &str,
+) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(format!(
+            "Below are numbered summaries of consecutive sections of {}.\n\n{}\n\n{} Cite the sections each point comes from by their numbers in square brackets, e.g. [1] or [2, 3].",
+            file_name,
+            section_summaries,
+            style_instruction(style)
+        )),
+    ]
+}
+
+/// Messages answering one sub-question of a multi-step query from its own
+/// passages
+pub fn sub_question_messages(sub_question: &str, context: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(
+            "You answer one step of a larger question. Be brief and use only what the passages say.",
+        ),
+        ChatMessage::user(format!(
+            "Passages:\n{}\n\nQuestion: {}\n\nAnswer in a few sentences. If the passages don't answer it, say what is missing.",
+            context, sub_question
+        )),
+    ]
+}
+
+/// Messages combining the answers to a question's sub-questions, with the
+/// passages behind them numbered for citation
+pub fn step_synthesis_messages(system_prompt: &str, query: &str, steps: &str, context: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(format!(
+            "Context:\n{}\n\nThe question was broken into steps, answered from the context:\n{}\n\nUsing the step answers and the context, answer: {}\n\nCite the context you rely on by its numbers in square brackets, e.g. [1] or [2, 3]. If a step could not be answered, say what is missing rather than guessing.",
+            context, steps, query
+        )),
+    ]
+}
+
+/// Context blocks grouped under a heading per document, numbered across
+/// all documents so citations stay unambiguous
+pub fn comparison_context(documents: &[(&str, Vec<&str>)]) -> String {
+    let mut number = 0;
+    documents
+        .iter()
+        .map(|(file_name, blocks)| {
+            let numbered: Vec<String> = blocks
+                .iter()
+                .map(|block| {
+                    number += 1;
+                    format!("[{}] {}", number, block)
+                })
+                .collect();
+            let body = if numbered.is_empty() {
+                "(nothing relevant found)".to_string()
+            } else {
+                numbered.join("\n\n")
+            };
+            format!("Document: {}\n{}", file_name, body)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n---\n\n")
+}
+
+/// Messages asking for a comparison of the documents in `context` on `query`
+pub fn comparison_messages(system_prompt: &str, query: &str, context: &str) -> Vec<ChatMessage> {
+    vec![
+        ChatMessage::system(system_prompt),
+        ChatMessage::user(format!(
+            "Excerpts from several documents follow, grouped by document.\n\n{}\n\nCompare the documents on: {}\n\nCover every document, point out where they agree and where they differ, and name the document each point comes from. If a document says nothing on a point, say so rather than guessing. Cite the excerpts you use by their numbers in square brackets, e.g. [1] or [2, 3].",
+            context, query
+        )),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_relevant() {
+        assert!(is_relevant("[2] Refunds take 14 days."));
+        assert!(!is_relevant(" none. "));
+        assert!(!is_relevant(""));
+    }
+
+    #[test]
+    fn test_comparison_context_numbers_across_documents() {
+        let context = comparison_context(&[
+            ("a.pdf", vec!["Term is 12 months.", "Notice is 30 days."]),
+            ("b.pdf", vec!["Term is 24 months."]),
+            ("c.pdf", vec![]),
+        ]);
+        assert_eq!(
+            context,
+            "Document: a.pdf\n[1] Term is 12 months.\n\n[2] Notice is 30 days.\n\n---\n\nDocument: b.pdf\n[3] Term is 24 months.\n\n---\n\nDocument: c.pdf\n(nothing relevant found)"
+        );
+    }
+
+    #[test]
+    fn test_group_sections_packs_consecutive_chunks() {
+        let chunk = |chunk_id: usize, len: usize| DocumentChunk {
+            text: "x".repeat(len).into(),
+            size: len,
+            chunk_id,
+            parent_id: None,
+            location: Default::default(),
+        };
+        let chunks = vec![chunk(0, 4000), chunk(1, 1500), chunk(2, 1000), chunk(3, 9000), chunk(4, 10)];
+
+        let sizes: Vec<usize> = group_sections(&chunks).iter().map(|s| s.len()).collect();
+        assert_eq!(sizes, vec![2, 1, 1, 1]);
+        assert!(group_sections(&[]).is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+use crate::models::ToolCall;
+use crate::services::llm_providers::ToolSpec;
+use crate::services::VectorStore;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Something the model can call while generating an answer
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn spec(&self) -> ToolSpec;
+
+    /// Run with the model's decoded arguments, returning text for the model
+    async fn call(&self, arguments: serde_json::Value) -> Result<String>;
+}
+
+/// Tools available to the model, by name
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    tools: HashMap<String, Arc<dyn Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn register(&mut self, tool: Arc<dyn Tool>) {
+        self.tools.insert(tool.spec().name, tool);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Specs of all registered tools, sorted by name
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        let mut specs: Vec<ToolSpec> = self.tools.values().map(|tool| tool.spec()).collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
+    }
+
+    /// Run a tool call. Failures are returned as text so the model can
+    /// correct itself instead of aborting the answer.
+    pub async fn execute(&self, call: &ToolCall) -> String {
+        let Some(tool) = self.tools.get(&call.function.name) else {
+            return format!("Error: unknown tool '{}'", call.function.name);
+        };
+
+        let arguments = if call.function.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            match serde_json::from_str(&call.function.arguments) {
+                Ok(arguments) => arguments,
+                Err(e) => return format!("Error: arguments are not valid JSON: {}", e),
+            }
+        };
+
+        match tool.call(arguments).await {
+            Ok(output) => output,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+}
+
+fn string_argument<'a>(arguments: &'a serde_json::Value, name: &str) -> Result<&'a str> {
+    arguments[name]
+        .as_str()
+        .filter(|value| !value.trim().is_empty())
+        .ok_or_else(|| anyhow!("missing required argument '{}'", name))
+}
+
+/// Evaluates arithmetic expressions
+pub struct Calculator;
+
+#[async_trait]
+impl Tool for Calculator {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "calculator".to_string(),
+            description: "Evaluate an arithmetic expression with + - * / % ^ and parentheses, e.g. (3.5 + 2) * 4".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "expression": {"type": "string", "description": "Expression to evaluate"}
+                },
+                "required": ["expression"]
+            }),
+        }
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<String> {
+        let value = evaluate(string_argument(&arguments, "expression")?)?;
+        Ok(value.to_string())
+    }
+}
+
+/// Searches the knowledge base again with a query the model chooses
+pub struct SearchAgain {
+    vector_store: Arc<Mutex<VectorStore>>,
+    k: usize,
+}
+
+impl SearchAgain {
+    pub fn new(vector_store: Arc<Mutex<VectorStore>>, k: usize) -> Self {
+        SearchAgain { vector_store, k }
+    }
+}
+
+#[async_trait]
+impl Tool for SearchAgain {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "search_again".to_string(),
+            description: "Search the knowledge base for passages relevant to a new or refined query".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "What to search for"}
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<String> {
+        let query = string_argument(&arguments, "query")?;
+        let results = self.vector_store.lock().unwrap().search(query, self.k, 0.0)?;
+
+        if results.is_empty() {
+            return Ok("No matching passages found.".to_string());
+        }
+
+        Ok(results
+            .iter()
+            .map(|r| format!("({}, chunk {}) {}", r.file_name, r.chunk_id, r.text))
+            .collect::<Vec<_>>()
+            .join("\n\n"))
+    }
+}
+
+/// Reads an indexed document's text by id or file name
+pub struct FetchDocument {
+    vector_store: Arc<Mutex<VectorStore>>,
+    max_chars: usize,
+}
+
+impl FetchDocument {
+    pub fn new(vector_store: Arc<Mutex<VectorStore>>, max_chars: usize) -> Self {
+        FetchDocument { vector_store, max_chars }
+    }
+}
+
+#[async_trait]
+impl Tool for FetchDocument {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "fetch_document".to_string(),
+            description: "Read the text of an indexed document, identified by its document id or file name".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "document": {"type": "string", "description": "Document id or file name"}
+                },
+                "required": ["document"]
+            }),
+        }
+    }
+
+    async fn call(&self, arguments: serde_json::Value) -> Result<String> {
+        let document = string_argument(&arguments, "document")?;
+        let text = self
+            .vector_store
+            .lock()
+            .unwrap()
+            .document_text(document)
+            .ok_or_else(|| anyhow!("no indexed document matches '{}'", document))?;
+
+        if text.chars().count() <= self.max_chars {
+            return Ok(text);
+        }
+        let truncated: String = text.chars().take(self.max_chars).collect();
+        Ok(format!("{}\n[truncated]", truncated))
+    }
+}
+
+/// Evaluate an arithmetic expression
+fn evaluate(expression: &str) -> Result<f64> {
+    let mut parser = ExpressionParser {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+    };
+    let value = parser.expression()?;
+    if parser.pos < parser.chars.len() {
+        return Err(anyhow!("unexpected '{}' in expression", parser.chars[parser.pos]));
+    }
+    if !value.is_finite() {
+        return Err(anyhow!("result is not a finite number"));
+    }
+    Ok(value)
+}
+
+/// Recursive-descent parser: expression := term (('+' | '-') term)*,
+/// term := power (('*' | '/' | '%') power)*, power := unary ('^' power)?
+struct ExpressionParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expression(&mut self) -> Result<f64> {
+        let mut value = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64> {
+        let mut value = self.power()?;
+        while let Some(op @ ('*' | '/' | '%')) = self.peek() {
+            self.pos += 1;
+            let rhs = self.power()?;
+            value = match op {
+                '*' => value * rhs,
+                _ if rhs == 0.0 => return Err(anyhow!("division by zero")),
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Ok(value)
+    }
+
+    fn power(&mut self) -> Result<f64> {
+        let base = self.unary()?;
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            return Ok(base.powf(self.power()?));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<f64> {
+        match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                Ok(-self.unary()?)
+            }
+            Some('+') => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<f64> {
+        if self.peek() == Some('(') {
+            self.pos += 1;
+            let value = self.expression()?;
+            if self.peek() != Some(')') {
+                return Err(anyhow!("missing closing parenthesis"));
+            }
+            self.pos += 1;
+            return Ok(value);
+        }
+
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return match self.peek() {
+                Some(c) => Err(anyhow!("unexpected '{}' in expression", c)),
+                None => Err(anyhow!("unexpected end of expression")),
+            };
+        }
+
+        let number: String = self.chars[start..self.pos].iter().collect();
+        number
+            .parse::<f64>()
+            .map_err(|_| anyhow!("invalid number '{}'", number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FunctionCall;
+
+    #[test]
+    fn test_evaluate_arithmetic() {
+        assert_eq!(evaluate("1 + 2 * 3").unwrap(), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3").unwrap(), 9.0);
+        assert_eq!(evaluate("2 ^ 3 ^ 2").unwrap(), 512.0);
+        assert_eq!(evaluate("-4 + 10 % 3").unwrap(), -3.0);
+        assert_eq!(evaluate("7.5 / 2.5").unwrap(), 3.0);
+        assert!(evaluate("1 / 0").is_err());
+        assert!(evaluate("2 +").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("rm -rf").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_registry_reports_errors_as_text() {
+        let mut registry = ToolRegistry::default();
+        registry.register(Arc::new(Calculator));
+
+        let call = |name: &str, arguments: &str| ToolCall {
+            id: "call_1".to_string(),
+            kind: "function".to_string(),
+            function: FunctionCall {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+
+        assert_eq!(registry.execute(&call("calculator", r#"{"expression": "6*7"}"#)).await, "42");
+        assert!(registry.execute(&call("calculator", "{}")).await.starts_with("Error:"));
+        assert!(registry.execute(&call("calculator", "not json")).await.starts_with("Error:"));
+        assert!(registry.execute(&call("weather", "{}")).await.starts_with("Error: unknown tool"));
+    }
+}
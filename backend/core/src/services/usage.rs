@@ -0,0 +1,213 @@
+use crate::services::llm_providers::TokenUsage;
+use chrono::{Duration, NaiveDate, Utc};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// USD price per million tokens of one model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+/// Parse `model=input:output` pairs separated by commas, e.g.
+/// `openai/gpt-oss-120b=0.15:0.75`. Malformed entries are skipped.
+pub fn parse_prices(spec: &str) -> HashMap<String, ModelPrice> {
+    let mut prices = HashMap::new();
+
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let parsed = entry.rsplit_once('=').and_then(|(model, price)| {
+            let (input, output) = price.split_once(':')?;
+            Some((
+                model.trim().to_string(),
+                ModelPrice {
+                    input_per_million: input.trim().parse().ok()?,
+                    output_per_million: output.trim().parse().ok()?,
+                },
+            ))
+        });
+
+        match parsed {
+            Some((model, price)) => {
+                prices.insert(model, price);
+            }
+            None => log::warn!("Ignoring malformed LLM price '{}', expected model=input:output", entry),
+        }
+    }
+
+    prices
+}
+
+/// Token counts accumulated over some set of requests
+#[derive(Debug, Clone, Copy, Default)]
+struct UsageTotals {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    /// Requests whose provider reported no usage, counted from estimates
+    estimated_requests: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.estimated_requests += other.estimated_requests;
+    }
+
+    fn cost(&self, price: &ModelPrice) -> f64 {
+        (self.prompt_tokens as f64 * price.input_per_million
+            + self.completion_tokens as f64 * price.output_per_million)
+            / 1_000_000.0
+    }
+
+    fn to_json(self, cost_usd: Option<f64>) -> serde_json::Value {
+        json!({
+            "requests": self.requests,
+            "prompt_tokens": self.prompt_tokens,
+            "completion_tokens": self.completion_tokens,
+            "total_tokens": self.prompt_tokens + self.completion_tokens,
+            "estimated_requests": self.estimated_requests,
+            "cost_usd": cost_usd
+        })
+    }
+}
+
+/// In-memory LLM token usage per UTC day, provider and model
+#[derive(Default)]
+pub struct UsageTracker {
+    prices: HashMap<String, ModelPrice>,
+    totals: Mutex<BTreeMap<(NaiveDate, String, String), UsageTotals>>,
+}
+
+impl UsageTracker {
+    pub fn new(prices: HashMap<String, ModelPrice>) -> Self {
+        UsageTracker {
+            prices,
+            totals: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Count one completion against today
+    pub fn record(&self, provider: &str, model: &str, usage: TokenUsage, estimated: bool) {
+        self.record_on(Utc::now().date_naive(), provider, model, usage, estimated);
+    }
+
+    fn record_on(&self, date: NaiveDate, provider: &str, model: &str, usage: TokenUsage, estimated: bool) {
+        let mut totals = self.totals.lock().unwrap();
+        totals
+            .entry((date, provider.to_string(), model.to_string()))
+            .or_default()
+            .add(&UsageTotals {
+                requests: 1,
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                estimated_requests: estimated as u64,
+            });
+    }
+
+    /// Totals overall, per model and per day, for the last `days` days
+    /// (including today) or for all recorded usage. Costs only cover models
+    /// with a configured price.
+    pub fn report(&self, days: Option<u32>) -> serde_json::Value {
+        let since = days.map(|days| Utc::now().date_naive() - Duration::days(i64::from(days.max(1)) - 1));
+        self.report_since(since)
+    }
+
+    fn report_since(&self, since: Option<NaiveDate>) -> serde_json::Value {
+        let totals = self.totals.lock().unwrap();
+
+        let mut overall = UsageTotals::default();
+        let mut overall_cost = 0.0;
+        let mut by_model: BTreeMap<(&str, &str), UsageTotals> = BTreeMap::new();
+        let mut by_day: BTreeMap<NaiveDate, (UsageTotals, f64)> = BTreeMap::new();
+
+        for ((date, provider, model), usage) in totals.iter() {
+            if since.is_some_and(|since| *date < since) {
+                continue;
+            }
+            let cost = self.prices.get(model).map_or(0.0, |price| usage.cost(price));
+
+            overall.add(usage);
+            overall_cost += cost;
+            by_model.entry((provider.as_str(), model.as_str())).or_default().add(usage);
+            let day = by_day.entry(*date).or_default();
+            day.0.add(usage);
+            day.1 += cost;
+        }
+
+        let by_model: Vec<serde_json::Value> = by_model
+            .into_iter()
+            .map(|((provider, model), usage)| {
+                let mut entry = usage.to_json(self.prices.get(model).map(|price| usage.cost(price)));
+                entry["provider"] = json!(provider);
+                entry["model"] = json!(model);
+                entry
+            })
+            .collect();
+
+        let by_day: Vec<serde_json::Value> = by_day
+            .into_iter()
+            .map(|(date, (usage, cost))| {
+                let mut entry = usage.to_json(Some(cost));
+                entry["date"] = json!(date.to_string());
+                entry
+            })
+            .collect();
+
+        json!({
+            "since": since.map(|date| date.to_string()),
+            "totals": overall.to_json(Some(overall_cost)),
+            "by_model": by_model,
+            "by_day": by_day
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_parse_prices_skips_malformed_entries() {
+        let prices = parse_prices("openai/gpt-oss-120b=0.15:0.75, broken, llama3.1=free:0");
+        assert_eq!(prices.len(), 1);
+        assert_eq!(prices["openai/gpt-oss-120b"].output_per_million, 0.75);
+    }
+
+    #[test]
+    fn test_report_aggregates_by_model_and_day() {
+        let tracker = UsageTracker::new(parse_prices("paid=1:2"));
+        let day1 = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let day2 = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+
+        tracker.record_on(day1, "groq", "paid", usage(1_000_000, 500_000), false);
+        tracker.record_on(day2, "groq", "paid", usage(1_000_000, 0), false);
+        tracker.record_on(day2, "ollama", "free", usage(100, 50), true);
+
+        let report = tracker.report_since(None);
+        assert_eq!(report["totals"]["requests"], 3);
+        assert_eq!(report["totals"]["total_tokens"], 2_500_150);
+        assert_eq!(report["totals"]["estimated_requests"], 1);
+        assert_eq!(report["totals"]["cost_usd"], 3.0);
+        assert_eq!(report["by_model"][0]["model"], "paid");
+        assert_eq!(report["by_model"][0]["cost_usd"], 3.0);
+        assert_eq!(report["by_model"][1]["provider"], "ollama");
+        assert!(report["by_model"][1]["cost_usd"].is_null());
+        assert_eq!(report["by_day"][0]["date"], "2026-03-01");
+        assert_eq!(report["by_day"][0]["cost_usd"], 2.0);
+
+        let recent = tracker.report_since(Some(day2));
+        assert_eq!(recent["totals"]["requests"], 2);
+        assert_eq!(recent["by_day"].as_array().unwrap().len(), 1);
+    }
+}
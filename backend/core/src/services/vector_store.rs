@@ -0,0 +1,2028 @@
+use crate::models::{
+    document_id, ChunkingStrategy, DocumentChunk, DocumentLabels, DocumentMetadata, ProcessedDocument, RetrievalMode,
+    SearchFilters, SearchResult,
+};
+use crate::services::cache_manager::{Cache, EmbeddingCache};
+use crate::services::document_dates::DocumentDate;
+use crate::services::knowledge_graph::KnowledgeGraph;
+use crate::services::vocabulary::{tokens, TokenId, Vocabulary};
+use anyhow::{anyhow, Result};
+use log::info;
+use serde_json::json;
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Candidates listed by `explain` at the least, however small `k` is
+const MIN_EXPLAINED_CANDIDATES: usize = 20;
+
+/// Query embeddings kept for repeated searches
+pub const DEFAULT_EMBEDDING_CACHE_SIZE: usize = 1000;
+
+/// The saved store: chunks, vectors, and everything derived from them in
+/// MessagePack, gzipped when compression is on
+const STORE_FILE: &str = "store.bin";
+
+/// Files of stores saved as JSON, read when there's no `STORE_FILE` and
+/// removed once it's written
+const LEGACY_FILES: [&str; 4] = ["metadata.json", "document_map.json", "parent_sections.json", "graph.json"];
+
+/// Documents indexed since the store was last saved whole, one
+/// length-prefixed MessagePack record per `add_documents`
+const STORE_LOG: &str = "store.log";
+
+/// First bytes of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// What `save_store` writes to `STORE_FILE`
+#[derive(serde::Serialize)]
+struct SavedStore<'a> {
+    metadata: &'a [DocumentMetadata],
+    document_map: &'a HashMap<String, DocumentInfo>,
+    parent_sections: &'a HashMap<String, Vec<Arc<str>>>,
+    graph: &'a KnowledgeGraph,
+    #[serde(serialize_with = "serialize_dimensions")]
+    vocabulary: &'a Vocabulary,
+    #[serde(serialize_with = "serialize_doc_frequencies")]
+    doc_frequencies: &'a Vocabulary,
+    vectors: &'a [Vec<f32>],
+}
+
+fn serialize_dimensions<S: serde::Serializer>(vocabulary: &&Vocabulary, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(vocabulary.dimension_map())
+}
+
+fn serialize_doc_frequencies<S: serde::Serializer>(
+    vocabulary: &&Vocabulary,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(vocabulary.doc_frequency_map())
+}
+
+/// One `add_documents` call as recorded in `STORE_LOG`: the documents'
+/// chunks and vectors, and the vocabulary they added or changed
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LoggedDocuments<'a> {
+    documents: Vec<LoggedDocument>,
+    metadata: Cow<'a, [DocumentMetadata]>,
+    vectors: Cow<'a, [Vec<f32>]>,
+    vocabulary: Vec<(String, usize)>,
+    doc_frequencies: Vec<(String, usize)>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct LoggedDocument {
+    file_path: String,
+    info: DocumentInfo,
+    parent_sections: Vec<Arc<str>>,
+}
+
+/// What `load_store` reads back from `STORE_FILE`
+#[derive(serde::Deserialize)]
+struct LoadedStore {
+    metadata: Vec<DocumentMetadata>,
+    document_map: HashMap<String, DocumentInfo>,
+    parent_sections: HashMap<String, Vec<Arc<str>>>,
+    graph: KnowledgeGraph,
+    vocabulary: HashMap<String, usize>,
+    doc_frequencies: HashMap<String, usize>,
+    vectors: Vec<Vec<f32>>,
+}
+
+/// What changed in the index, for whatever was derived from its contents
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexChange {
+    /// These documents were added, re-indexed, or deleted
+    Documents(Vec<String>),
+    /// Every document was removed
+    Cleared,
+}
+
+/// Called after the index changes, e.g. to drop cached answers
+pub type ChangeHook = Box<dyn Fn(&IndexChange) + Send + Sync>;
+
+pub struct VectorStore {
+    store_path: PathBuf,
+    embedding_model: String,
+    dimension: usize,
+    metadata: Vec<DocumentMetadata>,
+    document_map: HashMap<String, DocumentInfo>,
+    vectors: Vec<Vec<f32>>,
+    /// Norm and largest weight of each vector, aligned with `vectors`
+    vector_stats: Vec<VectorStats>,
+    vocabulary: Vocabulary,
+    /// Parent section texts per file_path, indexed by parent_id
+    parent_sections: HashMap<String, Vec<Arc<str>>>,
+    /// Entities and relations extracted from the chunks
+    graph: KnowledgeGraph,
+    change_hooks: Vec<ChangeHook>,
+    /// Embeddings of recent queries; dropped on every change, as the
+    /// vocabulary and document frequencies they're weighted by change too
+    embedding_cache: Arc<dyn Cache<str, Vec<f32>>>,
+    /// Gzip the saved store
+    compress: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct DocumentInfo {
+    pub file_name: String,
+    pub file_type: String,
+    pub num_chunks: usize,
+    pub file_size: u64,
+    #[serde(default)]
+    pub chunking_strategy: ChunkingStrategy,
+    #[serde(default)]
+    pub date: Option<DocumentDate>,
+    /// Name of the API key that added the document; documents without an
+    /// owner are visible to everyone
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Users besides the owner who may search the document, "*" for all
+    #[serde(default)]
+    pub shared_with: Vec<String>,
+    /// When the document was first indexed, RFC 3339; re-indexing keeps it.
+    /// Unset for documents indexed before it was recorded.
+    #[serde(default)]
+    pub indexed_at: Option<String>,
+    #[serde(flatten)]
+    pub labels: DocumentLabels,
+}
+
+/// A document as exported from the store, enough to index it again here or
+/// in another instance
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExportedDocument {
+    pub file_path: String,
+    #[serde(flatten)]
+    pub info: DocumentInfo,
+    pub chunks: Vec<DocumentChunk>,
+    #[serde(default)]
+    pub parent_sections: Vec<Arc<str>>,
+}
+
+impl DocumentInfo {
+    /// Whether `user` may search and read the document; `None` sees
+    /// everything
+    pub fn visible_to(&self, user: Option<&str>) -> bool {
+        let (Some(user), Some(owner)) = (user, &self.owner) else {
+            return true;
+        };
+        owner == user || self.shared_with.iter().any(|u| u == user || u == "*")
+    }
+
+    /// Whether `user` may replace, share, or delete the document
+    pub fn modifiable_by(&self, user: Option<&str>) -> bool {
+        match (user, &self.owner) {
+            (Some(user), Some(owner)) => owner == user,
+            _ => true,
+        }
+    }
+}
+
+impl VectorStore {
+    pub fn new(store_path: &str, embedding_model: &str) -> Result<Self> {
+        let path = PathBuf::from(store_path);
+        fs::create_dir_all(&path)?;
+
+        let dimension = Self::get_dimension(embedding_model);
+
+        let mut store = VectorStore {
+            store_path: path,
+            embedding_model: embedding_model.to_string(),
+            dimension,
+            metadata: Vec::new(),
+            document_map: HashMap::new(),
+            vectors: Vec::new(),
+            vector_stats: Vec::new(),
+            vocabulary: Vocabulary::default(),
+            parent_sections: HashMap::new(),
+            graph: KnowledgeGraph::default(),
+            change_hooks: Vec::new(),
+            embedding_cache: Arc::new(EmbeddingCache::new(DEFAULT_EMBEDDING_CACHE_SIZE)),
+            compress: false,
+        };
+
+        store.load_store()?;
+        Ok(store)
+    }
+
+    /// Replace the default in-memory query embedding cache
+    pub fn with_embedding_cache(mut self, cache: Arc<dyn Cache<str, Vec<f32>>>) -> Self {
+        self.embedding_cache = cache;
+        self
+    }
+
+    /// Gzip the store when saving it, trading save time for disk space.
+    /// Either kind is read back regardless of this setting.
+    pub fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    /// Call `hook` after every change to the indexed documents
+    pub fn on_change(&mut self, hook: impl Fn(&IndexChange) + Send + Sync + 'static) {
+        self.change_hooks.push(Box::new(hook));
+    }
+
+    fn notify(&self, change: IndexChange) {
+        self.embedding_cache.invalidate_where(&|_| true);
+        for hook in &self.change_hooks {
+            hook(&change);
+        }
+    }
+
+    /// Index documents. A document whose file_path is already indexed has
+    /// its previous chunks replaced rather than duplicated.
+    pub fn add_documents(&mut self, documents: Vec<ProcessedDocument>) -> Result<()> {
+        self.add_owned_documents(documents, None)
+    }
+
+    /// Index documents as owned by `owner`. Re-indexed documents keep who
+    /// they're shared with, their owner when `owner` is `None`, and their
+    /// labels when given none.
+    #[tracing::instrument(name = "index", skip_all, fields(documents = documents.len()))]
+    pub fn add_owned_documents(&mut self, documents: Vec<ProcessedDocument>, owner: Option<&str>) -> Result<()> {
+        let mut all_texts = Vec::new();
+        let mut all_metadata = Vec::new();
+
+        for doc in &documents {
+            let file_path = &doc.file_path;
+            let file_name = &doc.file_name;
+            let file_type = &doc.file_type;
+
+            for chunk in &doc.chunks {
+                all_texts.push(chunk.text.clone());
+
+                let metadata = DocumentMetadata {
+                    file_path: file_path.clone(),
+                    file_name: file_name.clone(),
+                    file_type: file_type.clone(),
+                    chunk_id: chunk.chunk_id,
+                    chunk_size: chunk.size,
+                    text: chunk.text.clone(),
+                    parent_id: chunk.parent_id,
+                    location: chunk.location.clone(),
+                };
+                all_metadata.push(metadata);
+            }
+        }
+
+        if all_texts.is_empty() {
+            return Ok(());
+        }
+
+        // Update vocabulary first (for TF-IDF calculation), taking out the
+        // words of documents being replaced so they aren't counted twice.
+        // It's put back as it was if embedding fails.
+        let checkpoint = self.vocabulary.checkpoint();
+        let vocabulary_before = self.vocabulary.dimensioned();
+        let mut words: HashSet<TokenId> = HashSet::new();
+        for doc in &documents {
+            if self.document_map.contains_key(&doc.file_path) {
+                words.extend(self.forget_doc_frequencies(&doc.file_path));
+            }
+        }
+        words.extend(self.update_vocabulary(&documents));
+        let words: Vec<TokenId> = words.into_iter().collect();
+
+        // Generate semantic embeddings based on document content
+        let embeddings = match tracing::info_span!("embed", texts = all_texts.len())
+            .in_scope(|| self.generate_embeddings(&all_texts))
+        {
+            Ok(embeddings) => embeddings,
+            Err(e) => {
+                self.vocabulary.restore(checkpoint);
+                return Err(e);
+            }
+        };
+
+        // Drop chunks of documents being re-indexed only once the new
+        // embeddings are ready, so a failure leaves the old chunks in place
+        for doc in &documents {
+            self.remove_document_chunks(&doc.file_path);
+            self.graph.remove_document(&doc.file_path);
+            for chunk in &doc.chunks {
+                self.graph.add_chunk(&doc.file_path, chunk.chunk_id, &chunk.text);
+            }
+        }
+
+        // Add vectors and metadata
+        let first_chunk = self.metadata.len();
+        self.vector_stats.extend(embeddings.iter().map(|v| VectorStats::of(v)));
+        self.vectors.extend(embeddings);
+        self.metadata.extend(all_metadata);
+
+        // Update document map
+        let changed: Vec<String> = documents.iter().map(|doc| doc.file_path.clone()).collect();
+        let indexed_at = chrono::Utc::now().to_rfc3339();
+        for doc in documents {
+            let doc_id = doc.file_path.clone();
+            if doc.parent_chunks.is_empty() {
+                self.parent_sections.remove(&doc_id);
+            } else {
+                self.parent_sections.insert(
+                    doc_id.clone(),
+                    doc.parent_chunks.into_iter().map(|p| p.text).collect(),
+                );
+            }
+            let previous = self.document_map.remove(&doc_id);
+            let labels = match &previous {
+                Some(previous) if doc.labels.is_empty() => previous.labels.clone(),
+                _ => doc.labels,
+            };
+            self.document_map.insert(
+                doc_id,
+                DocumentInfo {
+                    file_name: doc.file_name,
+                    file_type: doc.file_type,
+                    num_chunks: doc.num_chunks,
+                    file_size: doc.file_size,
+                    chunking_strategy: doc.chunking_strategy,
+                    date: doc.date,
+                    owner: owner
+                        .map(str::to_string)
+                        .or_else(|| previous.as_ref().and_then(|p| p.owner.clone())),
+                    indexed_at: match &previous {
+                        Some(previous) => previous.indexed_at.clone(),
+                        None => Some(indexed_at.clone()),
+                    },
+                    shared_with: previous.map(|p| p.shared_with).unwrap_or_default(),
+                    labels,
+                },
+            );
+        }
+
+        tracing::info_span!("save_store")
+            .in_scope(|| self.log_added_documents(&changed, first_chunk, vocabulary_before, words))?;
+        self.notify(IndexChange::Documents(changed));
+        info!("Added {} vectors to store. Vocabulary size: {}", self.vectors.len(), self.vocabulary.dimensioned());
+        Ok(())
+    }
+
+    pub fn search(
+        &self,
+        query: &str,
+        k: usize,
+        score_threshold: f32,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_filtered(query, k, score_threshold, &SearchFilters::default())
+    }
+
+    /// Search only the chunks of documents and file types `filters` allows
+    #[tracing::instrument(name = "search", skip(self, query, filters), fields(results, scored))]
+    pub fn search_filtered(
+        &self,
+        query: &str,
+        k: usize,
+        score_threshold: f32,
+        filters: &SearchFilters,
+    ) -> Result<Vec<SearchResult>> {
+        if self.vectors.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let query_vec = &self.query_embedding(query)?;
+        let query = QueryStats::of(query_vec);
+        let allowed = self.allowed_chunks(filters);
+
+        // Score the allowed chunks in order of the most they could score,
+        // stopping once that can't beat the k-th best so far. Chunks that
+        // can't reach the threshold are never scored.
+        let mut candidates: Vec<(usize, f32)> = self
+            .vector_stats
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| allowed(&self.metadata[*idx]))
+            .map(|(idx, stats)| (idx, stats.bound(&query)))
+            .filter(|(_, bound)| *bound >= score_threshold)
+            .collect();
+        candidates.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut best = TopK::new(k);
+        let mut scored = 0;
+        for (idx, bound) in candidates {
+            if best.worst().is_some_and(|worst| bound < worst) {
+                break;
+            }
+            best.push(idx, self.vector_stats[idx].score(query_vec, &query, &self.vectors[idx]));
+            scored += 1;
+        }
+
+        // Convert to search results
+        let results: Vec<SearchResult> = best
+            .into_sorted()
+            .into_iter()
+            .filter(|(_, score)| *score >= score_threshold)
+            .map(|(idx, score)| self.search_result(idx, score))
+            .collect();
+
+        tracing::Span::current().record("results", results.len()).record("scored", scored);
+        Ok(results)
+    }
+
+    /// Search in `mode`: graph mode adds up to `k` chunks that mention the
+    /// entities named in the query or entities related to them; other
+    /// modes search as `search_filtered`
+    #[tracing::instrument(name = "retrieve", skip(self, query, filters))]
+    pub fn retrieve(
+        &self,
+        query: &str,
+        k: usize,
+        score_threshold: f32,
+        filters: &SearchFilters,
+        mode: RetrievalMode,
+    ) -> Result<Vec<SearchResult>> {
+        let mut results = self.search_filtered(query, k, score_threshold, filters)?;
+        if mode != RetrievalMode::Graph {
+            return Ok(results);
+        }
+        let keys = self.graph.match_entities(query);
+        if keys.is_empty() {
+            return Ok(results);
+        }
+
+        let query_vec = &self.query_embedding(query)?;
+        let allowed = self.allowed_chunks(filters);
+        let positions: HashMap<(&str, usize), usize> = self
+            .metadata
+            .iter()
+            .enumerate()
+            .map(|(idx, m)| ((m.file_path.as_str(), m.chunk_id), idx))
+            .collect();
+
+        // Fewest hops from the query's entities first, then most similar
+        let mut linked: Vec<(usize, usize, f32)> = self
+            .graph
+            .neighborhood(&keys)
+            .into_iter()
+            .filter_map(|(chunk, hops)| {
+                let idx = *positions.get(&(chunk.file_path.as_str(), chunk.chunk_id))?;
+                let metadata = &self.metadata[idx];
+                let found = results
+                    .iter()
+                    .any(|r| r.file_path == metadata.file_path && r.chunk_id == metadata.chunk_id);
+                (allowed(metadata) && !found).then(|| (idx, hops, cosine_similarity(query_vec, &self.vectors[idx])))
+            })
+            .collect();
+        linked.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.total_cmp(&a.2)));
+
+        info!(
+            "Graph retrieval matched entities {:?}, adding {} linked chunks",
+            keys,
+            linked.len().min(k)
+        );
+        results.extend(linked.into_iter().take(k).map(|(idx, _, score)| self.search_result(idx, score)));
+        Ok(results)
+    }
+
+    /// How `search_filtered` would treat each chunk for `query`: which
+    /// filter left it out, or its similarity, query terms it contains, and
+    /// whether it was returned, ranked past `k`, or fell below the
+    /// threshold. Lists the best-scoring candidates only.
+    pub fn explain(
+        &self,
+        query: &str,
+        k: usize,
+        score_threshold: f32,
+        filters: &SearchFilters,
+    ) -> Result<serde_json::Value> {
+        let query_terms: Vec<String> = {
+            let mut seen = HashSet::new();
+            self.tokenize(query).into_iter().filter(|t| seen.insert(t.clone())).collect()
+        };
+        let query_vec = if self.vectors.is_empty() {
+            Vec::new()
+        } else {
+            self.query_embedding(query)?
+        };
+
+        let mut excluded: HashMap<&str, usize> = HashMap::new();
+        let mut scores: Vec<(usize, f32)> = Vec::new();
+        let mut visible_chunks = 0;
+        for (idx, metadata) in self.metadata.iter().enumerate() {
+            let info = self.document_map.get(&metadata.file_path);
+            // Other users' documents aren't mentioned at all
+            if !info.is_some_and(|info| info.visible_to(filters.visible_to.as_deref())) {
+                continue;
+            }
+            visible_chunks += 1;
+            let named = filters.documents.is_empty()
+                || info.is_some_and(|info| {
+                    filters.documents.iter().any(|d| {
+                        *d == document_id(&metadata.file_path) || *d == metadata.file_path || *d == info.file_name
+                    })
+                });
+            let reason = if !named {
+                Some("document_filter")
+            } else if !filters.matches_file_type(&metadata.file_type) {
+                Some("file_type_filter")
+            } else if !filters.matches_date(info.and_then(|info| info.date.as_ref())) {
+                Some("as_of_filter")
+            } else if !info.is_some_and(|info| filters.matches_labels(&info.labels)) {
+                Some("label_filter")
+            } else {
+                None
+            };
+            match reason {
+                Some(reason) => *excluded.entry(reason).or_default() += 1,
+                None => scores.push((idx, cosine_similarity(&query_vec, &self.vectors[idx]))),
+            }
+        }
+        let scored = scores.len();
+
+        let candidates: Vec<serde_json::Value> = top_k(scores, (k * 3).max(MIN_EXPLAINED_CANDIDATES))
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (idx, score))| {
+                let metadata = &self.metadata[idx];
+                let chunk_terms: HashSet<String> = self.tokenize(&metadata.text).into_iter().collect();
+                let matched: Vec<&String> = query_terms.iter().filter(|t| chunk_terms.contains(*t)).collect();
+                let status = if rank >= k {
+                    "beyond_k"
+                } else if score < score_threshold {
+                    "below_threshold"
+                } else {
+                    "returned"
+                };
+                json!({
+                    "rank": rank + 1,
+                    "file_name": metadata.file_name,
+                    "file_path": metadata.file_path,
+                    "chunk_id": metadata.chunk_id,
+                    "dense_score": score,
+                    "matched_terms": matched,
+                    "term_overlap": if query_terms.is_empty() { 0.0 } else { matched.len() as f32 / query_terms.len() as f32 },
+                    "status": status
+                })
+            })
+            .collect();
+
+        Ok(json!({
+            "query_terms": query_terms,
+            "k": k,
+            "score_threshold": score_threshold,
+            "filters": {
+                "documents": filters.documents,
+                "file_types": filters.file_types,
+                "as_of": filters.as_of,
+                "collections": filters.collections,
+                "tags": filters.tags
+            },
+            "total_chunks": visible_chunks,
+            "excluded_by_filters": excluded,
+            "scored": scored,
+            "candidates": candidates
+        }))
+    }
+
+    /// Whether a chunk belongs to the documents and file types `filters`
+    /// allows
+    fn allowed_chunks(&self, filters: &SearchFilters) -> impl Fn(&DocumentMetadata) -> bool + '_ {
+        // File paths of the documents the filter names, matched by id, path, or name
+        // and, as of a date, only those dated by then, in the filter's
+        // collections and with its tags, and visible to the user
+        let limited = !filters.documents.is_empty()
+            || filters.as_of.is_some()
+            || filters.visible_to.is_some()
+            || !filters.collections.is_empty()
+            || !filters.tags.is_empty();
+        let documents: Option<HashSet<String>> = limited.then(|| {
+            self.document_map
+                .iter()
+                .filter(|(file_path, info)| {
+                    let named = filters.documents.is_empty()
+                        || filters.documents.iter().any(|d| {
+                            *d == document_id(file_path) || d == *file_path || *d == info.file_name
+                        });
+                    named
+                        && filters.matches_date(info.date.as_ref())
+                        && filters.matches_labels(&info.labels)
+                        && info.visible_to(filters.visible_to.as_deref())
+                })
+                .map(|(file_path, _)| file_path.clone())
+                .collect()
+        });
+        let file_types = filters.clone();
+
+        move |metadata| {
+            documents.as_ref().is_none_or(|paths| paths.contains(&metadata.file_path))
+                && file_types.matches_file_type(&metadata.file_type)
+        }
+    }
+
+    fn search_result(&self, idx: usize, score: f32) -> SearchResult {
+        let metadata = &self.metadata[idx];
+        SearchResult {
+            file_path: metadata.file_path.clone(),
+            file_name: metadata.file_name.clone(),
+            file_type: metadata.file_type.clone(),
+            chunk_id: metadata.chunk_id,
+            chunk_size: metadata.chunk_size,
+            text: metadata.text.to_string(),
+            similarity_score: score,
+            parent_id: metadata.parent_id,
+            parent_text: self.get_parent_text(&metadata.file_path, metadata.parent_id),
+            location: metadata.location.clone(),
+            rerank_score: None,
+            highlights: None,
+            document_date: self
+                .document_map
+                .get(&metadata.file_path)
+                .and_then(|info| info.date.as_ref())
+                .map(|date| date.date.clone()),
+        }
+    }
+
+    fn get_parent_text(&self, file_path: &str, parent_id: Option<usize>) -> Option<String> {
+        let sections = self.parent_sections.get(file_path)?;
+        sections.get(parent_id?).map(|section| section.to_string())
+    }
+
+    /// Store statistics, listing only the documents visible to `user`
+    pub fn get_stats(&self, user: Option<&str>) -> Result<serde_json::Value> {
+        let storage_size_mb = self.get_storage_size()?;
+        let documents: Vec<&String> = self
+            .document_map
+            .iter()
+            .filter(|(_, info)| info.visible_to(user))
+            .map(|(file_path, _)| file_path)
+            .collect();
+        let mut collections: HashMap<&str, usize> = HashMap::new();
+        for file_path in &documents {
+            if let Some(collection) = self.document_map[*file_path].labels.collection.as_deref() {
+                *collections.entry(collection).or_default() += 1;
+            }
+        }
+
+        Ok(json!({
+            "total_vectors": self.vectors.len(),
+            "total_documents": documents.len(),
+            "embedding_model": self.embedding_model,
+            "dimension": self.dimension,
+            "store_path": self.store_path.to_string_lossy(),
+            "document_ids": documents
+                .iter()
+                .map(|file_path| (file_path.to_string(), document_id(file_path)))
+                .collect::<HashMap<_, _>>(),
+            "documents": documents,
+            "collections": collections,
+            "graph": {
+                "entities": self.graph.num_entities(),
+                "relations": self.graph.num_relations()
+            },
+            "embedding_cache": self.embedding_cache.stats(),
+            "storage_size_mb": storage_size_mb
+        }))
+    }
+
+    pub fn delete_document(&mut self, file_path: &str) -> Result<bool> {
+        Ok(!self.delete_documents(&[file_path.to_string()])?.is_empty())
+    }
+
+    /// Delete several documents, saving the store once. Returns the file
+    /// paths that were indexed.
+    pub fn delete_documents(&mut self, file_paths: &[String]) -> Result<Vec<String>> {
+        let mut deleted = Vec::new();
+        for file_path in file_paths {
+            if !self.document_map.contains_key(file_path) {
+                continue;
+            }
+            self.forget_doc_frequencies(file_path);
+            self.remove_document_chunks(file_path);
+            self.document_map.remove(file_path);
+            self.parent_sections.remove(file_path);
+            self.graph.remove_document(file_path);
+            deleted.push(file_path.clone());
+        }
+        if deleted.is_empty() {
+            return Ok(deleted);
+        }
+
+        self.save_store()?;
+        self.notify(IndexChange::Documents(deleted.clone()));
+        Ok(deleted)
+    }
+
+    /// Replace the chunks of an already-indexed document in a single step.
+    /// Returns false if the document is not in the store.
+    pub fn replace_document(&mut self, document: ProcessedDocument) -> Result<bool> {
+        if !self.document_map.contains_key(&document.file_path) {
+            return Ok(false);
+        }
+
+        self.add_documents(vec![document])?;
+        Ok(true)
+    }
+
+    /// Replace who besides its owner may search the document at `file_path`
+    pub fn share_document(&mut self, file_path: &str, shared_with: Vec<String>) -> Result<bool> {
+        let Some(info) = self.document_map.get_mut(file_path) else {
+            return Ok(false);
+        };
+        info.shared_with = shared_with;
+        self.save_store()?;
+        Ok(true)
+    }
+
+    /// File paths of every indexed document visible to `user`, sorted
+    pub fn document_paths(&self, user: Option<&str>) -> Vec<&String> {
+        let mut paths: Vec<&String> = self
+            .document_map
+            .iter()
+            .filter(|(_, info)| info.visible_to(user))
+            .map(|(file_path, _)| file_path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Info of the document indexed at exactly `file_path`
+    pub fn document_info(&self, file_path: &str) -> Option<&DocumentInfo> {
+        self.document_map.get(file_path)
+    }
+
+    /// Look up an indexed document by its id, returning its file_path and info
+    pub fn find_document_by_id(&self, id: &str) -> Option<(&String, &DocumentInfo)> {
+        self.document_map
+            .iter()
+            .find(|(file_path, _)| document_id(file_path) == id)
+    }
+
+    /// Text of an indexed document, found by document id, file path, or file
+    /// name, rebuilt from its chunks in order
+    pub fn document_text(&self, document: &str) -> Option<String> {
+        let (_, chunks) = self.document_chunks(document)?;
+        Some(chunks.iter().map(|c| &*c.text).collect::<Vec<_>>().join("\n"))
+    }
+
+    /// File path and info of an indexed document found by document id, file
+    /// path, or file name
+    pub fn find_document(&self, document: &str) -> Option<(&String, &DocumentInfo)> {
+        self.find_visible_document(document, None)
+    }
+
+    /// `find_document` among the documents visible to `user`
+    pub fn find_visible_document(&self, document: &str, user: Option<&str>) -> Option<(&String, &DocumentInfo)> {
+        self.document_map.iter().find(|(file_path, info)| {
+            (document_id(file_path) == document || *file_path == document || info.file_name == document)
+                && info.visible_to(user)
+        })
+    }
+
+    /// File name and chunks, in order, of an indexed document found by
+    /// document id, file path, or file name
+    pub fn document_chunks(&self, document: &str) -> Option<(String, Vec<DocumentChunk>)> {
+        let (file_path, info) = self.find_document(document)?;
+
+        let mut chunks: Vec<DocumentChunk> = self
+            .metadata
+            .iter()
+            .filter(|m| &m.file_path == file_path)
+            .map(|m| DocumentChunk {
+                text: m.text.clone(),
+                size: m.chunk_size,
+                chunk_id: m.chunk_id,
+                parent_id: m.parent_id,
+                location: m.location.clone(),
+            })
+            .collect();
+        chunks.sort_by_key(|c| c.chunk_id);
+
+        Some((info.file_name.clone(), chunks))
+    }
+
+    /// Every document with its chunks, for backups and moving the store
+    pub fn export_documents(&self) -> Vec<ExportedDocument> {
+        let mut documents: Vec<ExportedDocument> = self
+            .document_map
+            .iter()
+            .filter_map(|(file_path, info)| {
+                let (_, chunks) = self.document_chunks(file_path)?;
+                Some(ExportedDocument {
+                    file_path: file_path.clone(),
+                    info: info.clone(),
+                    chunks,
+                    parent_sections: self.parent_sections.get(file_path).cloned().unwrap_or_default(),
+                })
+            })
+            .collect();
+        documents.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+        documents
+    }
+
+    /// Index exported documents again with this store's embeddings, keeping
+    /// their owners and sharing. Documents already here are replaced.
+    pub fn import_documents(&mut self, documents: Vec<ExportedDocument>) -> Result<usize> {
+        let count = documents.len();
+        let mut access = Vec::with_capacity(count);
+        let processed = documents
+            .into_iter()
+            .map(|doc| {
+                access.push((doc.file_path.clone(), doc.info.owner, doc.info.shared_with, doc.info.indexed_at));
+                ProcessedDocument {
+                    document_id: document_id(&doc.file_path),
+                    text: doc.chunks.iter().map(|c| &*c.text).collect::<Vec<_>>().join("\n"),
+                    text_length: doc.chunks.iter().map(|c| c.text.len()).sum(),
+                    num_chunks: doc.chunks.len(),
+                    parent_chunks: doc
+                        .parent_sections
+                        .into_iter()
+                        .enumerate()
+                        .map(|(chunk_id, text)| DocumentChunk {
+                            size: text.len(),
+                            text,
+                            chunk_id,
+                            parent_id: None,
+                            location: Default::default(),
+                        })
+                        .collect(),
+                    file_path: doc.file_path,
+                    file_name: doc.info.file_name,
+                    file_type: doc.info.file_type,
+                    chunks: doc.chunks,
+                    file_size: doc.info.file_size,
+                    chunking_strategy: doc.info.chunking_strategy,
+                    date: doc.info.date,
+                    labels: doc.info.labels,
+                }
+            })
+            .collect();
+        self.add_documents(processed)?;
+
+        for (file_path, owner, shared_with, indexed_at) in access {
+            if let Some(info) = self.document_map.get_mut(&file_path) {
+                info.owner = owner;
+                info.shared_with = shared_with;
+                info.indexed_at = indexed_at.or(info.indexed_at.take());
+            }
+        }
+        self.save_store()?;
+        info!("Imported {} documents", count);
+        Ok(count)
+    }
+
+    /// Remove a document's vectors and metadata, keeping the two aligned
+    fn remove_document_chunks(&mut self, file_path: &str) -> usize {
+        let keep: Vec<bool> = self
+            .metadata
+            .iter()
+            .map(|m| m.file_path != file_path)
+            .collect();
+
+        let mut keep_iter = keep.iter();
+        self.vectors.retain(|_| *keep_iter.next().unwrap_or(&true));
+        let mut keep_iter = keep.iter();
+        self.vector_stats.retain(|_| *keep_iter.next().unwrap_or(&true));
+
+        let initial_count = self.metadata.len();
+        self.metadata.retain(|m| m.file_path != file_path);
+        initial_count - self.metadata.len()
+    }
+
+    pub fn clear_store(&mut self) -> Result<()> {
+        self.vectors.clear();
+        self.vector_stats.clear();
+        self.metadata.clear();
+        self.document_map.clear();
+        self.parent_sections.clear();
+        self.graph.clear();
+
+        if self.store_path.exists() {
+            fs::remove_dir_all(&self.store_path)?;
+            fs::create_dir_all(&self.store_path)?;
+        }
+
+        self.notify(IndexChange::Cleared);
+        info!("Vector store cleared");
+        Ok(())
+    }
+
+    /// Save the store whole if additions are waiting in its log, so they
+    /// needn't be replayed on the next start. Returns whether it saved.
+    pub fn compact(&self) -> Result<bool> {
+        if !self.store_path.join(STORE_LOG).exists() {
+            return Ok(false);
+        }
+        self.save_store()?;
+        Ok(true)
+    }
+
+    pub fn store_path(&self) -> &Path {
+        &self.store_path
+    }
+
+    /// Check the saved store can be read back and was built with this
+    /// store's embedding dimension, for deep health checks
+    pub fn check_saved_store(&self) -> Result<()> {
+        for name in [STORE_FILE, STORE_LOG, "metadata.json", "document_map.json"] {
+            let path = self.store_path.join(name);
+            if path.exists() {
+                fs::File::open(&path).map_err(|e| anyhow!("Cannot read {}: {}", path.display(), e))?;
+            }
+        }
+
+        let config_path = self.store_path.join("config.json");
+        if config_path.exists() {
+            let config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&config_path)?)
+                .map_err(|e| anyhow!("Invalid {}: {}", config_path.display(), e))?;
+            let dimension = config["dimension"].as_u64().unwrap_or_default() as usize;
+            if dimension != self.dimension {
+                return Err(anyhow!(
+                    "Saved store has dimension {}, but {} uses {}",
+                    dimension,
+                    self.embedding_model,
+                    self.dimension
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Embed a probe text and check the vector is usable, for deep health
+    /// checks
+    pub fn check_embeddings(&self) -> Result<()> {
+        let embeddings = self.generate_embeddings(&["health check probe".to_string()])?;
+        match embeddings.first() {
+            Some(embedding) if embedding.len() == self.dimension && embedding.iter().all(|v| v.is_finite()) => Ok(()),
+            _ => Err(anyhow!("{} returned an unusable embedding", self.embedding_model)),
+        }
+    }
+
+    /// Embedding of a search query, from the cache when it was searched
+    /// since the index last changed
+    pub fn query_embedding(&self, query: &str) -> Result<Vec<f32>> {
+        if let Some(embedding) = self.embedding_cache.get(query) {
+            return Ok(embedding);
+        }
+        let embedding = self
+            .generate_embeddings(&[query.to_string()])?
+            .pop()
+            .ok_or_else(|| anyhow!("No embedding generated for the query"))?;
+        self.embedding_cache.put(query, embedding.clone());
+        Ok(embedding)
+    }
+
+    /// Embed texts with the vocabulary of the indexed documents
+    pub fn generate_embeddings(&self, texts: &[impl AsRef<str>]) -> Result<Vec<Vec<f32>>> {
+        // TF-IDF based semantic embedding generation
+        // This captures actual semantic meaning from text content
+        let mut embeddings = Vec::new();
+
+        for text in texts {
+            let mut embedding = vec![0.0f32; self.dimension];
+
+            // Extract and tokenize text, counting the known tokens by id
+            let lowercase = text.as_ref().to_lowercase();
+            let mut total_tokens = 0;
+            let mut token_counts: HashMap<TokenId, f32> = HashMap::new();
+            for token in tokens(&lowercase) {
+                total_tokens += 1;
+                if let Some(id) = self.vocabulary.id(token) {
+                    *token_counts.entry(id).or_insert(0.0) += 1.0;
+                }
+            }
+
+            if total_tokens == 0 {
+                embeddings.push(embedding);
+                continue;
+            }
+
+            // TF-IDF weights of the tokens with a dimension
+            let num_docs = (self.metadata.len() as f32).max(1.0);
+            for (id, count) in token_counts {
+                let Some(idx) = self.vocabulary.dimension(id).filter(|&idx| idx < self.dimension) else {
+                    continue;
+                };
+                let tf = count / total_tokens as f32;
+                let idf = match self.vocabulary.doc_frequency(id) {
+                    0 => 1.0,
+                    doc_count => (num_docs / doc_count as f32).ln() + 1.0,
+                };
+                embedding[idx] += tf * idf;
+            }
+
+            // Normalize to unit vector
+            let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                embedding = embedding.iter().map(|x| x / norm).collect();
+            } else {
+                // Handle empty text - use small random values
+                for value in embedding.iter_mut().take(self.dimension.min(5)) {
+                    *value = 0.1;
+                }
+                let norm: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    embedding = embedding.iter().map(|x| x / norm).collect();
+                }
+            }
+
+            embeddings.push(embedding);
+        }
+
+        Ok(embeddings)
+    }
+
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        tokens(&text.to_lowercase()).map(str::to_string).collect()
+    }
+
+    /// Add the documents' words to the vocabulary and count the documents
+    /// in their document frequencies, returning the tokens whose
+    /// frequencies changed
+    fn update_vocabulary(&mut self, documents: &[ProcessedDocument]) -> Vec<TokenId> {
+        let mut token_documents: HashMap<TokenId, HashSet<&str>> = HashMap::new();
+        for doc in documents {
+            for chunk in &doc.chunks {
+                let lowercase = chunk.text.to_lowercase();
+                for token in tokens(&lowercase) {
+                    let id = self.vocabulary.intern(token);
+                    token_documents.entry(id).or_default().insert(&doc.file_path);
+                }
+            }
+        }
+
+        // New tokens take the free dimensions until every one is taken
+        for (&id, docs) in &token_documents {
+            self.vocabulary.assign_dimension(id, self.dimension);
+            self.vocabulary.set_doc_frequency(id, self.vocabulary.doc_frequency(id) + docs.len());
+        }
+        token_documents.into_keys().collect()
+    }
+
+    /// Take an indexed document out of the document frequencies of its
+    /// words, returning the tokens whose frequencies changed
+    fn forget_doc_frequencies(&mut self, file_path: &str) -> Vec<TokenId> {
+        let mut ids = HashSet::new();
+        for meta in self.metadata.iter().filter(|m| m.file_path == file_path) {
+            let lowercase = meta.text.to_lowercase();
+            ids.extend(tokens(&lowercase).filter_map(|token| self.vocabulary.id(token)));
+        }
+        for &id in &ids {
+            let count = self.vocabulary.doc_frequency(id);
+            self.vocabulary.set_doc_frequency(id, count.saturating_sub(1));
+        }
+        ids.into_iter().collect()
+    }
+
+    fn get_storage_size(&self) -> Result<f64> {
+        use std::fs;
+        use std::path::Path;
+
+        fn calculate_dir_size(path: &Path) -> std::io::Result<u64> {
+            let mut total = 0u64;
+
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+
+                if metadata.is_file() {
+                    total += metadata.len();
+                } else if metadata.is_dir() {
+                    // Recursively calculate subdirectories
+                    total += calculate_dir_size(&entry.path())?;
+                }
+            }
+
+            Ok(total)
+        }
+
+        let total_size = calculate_dir_size(&self.store_path)?;
+        Ok((total_size as f64) / (1024.0 * 1024.0))
+    }
+
+    fn save_store(&self) -> Result<()> {
+        // Save chunks, vectors, and the rest in one file, written aside and
+        // renamed so a crash mid-save leaves the previous one intact
+        let saved = SavedStore {
+            metadata: &self.metadata,
+            document_map: &self.document_map,
+            parent_sections: &self.parent_sections,
+            graph: &self.graph,
+            vocabulary: &self.vocabulary,
+            doc_frequencies: &self.vocabulary,
+            vectors: &self.vectors,
+        };
+        let mut bytes = rmp_serde::to_vec_named(&saved)?;
+        if self.compress {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(&bytes)?;
+            bytes = encoder.finish()?;
+        }
+        let store_file = self.store_path.join(STORE_FILE);
+        let partial = store_file.with_extension("bin.tmp");
+        fs::write(&partial, bytes)?;
+        fs::rename(&partial, &store_file)?;
+
+        // Everything logged is in the saved store now
+        let log_path = self.store_path.join(STORE_LOG);
+        if log_path.exists() {
+            fs::remove_file(log_path)?;
+        }
+
+        for name in LEGACY_FILES {
+            let path = self.store_path.join(name);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+
+        // Save config
+        let config = serde_json::json!({
+            "embedding_model": self.embedding_model,
+            "dimension": self.dimension,
+            "total_vectors": self.vectors.len(),
+            "version": "2.0.0"
+        });
+        let config_path = self.store_path.join("config.json");
+        fs::write(config_path, serde_json::to_string_pretty(&config)?)?;
+
+        info!("Vector store saved to {:?}", self.store_path);
+        Ok(())
+    }
+
+    fn load_store(&mut self) -> Result<()> {
+        self.load_saved()?;
+        let replayed = self.replay_log()?;
+        self.share_texts();
+
+        // Vectors from another embedding model, or from stores saved before
+        // vectors were, are generated again
+        let usable = self.vectors.len() == self.metadata.len()
+            && self.vectors.iter().all(|v| v.len() == self.dimension);
+        if !usable {
+            let texts: Vec<Arc<str>> = self.metadata.iter().map(|m| m.text.clone()).collect();
+            self.vectors = self.generate_embeddings(&texts)?;
+            self.vector_stats = self.vectors.iter().map(|v| VectorStats::of(v)).collect();
+        }
+
+        if !self.metadata.is_empty() || replayed > 0 {
+            info!(
+                "Loaded vector store: {} vectors, {} documents ({} logged additions)",
+                self.vectors.len(),
+                self.document_map.len(),
+                replayed
+            );
+        }
+        Ok(())
+    }
+
+    /// Point chunks and parent sections with the same text at one copy of
+    /// it. Each is read back as its own copy, including the chunk text a
+    /// small parent section repeats.
+    fn share_texts(&mut self) {
+        let mut texts: HashSet<Arc<str>> = HashSet::new();
+        let mut share = |text: &mut Arc<str>| match texts.get(&**text) {
+            Some(shared) => *text = shared.clone(),
+            None => {
+                texts.insert(text.clone());
+            }
+        };
+        for metadata in &mut self.metadata {
+            share(&mut metadata.text);
+        }
+        for section in self.parent_sections.values_mut().flatten() {
+            share(section);
+        }
+    }
+
+    /// Read the store as last saved whole, from `STORE_FILE` or the JSON
+    /// files of earlier versions
+    fn load_saved(&mut self) -> Result<()> {
+        let store_file = self.store_path.join(STORE_FILE);
+        if store_file.exists() {
+            let mut bytes = fs::read(&store_file)?;
+            if bytes.starts_with(&GZIP_MAGIC) {
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+                bytes = decompressed;
+            }
+            let loaded: LoadedStore = rmp_serde::from_slice(&bytes)
+                .map_err(|e| anyhow!("Invalid {}: {}", store_file.display(), e))?;
+            self.metadata = loaded.metadata;
+            self.document_map = loaded.document_map;
+            self.parent_sections = loaded.parent_sections;
+            self.graph = loaded.graph;
+            self.vocabulary = Vocabulary::from_maps(loaded.vocabulary, loaded.doc_frequencies);
+            self.vectors = loaded.vectors;
+            self.vector_stats = self.vectors.iter().map(|v| VectorStats::of(v)).collect();
+            return Ok(());
+        }
+
+        let metadata_path = self.store_path.join("metadata.json");
+        let doc_map_path = self.store_path.join("document_map.json");
+
+        if !metadata_path.exists() || !doc_map_path.exists() {
+            info!("Vector store does not exist yet");
+            return Ok(());
+        }
+
+        // Load metadata
+        let metadata_json = fs::read_to_string(&metadata_path)?;
+        self.metadata = serde_json::from_str(&metadata_json)?;
+
+        // Load document map
+        let doc_map_json = fs::read_to_string(&doc_map_path)?;
+        self.document_map = serde_json::from_str(&doc_map_json)?;
+
+        // Load parent sections (absent in stores created before small-to-big chunking)
+        let parents_path = self.store_path.join("parent_sections.json");
+        if parents_path.exists() {
+            let parents_json = fs::read_to_string(&parents_path)?;
+            self.parent_sections = serde_json::from_str(&parents_json)?;
+        }
+
+        // Load the knowledge graph, extracting it again for stores created
+        // before graph retrieval
+        let graph_path = self.store_path.join("graph.json");
+        if graph_path.exists() {
+            let graph_json = fs::read_to_string(&graph_path)?;
+            self.graph = serde_json::from_str(&graph_json)?;
+        } else {
+            for m in &self.metadata {
+                self.graph.add_chunk(&m.file_path, m.chunk_id, &m.text);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record documents just indexed by appending them to `STORE_LOG`, so
+    /// the cost of adding doesn't grow with the store. The store is saved
+    /// whole, emptying the log, once the log outgrows the saved store.
+    fn log_added_documents(
+        &mut self,
+        file_paths: &[String],
+        first_chunk: usize,
+        vocabulary_before: usize,
+        words: Vec<TokenId>,
+    ) -> Result<()> {
+        let entry = LoggedDocuments {
+            documents: file_paths
+                .iter()
+                .filter_map(|file_path| {
+                    Some(LoggedDocument {
+                        file_path: file_path.clone(),
+                        info: self.document_map.get(file_path)?.clone(),
+                        parent_sections: self.parent_sections.get(file_path).cloned().unwrap_or_default(),
+                    })
+                })
+                .collect(),
+            metadata: Cow::Borrowed(&self.metadata[first_chunk..]),
+            vectors: Cow::Borrowed(&self.vectors[first_chunk..]),
+            vocabulary: words
+                .iter()
+                .filter_map(|&id| {
+                    let dimension = self.vocabulary.dimension(id).filter(|&d| d >= vocabulary_before)?;
+                    Some((self.vocabulary.token(id).to_string(), dimension))
+                })
+                .collect(),
+            doc_frequencies: words
+                .iter()
+                .map(|&id| (self.vocabulary.token(id).to_string(), self.vocabulary.doc_frequency(id)))
+                .collect(),
+        };
+
+        let record = rmp_serde::to_vec_named(&entry)?;
+        let mut frame = Vec::with_capacity(record.len() + 4);
+        frame.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&record);
+        let mut log = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.store_path.join(STORE_LOG))?;
+        log.write_all(&frame)?;
+
+        let log_size = log.metadata()?.len();
+        let saved_size = fs::metadata(self.store_path.join(STORE_FILE)).map(|m| m.len()).unwrap_or(0);
+        if log_size > saved_size {
+            self.save_store()?;
+        }
+        Ok(())
+    }
+
+    /// Apply the additions in `STORE_LOG` on top of the saved store,
+    /// returning how many there were. A record cut short by a crash while
+    /// appending is skipped.
+    fn replay_log(&mut self) -> Result<usize> {
+        let log_path = self.store_path.join(STORE_LOG);
+        if !log_path.exists() {
+            return Ok(0);
+        }
+
+        let log = fs::read(&log_path)?;
+        let mut rest = log.as_slice();
+        let mut replayed = 0;
+        while rest.len() >= 4 {
+            let len = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let Some(record) = rest.get(4..4 + len) else {
+                break;
+            };
+            let entry: LoggedDocuments = rmp_serde::from_slice(record)
+                .map_err(|e| anyhow!("Invalid {}: {}", log_path.display(), e))?;
+            self.apply_logged(entry);
+            rest = &rest[4 + len..];
+            replayed += 1;
+        }
+        if !rest.is_empty() {
+            // Cut the partial record off so the next addition isn't
+            // appended behind it and read back as part of it
+            log::warn!("Dropped an incomplete record at the end of {}", log_path.display());
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&log_path)?
+                .set_len((log.len() - rest.len()) as u64)?;
+        }
+        Ok(replayed)
+    }
+
+    fn apply_logged(&mut self, entry: LoggedDocuments) {
+        for doc in &entry.documents {
+            self.remove_document_chunks(&doc.file_path);
+            self.graph.remove_document(&doc.file_path);
+        }
+        for m in entry.metadata.iter() {
+            self.graph.add_chunk(&m.file_path, m.chunk_id, &m.text);
+        }
+        self.metadata.extend(entry.metadata.into_owned());
+        self.vector_stats.extend(entry.vectors.iter().map(|v| VectorStats::of(v)));
+        self.vectors.extend(entry.vectors.into_owned());
+
+        for doc in entry.documents {
+            if doc.parent_sections.is_empty() {
+                self.parent_sections.remove(&doc.file_path);
+            } else {
+                self.parent_sections.insert(doc.file_path.clone(), doc.parent_sections);
+            }
+            self.document_map.insert(doc.file_path, doc.info);
+        }
+        for (token, dimension) in entry.vocabulary {
+            let id = self.vocabulary.intern(&token);
+            self.vocabulary.set_dimension(id, dimension);
+        }
+        for (token, count) in entry.doc_frequencies {
+            let id = self.vocabulary.intern(&token);
+            self.vocabulary.set_doc_frequency(id, count);
+        }
+    }
+
+    fn get_dimension(model: &str) -> usize {
+        match model {
+            "all-MiniLM-L6-v2" => 384,
+            "all-mpnet-base-v2" => 768,
+            "all-distilroberta-v1" => 768,
+            "paraphrase-MiniLM-L6-v2" => 384,
+            "paraphrase-mpnet-base-v2" => 768,
+            _ => 384,
+        }
+    }
+}
+
+/// Cosine similarity of two embeddings; 0 when either is empty or zero or
+/// their dimensions differ
+pub fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
+    if vec1.len() != vec2.len() || vec1.is_empty() {
+        return 0.0;
+    }
+
+    let dot_product: f32 = vec1.iter().zip(vec2.iter()).map(|(a, b)| a * b).sum();
+    let norm1 = norm(vec1);
+    let norm2 = norm(vec2);
+
+    if norm1 == 0.0 || norm2 == 0.0 {
+        return 0.0;
+    }
+
+    dot_product / (norm1 * norm2)
+}
+
+fn norm(vector: &[f32]) -> f32 {
+    vector.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Room left in `VectorStats::bound` for rounding, so a chunk is never
+/// skipped when its score would only tie the bound
+const BOUND_SLACK: f32 = 1.0 + 1e-5;
+
+/// A stored vector's norm and largest positive and negative weights,
+/// computed once when it's added so search can bound its score before
+/// scoring it
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct VectorStats {
+    norm: f32,
+    max_weight: f32,
+    /// Magnitude of the most negative weight
+    min_weight: f32,
+}
+
+/// The same for a query, with the sums of its positive weights and of the
+/// magnitudes of its negative ones
+struct QueryStats {
+    norm: f32,
+    positive: f32,
+    negative: f32,
+}
+
+impl QueryStats {
+    fn of(query: &[f32]) -> Self {
+        QueryStats {
+            norm: norm(query),
+            positive: query.iter().filter(|x| **x > 0.0).sum(),
+            negative: -query.iter().filter(|x| **x < 0.0).sum::<f32>(),
+        }
+    }
+}
+
+impl VectorStats {
+    fn of(vector: &[f32]) -> Self {
+        VectorStats {
+            norm: norm(vector),
+            max_weight: vector.iter().fold(0.0, |max, x| x.max(max)),
+            min_weight: vector.iter().fold(0.0, |max, x| (-x).max(max)),
+        }
+    }
+
+    /// Most the vector can score against `query`: each positive weight of
+    /// the query adds at most its product with `max_weight` to the dot
+    /// product, and each negative one its product with `min_weight`
+    fn bound(&self, query: &QueryStats) -> f32 {
+        if self.norm == 0.0 || query.norm == 0.0 {
+            return 0.0;
+        }
+        let dot_product = query.positive * self.max_weight + query.negative * self.min_weight;
+        dot_product / (query.norm * self.norm) * BOUND_SLACK
+    }
+
+    /// `cosine_similarity` of `query` and `vector`, this vector's stats,
+    /// without computing either norm again
+    fn score(&self, query: &[f32], stats: &QueryStats, vector: &[f32]) -> f32 {
+        if query.len() != vector.len() || query.is_empty() || self.norm == 0.0 || stats.norm == 0.0 {
+            return 0.0;
+        }
+        let dot_product: f32 = query.iter().zip(vector.iter()).map(|(a, b)| a * b).sum();
+        dot_product / (stats.norm * self.norm)
+    }
+}
+
+/// A chunk's index and score, ordered by score, then earlier chunks first
+#[derive(PartialEq)]
+struct Ranked(usize, f32);
+
+impl Eq for Ranked {}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.1.total_cmp(&other.1).then(other.0.cmp(&self.0))
+    }
+}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The `k` highest `(index, score)` pairs, best first, ties going to the
+/// lower index. Only the best `k` seen so far are kept, in a min-heap, so
+/// this is O(n log k) where sorting every score would be O(n log n).
+pub fn top_k(scores: impl IntoIterator<Item = (usize, f32)>, k: usize) -> Vec<(usize, f32)> {
+    let mut best = TopK::new(k);
+    for (idx, score) in scores {
+        best.push(idx, score);
+    }
+    best.into_sorted()
+}
+
+/// The best `k` scores pushed so far, for `top_k` and for searches that
+/// stop early
+struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Ranked>>,
+}
+
+impl TopK {
+    fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    fn push(&mut self, idx: usize, score: f32) {
+        let candidate = Reverse(Ranked(idx, score));
+        if self.heap.len() < self.k {
+            self.heap.push(candidate);
+        } else if self.heap.peek().is_some_and(|worst| candidate < *worst) {
+            self.heap.pop();
+            self.heap.push(candidate);
+        }
+    }
+
+    /// The k-th best score, once there are k; a score below it can't be kept
+    fn worst(&self) -> Option<f32> {
+        if self.k == 0 {
+            return Some(f32::INFINITY);
+        }
+        if self.heap.len() < self.k {
+            return None;
+        }
+        self.heap.peek().map(|Reverse(Ranked(_, score))| *score)
+    }
+
+    fn into_sorted(self) -> Vec<(usize, f32)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(Ranked(idx, score))| (idx, score))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        let vec1 = vec![1.0, 0.0, 0.0];
+        let vec2 = vec![1.0, 0.0, 0.0];
+        let similarity = cosine_similarity(&vec1, &vec2);
+        assert!((similarity - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_top_k_matches_a_full_sort() {
+        let scores: Vec<(usize, f32)> = (0..500).map(|i| (i, ((i * 7919) % 101) as f32 / 100.0)).collect();
+        let mut sorted = scores.clone();
+        sorted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for k in [1, 5, 101, 1000] {
+            assert_eq!(top_k(scores.clone(), k), sorted.iter().copied().take(k).collect::<Vec<_>>());
+        }
+        assert!(top_k(scores, 0).is_empty());
+    }
+
+    fn test_document(file_path: &str, texts: &[&str]) -> ProcessedDocument {
+        let chunks: Vec<_> = texts
+            .iter()
+            .enumerate()
+            .map(|(chunk_id, text)| crate::models::DocumentChunk {
+                text: (*text).into(),
+                size: text.len(),
+                chunk_id,
+                parent_id: None,
+                location: Default::default(),
+            })
+            .collect();
+
+        ProcessedDocument {
+            document_id: document_id(file_path),
+            file_path: file_path.to_string(),
+            file_name: file_path.to_string(),
+            file_type: ".txt".to_string(),
+            text: texts.join(" "),
+            text_length: 0,
+            num_chunks: chunks.len(),
+            chunks,
+            file_size: 0,
+            parent_chunks: Vec::new(),
+            chunking_strategy: ChunkingStrategy::default(),
+            date: None,
+            labels: DocumentLabels::default(),
+        }
+    }
+
+    #[test]
+    fn test_replace_document_keeps_vectors_aligned() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+
+        store
+            .add_documents(vec![
+                test_document("a.txt", &["apples grow on trees", "apple orchards"]),
+                test_document("b.txt", &["bananas are yellow"]),
+            ])
+            .unwrap();
+        assert!(store
+            .replace_document(test_document("a.txt", &["apples are red fruit"]))
+            .unwrap());
+
+        assert_eq!(store.vectors.len(), store.metadata.len());
+        assert_eq!(store.metadata.len(), 2);
+        assert_eq!(store.metadata[0].file_path, "b.txt");
+
+        let (file_path, _) = store.find_document_by_id(&document_id("a.txt")).unwrap();
+        assert_eq!(file_path, "a.txt");
+    }
+
+    #[test]
+    fn test_re_adding_a_document_keeps_doc_frequencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        store
+            .add_documents(vec![test_document("a.txt", &["apples grow on trees", "apple orchards"])])
+            .unwrap();
+        store.add_documents(vec![test_document("b.txt", &["apples are yellow"])]).unwrap();
+        let frequency = |store: &VectorStore, token: &str| store.vocabulary.doc_frequency(store.vocabulary.id(token).unwrap());
+        assert_eq!((frequency(&store, "apples"), frequency(&store, "trees")), (2, 1));
+
+        for _ in 0..3 {
+            store
+                .add_documents(vec![test_document("a.txt", &["apples grow on trees", "apple orchards"])])
+                .unwrap();
+        }
+        assert_eq!((frequency(&store, "apples"), frequency(&store, "trees")), (2, 1));
+
+        store.replace_document(test_document("a.txt", &["pears grow on trees"])).unwrap();
+        assert_eq!((frequency(&store, "apples"), frequency(&store, "pears")), (1, 1));
+        store.delete_document("a.txt").unwrap();
+        assert_eq!((frequency(&store, "trees"), frequency(&store, "apples")), (0, 1));
+    }
+
+    #[test]
+    fn test_chunk_text_is_shared_not_copied() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let document = test_document("a.txt", &["apples grow on trees"]);
+        let text = document.chunks[0].text.clone();
+        store.add_documents(vec![document]).unwrap();
+
+        assert!(Arc::ptr_eq(&store.metadata[0].text, &text));
+        let (_, chunks) = store.document_chunks("a.txt").unwrap();
+        assert!(Arc::ptr_eq(&chunks[0].text, &text));
+
+        // Loading gives every copy of a text its own allocation until
+        // they're shared again
+        let mut copy = test_document("b.txt", &["apples grow on trees"]);
+        copy.parent_chunks = copy
+            .chunks
+            .iter()
+            .map(|chunk| DocumentChunk {
+                text: (*chunk.text).into(),
+                ..chunk.clone()
+            })
+            .collect();
+        store.add_documents(vec![copy]).unwrap();
+        drop(store);
+        let store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        assert!(Arc::ptr_eq(&store.metadata[0].text, &store.metadata[1].text));
+        assert!(Arc::ptr_eq(&store.metadata[0].text, &store.parent_sections["b.txt"][0]));
+    }
+
+    #[test]
+    fn test_pruned_search_matches_a_full_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let words = ["refund", "policy", "invoice", "travel", "expense", "password", "backup", "ticket"];
+        let documents = (0..40)
+            .map(|i| {
+                let texts: Vec<String> = (0..3)
+                    .map(|j| format!("{} {} {}", words[i % 8], words[(i + j) % 8], words[(i * j + 3) % 8]))
+                    .collect();
+                let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+                test_document(&format!("doc-{}.txt", i), &texts)
+            })
+            .collect();
+        store.add_documents(documents).unwrap();
+        store.delete_document("doc-7.txt").unwrap();
+        assert_eq!(store.vector_stats.len(), store.vectors.len());
+
+        for query in ["refund policy", "backup password ticket", "travel", "nothing matches this"] {
+            let query_vec = store.query_embedding(query).unwrap();
+            let stats = QueryStats::of(&query_vec);
+            let scores: Vec<(usize, f32)> = store
+                .vectors
+                .iter()
+                .enumerate()
+                .map(|(idx, vec)| (idx, cosine_similarity(&query_vec, vec)))
+                .collect();
+            for &(idx, score) in &scores {
+                assert!(store.vector_stats[idx].bound(&stats) >= score);
+            }
+            for k in [0, 1, 5, 200] {
+                let expected: Vec<(String, usize, f32)> = top_k(scores.clone(), k)
+                    .into_iter()
+                    .filter(|(_, score)| *score >= 0.0)
+                    .map(|(idx, score)| (store.metadata[idx].file_path.clone(), store.metadata[idx].chunk_id, score))
+                    .collect();
+                let found: Vec<(String, usize, f32)> = store
+                    .search(query, k, 0.0)
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| (r.file_path, r.chunk_id, r.similarity_score))
+                    .collect();
+                assert_eq!(found, expected, "{} with k = {}", query, k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let mut report = test_document("report.pdf", &["quarterly fruit sales rose"]);
+        report.file_type = ".pdf".to_string();
+        report.labels = DocumentLabels {
+            collection: Some("finance".to_string()),
+            tags: vec!["quarterly".to_string(), "sales".to_string()],
+            language: None,
+            retain_days: None,
+        };
+        store
+            .add_documents(vec![test_document("a.txt", &["apples grow on trees"]), report])
+            .unwrap();
+
+        let by_document = SearchFilters {
+            documents: vec![document_id("a.txt")],
+            ..Default::default()
+        };
+        let results = store.search_filtered("fruit", 5, 0.0, &by_document).unwrap();
+        assert!(!results.is_empty());
+        assert!(results.iter().all(|r| r.file_path == "a.txt"));
+
+        let by_type = SearchFilters {
+            file_types: vec!["PDF".to_string()],
+            ..Default::default()
+        };
+        let results = store.search_filtered("fruit", 5, 0.0, &by_type).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_path, "report.pdf");
+
+        let unknown = SearchFilters {
+            documents: vec!["missing.txt".to_string()],
+            ..Default::default()
+        };
+        assert!(store.search_filtered("fruit", 5, 0.0, &unknown).unwrap().is_empty());
+
+        // Re-indexing without labels keeps the old ones
+        store
+            .add_documents(vec![test_document("report.pdf", &["quarterly fruit sales rose"])])
+            .unwrap();
+        let by_labels = SearchFilters {
+            collections: vec!["Finance".to_string()],
+            tags: vec!["sales".to_string()],
+            ..Default::default()
+        };
+        let results = store.search_filtered("fruit", 5, 0.0, &by_labels).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].file_path, "report.pdf");
+        let other_tag = SearchFilters {
+            tags: vec!["sales".to_string(), "annual".to_string()],
+            ..Default::default()
+        };
+        assert!(store.search_filtered("fruit", 5, 0.0, &other_tag).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_explain_reports_filters_and_ranks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let mut report = test_document("report.pdf", &["quarterly fruit sales rose"]);
+        report.file_type = ".pdf".to_string();
+        store
+            .add_documents(vec![
+                test_document("a.txt", &["apples grow on trees", "fruit trees need water"]),
+                report,
+            ])
+            .unwrap();
+
+        let filters = SearchFilters {
+            file_types: vec!["txt".to_string()],
+            ..Default::default()
+        };
+        let explained = store.explain("fruit trees", 1, 0.0, &filters).unwrap();
+        assert_eq!(explained["excluded_by_filters"]["file_type_filter"], 1);
+        let candidates = explained["candidates"].as_array().unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0]["chunk_id"], 1);
+        assert_eq!(candidates[0]["status"], "returned");
+        assert_eq!(candidates[0]["term_overlap"], 1.0);
+        assert_eq!(candidates[1]["status"], "beyond_k");
+        assert_eq!(candidates[1]["matched_terms"], json!(["trees"]));
+    }
+
+    #[test]
+    fn test_as_of_skips_later_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let dated = |file_path: &str, text: &str, date: &str| ProcessedDocument {
+            date: Some(DocumentDate {
+                date: date.to_string(),
+                source: crate::services::document_dates::DateSource::Text,
+            }),
+            ..test_document(file_path, &[text])
+        };
+        store
+            .add_documents(vec![
+                dated("pto-2023.txt", "employees accrue 20 days of pto", "2023-01-01"),
+                dated("pto-2024.txt", "employees accrue 25 days of pto", "2024-01-01"),
+                test_document("notes.txt", &["pto requests go to managers"]),
+            ])
+            .unwrap();
+
+        let as_of = SearchFilters {
+            as_of: Some("2023-06-30".to_string()),
+            ..Default::default()
+        };
+        let mut found: Vec<_> = store
+            .search_filtered("pto days", 5, -1.0, &as_of)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.file_path, r.document_date))
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ("notes.txt".to_string(), None),
+                ("pto-2023.txt".to_string(), Some("2023-01-01".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_documents_are_scoped_to_owners() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        store
+            .add_owned_documents(vec![test_document("alice.txt", &["alice salary review notes"])], Some("alice"))
+            .unwrap();
+        store
+            .add_owned_documents(vec![test_document("bob.txt", &["bob salary review notes"])], Some("bob"))
+            .unwrap();
+        store
+            .add_documents(vec![test_document("handbook.txt", &["salary reviews happen yearly"])])
+            .unwrap();
+
+        let searched_by = |store: &VectorStore, user: &str| {
+            let filters = SearchFilters {
+                visible_to: Some(user.to_string()),
+                ..Default::default()
+            };
+            let mut paths: Vec<_> = store
+                .search_filtered("salary review", 5, -1.0, &filters)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.file_path)
+                .collect();
+            paths.sort();
+            paths
+        };
+        assert_eq!(searched_by(&store, "alice"), vec!["alice.txt", "handbook.txt"]);
+        assert!(store.find_visible_document("bob.txt", Some("alice")).is_none());
+
+        assert!(store.share_document("bob.txt", vec!["alice".to_string()]).unwrap());
+        assert_eq!(searched_by(&store, "alice"), vec!["alice.txt", "bob.txt", "handbook.txt"]);
+        let (_, shared) = store.find_visible_document("bob.txt", Some("alice")).unwrap();
+        assert!(!shared.modifiable_by(Some("alice")));
+
+        // Re-indexing keeps the owner and sharing
+        store.add_documents(vec![test_document("bob.txt", &["bob salary notes v2"])]).unwrap();
+        let (_, info) = store.find_document("bob.txt").unwrap();
+        assert_eq!((info.owner.as_deref(), info.shared_with.len()), (Some("bob"), 1));
+        assert_eq!(store.get_stats(Some("carol")).unwrap()["total_documents"], 1);
+    }
+
+    #[test]
+    fn test_export_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        store
+            .add_owned_documents(vec![test_document("a.txt", &["alpha notes", "beta notes"])], Some("alice"))
+            .unwrap();
+        store.share_document("a.txt", vec!["bob".to_string()]).unwrap();
+        let exported = store.export_documents();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].chunks.len(), 2);
+
+        let other = tempfile::tempdir().unwrap();
+        let mut copy = VectorStore::new(other.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        let json = serde_json::to_string(&exported).unwrap();
+        assert_eq!(copy.import_documents(serde_json::from_str(&json).unwrap()).unwrap(), 1);
+        let info = copy.document_info("a.txt").unwrap();
+        assert_eq!((info.owner.as_deref(), info.shared_with.clone()), (Some("alice"), vec!["bob".to_string()]));
+        assert_eq!(copy.search("alpha", 1, 0.0).unwrap()[0].file_path, "a.txt");
+    }
+
+    #[test]
+    fn test_saved_store_reloads_with_its_vectors() {
+        for compress in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().to_str().unwrap();
+            let mut store = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap().with_compression(compress);
+            store
+                .add_owned_documents(vec![test_document("a.txt", &["alpha notes", "beta notes"])], Some("alice"))
+                .unwrap();
+            store.add_documents(vec![test_document("b.txt", &["gamma report"])]).unwrap();
+            let saved = fs::read(dir.path().join(STORE_FILE)).unwrap();
+            assert_eq!(saved.starts_with(&GZIP_MAGIC), compress);
+
+            let reopened = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+            assert_eq!(reopened.vectors, store.vectors);
+            assert_eq!(reopened.document_info("a.txt").unwrap().owner.as_deref(), Some("alice"));
+            assert_eq!(reopened.search("gamma", 1, 0.0).unwrap()[0].file_path, "b.txt");
+        }
+    }
+
+    #[test]
+    fn test_additions_are_logged_until_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let mut store = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+        let long: Vec<String> = (0..50).map(|i| format!("handbook section {} on leave policy", i)).collect();
+        let long: Vec<&str> = long.iter().map(String::as_str).collect();
+        store.add_documents(vec![test_document("handbook.txt", &long)]).unwrap();
+        let saved = fs::read(dir.path().join(STORE_FILE)).unwrap();
+
+        store.add_documents(vec![test_document("memo.txt", &["gamma memo"])]).unwrap();
+        store.add_documents(vec![test_document("memo.txt", &["delta memo"])]).unwrap();
+        assert_eq!(fs::read(dir.path().join(STORE_FILE)).unwrap(), saved);
+        assert!(dir.path().join(STORE_LOG).exists());
+
+        // A record cut short by a crash is skipped
+        let mut log = fs::OpenOptions::new().append(true).open(dir.path().join(STORE_LOG)).unwrap();
+        log.write_all(&[200, 0, 0, 0, 1, 2]).unwrap();
+
+        let mut reopened = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+        assert_eq!(reopened.vectors, store.vectors);
+        assert_eq!(reopened.metadata.len(), 51);
+        assert_eq!(reopened.search("delta", 1, 0.0).unwrap()[0].file_path, "memo.txt");
+
+        assert!(reopened.compact().unwrap());
+        assert!(!dir.path().join(STORE_LOG).exists());
+        assert!(!reopened.compact().unwrap());
+        assert_eq!(VectorStore::new(path, "all-MiniLM-L6-v2").unwrap().metadata.len(), 51);
+
+        reopened.delete_document("memo.txt").unwrap();
+        assert_eq!(VectorStore::new(path, "all-MiniLM-L6-v2").unwrap().metadata.len(), 50);
+    }
+
+    #[test]
+    fn test_additions_after_a_partial_record_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let mut store = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+        store.add_documents(vec![test_document("handbook.txt", &["leave policy"])]).unwrap();
+        store.add_documents(vec![test_document("memo.txt", &["gamma memo"])]).unwrap();
+        let log_path = dir.path().join(STORE_LOG);
+        let logged = fs::read(&log_path).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap()
+            .write_all(&[200, 0, 0, 0, 1, 2])
+            .unwrap();
+
+        let mut reopened = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+        assert_eq!(fs::read(&log_path).unwrap(), logged);
+        reopened.add_documents(vec![test_document("notes.txt", &["delta notes"])]).unwrap();
+
+        let reopened = VectorStore::new(path, "all-MiniLM-L6-v2").unwrap();
+        assert_eq!(reopened.metadata.len(), 3);
+        assert_eq!(reopened.search("delta", 1, 0.0).unwrap()[0].file_path, "notes.txt");
+    }
+
+    #[test]
+    fn test_health_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        store.add_documents(vec![test_document("a.txt", &["some text"])]).unwrap();
+        assert!(store.check_saved_store().is_ok());
+        assert!(store.check_embeddings().is_ok());
+
+        fs::write(dir.path().join("config.json"), r#"{"dimension": 3}"#).unwrap();
+        assert!(store.check_saved_store().is_err());
+    }
+
+    #[test]
+    fn test_graph_retrieval_adds_related_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        store
+            .add_documents(vec![test_document(
+                "projects.txt",
+                &[
+                    "Project Atlas was approved by Maria Chen.",
+                    "Maria Chen heads the finance department.",
+                    "The cafeteria serves lunch daily.",
+                ],
+            )])
+            .unwrap();
+
+        let filters = SearchFilters::default();
+        let standard = store
+            .retrieve("Project Atlas", 1, 0.0, &filters, RetrievalMode::Standard)
+            .unwrap();
+        assert_eq!(standard.iter().map(|r| r.chunk_id).collect::<Vec<_>>(), vec![0]);
+        let graph = store
+            .retrieve("Project Atlas", 1, 0.0, &filters, RetrievalMode::Graph)
+            .unwrap();
+        assert_eq!(graph.iter().map(|r| r.chunk_id).collect::<Vec<_>>(), vec![0, 1]);
+
+        // The graph is saved with the store
+        let reloaded = VectorStore::new(dir.path().to_str().unwrap(), "all-MiniLM-L6-v2").unwrap();
+        assert_eq!(reloaded.graph.num_entities(), store.graph.num_entities());
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Index of a token in a `Vocabulary`
+pub type TokenId = u32;
+
+/// Words of `lowercase` text as the store embeds them: runs of letters and
+/// digits longer than two characters
+pub fn tokens(lowercase: &str) -> impl Iterator<Item = &str> {
+    lowercase
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| s.len() > 2)
+}
+
+/// Tokens of the indexed chunks, each stored once and referred to by id,
+/// with how many documents use each and, for the first tokens up to the
+/// embedding dimension, which dimension they fill
+#[derive(Debug, Default)]
+pub struct Vocabulary {
+    ids: HashMap<Arc<str>, TokenId>,
+    /// Indexed by token id
+    tokens: Vec<Arc<str>>,
+    doc_frequencies: Vec<u32>,
+    dimensions: Vec<Option<u32>>,
+    dimensioned: usize,
+}
+
+/// A `Vocabulary` as it was, to go back to with `restore`
+#[derive(Debug)]
+pub struct VocabularyCheckpoint {
+    tokens: usize,
+    doc_frequencies: Vec<u32>,
+    dimensions: Vec<Option<u32>>,
+    dimensioned: usize,
+}
+
+impl Vocabulary {
+    /// Rebuild from saved maps of tokens to dimensions and to document
+    /// frequencies
+    pub fn from_maps(dimensions: HashMap<String, usize>, doc_frequencies: HashMap<String, usize>) -> Self {
+        let mut vocabulary = Vocabulary::default();
+        for (token, dimension) in dimensions {
+            let id = vocabulary.intern(&token);
+            vocabulary.set_dimension(id, dimension);
+        }
+        for (token, count) in doc_frequencies {
+            let id = vocabulary.intern(&token);
+            vocabulary.set_doc_frequency(id, count);
+        }
+        vocabulary
+    }
+
+    pub fn id(&self, token: &str) -> Option<TokenId> {
+        self.ids.get(token).copied()
+    }
+
+    /// Id of `token`, adding it if it's new
+    pub fn intern(&mut self, token: &str) -> TokenId {
+        if let Some(id) = self.id(token) {
+            return id;
+        }
+        let id = self.tokens.len() as TokenId;
+        let token: Arc<str> = token.into();
+        self.ids.insert(token.clone(), id);
+        self.tokens.push(token);
+        self.doc_frequencies.push(0);
+        self.dimensions.push(None);
+        id
+    }
+
+    pub fn token(&self, id: TokenId) -> &str {
+        &self.tokens[id as usize]
+    }
+
+    /// Indexed documents the token appears in
+    pub fn doc_frequency(&self, id: TokenId) -> usize {
+        self.doc_frequencies[id as usize] as usize
+    }
+
+    pub fn set_doc_frequency(&mut self, id: TokenId, count: usize) {
+        self.doc_frequencies[id as usize] = count as u32;
+    }
+
+    /// Embedding dimension the token fills, if it has one
+    pub fn dimension(&self, id: TokenId) -> Option<usize> {
+        self.dimensions[id as usize].map(|d| d as usize)
+    }
+
+    /// Give the token the next dimension unless it has one or all `limit`
+    /// are taken
+    pub fn assign_dimension(&mut self, id: TokenId, limit: usize) {
+        if self.dimensions[id as usize].is_none() && self.dimensioned < limit {
+            self.set_dimension(id, self.dimensioned);
+        }
+    }
+
+    pub fn set_dimension(&mut self, id: TokenId, dimension: usize) {
+        if self.dimensions[id as usize].replace(dimension as u32).is_none() {
+            self.dimensioned += 1;
+        }
+    }
+
+    /// Tokens that have a dimension
+    pub fn dimensioned(&self) -> usize {
+        self.dimensioned
+    }
+
+    /// The vocabulary as it is now, so later changes can be undone
+    pub fn checkpoint(&self) -> VocabularyCheckpoint {
+        VocabularyCheckpoint {
+            tokens: self.tokens.len(),
+            doc_frequencies: self.doc_frequencies.clone(),
+            dimensions: self.dimensions.clone(),
+            dimensioned: self.dimensioned,
+        }
+    }
+
+    /// Undo every change made since `checkpoint`, forgetting the tokens
+    /// added after it
+    pub fn restore(&mut self, checkpoint: VocabularyCheckpoint) {
+        for token in self.tokens.drain(checkpoint.tokens..) {
+            self.ids.remove(&token);
+        }
+        self.doc_frequencies = checkpoint.doc_frequencies;
+        self.dimensions = checkpoint.dimensions;
+        self.dimensioned = checkpoint.dimensioned;
+    }
+
+    /// Tokens and their dimensions, for saving
+    pub fn dimension_map(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tokens
+            .iter()
+            .zip(&self.dimensions)
+            .filter_map(|(token, dimension)| Some((&**token, (*dimension)? as usize)))
+    }
+
+    /// Tokens and their document frequencies, for saving
+    pub fn doc_frequency_map(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.tokens
+            .iter()
+            .zip(&self.doc_frequencies)
+            .map(|(token, &count)| (&**token, count as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_interned_once_with_bounded_dimensions() {
+        let mut vocabulary = Vocabulary::default();
+        let words: Vec<&str> = tokens("the refund policy, the refund window, an ok plan").collect();
+        assert_eq!(words, ["the", "refund", "policy", "the", "refund", "window", "plan"]);
+
+        let ids: Vec<TokenId> = words.iter().map(|word| vocabulary.intern(word)).collect();
+        assert_eq!(ids, [0, 1, 2, 0, 1, 3, 4]);
+        assert!(Arc::ptr_eq(&vocabulary.tokens[1], vocabulary.ids.get_key_value("refund").unwrap().0));
+
+        for &id in &ids {
+            vocabulary.assign_dimension(id, 3);
+        }
+        assert_eq!(vocabulary.dimensioned(), 3);
+        assert_eq!(vocabulary.dimension(vocabulary.id("policy").unwrap()), Some(2));
+        assert_eq!(vocabulary.dimension(vocabulary.id("window").unwrap()), None);
+
+        vocabulary.set_doc_frequency(1, 2);
+        let dimensions = vocabulary.dimension_map().map(|(t, d)| (t.to_string(), d)).collect();
+        let frequencies = vocabulary.doc_frequency_map().map(|(t, n)| (t.to_string(), n)).collect();
+        let restored = Vocabulary::from_maps(dimensions, frequencies);
+        let refund = restored.id("refund").unwrap();
+        assert_eq!((restored.dimension(refund), restored.doc_frequency(refund)), (Some(1), 2));
+        assert_eq!(restored.dimensioned(), 3);
+    }
+
+    #[test]
+    fn test_restore_undoes_changes_since_the_checkpoint() {
+        let mut vocabulary = Vocabulary::default();
+        let refund = vocabulary.intern("refund");
+        vocabulary.assign_dimension(refund, 10);
+        vocabulary.set_doc_frequency(refund, 2);
+
+        let checkpoint = vocabulary.checkpoint();
+        vocabulary.set_doc_frequency(refund, 1);
+        let window = vocabulary.intern("window");
+        vocabulary.assign_dimension(window, 10);
+        vocabulary.restore(checkpoint);
+
+        assert_eq!(vocabulary.doc_frequency(refund), 2);
+        assert_eq!(vocabulary.id("window"), None);
+        assert_eq!(vocabulary.dimensioned(), 1);
+        assert_eq!(vocabulary.intern("window"), window);
+    }
+}
@@ -0,0 +1,150 @@
+//! Send search or query requests to a running KnoRa server at a fixed
+//! concurrency and report throughput and latency percentiles.
+//!
+//! ```text
+//! knora-loadgen --requests 500 --concurrency 16
+//! knora-loadgen --endpoint query --queries queries.txt --api-key "$KEY"
+//! ```
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use futures::stream::{self, StreamExt};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Queries sent when no `--queries` file is given
+const DEFAULT_QUERIES: &[&str] = &[
+    "What is the refund policy?",
+    "How many vacation days do employees get?",
+    "Who approves travel expenses?",
+    "How do I reset my password?",
+    "When are quarterly reports due?",
+];
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Endpoint {
+    /// `POST /api/search`: retrieval only
+    Search,
+    /// `POST /api/query`: retrieval and an LLM answer
+    Query,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "knora-loadgen", version)]
+struct Args {
+    /// Server to send requests to
+    #[arg(long, default_value = "http://127.0.0.1:8000")]
+    url: String,
+    #[arg(long, value_enum, default_value_t = Endpoint::Search)]
+    endpoint: Endpoint,
+    /// File of queries, one per line; blank lines and `#` comments are skipped
+    #[arg(long)]
+    queries: Option<std::path::PathBuf>,
+    /// Requests to send in total
+    #[arg(short = 'n', long, default_value_t = 200)]
+    requests: usize,
+    /// Requests in flight at once
+    #[arg(short = 'c', long, default_value_t = 8)]
+    concurrency: usize,
+    /// Chunks to retrieve per request
+    #[arg(short = 'k', long, default_value_t = 5)]
+    top_k: usize,
+    /// Sent as `Authorization: Bearer <key>`
+    #[arg(long, env = "KNORA_API_KEY")]
+    api_key: Option<String>,
+}
+
+fn load_queries(args: &Args) -> Result<Vec<String>> {
+    let Some(path) = &args.queries else {
+        return Ok(DEFAULT_QUERIES.iter().map(|q| q.to_string()).collect());
+    };
+    let queries: Vec<String> = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if queries.is_empty() {
+        bail!("{} has no queries", path.display());
+    }
+    Ok(queries)
+}
+
+/// The `p`th percentile of sorted `latencies`
+fn percentile(latencies: &[Duration], p: f64) -> Duration {
+    let rank = ((latencies.len() as f64 * p / 100.0).ceil() as usize).clamp(1, latencies.len());
+    latencies[rank - 1]
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.requests == 0 || args.concurrency == 0 {
+        bail!("--requests and --concurrency must be at least 1");
+    }
+    let queries = load_queries(&args)?;
+    let url = match args.endpoint {
+        Endpoint::Search => format!("{}/api/search", args.url.trim_end_matches('/')),
+        Endpoint::Query => format!("{}/api/query", args.url.trim_end_matches('/')),
+    };
+    let client = reqwest::Client::new();
+
+    let started = Instant::now();
+    let results: Vec<Result<Duration, String>> = stream::iter(0..args.requests)
+        .map(|i| {
+            let mut request = client
+                .post(&url)
+                .json(&json!({ "query": queries[i % queries.len()], "k": args.top_k }));
+            if let Some(key) = &args.api_key {
+                request = request.bearer_auth(key);
+            }
+            async move {
+                let sent = Instant::now();
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => {
+                        // Wait for the whole body, as a client would
+                        response.bytes().await.map_err(|e| e.to_string())?;
+                        Ok(sent.elapsed())
+                    }
+                    Ok(response) => Err(format!("HTTP {}", response.status())),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        })
+        .buffer_unordered(args.concurrency)
+        .collect()
+        .await;
+    let elapsed = started.elapsed();
+
+    let mut latencies: Vec<Duration> = results.iter().filter_map(|r| r.as_ref().ok().copied()).collect();
+    latencies.sort_unstable();
+    let mut errors: Vec<&String> = results.iter().filter_map(|r| r.as_ref().err()).collect();
+    errors.sort_unstable();
+    errors.dedup();
+
+    println!("{} requests to {} at concurrency {}", args.requests, url, args.concurrency);
+    println!(
+        "{} succeeded, {} failed in {:.2}s ({:.1} requests/s)",
+        latencies.len(),
+        args.requests - latencies.len(),
+        elapsed.as_secs_f64(),
+        args.requests as f64 / elapsed.as_secs_f64()
+    );
+    if !latencies.is_empty() {
+        println!(
+            "latency p50 {:?}  p90 {:?}  p99 {:?}  max {:?}",
+            percentile(&latencies, 50.0),
+            percentile(&latencies, 90.0),
+            percentile(&latencies, 99.0),
+            latencies[latencies.len() - 1]
+        );
+    }
+    for error in errors {
+        println!("error: {}", error);
+    }
+    if latencies.is_empty() {
+        bail!("Every request failed");
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::config::AppConfig;
+use crate::handlers::upload::is_supported_format;
+use crate::models::ChunkingParams;
+use crate::services::VectorStore;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// KnoRa knowledge assistant: run the API server, or work with the vector
+/// store directly. Settings come from the environment and `.env`, as for
+/// the server.
+#[derive(Debug, Parser)]
+#[command(name = "knora", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP API (the default)
+    Serve,
+    /// Process and index files, and every supported file under directories
+    ///
+    /// Stop the server first; it would overwrite the store with its own copy.
+    Ingest {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Index the documents as this user's, like uploads with their API key
+        #[arg(long)]
+        owner: Option<String>,
+    },
+    /// Print the chunks most similar to a query
+    Search {
+        query: String,
+        #[arg(short = 'k', long, default_value_t = 5)]
+        top_k: usize,
+        /// Print the results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write every document and its chunks to a JSON file that
+    /// `POST /api/admin/import` accepts
+    Export { file: PathBuf },
+}
+
+fn open_store(config: &AppConfig) -> Result<VectorStore> {
+    VectorStore::new(&config.vector_store_path.to_string_lossy(), &config.embedding_model)
+        .map(|store| store.with_compression(config.store_compression))
+        .context("Failed to open the vector store")
+}
+
+/// Supported files at `path`, searching directories recursively
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, files)?;
+        } else if entry.file_name().and_then(|name| name.to_str()).is_some_and(is_supported_format) {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+pub fn ingest(config: &AppConfig, paths: &[PathBuf], owner: Option<&str>) -> Result<()> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, &mut files)?;
+    }
+    if files.is_empty() {
+        bail!("No supported files found");
+    }
+
+    let processor = crate::document_processor(config);
+    let mut store = open_store(config)?;
+    // Absolute paths so re-chunking and re-indexing can find the file again
+    let file_paths: Vec<(String, Option<String>)> = files
+        .iter()
+        .map(|file| (fs::canonicalize(file).unwrap_or_else(|_| file.clone()).to_string_lossy().into_owned(), None))
+        .collect();
+    let mut failed = 0;
+    processor.process_files(&file_paths, &ChunkingParams::default(), |i, processed| {
+        let file = &files[i];
+        let indexed = processed.and_then(|document| {
+            let chunks = document.num_chunks;
+            store.add_owned_documents(vec![document], owner).map(|_| chunks)
+        });
+        match indexed {
+            Ok(chunks) => println!("Indexed {} ({} chunks)", file.display(), chunks),
+            Err(e) => {
+                eprintln!("Failed to index {}: {}", file.display(), e);
+                failed += 1;
+            }
+        }
+    });
+
+    if failed > 0 {
+        bail!("{} of {} files could not be indexed", failed, files.len());
+    }
+    println!("Indexed {} files", files.len());
+    Ok(())
+}
+
+pub fn search(config: &AppConfig, query: &str, top_k: usize, json: bool) -> Result<()> {
+    let results = open_store(config)?.search(query, top_k, 0.0)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+    if results.is_empty() {
+        println!("No results");
+    }
+    for (rank, result) in results.iter().enumerate() {
+        println!(
+            "{}. {} #{} (score {:.3})\n{}\n",
+            rank + 1,
+            result.file_name,
+            result.chunk_id,
+            result.similarity_score,
+            result.text.trim()
+        );
+    }
+    Ok(())
+}
+
+pub fn export(config: &AppConfig, file: &Path) -> Result<()> {
+    let documents = open_store(config)?.export_documents();
+    let export = serde_json::json!({
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "count": documents.len(),
+        "documents": documents
+    });
+    fs::write(file, serde_json::to_vec_pretty(&export)?)
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+    println!("Exported {} documents to {}", documents.len(), file.display());
+    Ok(())
+}
@@ -1,20 +1,269 @@
+use crate::models::{ChunkingProfile, ChunkingStrategy, RetrievalMode, StrictMode};
+use crate::services::dedup::DEFAULT_DEDUP_THRESHOLD;
+use crate::services::groundedness::VerificationMode;
+use crate::services::cache_manager::CacheBackend;
+use crate::services::rerank::RerankMode;
+use crate::services::HttpClientSettings;
+use crate::services::semantic_cache::DEFAULT_SEMANTIC_CACHE_SIZE;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     pub app_name: String,
     pub app_version: String,
     pub vector_store_path: PathBuf,
+    /// Gzip the saved vector store
+    pub store_compression: bool,
     pub upload_dir: PathBuf,
+    /// Largest file accepted by uploads, in MB
+    pub max_upload_size_mb: u64,
+    /// `extension=megabytes` overrides of `max_upload_size_mb`
+    pub upload_size_limits: String,
+    /// Largest JSON request body accepted, in KB
+    pub max_json_payload_kb: u64,
+    /// How long responses to requests with an `Idempotency-Key` are replayed
+    pub idempotency_ttl_secs: u64,
+    /// Where the embedding and answer caches are kept
+    pub cache_backend: CacheBackend,
+    /// Directory of the disk cache backend
+    pub cache_dir: PathBuf,
+    /// Server of the Redis cache backend
+    pub redis_url: String,
+    /// Query embeddings kept for repeated searches (0 = none)
+    pub embedding_cache_size: usize,
+    /// How long a query embedding is kept (0 = until evicted or the index
+    /// changes)
+    pub embedding_cache_ttl_secs: u64,
+    /// Generated answers kept for repeated questions (0 = none)
+    pub response_cache_size: usize,
+    /// How long a generated answer is kept (0 = until evicted or a document
+    /// it drew on changes)
+    pub response_cache_ttl_secs: u64,
+    /// Similarity at which a query is answered with the cached answer to
+    /// an earlier one asked the same way; 0 disables
+    pub semantic_cache_threshold: f32,
+    /// Answers kept for similar queries
+    pub semantic_cache_size: usize,
+    /// Where cached LLM answers are saved, to survive restarts; not saved
+    /// when unset
+    pub response_cache_file: Option<PathBuf>,
+    /// How often cached answers are saved besides at shutdown (0 = only
+    /// then)
+    pub cache_save_interval_secs: u64,
+    /// Frequent queries, one per line, run at startup so their embeddings
+    /// are cached before users ask them; none when unset
+    pub warmup_queries_file: Option<PathBuf>,
+    /// Also generate the warm-up queries' answers, caching them too
+    pub warmup_generate: bool,
+    /// How often the warm-up queries are run again (0 = only at startup)
+    pub warmup_interval_secs: u64,
     pub embedding_model: String,
     pub default_chunk_size: usize,
     pub default_chunk_overlap: usize,
+    pub parent_chunk_size: usize,
+    /// Chunking defaults keyed by file extension (e.g. ".pdf")
+    pub chunking_profiles: HashMap<String, ChunkingProfile>,
+    /// Files extracted and chunked at once when ingesting or re-indexing
+    /// many
+    pub ingest_workers: usize,
     pub groq_api_key: String,
+    /// Retries for transient Groq failures (429, 5xx, timeouts); 0 disables
+    pub groq_max_retries: u32,
+    /// First backoff delay, doubled on each retry up to the max
+    pub groq_retry_base_delay_ms: u64,
+    pub groq_retry_max_delay_ms: u64,
+    /// Client-side LLM rate limits; 0 disables each
+    pub llm_requests_per_minute: u32,
+    pub llm_tokens_per_minute: u32,
+    /// How long a request the provider refused, e.g. as too long, fails fast
+    /// with the same error instead of being sent again; 0 disables
+    pub llm_failure_cache_secs: u64,
+    /// Requests per minute allowed from each API key or IP, and the stricter
+    /// limit on routes that call the LLM; 0 disables each
+    pub rate_limit_per_minute: u32,
+    pub llm_rate_limit_per_minute: u32,
+    /// Seconds to wait for a connection to an LLM provider
+    pub llm_connect_timeout_secs: u64,
+    /// Seconds to wait for a whole response, or between chunks of a streamed one
+    pub llm_read_timeout_secs: u64,
+    /// Outbound HTTP client shared by LLM providers and webhooks: idle
+    /// connections kept per host, seconds they stay pooled and between TCP
+    /// keep-alive probes (0 disables each), HTTP/2, and a proxy overriding
+    /// `HTTP_PROXY`/`HTTPS_PROXY`
+    pub http_pool_max_idle_per_host: usize,
+    pub http_pool_idle_timeout_secs: u64,
+    pub http_tcp_keepalive_secs: u64,
+    pub http2: bool,
+    pub http_proxy_url: Option<String>,
+    /// Test the LLM with a tiny completion at startup; on failure the server
+    /// runs without LLM features
+    pub llm_startup_check: bool,
+    /// USD per million tokens for usage reports, e.g. "openai/gpt-oss-120b=0.15:0.75"
+    pub llm_prices: String,
     pub server_host: String,
     pub server_port: u16,
+    /// Origins browsers may call the API from, or "*" for any; only the
+    /// server's own origin when empty
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
     pub default_llm_model: String,
+    /// LLM backend: "groq" (default), "ollama", "openai" (any
+    /// OpenAI-compatible server), "azure", or "gemini"
+    pub llm_provider: String,
+    pub ollama_base_url: String,
+    pub openai_compatible: OpenAICompatibleConfig,
+    pub azure_openai: AzureOpenAIConfig,
+    pub gemini_api_key: String,
+    /// Blocking level for every Gemini harm category: none, low, medium, high
+    pub gemini_safety_threshold: String,
+    /// Per-category overrides, e.g. "harassment=low,dangerous_content=high"
+    pub gemini_safety_settings: String,
+    /// Replaces the built-in system prompt (tone, language, domain rules)
+    pub system_prompt: Option<String>,
+    /// Directory of named prompt templates (`<name>.txt`)
+    pub prompts_dir: PathBuf,
+    /// Where temporary workspaces keep their stores and uploads
+    pub workspaces_dir: PathBuf,
+    /// Workspaces unused this long are deleted with their documents
+    pub workspace_ttl_secs: u64,
+    /// Maintenance jobs and how often they run, in seconds (0 = never):
+    /// deleting uploads older than `cleanup_max_age_days` with their
+    /// documents, saving the store whole, snapshotting it to
+    /// `snapshot_dir` keeping the newest `snapshot_keep`, dropping expired
+    /// cache entries, and deleting documents past their retention period
+    pub cleanup_interval_secs: u64,
+    pub cleanup_max_age_days: u64,
+    pub compaction_interval_secs: u64,
+    pub snapshot_interval_secs: u64,
+    pub snapshot_dir: PathBuf,
+    pub snapshot_keep: usize,
+    pub cache_prune_interval_secs: u64,
+    pub retention_interval_secs: u64,
+    /// `tag=days` retention periods for documents without their own
+    pub retention_by_tag: String,
+    /// Built frontend served from `/`, with unknown paths getting its
+    /// `index.html`; off when unset
+    pub static_dir: Option<PathBuf>,
+    /// OTLP collector receiving the server's trace spans over gRPC; tracing
+    /// is off when unset
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` of exported spans
+    pub otel_service_name: String,
+    /// JSON file of `{question, answer}` examples prepended to answer prompts
+    pub few_shot_file: PathBuf,
+    /// JSON object of named query pipelines (rewrite, retrieve, rerank,
+    /// compress, generate, verify stages)
+    pub pipelines_file: PathBuf,
+    /// Pipeline run by queries that don't name one; handler settings otherwise
+    pub default_pipeline: Option<String>,
+    /// JSON experiment splitting `/api/query` traffic between pipelines
+    pub experiment_file: PathBuf,
+    /// Directory where RAG evaluation runs are saved, one JSON file each
+    pub eval_runs_dir: PathBuf,
+    /// JSON array of `{name, key, role}` API keys; access is open without it
+    pub api_keys_file: PathBuf,
+    /// Append-only JSON Lines record of uploads, deletions, and admin actions
+    pub audit_log_file: PathBuf,
+    /// Log query text, result counts, and cited documents for
+    /// `/api/analytics`; off by default
+    pub analytics_enabled: bool,
+    pub analytics_file: PathBuf,
+    /// Token for the `/api/admin` routes, besides admin API keys
+    pub admin_token: Option<String>,
+    /// URLs that receive document lifecycle events
+    pub webhook_urls: Vec<String>,
+    /// Key webhook deliveries are signed with; unsigned without one
+    pub webhook_secret: Option<String>,
+    /// Groundedness check on answers: off, lexical, or llm
+    pub answer_verification: VerificationMode,
+    /// Default handling of answers with unsupported claims: off, regenerate, or refuse
+    pub strict_mode: StrictMode,
+    /// Answer greetings, small talk, and commands without document context
+    pub intent_routing: bool,
+    /// Rewrite follow-up questions into standalone ones before searching
+    pub query_rewriting: bool,
+    /// Best retrieval score below which the LLM is skipped and "I don't
+    /// know" returned (0 = disabled)
+    pub answer_score_floor: f32,
+    /// Text overlap at which a retrieved chunk duplicates a higher-ranked one
+    /// and is left out of the context (0 = keep all)
+    pub dedup_threshold: f32,
+    /// Tokens of retrieved text packed into an answer's context by score
+    /// and novelty (0 = take the top chunks as ranked)
+    pub context_budget: usize,
+    /// How the store is searched for a query: standard or hyde
+    pub retrieval_mode: RetrievalMode,
+    /// Reorder retrieved chunks by LLM relevance scores: off or llm
+    pub rerank_mode: RerankMode,
+    /// Small, fast model that scores chunks; the answering model when unset
+    pub rerank_model: Option<String>,
+    /// File of block/redact rules applied to queries and answers
+    pub moderation_rules: Option<PathBuf>,
+    /// Guard model (e.g. a Llama Guard model on the active provider) that
+    /// classifies queries and answers as safe or unsafe
+    pub moderation_model: Option<String>,
+}
+
+/// Settings for a server speaking the OpenAI chat-completions API, such as
+/// vLLM, LM Studio, Together, or OpenRouter
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpenAICompatibleConfig {
+    /// Base URL including the version prefix, e.g. http://localhost:8000/v1
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    /// Whether the server accepts OpenAI `tools` (function calling)
+    pub supports_tools: bool,
+}
+
+impl OpenAICompatibleConfig {
+    fn from_env() -> Self {
+        OpenAICompatibleConfig {
+            base_url: env::var("LLM_BASE_URL").unwrap_or_default(),
+            api_key: env::var("LLM_API_KEY").ok().filter(|key| !key.is_empty()),
+            model: env::var("LLM_MODEL").unwrap_or_default(),
+            supports_tools: env::var("LLM_SUPPORTS_TOOLS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Azure OpenAI settings. Authentication uses the first of: API key, AAD
+/// token, or AAD client credentials (tenant, client id, client secret).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AzureOpenAIConfig {
+    pub endpoint: String,
+    pub deployment: String,
+    pub api_version: String,
+    pub api_key: Option<String>,
+    pub ad_token: Option<String>,
+    pub tenant_id: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+impl AzureOpenAIConfig {
+    fn from_env() -> Self {
+        let optional = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+
+        AzureOpenAIConfig {
+            endpoint: env::var("AZURE_OPENAI_ENDPOINT").unwrap_or_default(),
+            deployment: env::var("AZURE_OPENAI_DEPLOYMENT").unwrap_or_default(),
+            api_version: env::var("AZURE_OPENAI_API_VERSION")
+                .unwrap_or_else(|_| "2024-06-01".to_string()),
+            api_key: optional("AZURE_OPENAI_API_KEY"),
+            ad_token: optional("AZURE_OPENAI_AD_TOKEN"),
+            tenant_id: optional("AZURE_TENANT_ID"),
+            client_id: optional("AZURE_CLIENT_ID"),
+            client_secret: optional("AZURE_CLIENT_SECRET"),
+        }
+    }
 }
 
 impl AppConfig {
@@ -27,6 +276,22 @@ impl AppConfig {
                 String::new()
             });
 
+        let parse_env = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        let groq_max_retries = parse_env("GROQ_MAX_RETRIES", 3) as u32;
+        let groq_retry_base_delay_ms = parse_env("GROQ_RETRY_BASE_DELAY_MS", 500);
+        let groq_retry_max_delay_ms = parse_env("GROQ_RETRY_MAX_DELAY_MS", 30_000);
+        let llm_requests_per_minute = parse_env("LLM_REQUESTS_PER_MINUTE", 0) as u32;
+        let llm_tokens_per_minute = parse_env("LLM_TOKENS_PER_MINUTE", 0) as u32;
+        let rate_limit_per_minute = parse_env("RATE_LIMIT_PER_MINUTE", 0) as u32;
+        let llm_rate_limit_per_minute = parse_env("LLM_RATE_LIMIT_PER_MINUTE", 0) as u32;
+        let llm_connect_timeout_secs = parse_env("LLM_CONNECT_TIMEOUT_SECS", 10).max(1);
+        let llm_read_timeout_secs = parse_env("LLM_READ_TIMEOUT_SECS", 120).max(1);
+
         let vector_store_path = env::var("VECTOR_STORE_PATH")
             .unwrap_or_else(|_| "data/vector_store".to_string());
 
@@ -44,27 +309,396 @@ impl AppConfig {
             .parse::<u16>()
             .unwrap_or(8000);
 
-        let default_llm_model = env::var("DEFAULT_LLM_MODEL")
-            .unwrap_or_else(|_| "openai/gpt-oss-120b".to_string());
+        let llm_provider = env::var("LLM_PROVIDER")
+            .unwrap_or_else(|_| "groq".to_string())
+            .to_lowercase();
+
+        let ollama_base_url = env::var("OLLAMA_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:11434".to_string());
+
+        let openai_compatible = OpenAICompatibleConfig::from_env();
+
+        let default_llm_model = env::var("DEFAULT_LLM_MODEL").unwrap_or_else(|_| {
+            if llm_provider == "openai" {
+                openai_compatible.model.clone()
+            } else {
+                provider_default_model(&llm_provider).to_string()
+            }
+        });
+
+        // 0 disables small-to-big (parent-child) chunking
+        let parent_chunk_size = env::var("PARENT_CHUNK_SIZE")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let prompts_dir = env::var("PROMPTS_DIR").unwrap_or_else(|_| "prompts".to_string());
+
+        let mut chunking_profiles = default_chunking_profiles();
+        if let Ok(spec) = env::var("CHUNKING_PROFILES") {
+            match parse_chunking_profiles(&spec) {
+                Ok(overrides) => chunking_profiles.extend(overrides),
+                Err(e) => eprintln!("Warning: ignoring invalid CHUNKING_PROFILES: {}", e),
+            }
+        }
 
         AppConfig {
             app_name: "KnoRa AI Knowledge Assistant".to_string(),
             app_version: "2.0.0".to_string(),
             vector_store_path: PathBuf::from(vector_store_path),
+            store_compression: env::var("STORE_COMPRESSION").map(|v| v == "true" || v == "1").unwrap_or(false),
             upload_dir: PathBuf::from(upload_dir),
+            max_upload_size_mb: parse_env("MAX_UPLOAD_SIZE_MB", 100),
+            upload_size_limits: env::var("UPLOAD_SIZE_LIMITS").unwrap_or_default(),
+            max_json_payload_kb: parse_env("MAX_JSON_PAYLOAD_KB", 2048),
+            idempotency_ttl_secs: parse_env("IDEMPOTENCY_TTL_SECS", 24 * 60 * 60),
+            cache_backend: env::var("CACHE_BACKEND")
+                .ok()
+                .and_then(|v| match v.parse() {
+                    Ok(backend) => Some(backend),
+                    Err(e) => {
+                        eprintln!("Warning: {}, caching in memory", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            cache_dir: PathBuf::from(env::var("CACHE_DIR").unwrap_or_else(|_| "data/cache".to_string())),
+            redis_url: env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string()),
+            embedding_cache_size: parse_env("EMBEDDING_CACHE_SIZE", 1000) as usize,
+            embedding_cache_ttl_secs: parse_env("EMBEDDING_CACHE_TTL_SECS", 0),
+            response_cache_size: parse_env("RESPONSE_CACHE_SIZE", 1000) as usize,
+            response_cache_ttl_secs: parse_env("RESPONSE_CACHE_TTL_SECS", 0),
+            semantic_cache_threshold: env::var("SEMANTIC_CACHE_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse::<f32>().ok())
+                .unwrap_or(0.0),
+            semantic_cache_size: parse_env("SEMANTIC_CACHE_SIZE", DEFAULT_SEMANTIC_CACHE_SIZE as u64) as usize,
+            response_cache_file: env::var("RESPONSE_CACHE_FILE")
+                .unwrap_or_else(|_| "data/response_cache.json".to_string())
+                .split_whitespace()
+                .next()
+                .map(PathBuf::from),
+            cache_save_interval_secs: parse_env("CACHE_SAVE_INTERVAL_SECS", 5 * 60),
+            warmup_queries_file: env::var("WARMUP_QUERIES_FILE").ok().filter(|v| !v.is_empty()).map(PathBuf::from),
+            warmup_generate: env::var("WARMUP_GENERATE").map(|v| v == "true" || v == "1").unwrap_or(false),
+            warmup_interval_secs: parse_env("WARMUP_INTERVAL_SECS", 0),
             embedding_model,
-            default_chunk_size: 1000,
-            default_chunk_overlap: 200,
+            default_chunk_size: parse_env("CHUNK_SIZE", 1000).max(1) as usize,
+            // Overlap is counted in tokens; ~40 tokens is ~200 characters
+            default_chunk_overlap: parse_env("CHUNK_OVERLAP", 40) as usize,
+            parent_chunk_size,
+            chunking_profiles,
+            ingest_workers: parse_env("INGEST_WORKERS", 4).max(1) as usize,
             groq_api_key,
+            groq_max_retries,
+            groq_retry_base_delay_ms,
+            groq_retry_max_delay_ms,
+            llm_requests_per_minute,
+            llm_tokens_per_minute,
+            llm_failure_cache_secs: parse_env("LLM_FAILURE_CACHE_SECS", 60),
+            rate_limit_per_minute,
+            llm_rate_limit_per_minute,
+            llm_connect_timeout_secs,
+            llm_read_timeout_secs,
+            http_pool_max_idle_per_host: parse_env("HTTP_POOL_MAX_IDLE_PER_HOST", 32) as usize,
+            http_pool_idle_timeout_secs: parse_env("HTTP_POOL_IDLE_TIMEOUT_SECS", 90),
+            http_tcp_keepalive_secs: parse_env("HTTP_TCP_KEEPALIVE_SECS", 60),
+            http2: env::var("HTTP2").map(|v| v != "false" && v != "0").unwrap_or(true),
+            http_proxy_url: env::var("HTTP_PROXY_URL").ok().filter(|url| !url.is_empty()),
+            llm_startup_check: env::var("LLM_STARTUP_CHECK")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            llm_prices: env::var("LLM_PRICES").unwrap_or_default(),
             server_host,
             server_port,
+            cors_allowed_origins: env_list("CORS_ALLOWED_ORIGINS", &[]),
+            cors_allowed_methods: env_list("CORS_ALLOWED_METHODS", &["GET", "POST", "PUT", "DELETE"])
+                .into_iter()
+                .map(|method| method.to_uppercase())
+                .filter(|method| {
+                    let valid = actix_web::http::Method::from_bytes(method.as_bytes()).is_ok();
+                    if !valid {
+                        eprintln!("Warning: ignoring invalid method '{}' in CORS_ALLOWED_METHODS", method);
+                    }
+                    valid
+                })
+                .collect(),
+            cors_allowed_headers: env_list("CORS_ALLOWED_HEADERS", &["Authorization", "Content-Type", "X-API-Key", "X-Request-Id", "Idempotency-Key"]),
             default_llm_model,
+            llm_provider,
+            ollama_base_url,
+            openai_compatible,
+            azure_openai: AzureOpenAIConfig::from_env(),
+            gemini_api_key: env::var("GEMINI_API_KEY").unwrap_or_default(),
+            gemini_safety_threshold: env::var("GEMINI_SAFETY_THRESHOLD")
+                .unwrap_or_else(|_| "medium".to_string()),
+            gemini_safety_settings: env::var("GEMINI_SAFETY_SETTINGS").unwrap_or_default(),
+            system_prompt: load_system_prompt(),
+            answer_verification: env::var("ANSWER_VERIFICATION")
+                .ok()
+                .and_then(|v| match v.parse() {
+                    Ok(mode) => Some(mode),
+                    Err(e) => {
+                        eprintln!("Warning: {}, using lexical verification", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            strict_mode: env::var("STRICT_MODE")
+                .ok()
+                .and_then(|v| match v.parse() {
+                    Ok(mode) => Some(mode),
+                    Err(e) => {
+                        eprintln!("Warning: {}, strict mode disabled", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            intent_routing: env::var("INTENT_ROUTING")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            query_rewriting: env::var("QUERY_REWRITING")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            answer_score_floor: env::var("ANSWER_SCORE_FLOOR")
+                .ok()
+                .and_then(|v| v.parse::<f32>().ok())
+                .unwrap_or(0.0),
+            dedup_threshold: env::var("DEDUP_THRESHOLD")
+                .ok()
+                .and_then(|v| v.parse::<f32>().ok())
+                .unwrap_or(DEFAULT_DEDUP_THRESHOLD),
+            context_budget: env::var("CONTEXT_TOKEN_BUDGET")
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0),
+            prompts_dir: PathBuf::from(&prompts_dir),
+            workspaces_dir: PathBuf::from(env::var("WORKSPACES_DIR").unwrap_or_else(|_| "data/workspaces".to_string())),
+            workspace_ttl_secs: parse_env("WORKSPACE_TTL_SECS", 60 * 60).max(1),
+            cleanup_interval_secs: parse_env("CLEANUP_INTERVAL_SECS", 0),
+            cleanup_max_age_days: parse_env("CLEANUP_MAX_AGE_DAYS", 30).max(1),
+            compaction_interval_secs: parse_env("COMPACTION_INTERVAL_SECS", 60 * 60),
+            snapshot_interval_secs: parse_env("SNAPSHOT_INTERVAL_SECS", 0),
+            snapshot_dir: PathBuf::from(env::var("SNAPSHOT_DIR").unwrap_or_else(|_| "data/snapshots".to_string())),
+            snapshot_keep: parse_env("SNAPSHOT_KEEP", 7).max(1) as usize,
+            cache_prune_interval_secs: parse_env("CACHE_PRUNE_INTERVAL_SECS", 10 * 60),
+            retention_interval_secs: parse_env("RETENTION_INTERVAL_SECS", 60 * 60),
+            retention_by_tag: env::var("RETENTION_BY_TAG").unwrap_or_default(),
+            static_dir: env::var("STATIC_DIR").ok().filter(|v| !v.is_empty()).map(PathBuf::from),
+            otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok().filter(|v| !v.is_empty()),
+            otel_service_name: env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "knora".to_string()),
+            few_shot_file: env::var("FEW_SHOT_FILE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| PathBuf::from(&prompts_dir).join("few_shot.json")),
+            pipelines_file: PathBuf::from(
+                env::var("PIPELINES_FILE").unwrap_or_else(|_| "pipelines.json".to_string()),
+            ),
+            default_pipeline: env::var("DEFAULT_PIPELINE").ok().filter(|v| !v.is_empty()),
+            experiment_file: PathBuf::from(
+                env::var("EXPERIMENT_FILE").unwrap_or_else(|_| "experiment.json".to_string()),
+            ),
+            eval_runs_dir: PathBuf::from(
+                env::var("EVAL_RUNS_DIR").unwrap_or_else(|_| "data/eval_runs".to_string()),
+            ),
+            api_keys_file: PathBuf::from(
+                env::var("API_KEYS_FILE").unwrap_or_else(|_| "api_keys.json".to_string()),
+            ),
+            audit_log_file: PathBuf::from(
+                env::var("AUDIT_LOG_FILE").unwrap_or_else(|_| "data/audit.jsonl".to_string()),
+            ),
+            analytics_enabled: env::var("ANALYTICS_ENABLED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            analytics_file: PathBuf::from(
+                env::var("ANALYTICS_FILE").unwrap_or_else(|_| "data/analytics.jsonl".to_string()),
+            ),
+            // AUTH_TOKEN is what the storage routes checked before the admin scope
+            admin_token: env::var("ADMIN_TOKEN")
+                .or_else(|_| env::var("AUTH_TOKEN"))
+                .ok()
+                .filter(|token| !token.is_empty()),
+            webhook_urls: env_list("WEBHOOK_URLS", &[]),
+            webhook_secret: env::var("WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty()),
+            retrieval_mode: env::var("RETRIEVAL_MODE")
+                .ok()
+                .and_then(|v| match v.parse() {
+                    Ok(mode) => Some(mode),
+                    Err(e) => {
+                        eprintln!("Warning: {}, using standard retrieval", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            rerank_mode: env::var("RERANK_MODE")
+                .ok()
+                .and_then(|v| match v.parse() {
+                    Ok(mode) => Some(mode),
+                    Err(e) => {
+                        eprintln!("Warning: {}, reranking disabled", e);
+                        None
+                    }
+                })
+                .unwrap_or_default(),
+            rerank_model: env::var("RERANK_MODEL")
+                .ok()
+                .filter(|model| !model.trim().is_empty()),
+            moderation_rules: env::var("MODERATION_RULES")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            moderation_model: env::var("MODERATION_MODEL")
+                .ok()
+                .filter(|model| !model.trim().is_empty()),
         }
     }
 
+    /// Config read again with `.env` taking precedence over the values it
+    /// set before, for applying edits to it without a restart. Variables
+    /// removed from `.env` keep their old values.
+    pub fn reload() -> anyhow::Result<Self> {
+        // Deprecated, but the other loaders never override a variable
+        #[allow(deprecated)]
+        match dotenv::dotenv_iter() {
+            Ok(vars) => {
+                for var in vars {
+                    let (name, value) = var?;
+                    env::set_var(name, value);
+                }
+            }
+            Err(e) if e.not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(Self::from_env())
+    }
+
     pub fn server_addr(&self) -> String {
         format!("{}:{}", self.server_host, self.server_port)
     }
+
+    /// Model a provider should use by default: `DEFAULT_LLM_MODEL` for the
+    /// configured provider, the provider's own default for the others
+    pub fn model_for(&self, provider: &str) -> String {
+        if provider == self.llm_provider {
+            self.default_llm_model.clone()
+        } else if provider == "openai" {
+            self.openai_compatible.model.clone()
+        } else {
+            provider_default_model(provider).to_string()
+        }
+    }
+
+    /// Settings for the shared outbound HTTP client
+    pub fn http_client_settings(&self) -> HttpClientSettings {
+        let seconds = |secs: u64| (secs > 0).then(|| Duration::from_secs(secs));
+        HttpClientSettings {
+            pool_max_idle_per_host: self.http_pool_max_idle_per_host,
+            pool_idle_timeout: seconds(self.http_pool_idle_timeout_secs),
+            tcp_keepalive: seconds(self.http_tcp_keepalive_secs),
+            http2: self.http2,
+            proxy: self.http_proxy_url.clone(),
+            connect_timeout: Duration::from_secs(self.llm_connect_timeout_secs),
+        }
+    }
+}
+
+/// Comma-separated values of `name`, or `default` when it's unset
+fn env_list(name: &str, default: &[&str]) -> Vec<String> {
+    match env::var(name) {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => default.iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+/// `SYSTEM_PROMPT_FILE` wins over an inline `SYSTEM_PROMPT`
+fn load_system_prompt() -> Option<String> {
+    if let Ok(path) = env::var("SYSTEM_PROMPT_FILE") {
+        match std::fs::read_to_string(&path) {
+            Ok(prompt) if !prompt.trim().is_empty() => return Some(prompt.trim().to_string()),
+            Ok(_) => eprintln!("Warning: SYSTEM_PROMPT_FILE {} is empty, ignoring", path),
+            Err(e) => eprintln!("Warning: Failed to read SYSTEM_PROMPT_FILE {}: {}", path, e),
+        }
+    }
+
+    env::var("SYSTEM_PROMPT")
+        .ok()
+        .filter(|prompt| !prompt.trim().is_empty())
+}
+
+fn provider_default_model(provider: &str) -> &'static str {
+    match provider {
+        "ollama" => "llama3.1",
+        "gemini" => "gemini-1.5-flash",
+        _ => "openai/gpt-oss-120b",
+    }
+}
+
+/// Built-in per-file-type chunking defaults; file types not listed here use
+/// the global chunk size and overlap.
+fn default_chunking_profiles() -> HashMap<String, ChunkingProfile> {
+    let profile = |strategy, chunk_size, chunk_overlap| ChunkingProfile {
+        strategy,
+        chunk_size,
+        chunk_overlap,
+    };
+
+    HashMap::from([
+        (".csv".to_string(), profile(ChunkingStrategy::Token, 300, 30)),
+        (".xlsx".to_string(), profile(ChunkingStrategy::Token, 300, 30)),
+        (".xls".to_string(), profile(ChunkingStrategy::Token, 300, 30)),
+        (".pdf".to_string(), profile(ChunkingStrategy::Recursive, 800, 20)),
+        (".md".to_string(), profile(ChunkingStrategy::Markdown, 1000, 20)),
+    ])
+}
+
+/// Parse `CHUNKING_PROFILES`, a comma-separated list of
+/// `<extension>=<strategy>:<chunk_size>:<chunk_overlap>` entries, e.g.
+/// `.csv=token:300:30,.pdf=recursive:800:100`.
+fn parse_chunking_profiles(spec: &str) -> Result<HashMap<String, ChunkingProfile>, String> {
+    let mut profiles = HashMap::new();
+
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (extension, settings) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("expected <extension>=<settings> in '{}'", entry))?;
+
+        let parts: Vec<&str> = settings.split(':').collect();
+        if parts.len() != 3 {
+            return Err(format!("expected <strategy>:<size>:<overlap> in '{}'", entry));
+        }
+
+        let strategy = parts[0].parse::<ChunkingStrategy>()?;
+        let chunk_size = parts[1]
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| format!("invalid chunk size in '{}': {}", entry, e))?;
+        let chunk_overlap = parts[2]
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| format!("invalid chunk overlap in '{}': {}", entry, e))?;
+
+        let extension = extension.trim().to_lowercase();
+        let extension = if extension.starts_with('.') {
+            extension
+        } else {
+            format!(".{}", extension)
+        };
+
+        profiles.insert(
+            extension,
+            ChunkingProfile {
+                strategy,
+                chunk_size,
+                chunk_overlap,
+            },
+        );
+    }
+
+    Ok(profiles)
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,282 @@
+use actix_web::error::JsonPayloadError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+use crate::services::llm_handler::RecentFailure;
+use crate::services::llm_providers::ProviderError;
+
+/// Request fields whose settings are given at the top level of the JSON,
+/// so their errors are reported without the field's name
+const FLATTENED_FIELDS: &[&str] = &["sampling", "chunking"];
+
+/// Machine-readable reason a request failed, stable across releases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request was malformed or asked for something invalid
+    InvalidRequest,
+    /// Fields of the request are out of range or malformed; `details.fields`
+    /// says which and why
+    ValidationFailed,
+    /// No valid API key was sent
+    Unauthorized,
+    /// The API key may not do this
+    Forbidden,
+    NotFound,
+    /// A request with the same idempotency key hasn't finished yet
+    RequestInProgress,
+    /// The request body or uploaded file is over the configured limit
+    PayloadTooLarge,
+    /// Too many requests from this client
+    RateLimited,
+    /// LLM features are off because no provider is configured or reachable
+    LlmUnavailable,
+    /// The LLM provider didn't answer in time
+    LlmTimeout,
+    /// The LLM provider failed or returned something unusable
+    LlmError,
+    /// The LLM provider refused the request as sent, e.g. as too long for
+    /// the model; `details` has its status and, when the refusal was
+    /// remembered from an identical request, `retry_after_secs`
+    LlmRejected,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::ValidationFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::RequestInProgress => StatusCode::CONFLICT,
+            ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::LlmUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::LlmTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::LlmError => StatusCode::BAD_GATEWAY,
+            ErrorCode::LlmRejected => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later
+    pub fn retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorCode::RateLimited | ErrorCode::LlmTimeout | ErrorCode::RequestInProgress
+        )
+    }
+}
+
+/// Error returned by every handler as
+/// `{"code", "message", "details", "retryable", "error", "request_id"}`,
+/// where `error` repeats the message for clients of the older
+/// `{"error": "..."}` form
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    pub retryable: bool,
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+            details: None,
+            retryable: code.retryable(),
+        }
+    }
+
+    pub fn invalid(message: impl fmt::Display) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message.to_string())
+    }
+
+    /// A request that failed validation, with each failing field's
+    /// messages, keyed by paths such as `filters.as_of` or `cases[2].query`
+    pub fn validation(errors: ValidationErrors) -> Self {
+        let mut fields = BTreeMap::new();
+        collect_field_errors(&errors, "", &mut fields);
+        let summary: Vec<&String> = fields.values().flatten().collect();
+        Self::new(
+            ErrorCode::ValidationFailed,
+            format!("Invalid request: {}", summary.iter().map(|m| m.as_str()).collect::<Vec<_>>().join("; ")),
+        )
+        .with_details(serde_json::json!({ "fields": fields }))
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Forbidden, message)
+    }
+
+    /// A server-side failure while doing `action`, logged here
+    pub fn internal(action: &str, e: impl fmt::Display) -> Self {
+        log::error!("{}: {}", action, e);
+        Self::new(ErrorCode::Internal, format!("{}: {}", action, e))
+    }
+
+    /// An LLM call made while doing `action` failed, logged here; timeouts
+    /// are retryable, and requests the provider refused are reported with
+    /// its status
+    pub fn llm(action: &str, e: anyhow::Error) -> Self {
+        log::error!("{}: {}", action, e);
+        if let Some(recent) = e.downcast_ref::<RecentFailure>() {
+            return Self::new(ErrorCode::LlmRejected, format!("{}: {}", action, e)).with_details(serde_json::json!({
+                "provider": recent.error.provider,
+                "provider_status": recent.error.status,
+                "retry_after_secs": recent.retry_after_secs,
+            }));
+        }
+        let refused = e
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<ProviderError>())
+            .filter(|error| error.is_permanent());
+        if let Some(error) = refused {
+            return Self::new(ErrorCode::LlmRejected, format!("{}: {}", action, e)).with_details(serde_json::json!({
+                "provider": error.provider,
+                "provider_status": error.status,
+            }));
+        }
+        let code = if crate::services::is_timeout(&e) {
+            ErrorCode::LlmTimeout
+        } else {
+            ErrorCode::LlmError
+        };
+        Self::new(code, format!("{}: {}", action, e))
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// JSON sent to clients, also used for failed items of batch responses,
+    /// with the id of the request it failed in
+    pub fn body(&self) -> serde_json::Value {
+        let mut body = serde_json::to_value(self).unwrap_or_default();
+        body["error"] = serde_json::json!(self.message);
+        if let Some(request_id) = crate::middleware::current_request_id() {
+            body["request_id"] = serde_json::json!(request_id);
+        }
+        body
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.code.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, fields: &mut BTreeMap<String, Vec<String>>) {
+    for (field, kind) in errors.errors() {
+        let path = match (prefix, field.as_ref()) {
+            (_, field) if FLATTENED_FIELDS.contains(&field) => prefix.to_string(),
+            ("", field) => field.to_string(),
+            (prefix, field) => format!("{}.{}", prefix, field),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                let messages: Vec<String> = errors
+                    .iter()
+                    .map(|e| match &e.message {
+                        Some(message) => message.to_string(),
+                        None => format!("{} is invalid ({})", path, e.code),
+                    })
+                    .collect();
+                fields.entry(path).or_default().extend(messages);
+            }
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (i, errors) in items {
+                    collect_field_errors(errors, &format!("{}[{}]", path, i), fields);
+                }
+            }
+        }
+    }
+}
+
+/// `JsonConfig` error handler answering unreadable JSON bodies with an
+/// `ApiError`, 413 for ones over the size limit
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let code = match err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            ErrorCode::PayloadTooLarge
+        }
+        _ => ErrorCode::InvalidRequest,
+    };
+    ApiError::new(code, err.to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_error_body() {
+        let response = ApiError::new(ErrorCode::RateLimited, "Too many requests")
+            .with_details(serde_json::json!({"retry_after_secs": 30}))
+            .error_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({
+                "code": "rate_limited",
+                "message": "Too many requests",
+                "details": {"retry_after_secs": 30},
+                "retryable": true,
+                "error": "Too many requests"
+            })
+        );
+        assert!(!ApiError::not_found("Document not found: a.txt").retryable);
+    }
+
+    #[test]
+    fn test_validation_error_fields() {
+        use validator::Validate;
+
+        let request: crate::models::QueryRequest = serde_json::from_value(serde_json::json!({
+            "query": " ",
+            "k": 0,
+            "filters": {"as_of": "soon"},
+            "top_p": 1.5
+        }))
+        .unwrap();
+        let error = ApiError::validation(request.validate().unwrap_err());
+        assert_eq!(error.code.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.details.unwrap()["fields"],
+            serde_json::json!({
+                "filters.as_of": ["as_of must be a date such as 2024-03-15"],
+                "k": ["k must be between 1 and 100"],
+                "query": ["query must not be empty"],
+                "top_p": ["top_p must be greater than 0 and at most 1"]
+            })
+        );
+    }
+}
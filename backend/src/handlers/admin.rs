@@ -0,0 +1,310 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use log::info;
+use serde_json::json;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::middleware::{AuditDetails, Caller};
+use crate::models::ChunkingParams;
+use crate::reload::ConfigReloader;
+use crate::services::audit::{AuditLog, AuditQuery};
+use crate::services::maintenance::{cleanup_uploads, CleanupDefaults, CleanupQuery};
+use crate::services::scheduler::Scheduler;
+use crate::services::vector_store::ExportedDocument;
+use crate::services::webhooks::{deleted_document, indexed_document, WebhookEvent, Webhooks};
+use crate::services::{DocumentProcessor, VectorStore};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+/// Remove every document from the store
+pub async fn clear_store(
+    caller: Option<web::ReqData<Caller>>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    let file_paths = blocking(move || {
+        let mut store = vector_store.lock().unwrap();
+        let file_paths: Vec<String> = store.document_paths(None).into_iter().cloned().collect();
+        store
+            .clear_store()
+            .map_err(|e| ApiError::internal("Error clearing store", e))?;
+        Ok(file_paths)
+    })
+    .await?;
+    info!("Vector store cleared");
+    let deleted_by = caller.as_deref().map(|c| c.name.as_str());
+    for file_path in &file_paths {
+        webhooks.notify(WebhookEvent::DocumentDeleted, deleted_document(file_path, deleted_by));
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Vector store cleared successfully"
+    })))
+}
+
+/// Files in the upload directory and their sizes
+pub async fn get_storage_info(
+    upload_dir: web::Data<String>,
+) -> Result<HttpResponse, ApiError> {
+    let dir = upload_dir.clone();
+    let (files, total_size) = blocking(move || {
+        let dir_path = Path::new(dir.as_str());
+
+        // List all uploaded files
+        let mut files = Vec::new();
+        let mut total_size: u64 = 0;
+
+        if dir_path.exists() {
+            match fs::read_dir(dir_path) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        if let Ok(metadata) = entry.metadata() {
+                            if metadata.is_file() {
+                                let file_name = entry
+                                    .file_name()
+                                    .into_string()
+                                    .unwrap_or_else(|_| "unknown".to_string());
+                                let file_size = metadata.len();
+                                total_size += file_size;
+
+                                files.push(json!({
+                                    "name": file_name,
+                                    "size_bytes": file_size,
+                                    "size_mb": format!("{:.2}", file_size as f64 / (1024.0 * 1024.0))
+                                }));
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::warn!("Failed to read upload directory: {}", e);
+                }
+            }
+        }
+
+        Ok((files, total_size))
+    })
+    .await?;
+
+    info!(
+        "Retrieved storage info: {} files, {:.2} MB total",
+        files.len(),
+        total_size as f64 / (1024.0 * 1024.0)
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "upload_dir": upload_dir.as_str(),
+        "total_files": files.len(),
+        "total_size_bytes": total_size,
+        "total_size_mb": format!("{:.2}", total_size as f64 / (1024.0 * 1024.0)),
+        "files": files
+    })))
+}
+
+/// Delete uploads older than `days` (`CLEANUP_MAX_AGE_DAYS` by default)
+/// and their documents, optionally only some file types or those indexed
+/// in a collection or with a tag; `dry_run` only lists them
+pub async fn cleanup_old_files(
+    query: web::Query<CleanupQuery>,
+    upload_dir: web::Data<String>,
+    defaults: web::Data<CleanupDefaults>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    query.validate().map_err(ApiError::validation)?;
+    let default_days = defaults.max_age_days;
+    let report = blocking(move || {
+        cleanup_uploads(Path::new(upload_dir.as_str()), &query, default_days, &vector_store, &webhooks).map_err(|e| {
+            let mut errors = ValidationErrors::new();
+            errors.add("days", ValidationError::new("range").with_message(e.to_string().into()));
+            ApiError::validation(errors)
+        })
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "dry_run": report.dry_run,
+        "older_than_days": report.older_than_days,
+        "deleted_files": report.deleted_files,
+        "files": report.files,
+        "deleted_documents": report.deleted_documents,
+        "freed_space_bytes": report.freed_space_bytes,
+        "freed_space_mb": format!("{:.2}", report.freed_space_bytes as f64 / (1024.0 * 1024.0))
+    })))
+}
+
+/// Maintenance jobs, their schedules, and how their last runs went
+pub async fn get_scheduled_jobs(scheduler: web::Data<Scheduler>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "jobs": scheduler.statuses() }))
+}
+
+/// Run a maintenance job now, whatever its schedule
+pub async fn run_scheduled_job(
+    path: web::Path<String>,
+    scheduler: web::Data<Scheduler>,
+) -> Result<HttpResponse, ApiError> {
+    let status = scheduler
+        .run(&path)
+        .await
+        .map_err(|e| ApiError::not_found(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(json!({
+        "success": status.last_error.is_none(),
+        "job": status
+    })))
+}
+
+/// Process every document's source file again with the current chunking
+/// settings and replace its chunks. Documents whose files are gone or fail
+/// to process keep their old chunks and are listed as failed.
+pub async fn reindex(
+    processor: web::Data<Mutex<DocumentProcessor>>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    let (reindexed, failed) = blocking(move || {
+        let documents: Vec<(String, String, Option<String>)> = {
+            let store = vector_store.lock().unwrap();
+            store
+                .document_paths(None)
+                .into_iter()
+                .filter_map(|file_path| {
+                    let info = store.document_info(file_path)?;
+                    Some((file_path.clone(), info.file_name.clone(), info.owner.clone()))
+                })
+                .collect()
+        };
+
+        // Files are processed in parallel by a copy of the processor, so
+        // uploads aren't held up meanwhile, and replaced one at a time
+        let processor = processor.lock().unwrap().clone();
+        let files: Vec<(String, Option<String>)> = documents
+            .iter()
+            .map(|(file_path, file_name, _)| (file_path.clone(), Some(file_name.clone())))
+            .collect();
+        let mut reindexed = 0;
+        let mut failed = Vec::new();
+        processor.process_files(&files, &ChunkingParams::default(), |i, processing_result| {
+            let (file_path, file_name, owner) = &documents[i];
+            let result = processing_result.and_then(|mut document| {
+                document.file_name = file_name.clone();
+                vector_store.lock().unwrap().replace_document(document.clone())?;
+                Ok(document)
+            });
+            match result {
+                Ok(document) => {
+                    reindexed += 1;
+                    webhooks.notify(WebhookEvent::DocumentIndexed, indexed_document(&document, owner.as_deref()));
+                }
+                Err(e) => {
+                    log::warn!("Could not re-index {}: {}", file_path, e);
+                    webhooks.notify(
+                        WebhookEvent::IngestionFailed,
+                        json!({ "file_path": file_path, "file_name": file_name, "error": e.to_string() }),
+                    );
+                    failed.push(json!({ "file_path": file_path, "error": e.to_string() }));
+                }
+            }
+        });
+        Ok((reindexed, failed))
+    })
+    .await?;
+
+    info!("Re-indexed {} documents ({} failed)", reindexed, failed.len());
+    Ok(HttpResponse::Ok().json(json!({
+        "success": failed.is_empty(),
+        "reindexed": reindexed,
+        "failed": failed
+    })))
+}
+
+/// Every document with its chunks, owner, and sharing, for backups or
+/// moving to another instance with `import`
+pub async fn export_store(
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let documents = blocking(move || Ok(vector_store.lock().unwrap().export_documents())).await?;
+    info!("Exported {} documents", documents.len());
+    Ok(HttpResponse::Ok().json(json!({
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "count": documents.len(),
+        "documents": documents
+    })))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ImportRequest {
+    pub documents: Vec<ExportedDocument>,
+}
+
+/// Index documents from `export`, replacing ones with the same path
+pub async fn import_store(
+    req: web::Json<ImportRequest>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    let documents = req.into_inner().documents;
+    let events: Vec<_> = documents
+        .iter()
+        .map(|doc| {
+            json!({
+                "document_id": crate::models::document_id(&doc.file_path),
+                "file_path": doc.file_path,
+                "file_name": doc.info.file_name,
+                "file_type": doc.info.file_type,
+                "num_chunks": doc.chunks.len(),
+                "owner": doc.info.owner
+            })
+        })
+        .collect();
+    let count = blocking(move || {
+        vector_store
+            .lock()
+            .unwrap()
+            .import_documents(documents)
+            .map_err(|e| ApiError::internal("Error importing documents", e))
+    })
+    .await?;
+    for event in events {
+        webhooks.notify(WebhookEvent::DocumentIndexed, event);
+    }
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "imported": count
+    })))
+}
+
+/// Re-read the environment and `.env`, applying what can change without a
+/// restart and listing edited settings that can't
+pub async fn reload_config(
+    req: HttpRequest,
+    reloader: web::Data<ConfigReloader>,
+) -> Result<HttpResponse, ApiError> {
+    let report = reloader
+        .reload()
+        .map_err(|e| ApiError::internal("Error reloading configuration", e))?;
+    req.extensions_mut().insert(AuditDetails(json!({
+        "changed": report.changed,
+        "requires_restart": report.requires_restart
+    })));
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "changed": report.changed,
+        "requires_restart": report.requires_restart
+    })))
+}
+
+/// Audit log entries, newest first, optionally only one action (or a
+/// prefix of actions like `document.`) or one caller's
+pub async fn get_audit_log(
+    query: web::Query<AuditQuery>,
+    audit_log: web::Data<AuditLog>,
+) -> Result<HttpResponse, ApiError> {
+    let page = audit_log
+        .page(&query)
+        .map_err(|e| ApiError::internal("Error reading audit log", e))?;
+    Ok(HttpResponse::Ok().json(page))
+}
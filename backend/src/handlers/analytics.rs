@@ -0,0 +1,28 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use crate::error::ApiError;
+use crate::services::analytics::QueryAnalytics;
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsQuery {
+    /// Days back to summarize; defaults to 30
+    pub days: Option<i64>,
+    /// Entries per list; defaults to 20
+    pub limit: Option<usize>,
+}
+
+/// Most asked queries, queries nothing was found for, and the documents
+/// searches and answers pointed to most, when ANALYTICS_ENABLED is on
+pub async fn get_analytics(
+    query: web::Query<AnalyticsQuery>,
+    analytics: Option<web::Data<QueryAnalytics>>,
+) -> Result<HttpResponse, ApiError> {
+    let analytics = analytics
+        .ok_or_else(|| ApiError::not_found("Query analytics are off; set ANALYTICS_ENABLED=true to collect them"))?;
+    let days = query.days.unwrap_or(30).clamp(1, 3650);
+    let limit = query.limit.unwrap_or(20).clamp(1, 200);
+    let summary = analytics
+        .summary(days, limit)
+        .map_err(|e| ApiError::internal("Error reading analytics", e))?;
+    Ok(HttpResponse::Ok().json(summary))
+}
@@ -0,0 +1,231 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, Closed, Session};
+use futures::StreamExt;
+use tracing::Instrument;
+use log::info;
+use serde_json::json;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::middleware::{current_request_id, with_request_id, Caller};
+use crate::models::{ChatMessage, ChatRequest, SearchFilters};
+use crate::services::analytics::{cited_documents, QueryAnalytics, QueryEvent};
+use crate::services::few_shot::FewShotStore;
+use crate::services::{AnswerOptions, LLMHandler, VectorStore};
+use std::sync::Mutex;
+use validator::Validate;
+
+/// Messages of earlier turns kept per connection and sent as history
+const MAX_HISTORY_MESSAGES: usize = 10;
+
+/// Largest chat message accepted from a client
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+async fn send(session: &mut Session, frame: serde_json::Value) -> Result<(), Closed> {
+    session.text(frame.to_string()).await
+}
+
+/// An `error` frame carrying the same fields as HTTP error responses
+async fn send_error(session: &mut Session, error: ApiError) -> Result<(), Closed> {
+    let mut frame = error.body();
+    frame["type"] = json!("error");
+    send(session, frame).await
+}
+
+/// Chat over a WebSocket. Each text message is a `ChatRequest`; the server
+/// replies with a `sources` frame, `token` frames as the answer streams, and
+/// a final `message` frame with the full response, or an `error` frame.
+pub async fn chat(
+    req: HttpRequest,
+    body: web::Payload,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+    analytics: Option<web::Data<QueryAnalytics>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let caller = req.extensions().get::<Caller>().cloned();
+    let user = caller.as_ref().and_then(Caller::document_user).map(str::to_string);
+    let (response, mut session, stream) = actix_ws::handle(&req, body)?;
+    let mut stream = stream
+        .aggregate_continuations()
+        .max_continuation_size(MAX_MESSAGE_SIZE);
+
+    // Logs and error frames of the whole connection carry the id of the
+    // request that opened it, and each message is traced under it
+    let connection = tracing::Span::current();
+    actix_web::rt::spawn(with_request_id(current_request_id(), async move {
+        let mut history = Vec::new();
+        let filters = SearchFilters {
+            visible_to: user,
+            ..Default::default()
+        };
+
+        while let Some(message) = stream.recv().await {
+            let result = match message {
+                Ok(AggregatedMessage::Text(text)) => {
+                    answer_query(
+                        &mut session,
+                        &text,
+                        &mut history,
+                        &filters,
+                        &llm_handler,
+                        &vector_store,
+                        &few_shot,
+                        analytics.as_ref().map(|analytics| (analytics.get_ref(), caller.as_ref())),
+                    )
+                    .instrument(tracing::info_span!(parent: &connection, "chat_message"))
+                    .await
+                }
+                Ok(AggregatedMessage::Ping(bytes)) => session.pong(&bytes).await,
+                Ok(AggregatedMessage::Close(reason)) => {
+                    let _ = session.close(reason).await;
+                    return;
+                }
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    log::warn!("Chat WebSocket protocol error: {}", e);
+                    break;
+                }
+            };
+
+            // The client went away mid-answer
+            if result.is_err() {
+                return;
+            }
+        }
+
+        let _ = session.close(None).await;
+    }));
+
+    Ok(response)
+}
+
+/// Answer one chat message, streaming frames to the client, and record
+/// searched questions in `analytics` as the caller's
+#[allow(clippy::too_many_arguments)]
+async fn answer_query(
+    session: &mut Session,
+    text: &str,
+    history: &mut Vec<ChatMessage>,
+    filters: &SearchFilters,
+    llm_handler: &LLMHandler,
+    vector_store: &web::Data<Mutex<VectorStore>>,
+    few_shot: &Mutex<FewShotStore>,
+    analytics: Option<(&QueryAnalytics, Option<&Caller>)>,
+) -> Result<(), Closed> {
+    let request: ChatRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return send_error(session, ApiError::invalid(format!("Invalid chat message: {}", e))).await,
+    };
+    if let Err(errors) = request.validate() {
+        return send_error(session, ApiError::validation(errors)).await;
+    }
+    if let Err(e) = llm_handler.provider(request.provider.as_deref()) {
+        return send_error(session, ApiError::invalid(e)).await;
+    }
+    if request.reset {
+        history.clear();
+    }
+
+    let defaults = AnswerOptions::default();
+    let options = AnswerOptions {
+        max_tokens: request.max_tokens.unwrap_or(defaults.max_tokens),
+        temperature: request.temperature.unwrap_or(defaults.temperature),
+        sampling: request.sampling.clone(),
+        provider: request.provider.clone(),
+        model: request.model.clone(),
+        system_prompt: request.system_prompt.clone(),
+        examples: few_shot.lock().unwrap().examples().to_vec(),
+        language: request.language.clone(),
+        history: history.clone(),
+        retrieval: request.retrieval,
+        follow_ups: request.follow_ups,
+        ..defaults
+    };
+    if let Err(e) = options.validate() {
+        return send_error(session, ApiError::invalid(e)).await;
+    }
+
+    let mut standalone_query = None;
+    let searched = llm_handler.needs_retrieval(&request.query);
+    let results = if searched {
+        // Follow-ups are searched as standalone questions
+        let search_query = match llm_handler.standalone_query(&request.query, &options).await {
+            Ok(search_query) => search_query,
+            Err(e) => return send_error(session, ApiError::llm("Error preparing search", e)).await,
+        };
+        let search_text = match llm_handler.search_text(&search_query, &options).await {
+            Ok(text) => text,
+            Err(e) => return send_error(session, ApiError::llm("Error preparing search", e)).await,
+        };
+        let k = request.k.unwrap_or(5);
+        let score_threshold = request.score_threshold.unwrap_or(0.0);
+        let mode = llm_handler.retrieval_mode(&options);
+        let (vector_store, filters) = (vector_store.clone(), filters.clone());
+        let search = blocking(move || {
+            vector_store
+                .lock()
+                .unwrap()
+                .retrieve(&search_text, k, score_threshold, &filters, mode)
+                .map_err(|e| ApiError::internal("Search error", e))
+        })
+        .await;
+        if search_query != request.query {
+            standalone_query = Some(search_query);
+        }
+        match search {
+            Ok(results) => results,
+            Err(e) => return send_error(session, e).await,
+        }
+    } else {
+        Vec::new()
+    };
+
+    let response = if results.is_empty()
+        || llm_handler.moderation_enabled()
+        || llm_handler.below_score_floor(&request.query, &results)
+    {
+        // Small talk, commands, and queries with no (good enough) matches
+        // answer in one go, as do all queries under moderation, whose answers
+        // are checked first
+        match llm_handler.generate_answer(&request.query, &results, &options).await {
+            Ok(response) => response,
+            Err(e) => return send_error(session, ApiError::llm("Error generating answer", e)).await,
+        }
+    } else {
+        let (mut tokens, pending) = match llm_handler.stream_answer(&request.query, &results, &options).await {
+            Ok(stream) => stream,
+            Err(e) => return send_error(session, ApiError::llm("Error generating answer", e)).await,
+        };
+        send(session, json!({"type": "sources", "sources": pending.sources()})).await?;
+
+        let mut answer = String::new();
+        while let Some(token) = tokens.next().await {
+            match token {
+                Ok(token) => {
+                    answer.push_str(&token);
+                    send(session, json!({"type": "token", "content": token})).await?;
+                }
+                Err(e) => return send_error(session, ApiError::llm("Error generating answer", e)).await,
+            }
+        }
+
+        llm_handler.finish_answer(pending, answer.trim()).await
+    };
+
+    info!("Answered chat query: {}", request.query);
+    if let Some((analytics, caller)) = analytics.filter(|_| searched) {
+        let user = caller.map(|c| c.name.as_str());
+        analytics.record(QueryEvent::new("chat", &request.query, results.len(), cited_documents(&response), user));
+    }
+    history.push(ChatMessage::user(request.query));
+    history.push(ChatMessage::assistant(response["answer"].as_str().unwrap_or_default()));
+    let excess = history.len().saturating_sub(MAX_HISTORY_MESSAGES);
+    history.drain(..excess);
+
+    let mut frame = response;
+    frame["type"] = json!("message");
+    if let Some(standalone_query) = standalone_query {
+        frame["rewritten_query"] = json!(standalone_query);
+    }
+    send(session, frame).await
+}
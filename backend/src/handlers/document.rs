@@ -1,65 +1,284 @@
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
 use actix_web::{web, HttpResponse};
 use log::info;
-use crate::models::{ProcessFileRequest, ProcessFileResponse};
-use crate::services::DocumentProcessor;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::middleware::Caller;
+use crate::models::{ChunkingParams, ProcessFileRequest, ProcessFileResponse, ShareRequest};
+use crate::services::webhooks::{deleted_document, indexed_document, WebhookEvent, Webhooks};
+use crate::services::{DocumentProcessor, VectorStore};
 use std::sync::Mutex;
 use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use validator::Validate;
 
 pub async fn process_file(
     req: web::Json<ProcessFileRequest>,
     processor: web::Data<Mutex<DocumentProcessor>>,
-) -> HttpResponse {
-    let processor = processor.lock().unwrap();
-
-    match processor.process_file(&req.file_path) {
-        Ok(document) => {
-            info!("Successfully processed file: {}", req.file_path);
-            HttpResponse::Ok().json(ProcessFileResponse {
-                success: true,
-                message: format!("File processed successfully: {}", document.file_name),
-                document: Some(document),
-            })
-        }
-        Err(e) => {
-            log::error!("Error processing file: {}", e);
-            HttpResponse::BadRequest().json(ProcessFileResponse {
-                success: false,
-                message: format!("Error processing file: {}", e),
-                document: None,
-            })
-        }
-    }
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    let req = req.into_inner();
+
+    let document = blocking(move || {
+        let document = processor
+            .lock()
+            .unwrap()
+            .process_file_with_params(&req.file_path, None, &req.chunking)
+            .map_err(|e| {
+                log::error!("Error processing file: {}", e);
+                ApiError::invalid(format!("Error processing file: {}", e))
+            })?;
+        info!("Successfully processed file: {}", req.file_path);
+        Ok(document)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(ProcessFileResponse {
+        success: true,
+        message: format!("File processed successfully: {}", document.file_name),
+        document: Some(document),
+    }))
 }
 
 pub async fn get_file_stats(
     query: web::Query<HashMap<String, String>>,
     processor: web::Data<Mutex<DocumentProcessor>>,
-) -> HttpResponse {
-    let file_path = match query.get("file_path") {
-        Some(path) => path,
-        None => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "file_path query parameter is required"
-            }))
+) -> Result<HttpResponse, ApiError> {
+    let file_path = query
+        .get("file_path")
+        .ok_or_else(|| ApiError::invalid("file_path query parameter is required"))?
+        .clone();
+
+    let document = blocking(move || {
+        processor
+            .lock()
+            .unwrap()
+            .process_file(&file_path)
+            .map_err(|e| ApiError::not_found(format!("File not found: {}", e)))
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "file_name": document.file_name,
+        "file_type": document.file_type,
+        "file_size": document.file_size,
+        "num_chunks": document.num_chunks,
+        "text_length": document.text_length,
+    })))
+}
+
+/// Re-chunk and re-embed an indexed document with new chunking parameters,
+/// re-reading the source file from disk instead of requiring a re-upload
+pub async fn rechunk_document(
+    path: web::Path<String>,
+    params: web::Json<ChunkingParams>,
+    caller: Option<web::ReqData<Caller>>,
+    processor: web::Data<Mutex<DocumentProcessor>>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    params.validate().map_err(ApiError::validation)?;
+    let document_id = path.into_inner();
+    let user = caller.as_deref().and_then(Caller::document_user);
+
+    let found = {
+        let store = vector_store.lock().unwrap();
+        store
+            .find_document_by_id(&document_id)
+            .filter(|(_, info)| info.visible_to(user))
+            .map(|(file_path, info)| (file_path.clone(), info.file_name.clone(), info.modifiable_by(user)))
+    };
+
+    let (file_path, file_name) = match found {
+        Some((file_path, file_name, true)) => (file_path, file_name),
+        Some((_, file_name, false)) => {
+            return Err(ApiError::forbidden(format!("Only the owner can re-chunk {}", file_name)))
         }
+        None => return Err(ApiError::not_found(format!("Document not found: {}", document_id))),
     };
 
-    let processor = processor.lock().unwrap();
-
-    match processor.process_file(file_path.as_str()) {
-        Ok(document) => {
-            HttpResponse::Ok().json(serde_json::json!({
-                "file_name": document.file_name,
-                "file_type": document.file_type,
-                "file_size": document.file_size,
-                "num_chunks": document.num_chunks,
-                "text_length": document.text.len(),
-            }))
+    let document = blocking(move || {
+        let processing_result = {
+            let processor = processor.lock().unwrap();
+            processor.process_file_with_params(&file_path, Some(&file_name), &params)
+        };
+
+        let mut document = processing_result.map_err(|e| {
+            log::error!("Error re-chunking document {}: {}", document_id, e);
+            webhooks.notify(
+                WebhookEvent::IngestionFailed,
+                serde_json::json!({
+                    "document_id": document_id,
+                    "file_path": file_path,
+                    "file_name": file_name,
+                    "error": e.to_string()
+                }),
+            );
+            ApiError::invalid(format!("Error re-chunking document: {}", e))
+        })?;
+        document.file_name = file_name;
+
+        let mut store = vector_store.lock().unwrap();
+        let replaced = store
+            .replace_document(document.clone())
+            .map_err(|e| ApiError::internal("Error replacing document chunks", e))?;
+        if !replaced {
+            return Err(ApiError::not_found(format!(
+                "Document was removed while re-chunking: {}",
+                document_id
+            )));
+        }
+        info!("Re-chunked document {} into {} chunks", file_path, document.num_chunks);
+        let owner = store.document_info(&file_path).and_then(|info| info.owner.as_deref());
+        webhooks.notify(WebhookEvent::DocumentIndexed, indexed_document(&document, owner));
+        Ok(document)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(ProcessFileResponse {
+        success: true,
+        message: format!("Document re-chunked successfully: {}", document.file_name),
+        document: Some(document),
+    }))
+}
+
+/// Set who besides its owner may search a document; only the owner or an
+/// admin may
+pub async fn share_document(
+    path: web::Path<String>,
+    req: web::Json<ShareRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let document_id = path.into_inner();
+    let user = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+    let shared_with: Vec<String> = req
+        .shared_with
+        .iter()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let (id, sharing) = (document_id.clone(), shared_with.clone());
+    blocking(move || {
+        let user = user.as_deref();
+        let mut store = vector_store.lock().unwrap();
+        let file_path = match store.find_document_by_id(&id) {
+            Some((file_path, info)) if info.modifiable_by(user) => file_path.clone(),
+            Some((_, info)) if info.visible_to(user) => {
+                return Err(ApiError::forbidden(format!("Only the owner can share {}", info.file_name)))
+            }
+            _ => return Err(ApiError::not_found(format!("Document not found: {}", id))),
+        };
+
+        store
+            .share_document(&file_path, sharing.clone())
+            .map_err(|e| ApiError::internal("Error sharing document", e))?;
+        info!("Shared {} with {:?}", file_path, sharing);
+        Ok(())
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "document_id": document_id,
+        "shared_with": shared_with
+    })))
+}
+
+/// `file_path` if it's an existing file in the upload directory
+fn uploaded_file(upload_dir: &str, file_path: &str) -> Option<PathBuf> {
+    let uploads = Path::new(upload_dir).canonicalize().ok()?;
+    let file = Path::new(file_path).canonicalize().ok()?;
+    (file.starts_with(&uploads) && file.is_file()).then_some(file)
+}
+
+/// The original file of an uploaded document, with its content type and
+/// support for range requests, so citations can link to the source. Only
+/// files in the upload directory are served.
+pub async fn download_document(
+    path: web::Path<String>,
+    caller: Option<web::ReqData<Caller>>,
+    upload_dir: web::Data<String>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<NamedFile, ApiError> {
+    let document_id = path.into_inner();
+    let user = caller.as_deref().and_then(Caller::document_user);
+    let (file_path, file_name) = vector_store
+        .lock()
+        .unwrap()
+        .find_document_by_id(&document_id)
+        .filter(|(_, info)| info.visible_to(user))
+        .map(|(file_path, info)| (file_path.clone(), info.file_name.clone()))
+        .ok_or_else(|| ApiError::not_found(format!("Document not found: {}", document_id)))?;
+
+    let not_found = || ApiError::not_found(format!("Original file of {} is no longer available", file_name));
+    let file = uploaded_file(&upload_dir, &file_path).ok_or_else(not_found)?;
+    let file = NamedFile::open_async(&file).await.map_err(|_| not_found())?;
+    Ok(file.set_content_disposition(ContentDisposition {
+        disposition: DispositionType::Inline,
+        parameters: vec![DispositionParam::Filename(file_name)],
+    }))
+}
+
+/// Remove a document's chunks from the store and its file from the upload
+/// directory together. The file is moved aside first and put back if the
+/// store can't be updated, so neither is left without the other.
+pub async fn delete_document(
+    path: web::Path<String>,
+    caller: Option<web::ReqData<Caller>>,
+    upload_dir: web::Data<String>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    webhooks: web::Data<Webhooks>,
+) -> Result<HttpResponse, ApiError> {
+    let document_id = path.into_inner();
+    let user = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+
+    let id = document_id.clone();
+    let (file_path, file_deleted) = blocking(move || {
+        let user = user.as_deref();
+        let mut store = vector_store.lock().unwrap();
+        let file_path = match store.find_document_by_id(&id) {
+            Some((file_path, info)) if info.modifiable_by(user) => file_path.clone(),
+            Some((_, info)) if info.visible_to(user) => {
+                return Err(ApiError::forbidden(format!("Only the owner can delete {}", info.file_name)))
+            }
+            _ => return Err(ApiError::not_found(format!("Document not found: {}", id))),
+        };
+
+        let set_aside = match uploaded_file(&upload_dir, &file_path) {
+            Some(file) => {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                let aside = file.with_file_name(format!(".{}.deleting", name));
+                fs::rename(&file, &aside).map_err(|e| ApiError::internal("Error removing the uploaded file", e))?;
+                Some((file, aside))
+            }
+            None => None,
+        };
+
+        if let Err(e) = store.delete_document(&file_path) {
+            if let Some((file, aside)) = &set_aside {
+                if let Err(e) = fs::rename(aside, file) {
+                    log::error!("Could not restore {} after a failed delete: {}", file.display(), e);
+                }
+            }
+            return Err(ApiError::internal("Error deleting document", e));
         }
-        Err(e) => {
-            HttpResponse::NotFound().json(serde_json::json!({
-                "error": format!("File not found: {}", e)
-            }))
+        if let Some((file, aside)) = &set_aside {
+            if let Err(e) = fs::remove_file(aside) {
+                log::warn!("Deleted {} from the store but not from disk: {}", file.display(), e);
+            }
         }
-    }
+        Ok((file_path, set_aside.is_some()))
+    })
+    .await?;
+
+    info!("Deleted document {} ({})", document_id, file_path);
+    webhooks.notify(
+        WebhookEvent::DocumentDeleted,
+        deleted_document(&file_path, caller.as_deref().map(|c| c.name.as_str())),
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "document_id": document_id,
+        "file_deleted": file_deleted
+    })))
 }
@@ -0,0 +1,326 @@
+use actix_web::{web, HttpResponse};
+use futures::stream::{self, StreamExt};
+use log::info;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::middleware::Caller;
+use crate::models::{document_id, RagCase, RagEvalRequest, RetrievalEvalRequest, SearchResult, TestsetRequest};
+use crate::services::evaluation::{
+    mean_metrics, mean_scores, ranking_metrics, testset_chunks, EvalRun, EvalRunStore, RagScores,
+};
+use crate::services::few_shot::FewShotStore;
+use crate::services::{AnswerOptions, LLMHandler, VectorStore};
+use std::sync::Mutex;
+use validator::Validate;
+
+/// Questions answered and judged at once unless the request asks otherwise
+const DEFAULT_EVAL_CONCURRENCY: usize = 4;
+
+/// Whether `expected` (a document id, file path, or file name) names the
+/// document `result` came from
+fn is_document(result: &SearchResult, expected: &String) -> bool {
+    *expected == result.file_path || *expected == result.file_name || *expected == document_id(&result.file_path)
+}
+
+/// Run each gold-set query against the store and report recall@k, MRR, and
+/// nDCG@k over the distinct documents retrieved, per query and on average
+pub async fn evaluate_retrieval(
+    req: web::Json<RetrievalEvalRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let mut req = req.into_inner();
+    req.validate().map_err(ApiError::validation)?;
+    req.filters.visible_to = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+
+    let k = req.k.unwrap_or(5);
+    // The store is locked per case, so other requests aren't held up for
+    // the whole run
+    let (cases, metrics) = blocking(move || {
+        let mut cases = Vec::with_capacity(req.cases.len());
+        let mut metrics = Vec::with_capacity(req.cases.len());
+        for case in &req.cases {
+            let results = vector_store
+                .lock()
+                .unwrap()
+                .search_filtered(&case.query, k, 0.0, &req.filters)
+                .map_err(|e| ApiError::internal("Search error", e))?;
+
+            // Several chunks of one document count as a single hit
+            let mut ranked: Vec<SearchResult> = Vec::new();
+            for result in results {
+                if !ranked.iter().any(|r| r.file_path == result.file_path) {
+                    ranked.push(result);
+                }
+            }
+
+            let case_metrics = ranking_metrics(&ranked, &case.expected_documents, k, is_document);
+            metrics.push(case_metrics);
+            cases.push(serde_json::json!({
+                "query": case.query,
+                "expected_documents": case.expected_documents,
+                "retrieved_documents": ranked.iter().map(|r| &r.file_name).collect::<Vec<_>>(),
+                "recall": case_metrics.recall,
+                "reciprocal_rank": case_metrics.reciprocal_rank,
+                "ndcg": case_metrics.ndcg
+            }));
+        }
+        Ok((cases, metrics))
+    })
+    .await?;
+
+    let mean = mean_metrics(&metrics);
+    info!(
+        "Retrieval evaluation over {} queries: recall@{} {:.3}, MRR {:.3}, nDCG@{} {:.3}",
+        cases.len(),
+        k,
+        mean.recall,
+        mean.reciprocal_rank,
+        k,
+        mean.ndcg
+    );
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "k": k,
+        "count": cases.len(),
+        "recall_at_k": mean.recall,
+        "mrr": mean.reciprocal_rank,
+        "ndcg_at_k": mean.ndcg,
+        "cases": cases
+    })))
+}
+
+/// Answer one evaluation question through the full pipeline and grade it
+async fn evaluate_case(
+    case: &RagCase,
+    req: &RagEvalRequest,
+    options: &AnswerOptions,
+    llm_handler: &LLMHandler,
+    vector_store: &web::Data<Mutex<VectorStore>>,
+) -> anyhow::Result<(serde_json::Value, RagScores)> {
+    let k = req.k.unwrap_or(5).max(1);
+    let search_text = llm_handler.search_text(&case.question, options).await?;
+    let mode = llm_handler.retrieval_mode(options);
+    let (vector_store, filters) = (vector_store.clone(), req.filters.clone());
+    let results = web::block(move || vector_store.lock().unwrap().retrieve(&search_text, k, 0.0, &filters, mode))
+        .await
+        .map_err(|e| anyhow::anyhow!("Background task failed: {}", e))??;
+
+    let response = llm_handler.generate_answer(&case.question, &results, options).await?;
+    let answer = response["answer"].as_str().unwrap_or_default();
+    let contexts: Vec<&str> = results.iter().map(|r| r.text.as_str()).collect();
+    let scores = llm_handler
+        .judge_rag(
+            req.judge_provider.as_deref().or(req.provider.as_deref()),
+            req.judge_model.as_deref().or(req.model.as_deref()),
+            &case.question,
+            &contexts,
+            answer,
+            case.reference_answer.as_deref(),
+        )
+        .await?;
+
+    let result = serde_json::json!({
+        "question": case.question,
+        "reference_answer": case.reference_answer,
+        "answer": answer,
+        "retrieved": results.iter().map(|r| serde_json::json!({
+            "file_name": r.file_name,
+            "chunk_id": r.chunk_id,
+            "similarity_score": r.similarity_score
+        })).collect::<Vec<_>>(),
+        "scores": scores
+    });
+    Ok((result, scores))
+}
+
+/// Run labeled questions through retrieval and generation, grade each with
+/// an LLM judge for faithfulness, answer relevance, and context precision,
+/// and save the run so later runs can be compared with it
+pub async fn evaluate_rag(
+    req: web::Json<RagEvalRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+    runs: web::Data<EvalRunStore>,
+) -> Result<HttpResponse, ApiError> {
+    let mut req = req.into_inner();
+    req.validate().map_err(ApiError::validation)?;
+    req.filters.visible_to = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+    for provider in [&req.provider, &req.judge_provider] {
+        llm_handler.provider(provider.as_deref()).map_err(ApiError::invalid)?;
+    }
+
+    let options = AnswerOptions {
+        provider: req.provider.clone(),
+        model: req.model.clone(),
+        examples: few_shot.lock().unwrap().examples().to_vec(),
+        ..Default::default()
+    };
+    let concurrency = req.concurrency.unwrap_or(DEFAULT_EVAL_CONCURRENCY);
+
+    let outcomes: Vec<anyhow::Result<(serde_json::Value, RagScores)>> = stream::iter(&req.cases)
+        .map(|case| evaluate_case(case, &req, &options, &llm_handler, &vector_store))
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let mut cases = Vec::with_capacity(outcomes.len());
+    let mut scores = Vec::new();
+    for (case, outcome) in req.cases.iter().zip(outcomes) {
+        match outcome {
+            Ok((result, case_scores)) => {
+                cases.push(result);
+                scores.push(case_scores);
+            }
+            Err(e) => {
+                log::warn!("Evaluation of '{}' failed: {}", case.question, e);
+                cases.push(serde_json::json!({
+                    "question": case.question,
+                    "error": e.to_string()
+                }));
+            }
+        }
+    }
+
+    let run = EvalRun {
+        id: uuid::Uuid::new_v4().to_string(),
+        name: req.name.clone(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        settings: serde_json::json!({
+            "k": req.k.unwrap_or(5).max(1),
+            "provider": llm_handler.provider(req.provider.as_deref()).map(|p| p.name().to_string()).ok(),
+            "model": req.model,
+            "judge_provider": req.judge_provider,
+            "judge_model": req.judge_model
+        }),
+        scores: mean_scores(&scores),
+        count: cases.len(),
+        failed: cases.len() - scores.len(),
+        cases,
+    };
+    if let Err(e) = runs.save(&run) {
+        log::error!("Failed to save evaluation run {}: {}", run.id, e);
+    }
+
+    info!(
+        "RAG evaluation {} over {} questions ({} failed): faithfulness {:.3}, answer relevance {:.3}, context precision {:.3}",
+        run.id,
+        run.count,
+        run.failed,
+        run.scores.faithfulness,
+        run.scores.answer_relevance,
+        run.scores.context_precision
+    );
+    Ok(HttpResponse::Ok().json(run))
+}
+
+/// Generate question/answer pairs from stored chunks of the given documents
+/// (all of them by default), each labeled with its source. The cases can
+/// be posted as-is to `/api/eval/rag` and `/api/eval/retrieval`.
+pub async fn generate_testset(
+    req: web::Json<TestsetRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    let n = req.n.unwrap_or(10);
+    llm_handler.provider(req.provider.as_deref()).map_err(ApiError::invalid)?;
+
+    // (file path, file name) and chunks of each document
+    let user = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+    let file_paths = req.file_paths.clone();
+    let (documents, chunk_lists): (Vec<(String, String)>, Vec<_>) = blocking(move || {
+        let store = vector_store.lock().unwrap();
+        let user = user.as_deref();
+        let names: Vec<String> = if file_paths.is_empty() {
+            store.document_paths(user).into_iter().cloned().collect()
+        } else {
+            file_paths
+        };
+        let mut documents = Vec::with_capacity(names.len());
+        for name in &names {
+            let Some((file_path, _)) = store.find_visible_document(name, user) else {
+                return Err(ApiError::not_found(format!("Document not found: {}", name)));
+            };
+            let file_path = file_path.clone();
+            if let Some((file_name, chunks)) = store.document_chunks(&file_path) {
+                documents.push(((file_path, file_name), chunks));
+            }
+        }
+        Ok(documents.into_iter().unzip())
+    })
+    .await?;
+    let picked = testset_chunks(&chunk_lists, n);
+    if picked.is_empty() {
+        return Err(ApiError::invalid(
+            "The documents have no chunks long enough to ask questions about",
+        ));
+    }
+
+    let concurrency = req.concurrency.unwrap_or(DEFAULT_EVAL_CONCURRENCY);
+    let outcomes: Vec<_> = stream::iter(&picked)
+        .map(|(doc, chunk)| {
+            let (_, file_name) = &documents[*doc];
+            llm_handler.generate_test_case(req.provider.as_deref(), req.model.as_deref(), file_name, &chunk.text)
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let mut cases = Vec::with_capacity(outcomes.len());
+    for ((doc, chunk), outcome) in picked.iter().zip(outcomes) {
+        let (file_path, file_name) = &documents[*doc];
+        match outcome {
+            Ok((question, answer)) => cases.push(serde_json::json!({
+                "question": question,
+                "reference_answer": answer,
+                "expected_documents": [file_path],
+                "source": {
+                    "file_name": file_name,
+                    "file_path": file_path,
+                    "chunk_id": chunk.chunk_id,
+                    "text": chunk.text
+                }
+            })),
+            Err(e) => log::warn!("No test question from {} chunk {}: {}", file_name, chunk.chunk_id, e),
+        }
+    }
+
+    info!(
+        "Generated {} test questions from {} documents ({} failed)",
+        cases.len(),
+        documents.len(),
+        picked.len() - cases.len()
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": cases.len(),
+        "failed": picked.len() - cases.len(),
+        "cases": cases
+    })))
+}
+
+/// Saved evaluation runs without their per-question results, newest first
+pub async fn list_runs(
+    runs: web::Data<EvalRunStore>,
+) -> Result<HttpResponse, ApiError> {
+    let runs = runs
+        .list()
+        .map_err(|e| ApiError::internal("Error listing evaluation runs", e))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": runs.len(),
+        "runs": runs
+    })))
+}
+
+pub async fn get_run(
+    path: web::Path<String>,
+    runs: web::Data<EvalRunStore>,
+) -> Result<HttpResponse, ApiError> {
+    let run = runs
+        .get(&path)
+        .ok_or_else(|| ApiError::not_found(format!("Evaluation run not found: {}", path)))?;
+    Ok(HttpResponse::Ok().json(run))
+}
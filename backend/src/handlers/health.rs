@@ -1,11 +1,127 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use log::info;
+use crate::handlers::blocking;
+use crate::handlers::llm::LLMStatus;
+use crate::services::{LLMHandler, VectorStore};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-pub async fn health_check() -> HttpResponse {
+/// Longest a deep health check waits for the LLM provider
+const LLM_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub async fn health_check(
+    llm_status: web::Data<LLMStatus>,
+) -> HttpResponse {
     info!("Health check endpoint called");
     HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
+        "status": if llm_status.available { "healthy" } else { "degraded" },
         "service": "KnoRa AI Backend",
-        "version": "2.0.0"
+        "version": "2.0.0",
+        "llm": llm_status.get_ref()
     }))
 }
+
+/// Write and remove a probe file in `dir`
+fn check_writable(dir: &Path) -> anyhow::Result<()> {
+    let probe = dir.join(format!(".health_check_{}", uuid::Uuid::new_v4()));
+    fs::write(&probe, b"ok").map_err(|e| anyhow::anyhow!("{} is not writable: {}", dir.display(), e))?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
+
+/// Status and latency of one dependency
+fn check_result(started: Instant, result: anyhow::Result<()>) -> serde_json::Value {
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(()) => serde_json::json!({ "status": "ok", "latency_ms": latency_ms }),
+        Err(e) => serde_json::json!({ "status": "failed", "latency_ms": latency_ms, "error": e.to_string() }),
+    }
+}
+
+/// Check every dependency: the vector store reads back and is writable,
+/// the upload directory is writable, embeddings work, and the LLM provider
+/// accepts our key. Answers 503 if any of them failed.
+pub async fn deep_health_check(
+    upload_dir: web::Data<String>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    llm_status: web::Data<LLMStatus>,
+    llm_handler: Option<web::Data<LLMHandler>>,
+) -> HttpResponse {
+    // Reading the saved store and writing probe files block, so they run
+    // off the async workers
+    let disk_checks = blocking(move || {
+        let (vector_store_check, embeddings_check) = {
+            let store = vector_store.lock().unwrap();
+            let started = Instant::now();
+            let saved = store.check_saved_store().and_then(|_| check_writable(store.store_path()));
+            let vector_store_check = check_result(started, saved);
+            let started = Instant::now();
+            let embeddings_check = check_result(started, store.check_embeddings());
+            (vector_store_check, embeddings_check)
+        };
+
+        let started = Instant::now();
+        let upload_dir_check = check_result(
+            started,
+            fs::create_dir_all(upload_dir.as_str())
+                .map_err(anyhow::Error::from)
+                .and_then(|_| check_writable(Path::new(upload_dir.as_str()))),
+        );
+        Ok((vector_store_check, embeddings_check, upload_dir_check))
+    })
+    .await;
+    let (vector_store_check, embeddings_check, upload_dir_check) = disk_checks.unwrap_or_else(|e| {
+        let failed = serde_json::json!({ "status": "failed", "error": e.to_string() });
+        (failed.clone(), failed.clone(), failed)
+    });
+
+    let llm_check = match &llm_handler {
+        Some(handler) => {
+            let started = Instant::now();
+            let result = tokio::time::timeout(LLM_CHECK_TIMEOUT, handler.check())
+                .await
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("No answer within {}s", LLM_CHECK_TIMEOUT.as_secs())));
+            check_result(started, result)
+        }
+        None => serde_json::json!({
+            "status": "disabled",
+            "error": llm_status.reason.as_deref().unwrap_or("not configured")
+        }),
+    };
+
+    let checks = serde_json::json!({
+        "vector_store": vector_store_check,
+        "upload_dir": upload_dir_check,
+        "embeddings": embeddings_check,
+        "llm": llm_check
+    });
+    let failed: Vec<&String> = checks
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(_, check)| check["status"] == "failed")
+        .map(|(name, _)| name)
+        .collect();
+    let status = if !failed.is_empty() {
+        log::warn!("Deep health check failed: {:?}", failed);
+        "unhealthy"
+    } else if llm_handler.is_none() {
+        "degraded"
+    } else {
+        "healthy"
+    };
+
+    let body = serde_json::json!({
+        "status": status,
+        "service": "KnoRa AI Backend",
+        "version": "2.0.0",
+        "checks": checks
+    });
+    if failed.is_empty() {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
@@ -1,51 +1,635 @@
 use actix_web::{web, HttpResponse};
+use futures::stream::{self, StreamExt};
 use log::info;
-use crate::models::AnswerRequest;
-use crate::services::LLMHandler;
+use serde::{Deserialize, Serialize};
+use crate::error::{ApiError, ErrorCode};
+use crate::models::{
+    AnswerRequest, BatchAnswerRequest, CompareRequest, ExperimentFeedbackRequest, QueryRequest, SearchFilters,
+    SearchResult, SummarizeRequest,
+};
+use crate::handlers::blocking;
+use crate::middleware::Caller;
+use crate::services::analytics::{cited_documents, QueryAnalytics, QueryEvent};
+use crate::services::experiment::ExperimentTracker;
+use crate::services::few_shot::FewShotStore;
+use crate::services::pipeline::PipelineStore;
+use crate::services::query_transform::fuse_results;
+use crate::services::semantic_cache::SemanticCache;
+use crate::services::workspaces::Workspaces;
+use crate::services::{AnswerOptions, DocumentProcessor, LLMHandler, PromptTemplateStore, VectorStore};
 use std::sync::Mutex;
+use std::time::Instant;
+use validator::Validate;
+
+/// Batch answers generated at once unless the request asks otherwise
+const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+
+/// Whether the LLM handler started, and why not if it didn't
+#[derive(Debug, Clone, Serialize)]
+pub struct LLMStatus {
+    pub available: bool,
+    pub reason: Option<String>,
+}
+
+impl LLMStatus {
+    pub fn available() -> Self {
+        LLMStatus {
+            available: true,
+            reason: None,
+        }
+    }
+
+    pub fn unavailable(reason: String) -> Self {
+        LLMStatus {
+            available: false,
+            reason: Some(reason),
+        }
+    }
+}
+
+/// Stands in for every LLM route when the handler failed to start
+pub async fn llm_unavailable(
+    status: web::Data<LLMStatus>,
+) -> Result<HttpResponse, ApiError> {
+    Err(ApiError::new(
+        ErrorCode::LlmUnavailable,
+        format!(
+            "LLM features are unavailable: {}",
+            status.reason.as_deref().unwrap_or("not configured")
+        ),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderQuery {
+    pub provider: Option<String>,
+}
+
+/// `options` completed with the few-shot examples and the named template,
+/// or the error to reject the request with. Tools search and read the
+/// whole store, so they're turned off for callers limited to their own
+/// documents.
+fn answer_options(
+    mut options: AnswerOptions,
+    template: Option<&str>,
+    caller: Option<&Caller>,
+    llm_handler: &LLMHandler,
+    templates: &PromptTemplateStore,
+    few_shot: &FewShotStore,
+) -> Result<AnswerOptions, ApiError> {
+    if caller.and_then(Caller::document_user).is_some() {
+        options.use_tools = false;
+    }
+    llm_handler
+        .provider(options.provider.as_deref())
+        .map_err(ApiError::invalid)?;
+
+    options.validate().map_err(ApiError::invalid)?;
+    options.examples = few_shot.examples().to_vec();
+    if let Some(name) = template {
+        match templates.get(name) {
+            Some(template) => options.template = Some(template.clone()),
+            None => return Err(ApiError::not_found(format!("Prompt template not found: {}", name))),
+        }
+    }
+
+    Ok(options)
+}
 
 pub async fn generate_answer(
     req: web::Json<AnswerRequest>,
-    llm_handler: web::Data<Mutex<LLMHandler>>,
-) -> HttpResponse {
-    let handler = llm_handler.lock().unwrap();
-    let max_tokens = req.max_tokens.unwrap_or(8192);
-    let temperature = req.temperature.unwrap_or(1.0);
-
-    match handler
-        .generate_answer(
-            &req.query,
-            &req.retrieved_chunks,
-            max_tokens,
-            temperature,
-        )
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    templates: web::Data<Mutex<PromptTemplateStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    let options = answer_options(
+        AnswerOptions::from(&*req),
+        req.template.as_deref(),
+        caller.as_deref(),
+        &llm_handler,
+        &templates.lock().unwrap(),
+        &few_shot.lock().unwrap(),
+    )?;
+
+    let response = llm_handler
+        .generate_answer(&req.query, &req.retrieved_chunks, &options)
         .await
+        .map_err(|e| ApiError::llm("Error generating answer", e))?;
+    info!("Successfully generated answer for query: {}", req.query);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Retrieve context for the query from the store and answer it, so
+/// clients neither round-trip the chunks nor supply their own context
+#[allow(clippy::too_many_arguments)]
+pub async fn query(
+    req: web::Json<QueryRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    templates: web::Data<Mutex<PromptTemplateStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+    pipelines: web::Data<PipelineStore>,
+    experiments: web::Data<ExperimentTracker>,
+    workspaces: web::Data<Workspaces>,
+    analytics: Option<web::Data<QueryAnalytics>>,
+    semantic_cache: Option<web::Data<SemanticCache>>,
+) -> Result<HttpResponse, ApiError> {
+    let started = Instant::now();
+    let mut req = req.into_inner();
+    req.validate().map_err(ApiError::validation)?;
+    req.filters.visible_to = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+
+    // A workspace's documents are searched instead of the knowledge base
+    let vector_store = match &req.workspace {
+        Some(id) => {
+            let (store, _) = workspaces
+                .open(id, caller.as_deref().map(|c| c.name.as_str()))
+                .ok_or_else(|| ApiError::not_found(format!("Workspace not found or expired: {}", id)))?;
+            web::Data::from(store)
+        }
+        None => vector_store,
+    };
+
+    // Requests that don't pick a pipeline themselves join the experiment
+    let variant = match (experiments.experiment(), &req.pipeline) {
+        (Some(experiment), None) => Some(experiment.assign()),
+        _ => None,
+    };
+    if let Some(variant) = variant {
+        req.pipeline = Some(variant.pipeline.clone());
+    }
+
+    let pipeline = pipelines
+        .resolve(req.pipeline.as_deref())
+        .map_err(|e| ApiError::not_found(e.to_string()))?;
+    if let Some(pipeline) = pipeline {
+        pipeline.apply(&mut req);
+    }
+
+    let mut options = answer_options(
+        AnswerOptions::from(&req),
+        req.template.as_deref(),
+        caller.as_deref(),
+        &llm_handler,
+        &templates.lock().unwrap(),
+        &few_shot.lock().unwrap(),
+    )?;
+    if let Some(pipeline) = pipeline {
+        pipeline.configure(&mut options);
+    }
+    // Pipelines may turn tools back on, and tools don't see workspaces
+    if req.filters.visible_to.is_some() || req.workspace.is_some() {
+        options.use_tools = false;
+    }
+
+    // A query limited to named documents says so in the prompt too
     {
-        Ok(response) => {
-            info!("Successfully generated answer for query: {}", req.query);
-            HttpResponse::Ok().json(response)
+        let store = vector_store.lock().unwrap();
+        for document in &req.filters.documents {
+            match store.find_visible_document(document, req.filters.visible_to.as_deref()) {
+                Some((_, info)) if options.scope.contains(&info.file_name) => {}
+                Some((_, info)) => options.scope.push(info.file_name.clone()),
+                None => return Err(ApiError::not_found(format!("Document not found: {}", document))),
+            }
+        }
+    }
+
+    // Rephrasings of a question already answered the same way get its
+    // answer; follow-ups depend on their conversation, so aren't shared
+    let semantic = semantic_cache
+        .filter(|_| req.workspace.is_none() && req.history.is_empty() && !req.debug && variant.is_none())
+        .and_then(|cache| {
+            let embedding = vector_store.lock().unwrap().query_embedding(&req.query).ok()?;
+            Some((cache, semantic_scope(&req, &options), embedding))
+        });
+    if let Some(hit) = semantic.as_ref().and_then(|(cache, scope, embedding)| cache.get(scope, embedding)) {
+        info!("Answered query '{}' with the cached answer to '{}'", req.query, hit.query);
+        let mut response = hit.response;
+        if let Some(analytics) = &analytics {
+            let user = caller.as_deref().map(|c| c.name.as_str());
+            let results = response["sources"].as_array().map_or(0, Vec::len);
+            analytics.record(QueryEvent::new("query", &req.query, results, cited_documents(&response), user));
+        }
+        response["cached"] = serde_json::json!({
+            "query": hit.query,
+            "similarity": hit.similarity
+        });
+        return Ok(HttpResponse::Ok().json(response));
+    }
+
+    // Small talk and commands are answered without document context; other
+    // questions are searched in one step, or one per sub-question
+    let mut expansions = Vec::new();
+    let mut standalone_query = None;
+    let mut steps: Vec<(String, Vec<SearchResult>)> = Vec::new();
+    let mut searches = Vec::new();
+    if llm_handler.needs_retrieval(&req.query) {
+        // Follow-ups in a conversation are searched as standalone questions
+        let search_query = llm_handler
+            .standalone_query(&req.query, &options)
+            .await
+            .map_err(|e| ApiError::llm("Error preparing search", e))?;
+        let questions = if req.decompose {
+            llm_handler
+                .decompose_query(&search_query, &options)
+                .await
+                .map_err(|e| ApiError::llm("Error decomposing query", e))?
+        } else {
+            vec![search_query.clone()]
+        };
+        let mut search_texts = Vec::with_capacity(questions.len());
+        for question in &questions {
+            let text = llm_handler
+                .search_text(question, &options)
+                .await
+                .map_err(|e| ApiError::llm("Error preparing search", e))?;
+            search_texts.push(text);
+        }
+        if req.expand_query && questions.len() == 1 {
+            expansions = llm_handler
+                .expand_query(&search_query, &options)
+                .await
+                .map_err(|e| ApiError::llm("Error expanding query", e))?;
         }
-        Err(e) => {
-            log::error!("Error generating answer: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Error generating answer: {}", e)
-            }))
+
+        let k = req.k.unwrap_or(5);
+        let score_threshold = req.score_threshold.unwrap_or(0.0);
+        let mode = llm_handler.retrieval_mode(&options);
+        let (debug, filters) = (req.debug, req.filters.clone());
+        let vector_store = vector_store.clone();
+        let results;
+        (expansions, searches, results) = blocking(move || {
+            let store = vector_store.lock().unwrap();
+            let mut searches = Vec::new();
+            if debug {
+                for text in search_texts.iter().chain(&expansions) {
+                    match store.explain(text, k, score_threshold, &filters) {
+                        Ok(mut explained) => {
+                            explained["search_text"] = serde_json::json!(text);
+                            searches.push(explained);
+                        }
+                        Err(e) => log::warn!("Could not explain search '{}': {}", text, e),
+                    }
+                }
+            }
+            let results = search_texts
+                .iter()
+                .map(|search_text| {
+                    std::iter::once(search_text)
+                        .chain(&expansions)
+                        .map(|text| store.retrieve(text, k, score_threshold, &filters, mode))
+                        .collect::<anyhow::Result<Vec<_>>>()
+                        .map(|lists| fuse_results(lists, k))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map_err(|e| ApiError::internal("Search error", e))?;
+            Ok((expansions, searches, results))
+        })
+        .await?;
+        if search_query != req.query {
+            standalone_query = Some(search_query);
         }
+        steps = questions.into_iter().zip(results).collect();
+    }
+
+    let answer = match steps.as_slice() {
+        [] => llm_handler.generate_answer(&req.query, &[], &options).await,
+        [(_, results)] => llm_handler.generate_answer(&req.query, results, &options).await,
+        _ => llm_handler.answer_in_steps(&req.query, &steps, &options).await,
+    };
+    let mut response = answer.map_err(|e| ApiError::llm("Error generating answer", e))?;
+    let retrieved: usize = steps.iter().map(|(_, results)| results.len()).sum();
+    info!("Answered query '{}' from {} retrieved chunks in {} steps", req.query, retrieved, steps.len().max(1));
+    // Workspaces hold throwaway documents, not the knowledge base
+    if let Some(analytics) = analytics.filter(|_| req.workspace.is_none() && !steps.is_empty()) {
+        let user = caller.as_deref().map(|c| c.name.as_str());
+        analytics.record(QueryEvent::new("query", &req.query, retrieved, cited_documents(&response), user));
+    }
+    if req.expand_query {
+        response["expanded_queries"] = serde_json::json!(expansions);
+    }
+    if let Some(standalone_query) = standalone_query {
+        response["rewritten_query"] = serde_json::json!(standalone_query);
+    }
+    if pipeline.is_some() {
+        response["pipeline"] = serde_json::json!(req.pipeline.as_deref().or(pipelines.default_name()));
+    }
+    if let Some((cache, scope, embedding)) = semantic {
+        cache.put(&scope, &req.query, embedding, &response);
     }
+    if req.debug {
+        mark_context_candidates(&mut searches, &response["sources"]);
+        response["debug"] = serde_json::json!({
+            "retrieval_mode": llm_handler.retrieval_mode(&options),
+            "searches": searches
+        });
+    }
+    if let (Some(experiment), Some(variant)) = (experiments.experiment(), variant) {
+        let request_id = uuid::Uuid::new_v4().to_string();
+        experiments.record_request(&request_id, &variant.name, started.elapsed());
+        response["experiment"] = serde_json::json!({
+            "name": experiment.name,
+            "variant": variant.name,
+            "request_id": request_id
+        });
+    }
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// What a query's answer depends on besides its text: the resolved
+/// options, and the filters, including whose documents were searched
+fn semantic_scope(req: &QueryRequest, options: &AnswerOptions) -> String {
+    use sha2::{Digest, Sha256};
+
+    let scope = format!(
+        "{:?}|{:?}|{:?}|{:?}|{:?}|{}|{}|{}",
+        options,
+        req.filters,
+        req.k,
+        req.score_threshold,
+        req.retrieval,
+        req.expand_query,
+        req.decompose,
+        req.follow_ups
+    );
+    format!("{:x}", Sha256::digest(scope.as_bytes()))
+}
+
+/// Mark each explained candidate with whether it reached the answer's
+/// context, and its rerank score if it was reranked. Returned candidates
+/// missing from the context were dropped as duplicates, past the context
+/// limit, or by the token budget.
+fn mark_context_candidates(searches: &mut [serde_json::Value], sources: &serde_json::Value) {
+    let sources = sources.as_array().map(Vec::as_slice).unwrap_or_default();
+    for candidate in searches
+        .iter_mut()
+        .filter_map(|search| search["candidates"].as_array_mut())
+        .flatten()
+    {
+        let source = sources
+            .iter()
+            .find(|s| s["file_path"] == candidate["file_path"] && s["chunk_id"] == candidate["chunk_id"]);
+        candidate["in_context"] = serde_json::json!(source.is_some());
+        if let Some(score) = source.map(|s| &s["rerank_score"]).filter(|s| !s.is_null()) {
+            candidate["rerank_score"] = score.clone();
+        }
+    }
+}
+
+/// Compare several documents on a question: each is searched on its own
+/// and the answer's sources are grouped by document
+pub async fn compare(
+    req: web::Json<CompareRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    let defaults = AnswerOptions::default();
+    let options = AnswerOptions {
+        max_tokens: req.max_tokens.unwrap_or(defaults.max_tokens),
+        temperature: req.temperature.unwrap_or(defaults.temperature),
+        sampling: req.sampling.clone(),
+        provider: req.provider.clone(),
+        model: req.model.clone(),
+        system_prompt: req.system_prompt.clone(),
+        language: req.language.clone(),
+        ..defaults
+    };
+    llm_handler
+        .provider(options.provider.as_deref())
+        .and_then(|_| options.validate())
+        .map_err(ApiError::invalid)?;
+
+    let search_text = llm_handler
+        .search_text(&req.query, &options)
+        .await
+        .map_err(|e| ApiError::llm("Error preparing search", e))?;
+    let k = req.k.unwrap_or(4);
+    let score_threshold = req.score_threshold.unwrap_or(0.0);
+    let user = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+    let requested = req.documents.clone();
+    let documents = blocking(move || {
+        let store = vector_store.lock().unwrap();
+        let mut documents = Vec::with_capacity(requested.len());
+        for document in &requested {
+            let (file_path, info) = store
+                .find_visible_document(document, user.as_deref())
+                .ok_or_else(|| ApiError::not_found(format!("Document not found: {}", document)))?;
+            let filters = SearchFilters {
+                documents: vec![file_path.clone()],
+                ..Default::default()
+            };
+            let results = store
+                .search_filtered(&search_text, k, score_threshold, &filters)
+                .map_err(|e| ApiError::internal("Search error", e))?;
+            documents.push((info.file_name.clone(), results));
+        }
+        Ok(documents)
+    })
+    .await?;
+
+    let response = llm_handler
+        .compare_documents(&req.query, &documents, &options)
+        .await
+        .map_err(|e| ApiError::llm("Error comparing documents", e))?;
+    info!("Compared {} documents on '{}'", documents.len(), req.query);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Rate an answer given during an experiment, crediting its variant
+pub async fn experiment_feedback(
+    req: web::Json<ExperimentFeedbackRequest>,
+    experiments: web::Data<ExperimentTracker>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    let variant = experiments
+        .record_feedback(&req.request_id, req.helpful)
+        .map_err(|e| ApiError::not_found(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "request_id": req.request_id,
+        "variant": variant,
+        "helpful": req.helpful
+    })))
+}
+
+/// Per-variant requests, latency, and feedback of the running experiment
+pub async fn get_experiment(
+    experiments: web::Data<ExperimentTracker>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(experiments.report())
+}
+
+/// Pipelines that `/api/query` requests can select by name
+pub async fn list_pipelines(
+    pipelines: web::Data<PipelineStore>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "default": pipelines.default_name(),
+        "pipelines": pipelines.list()
+    }))
+}
+
+/// Answer several queries, at most `concurrency` at a time. Results come
+/// back in request order; a failed item holds an `error` instead of failing
+/// the whole batch.
+pub async fn generate_answers(
+    req: web::Json<BatchAnswerRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    templates: web::Data<Mutex<PromptTemplateStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let req = req.into_inner();
+    req.validate().map_err(ApiError::validation)?;
+    let concurrency = req.concurrency.unwrap_or(DEFAULT_BATCH_CONCURRENCY);
+
+    let prepared: Vec<_> = {
+        let templates = templates.lock().unwrap();
+        let few_shot = few_shot.lock().unwrap();
+        req.requests
+            .iter()
+            .map(|item| {
+                answer_options(
+                    AnswerOptions::from(item),
+                    item.template.as_deref(),
+                    caller.as_deref(),
+                    &llm_handler,
+                    &templates,
+                    &few_shot,
+                )
+            })
+            .collect()
+    };
+
+    let results: Vec<serde_json::Value> = stream::iter(req.requests.iter().zip(prepared))
+        .map(|(item, options)| {
+            let llm_handler = &llm_handler;
+            async move {
+                let options = match options {
+                    Ok(options) => options,
+                    Err(error) => return error.body(),
+                };
+                match llm_handler
+                    .generate_answer(&item.query, &item.retrieved_chunks, &options)
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(e) => ApiError::llm("Error generating answer", e).body(),
+                }
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|r| r.get("error").is_some()).count();
+    info!("Answered batch of {} queries ({} failed)", results.len(), failed);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "results": results,
+        "count": results.len(),
+        "failed": failed
+    })))
+}
+
+pub async fn summarize(
+    req: web::Json<SummarizeRequest>,
+    caller: Option<web::ReqData<Caller>>,
+    llm_handler: web::Data<LLMHandler>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    processor: web::Data<Mutex<DocumentProcessor>>,
+) -> Result<HttpResponse, ApiError> {
+    req.validate().map_err(ApiError::validation)?;
+    llm_handler.provider(req.provider.as_deref()).map_err(ApiError::invalid)?;
+
+    // Prefer the indexed chunks; fall back to processing a file on disk,
+    // which callers limited to their own documents may not
+    let user = caller.as_deref().and_then(Caller::document_user).map(str::to_string);
+    let file_path = req.file_path.clone();
+    let (file_name, chunks) = blocking(move || {
+        let indexed = {
+            let store = vector_store.lock().unwrap();
+            store
+                .find_visible_document(&file_path, user.as_deref())
+                .and_then(|(file_path, _)| store.document_chunks(file_path))
+        };
+        match indexed {
+            Some(document) => Ok(document),
+            None if user.is_none() && std::path::Path::new(&file_path).is_file() => {
+                let document = processor
+                    .lock()
+                    .unwrap()
+                    .process_file(&file_path)
+                    .map_err(|e| ApiError::invalid(format!("Error processing file: {}", e)))?;
+                Ok((document.file_name, document.chunks))
+            }
+            None => Err(ApiError::not_found(format!("Document not found: {}", file_path))),
+        }
+    })
+    .await?;
+
+    let options = AnswerOptions {
+        provider: req.provider.clone(),
+        model: req.model.clone(),
+        language: req.language.clone(),
+        ..Default::default()
+    };
+
+    let response = llm_handler
+        .summarize_document(&file_name, &chunks, req.style, &options)
+        .await
+        .map_err(|e| ApiError::llm("Error summarizing document", e))?;
+    info!("Summarized {} ({} chunks)", file_name, chunks.len());
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClassifyRequest {
+    pub query: String,
+}
+
+/// Lets clients skip the search call for greetings, small talk, and commands
+pub async fn classify_query(
+    req: web::Json<ClassifyRequest>,
+    llm_handler: web::Data<LLMHandler>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(llm_handler.classify(&req.query))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// Only report the last N days, including today
+    pub days: Option<u32>,
+}
+
+pub async fn get_usage(
+    query: web::Query<UsageQuery>,
+    llm_handler: web::Data<LLMHandler>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(llm_handler.usage_report(query.days))
 }
 
 pub async fn get_model_info(
-    llm_handler: web::Data<Mutex<LLMHandler>>,
+    llm_handler: web::Data<LLMHandler>,
 ) -> HttpResponse {
-    let handler = llm_handler.lock().unwrap();
-    let model_info = handler.get_model_info();
+    let model_info = llm_handler.get_model_info();
 
     info!("Retrieved LLM model information");
     HttpResponse::Ok().json(model_info)
 }
 
-pub async fn get_supported_models() -> HttpResponse {
-    let models = crate::models::get_supported_models();
+pub async fn get_supported_models(
+    query: web::Query<ProviderQuery>,
+    llm_handler: web::Data<LLMHandler>,
+) -> Result<HttpResponse, ApiError> {
+    let provider = llm_handler.provider(query.provider.as_deref()).map_err(ApiError::invalid)?;
+    let models = provider
+        .list_models()
+        .await
+        .map_err(|e| ApiError::llm("Error listing models", e))?;
     info!("Retrieved list of supported LLM models");
-    HttpResponse::Ok().json(models)
+    Ok(HttpResponse::Ok().json(models))
 }
@@ -1,5 +1,69 @@
+pub mod admin;
+pub mod analytics;
 pub mod document;
 pub mod search;
 pub mod llm;
+pub mod chat;
+pub mod prompts;
 pub mod health;
+pub mod eval;
 pub mod upload;
+pub mod workspaces;
+
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use std::future::Future;
+use crate::error::{ApiError, ErrorCode};
+use crate::middleware::Caller;
+use crate::services::idempotency::{Claim, ClaimedKey, IdempotencyStore, MAX_KEY_LEN};
+
+/// Run `work` on the blocking thread pool, so file I/O, document
+/// processing, and store writes don't hold up the async workers
+async fn blocking<T, F>(work: F) -> Result<T, ApiError>
+where
+    F: FnOnce() -> Result<T, ApiError> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(work)
+        .await
+        .map_err(|e| ApiError::internal("Background task failed", e))?
+}
+
+/// Run `handle` once per `Idempotency-Key`: retries of a request that
+/// succeeded get its response again, marked `Idempotent-Replayed: true`.
+/// Keys are scoped to the caller's API key and the route, and released if
+/// the request is dropped before it finishes.
+async fn idempotent<F>(req: &HttpRequest, store: &IdempotencyStore, handle: F) -> Result<HttpResponse, ApiError>
+where
+    F: Future<Output = Result<serde_json::Value, ApiError>>,
+{
+    let Some(key) = req.headers().get("Idempotency-Key") else {
+        return handle.await.map(|body| HttpResponse::Ok().json(body));
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+        .ok_or_else(|| ApiError::invalid(format!("Idempotency-Key must be 1 to {} characters", MAX_KEY_LEN)))?;
+    let caller = req.extensions().get::<Caller>().map(|caller| caller.name.clone());
+    let key = format!("{}:{} {}:{}", caller.unwrap_or_default(), req.method(), req.path(), key);
+
+    match store.claim(&key) {
+        Claim::Done(body) => {
+            log::info!("Replaying response for idempotency key {}", key);
+            return Ok(HttpResponse::Ok().insert_header(("Idempotent-Replayed", "true")).json(body));
+        }
+        Claim::InProgress => {
+            return Err(ApiError::new(
+                ErrorCode::RequestInProgress,
+                "A request with this Idempotency-Key is still being processed",
+            ))
+        }
+        Claim::New => {}
+    }
+
+    let claimed = ClaimedKey::new(store, key);
+    let body = handle.await?;
+    claimed.complete(body.clone());
+    Ok(HttpResponse::Ok().json(body))
+}
@@ -0,0 +1,100 @@
+use actix_web::{web, HttpResponse};
+use log::info;
+use serde::Deserialize;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::services::few_shot::{FewShotExample, FewShotStore};
+use crate::services::PromptTemplateStore;
+use std::sync::Mutex;
+
+#[derive(Debug, Deserialize)]
+pub struct SaveTemplateRequest {
+    pub template: String,
+}
+
+pub async fn list_templates(
+    store: web::Data<Mutex<PromptTemplateStore>>,
+) -> HttpResponse {
+    let store = store.lock().unwrap();
+    HttpResponse::Ok().json(store.list())
+}
+
+pub async fn get_template(
+    path: web::Path<String>,
+    store: web::Data<Mutex<PromptTemplateStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let store = store.lock().unwrap();
+
+    let template = store
+        .get(&path)
+        .ok_or_else(|| ApiError::not_found(format!("Prompt template not found: {}", path)))?;
+    Ok(HttpResponse::Ok().json(template))
+}
+
+pub async fn save_template(
+    path: web::Path<String>,
+    req: web::Json<SaveTemplateRequest>,
+    store: web::Data<Mutex<PromptTemplateStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.into_inner();
+    let template = blocking(move || {
+        match store.lock().unwrap().save(&name, req.into_inner().template) {
+            Ok(template) => Ok(template.clone()),
+            Err(e) => {
+                log::error!("Error saving prompt template: {}", e);
+                Err(ApiError::invalid(format!("Error saving prompt template: {}", e)))
+            }
+        }
+    })
+    .await?;
+    info!("Saved prompt template: {}", template.name);
+    Ok(HttpResponse::Ok().json(template))
+}
+
+pub async fn delete_template(
+    path: web::Path<String>,
+    store: web::Data<Mutex<PromptTemplateStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let name = path.clone();
+    let deleted = blocking(move || {
+        store
+            .lock()
+            .unwrap()
+            .delete(&name)
+            .map_err(|e| ApiError::internal("Error deleting prompt template", e))
+    })
+    .await?;
+    if !deleted {
+        return Err(ApiError::not_found(format!("Prompt template not found: {}", path)));
+    }
+    info!("Deleted prompt template: {}", path);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": format!("Deleted prompt template: {}", path)
+    })))
+}
+
+pub async fn list_examples(
+    store: web::Data<Mutex<FewShotStore>>,
+) -> HttpResponse {
+    let store = store.lock().unwrap();
+    HttpResponse::Ok().json(store.examples())
+}
+
+pub async fn replace_examples(
+    req: web::Json<Vec<FewShotExample>>,
+    store: web::Data<Mutex<FewShotStore>>,
+) -> Result<HttpResponse, ApiError> {
+    let examples = blocking(move || {
+        match store.lock().unwrap().replace(req.into_inner()) {
+            Ok(examples) => Ok(examples.to_vec()),
+            Err(e) => {
+                log::error!("Error saving few-shot examples: {}", e);
+                Err(ApiError::invalid(format!("Error saving few-shot examples: {}", e)))
+            }
+        }
+    })
+    .await?;
+    info!("Saved {} few-shot examples", examples.len());
+    Ok(HttpResponse::Ok().json(examples))
+}
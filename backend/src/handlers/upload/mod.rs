@@ -1,13 +1,21 @@
-use actix_multipart::Multipart;
-use actix_web::{web, HttpResponse};
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use futures::StreamExt;
 use serde::Serialize;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 use log::{info, error};
-use crate::models::ProcessFileResponse;
+use crate::error::{ApiError, ErrorCode};
+use crate::handlers::{blocking, idempotent};
+use crate::middleware::{AuditDetails, AuditTarget, Caller};
+use crate::models::{document_id, ChunkingParams, DocumentLabels, ProcessFileResponse};
+use crate::services::idempotency::IdempotencyStore;
+use crate::services::webhooks::{indexed_document, WebhookEvent, Webhooks};
 use crate::services::{DocumentProcessor, VectorStore};
 use std::fs;
+use tokio::io::AsyncWriteExt;
+use validator::{Validate, ValidationError, ValidationErrors};
 
 #[derive(Serialize)]
 pub struct SupportedFormat {
@@ -21,219 +29,406 @@ pub struct SupportedFormatsResponse {
     pub formats: Vec<SupportedFormat>,
 }
 
-const MAX_FILE_SIZE: usize = 100 * 1024 * 1024;
-const SUPPORTED_EXTENSIONS: &[&str] = &[
-    ".pdf", ".txt", ".doc", ".docx", ".csv",
-    ".xlsx", ".xls", ".md", ".pptx", ".json"
+/// Accepted file extensions and what they're called
+const FORMATS: &[(&str, &str)] = &[
+    (".txt", "Plain Text"),
+    (".pdf", "PDF Document"),
+    (".docx", "Word Document (2007+)"),
+    (".doc", "Word Document (97-2003)"),
+    (".csv", "CSV Spreadsheet"),
+    (".xlsx", "Excel Spreadsheet (2007+)"),
+    (".xls", "Excel Spreadsheet (97-2003)"),
+    (".md", "Markdown Document"),
+    (".pptx", "PowerPoint Presentation"),
+    (".json", "JSON Data File"),
 ];
 
+/// Form fields describing the file, besides `file` itself; others are
+/// ignored
+const FORM_FIELDS: &[&str] = &["collection", "tags", "language", "retain_days", "chunking_strategy"];
+
+/// Largest value accepted for one of `FORM_FIELDS`
+const MAX_FORM_FIELD_SIZE: usize = 1024;
+
+/// Largest upload accepted, overall and for particular file extensions
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    default_bytes: usize,
+    per_format: HashMap<String, usize>,
+}
+
+impl UploadLimits {
+    /// `per_format` is `extension=megabytes` pairs separated by commas,
+    /// e.g. `pdf=50,csv=10`. Malformed entries are skipped.
+    pub fn new(default_mb: u64, per_format: &str) -> Self {
+        let mut limits = HashMap::new();
+        for entry in per_format.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(extension, mb)| {
+                let extension = format!(".{}", extension.trim().trim_start_matches('.').to_lowercase());
+                Some((extension, mb.trim().parse::<u64>().ok()?))
+            });
+            match parsed {
+                Some((extension, mb)) => {
+                    limits.insert(extension, megabytes(mb));
+                }
+                None => log::warn!("Ignoring malformed upload size limit '{}', expected extension=megabytes", entry),
+            }
+        }
+
+        UploadLimits {
+            default_bytes: megabytes(default_mb),
+            per_format: limits,
+        }
+    }
+
+    /// Limit in bytes for files with `extension`, like `.pdf`
+    pub fn for_extension(&self, extension: &str) -> usize {
+        self.per_format.get(extension).copied().unwrap_or(self.default_bytes)
+    }
+}
+
+fn megabytes(mb: u64) -> usize {
+    (mb as usize).saturating_mul(1024 * 1024)
+}
+
+/// Lowercase extension of `filename` with its dot, or an empty string
+fn extension(filename: &str) -> String {
+    Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|s| format!(".{}", s.to_lowercase()))
+        .unwrap_or_default()
+}
+
+/// Whether files named like `filename` can be processed
+pub fn is_supported_format(filename: &str) -> bool {
+    let extension = extension(filename);
+    FORMATS.iter().any(|(supported, _)| *supported == extension)
+}
+
+/// An upload being written straight to `upload_dir` as its chunks arrive,
+/// so large files are never held in memory. Removed when dropped unless
+/// it was kept under its final name.
+struct PartialUpload {
+    path: PathBuf,
+    file: tokio::fs::File,
+    size: usize,
+}
+
+impl PartialUpload {
+    async fn create(upload_dir: &str) -> std::io::Result<Self> {
+        tokio::fs::create_dir_all(upload_dir).await?;
+        let path = PathBuf::from(upload_dir).join(format!(".upload_{}.part", uuid::Uuid::new_v4()));
+        let file = tokio::fs::File::create(&path).await?;
+        Ok(PartialUpload { path, file, size: 0 })
+    }
+
+    async fn write(&mut self, chunk: &web::Bytes) -> std::io::Result<()> {
+        self.file.write_all(chunk).await?;
+        self.size += chunk.len();
+        Ok(())
+    }
+
+    /// Move the finished upload to `path`, replacing any file there
+    fn keep(mut self, path: &Path) -> std::io::Result<()> {
+        fs::rename(&self.path, path)?;
+        self.path = PathBuf::new();
+        Ok(())
+    }
+}
+
+impl Drop for PartialUpload {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Upload, process, and index a file. Retries sent with the same
+/// `Idempotency-Key` get the first successful response back.
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_file(
+    req: HttpRequest,
     mut payload: Multipart,
+    params: web::Query<ChunkingParams>,
+    caller: Option<web::ReqData<Caller>>,
     upload_dir: web::Data<String>,
+    limits: web::Data<RwLock<UploadLimits>>,
     processor: web::Data<Mutex<DocumentProcessor>>,
     vector_store: web::Data<Mutex<VectorStore>>,
-) -> HttpResponse {
-    match process_upload(&mut payload, upload_dir, processor, vector_store).await {
-        Ok(response) => HttpResponse::Ok().json(response),
-        Err(err_msg) => {
-            error!("Upload error: {}", err_msg);
-            HttpResponse::BadRequest().json(ProcessFileResponse {
-                success: false,
-                message: format!("Upload failed: {}", err_msg),
-                document: None,
-            })
+    webhooks: web::Data<Webhooks>,
+    idempotency: web::Data<IdempotencyStore>,
+) -> Result<HttpResponse, ApiError> {
+    let caller = caller.map(web::ReqData::into_inner);
+    let owner = caller.as_ref().map(|c| c.name.as_str());
+    let limits = limits.read().unwrap().clone();
+    idempotent(&req, &idempotency, async {
+        let response = process_upload(&mut payload, &params, caller.as_ref(), &upload_dir, &limits, &processor, &vector_store)
+            .await
+            .map_err(|mut error| {
+                error!("Upload error: {}", error);
+                webhooks.notify(
+                    WebhookEvent::IngestionFailed,
+                    serde_json::json!({ "error": error.message, "uploaded_by": owner }),
+                );
+                error.message = format!("Upload failed: {}", error.message);
+                error
+            })?;
+        if let Some(document) = &response.document {
+            req.extensions_mut().insert(AuditTarget(document_id(&document.file_path)));
+            req.extensions_mut().insert(AuditDetails(serde_json::json!({ "file_name": document.file_name })));
+            webhooks.notify(WebhookEvent::DocumentIndexed, indexed_document(document, owner));
         }
-    }
+        serde_json::to_value(response).map_err(|e| ApiError::internal("Error encoding upload response", e))
+    })
+    .await
 }
 
-async fn process_upload(
+/// Save the uploaded file to `upload_dir`, then process it and index it in
+/// `vector_store` as the caller's
+pub(super) async fn process_upload(
     payload: &mut Multipart,
-    upload_dir: web::Data<String>,
-    processor: web::Data<Mutex<DocumentProcessor>>,
-    vector_store: web::Data<Mutex<VectorStore>>,
-) -> Result<ProcessFileResponse, String> {
-    let mut file_bytes = Vec::new();
+    params: &ChunkingParams,
+    caller: Option<&Caller>,
+    upload_dir: &str,
+    limits: &UploadLimits,
+    processor: &web::Data<Mutex<DocumentProcessor>>,
+    vector_store: &web::Data<Mutex<VectorStore>>,
+) -> Result<ProcessFileResponse, ApiError> {
+    let mut params = params.clone();
+    let mut labels = DocumentLabels::default();
+    let mut upload: Option<PartialUpload> = None;
     let mut file_name = String::new();
 
     while let Some(field_result) = payload.next().await {
         let mut field = field_result
-            .map_err(|e| format!("Failed to read form field: {}", e))?;
+            .map_err(|e| ApiError::invalid(format!("Failed to read form field: {}", e)))?;
 
-        if field.name() == "file" {
+        let name = field.name().to_string();
+        if name != "file" {
+            if !FORM_FIELDS.contains(&name.as_str()) {
+                continue;
+            }
+            let value = read_text_field(&mut field, &name).await?;
+            let value = value.trim();
+            match name.as_str() {
+                "collection" => labels.collection = Some(value.to_string()).filter(|v| !v.is_empty()),
+                "tags" => labels.add_tags(value),
+                "language" => labels.language = Some(value.to_string()).filter(|v| !v.is_empty()),
+                "retain_days" if !value.is_empty() => {
+                    let days = value
+                        .parse()
+                        .map_err(|_| invalid_field("retain_days", "retain_days must be a number of days".to_string()))?;
+                    labels.retain_days = Some(days);
+                }
+                "retain_days" => {}
+                _ => {
+                    let strategy = value.parse().map_err(|e| invalid_field("chunking_strategy", e))?;
+                    params.chunking_strategy = Some(strategy);
+                }
+            }
+        } else {
+            if !file_name.is_empty() {
+                return Err(ApiError::invalid("Upload one file per request"));
+            }
             // Extract filename from content disposition header
             let disposition = field.content_disposition();
             if let Some(filename) = disposition.get_filename() {
                 file_name = filename.to_string();
             } else {
-                return Err("No filename provided".to_string());
+                return Err(ApiError::invalid("No filename provided"));
             }
 
-            validate_filename(&file_name)?;
-
-            // Read file content
+            if let Err(error) = validate_filename(&file_name) {
+                let mut errors = ValidationErrors::new();
+                errors.add("file", error);
+                return Err(ApiError::validation(errors));
+            }
+            let max_size = limits.for_extension(&extension(&file_name));
+
+            // Write each chunk to disk as it arrives
+            let part = upload.insert(
+                PartialUpload::create(upload_dir)
+                    .await
+                    .map_err(|e| ApiError::internal("Failed to create upload file", e))?,
+            );
             while let Some(chunk_result) = field.next().await {
                 let chunk = chunk_result
-                    .map_err(|e| format!("Failed to read file chunk: {}", e))?;
-
-                if file_bytes.len() + chunk.len() > MAX_FILE_SIZE {
-                    return Err(format!(
-                        "File size exceeds maximum of {} MB",
-                        MAX_FILE_SIZE / (1024 * 1024)
-                    ));
+                    .map_err(|e| ApiError::invalid(format!("Failed to read file chunk: {}", e)))?;
+
+                if part.size + chunk.len() > max_size {
+                    return Err(ApiError::new(
+                        ErrorCode::PayloadTooLarge,
+                        format!("File size exceeds maximum of {} MB", max_size / (1024 * 1024)),
+                    )
+                    .with_details(serde_json::json!({ "max_size_bytes": max_size })));
                 }
 
-                file_bytes.extend_from_slice(&chunk);
+                part.write(&chunk)
+                    .await
+                    .map_err(|e| ApiError::internal("Failed to write file", e))?;
             }
-            break;
+            part.file
+                .flush()
+                .await
+                .map_err(|e| ApiError::internal("Failed to write file", e))?;
         }
     }
+    params.validate().map_err(ApiError::validation)?;
+    labels.validate().map_err(ApiError::validation)?;
 
-    if file_name.is_empty() {
-        return Err("No file provided in request".to_string());
-    }
+    let Some(upload) = upload else {
+        return Err(ApiError::invalid("No file provided in request"));
+    };
 
-    if file_bytes.is_empty() {
-        return Err("File is empty".to_string());
+    if upload.size == 0 {
+        return Err(ApiError::invalid("File is empty"));
     }
 
-    // Create upload directory if it doesn't exist
-    fs::create_dir_all(upload_dir.as_str())
-        .map_err(|e| format!("Failed to create upload directory: {}", e))?;
-
-    // Create a unique filename in the upload directory
-    let upload_filename = format!("upload_{}", file_name);
-    let file_path = PathBuf::from(upload_dir.as_str()).join(&upload_filename);
-    let file_path_str = file_path.to_string_lossy().to_string();
+    // Saving, processing, and indexing read and write files and embed every
+    // chunk, so happen off the async workers
+    let upload_dir = upload_dir.to_string();
+    let caller = caller.cloned();
+    let processor = processor.clone();
+    let vector_store = vector_store.clone();
+    blocking(move || {
+        // Create a unique filename in the upload directory
+        let upload_filename = format!("upload_{}", file_name);
+        let file_path = PathBuf::from(&upload_dir).join(&upload_filename);
+        let file_path_str = file_path.to_string_lossy().to_string();
+
+        // The upload becomes the caller's; it can't overwrite another user's
+        let user = caller.as_ref().and_then(Caller::document_user);
+        if vector_store
+            .lock()
+            .unwrap()
+            .document_info(&file_path_str)
+            .is_some_and(|info| !info.modifiable_by(user))
+        {
+            return Err(ApiError::forbidden(format!(
+                "A document named {} belongs to another user",
+                file_name
+            )));
+        }
 
-    // Write file content to upload directory
-    fs::write(&file_path, &file_bytes)
-        .map_err(|e| format!("Failed to write file: {}", e))?;
+        // Give the saved upload its name
+        upload
+            .keep(&file_path)
+            .map_err(|e| ApiError::internal("Failed to write file", e))?;
+
+        info!("Uploaded file to: {}", file_path_str);
+        info!("Processing uploaded file: '{}'", file_name);
+
+        // Process the file using the original filename for extension detection
+        let processing_result = {
+            let processor_guard = processor.lock().unwrap();
+            processor_guard.process_file_with_params(&file_path_str, Some(&file_name), &params)
+        };
+
+        let mut document = processing_result.map_err(|e| {
+            let _ = fs::remove_file(&file_path);
+            ApiError::invalid(format!("Error processing file: {}", e))
+        })?;
+
+        // Restore original filename in document
+        document.file_name = file_name.clone();
+        document.labels = labels;
+
+        // Add processed document to the vector store
+        {
+            let mut store_guard = vector_store.lock().unwrap();
+            store_guard
+                .add_owned_documents(vec![document.clone()], caller.as_ref().map(|c| c.name.as_str()))
+                .map_err(|e| {
+                    let _ = fs::remove_file(&file_path);
+                    ApiError::internal("Error adding document to vector store", e)
+                })?;
+        }
 
-    info!("Uploaded file to: {}", file_path_str);
-    info!("Processing uploaded file: '{}'", file_name);
+        info!("Successfully processed and indexed uploaded file: {}", file_name);
 
-    // Process the file using the original filename for extension detection
-    let processing_result = {
-        let processor_guard = processor.lock().unwrap();
-        processor_guard.process_file_with_name(&file_path_str, Some(&file_name))
-    };
+        Ok(ProcessFileResponse {
+            success: true,
+            message: format!("File uploaded and processed successfully: {}", file_name),
+            document: Some(document),
+        })
+    })
+    .await
+}
 
-    let mut document = processing_result.map_err(|e| {
-        let _ = fs::remove_file(&file_path);
-        format!("Error processing file: {}", e)
-    })?;
-
-    // Restore original filename in document
-    document.file_name = file_name.clone();
-
-    // Add processed document to the vector store
-    {
-        let mut store_guard = vector_store.lock().unwrap();
-        store_guard
-            .add_documents(vec![document.clone()])
-            .map_err(|e| {
-                let _ = fs::remove_file(&file_path);
-                format!("Error adding document to vector store: {}", e)
-            })?;
+/// Read a text form field of at most `MAX_FORM_FIELD_SIZE` bytes
+async fn read_text_field(field: &mut Field, name: &str) -> Result<String, ApiError> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|e| ApiError::invalid(format!("Failed to read form field {}: {}", name, e)))?;
+        if bytes.len() + chunk.len() > MAX_FORM_FIELD_SIZE {
+            return Err(invalid_field(name, format!("{} is too long", name)));
+        }
+        bytes.extend_from_slice(&chunk);
     }
+    String::from_utf8(bytes).map_err(|_| invalid_field(name, format!("{} must be UTF-8 text", name)))
+}
 
-    info!("Successfully processed and indexed uploaded file: {}", file_name);
-
-    Ok(ProcessFileResponse {
-        success: true,
-        message: format!("File uploaded and processed successfully: {}", file_name),
-        document: Some(document),
-    })
+/// A 422 for one form field
+fn invalid_field(name: &str, message: String) -> ApiError {
+    let mut errors = ValidationErrors::new();
+    // Field names are static in validator's errors
+    let name = FORM_FIELDS.iter().find(|f| **f == name).copied().unwrap_or("form");
+    errors.add(name, ValidationError::new("invalid").with_message(message.into()));
+    ApiError::validation(errors)
 }
 
-fn validate_filename(filename: &str) -> Result<(), String> {
-    if filename.is_empty() {
-        return Err("Filename cannot be empty".to_string());
+fn validate_filename(filename: &str) -> Result<(), ValidationError> {
+    let invalid = |code, message: String| Err(ValidationError::new(code).with_message(message.into()));
+
+    if filename.trim().is_empty() {
+        return invalid("blank", "Filename cannot be empty".to_string());
     }
 
     if filename.len() > 255 {
-        return Err("Filename is too long (max 255 characters)".to_string());
+        return invalid("length", "Filename is too long (max 255 characters)".to_string());
     }
 
     if filename.contains('/') || filename.contains('\\') {
-        return Err("Invalid filename: path separators not allowed".to_string());
+        return invalid("charset", "Invalid filename: path separators not allowed".to_string());
     }
 
-    if filename.contains('\0') {
-        return Err("Invalid filename: contains null bytes".to_string());
+    // Control characters, null bytes included, would end up in file names
+    // on disk and in logs
+    if filename.chars().any(char::is_control) {
+        return invalid("charset", "Invalid filename: contains control characters".to_string());
     }
 
-    let extension = Path::new(filename)
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|s| format!(".{}", s.to_lowercase()))
-        .unwrap_or_else(|| "".to_string());
+    let extension = extension(filename);
 
     // Only validate extension if one exists
-    if !extension.is_empty() && !SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
-        return Err(format!(
-            "Unsupported file format: {}. Supported: {}",
-            extension,
-            SUPPORTED_EXTENSIONS.join(", ")
-        ));
+    if !extension.is_empty() && !is_supported_format(filename) {
+        return invalid(
+            "format",
+            format!(
+                "Unsupported file format: {}. Supported: {}",
+                extension,
+                FORMATS.iter().map(|(supported, _)| *supported).collect::<Vec<_>>().join(", ")
+            ),
+        );
     }
 
     Ok(())
 }
 
-pub async fn get_supported_formats() -> HttpResponse {
-    let formats = vec![
-        SupportedFormat {
-            extension: ".txt".to_string(),
-            name: "Plain Text".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".pdf".to_string(),
-            name: "PDF Document".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".docx".to_string(),
-            name: "Word Document (2007+)".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".doc".to_string(),
-            name: "Word Document (97-2003)".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".csv".to_string(),
-            name: "CSV Spreadsheet".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".xlsx".to_string(),
-            name: "Excel Spreadsheet (2007+)".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".xls".to_string(),
-            name: "Excel Spreadsheet (97-2003)".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".md".to_string(),
-            name: "Markdown Document".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".pptx".to_string(),
-            name: "PowerPoint Presentation".to_string(),
-            max_size_mb: 100,
-        },
-        SupportedFormat {
-            extension: ".json".to_string(),
-            name: "JSON Data File".to_string(),
-            max_size_mb: 100,
-        },
-    ];
+pub async fn get_supported_formats(
+    limits: web::Data<RwLock<UploadLimits>>,
+) -> HttpResponse {
+    let limits = limits.read().unwrap();
+    let formats = FORMATS
+        .iter()
+        .map(|(extension, name)| SupportedFormat {
+            extension: extension.to_string(),
+            name: name.to_string(),
+            max_size_mb: (limits.for_extension(extension) / (1024 * 1024)) as u64,
+        })
+        .collect();
 
     HttpResponse::Ok().json(SupportedFormatsResponse { formats })
 }
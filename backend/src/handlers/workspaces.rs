@@ -0,0 +1,87 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use log::info;
+use crate::error::ApiError;
+use crate::handlers::blocking;
+use crate::handlers::upload::{process_upload, UploadLimits};
+use crate::middleware::Caller;
+use crate::models::ChunkingParams;
+use crate::services::workspaces::Workspaces;
+use crate::services::DocumentProcessor;
+use std::sync::{Mutex, RwLock};
+
+fn owner(caller: &Option<web::ReqData<Caller>>) -> Option<&str> {
+    caller.as_deref().map(|c| c.name.as_str())
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::not_found(format!("Workspace not found or expired: {}", id))
+}
+
+/// Open a temporary workspace for the caller
+pub async fn create_workspace(
+    caller: Option<web::ReqData<Caller>>,
+    workspaces: web::Data<Workspaces>,
+) -> Result<HttpResponse, ApiError> {
+    let owner = owner(&caller).map(str::to_string);
+    let workspace = blocking(move || {
+        workspaces
+            .create(owner.as_deref())
+            .map_err(|e| ApiError::invalid(format!("Could not create workspace: {}", e)))
+    })
+    .await?;
+    info!("Created workspace {}", workspace.id);
+    Ok(HttpResponse::Created().json(workspace))
+}
+
+pub async fn get_workspace(
+    path: web::Path<String>,
+    caller: Option<web::ReqData<Caller>>,
+    workspaces: web::Data<Workspaces>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let workspace = workspaces.info(&id, owner(&caller)).ok_or_else(|| not_found(&id))?;
+    Ok(HttpResponse::Ok().json(workspace))
+}
+
+/// Delete a workspace and its documents before it expires
+pub async fn delete_workspace(
+    path: web::Path<String>,
+    caller: Option<web::ReqData<Caller>>,
+    workspaces: web::Data<Workspaces>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let (owner, workspace) = (owner(&caller).map(str::to_string), id.clone());
+    if !blocking(move || Ok(workspaces.remove(&workspace, owner.as_deref()))).await? {
+        return Err(not_found(&id));
+    }
+    info!("Deleted workspace {}", id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "success": true })))
+}
+
+/// Upload a file into a workspace, where only queries naming the workspace
+/// search it
+pub async fn upload_to_workspace(
+    path: web::Path<String>,
+    mut payload: Multipart,
+    params: web::Query<ChunkingParams>,
+    caller: Option<web::ReqData<Caller>>,
+    limits: web::Data<RwLock<UploadLimits>>,
+    processor: web::Data<Mutex<DocumentProcessor>>,
+    workspaces: web::Data<Workspaces>,
+) -> Result<HttpResponse, ApiError> {
+    let id = path.into_inner();
+    let (store, upload_dir) = workspaces.open(&id, owner(&caller)).ok_or_else(|| not_found(&id))?;
+    let limits = limits.read().unwrap().clone();
+    let response = process_upload(
+        &mut payload,
+        &params,
+        caller.as_deref(),
+        &upload_dir.to_string_lossy(),
+        &limits,
+        &processor,
+        &web::Data::from(store),
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(response))
+}
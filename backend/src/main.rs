@@ -1,33 +1,590 @@
-use actix_web::{web, App, HttpServer, middleware};
+use actix_web::{web, App, HttpServer, Scope};
+use actix_web::middleware::{from_fn, Logger};
 use actix_cors::Cors;
+use actix_files::{Files, NamedFile};
+use actix_web::dev::{fn_service, ServiceRequest, ServiceResponse};
+use clap::Parser;
 use log::info;
-use std::sync::Mutex;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
+mod cli;
 mod config;
+mod error;
 mod handlers;
-mod models;
+mod middleware;
+mod reload;
 mod services;
+mod telemetry;
+mod warmup;
 
+use knora_core::models;
+use cli::{Cli, Command};
 use config::AppConfig;
-use services::{DocumentProcessor, VectorStore, LLMHandler};
+use services::{
+    gemini_safety_settings, AzureAuth, AzureOpenAILLM, DocumentProcessor, GeminiLLM, GroqLLM, VectorStore,
+    HttpTimeouts, LLMHandler, LLMProvider, OllamaLLM, OpenAICompatibleLLM, PromptTemplateStore, RetryPolicy,
+};
+use services::cache_manager::{Cache, CacheBackend, EmbeddingCache, LruCache};
+use services::disk_cache::DiskCache;
+use services::llm_handler::CachedAnswer;
+use services::redis_cache::RedisCache;
+use services::semantic_cache::SemanticCache;
+use services::tools::{Calculator, FetchDocument, SearchAgain, ToolRegistry};
+use services::analytics::QueryAnalytics;
+use services::audit::{AuditEvent, AuditLog};
+use services::evaluation::EvalRunStore;
+use services::experiment::{Experiment, ExperimentTracker};
+use services::few_shot::FewShotStore;
+use services::idempotency::IdempotencyStore;
+use services::pipeline::PipelineStore;
+use services::moderation::Moderator;
+use services::usage::parse_prices;
+use services::webhooks::Webhooks;
+use services::maintenance::{
+    cleanup_uploads, purge_expired_documents, snapshot_store, CleanupDefaults, CleanupQuery, RetentionPolicy,
+};
+use services::scheduler::Scheduler;
+use services::workspaces::Workspaces;
+use middleware::{
+    assign_request_id, audit_requests, authorize, limit_rejected_requests, limit_requests, trace_request,
+    AccessControl, RequestLimits,
+};
 use handlers::*;
+use handlers::llm::LLMStatus;
+use handlers::upload::UploadLimits;
+use reload::ConfigReloader;
+use warmup::WarmUp;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+/// actix's default access log line, led by the request id
+const REQUEST_LOG_FORMAT: &str = r#"%{x-request-id}o %a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#;
+
+/// env_logger's usual format, with the id of the request a line was logged
+/// for after the target
+fn init_logging() {
+    env_logger::Builder::from_env(env_logger::Env::new().default_filter_or("info"))
+        .format(|buf, record| {
+            let style = buf.default_level_style(record.level());
+            let request_id = middleware::current_request_id()
+                .map(|id| format!(" {}", id))
+                .unwrap_or_default();
+            writeln!(
+                buf,
+                "[{} {style}{:<5}{style:#} {}{}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                request_id,
+                record.args()
+            )
+        })
+        .init();
+}
+
+fn azure_llm(azure: &config::AzureOpenAIConfig) -> anyhow::Result<AzureOpenAILLM> {
+    let auth = if let Some(key) = &azure.api_key {
+        AzureAuth::ApiKey(key.clone())
+    } else if let Some(token) = &azure.ad_token {
+        AzureAuth::AadToken(token.clone())
+    } else if let (Some(tenant_id), Some(client_id), Some(client_secret)) =
+        (&azure.tenant_id, &azure.client_id, &azure.client_secret)
+    {
+        AzureAuth::ClientCredentials {
+            tenant_id: tenant_id.clone(),
+            client_id: client_id.clone(),
+            client_secret: client_secret.clone(),
+        }
+    } else {
+        anyhow::bail!(
+            "Azure OpenAI requires AZURE_OPENAI_API_KEY, AZURE_OPENAI_AD_TOKEN, or AZURE_TENANT_ID/AZURE_CLIENT_ID/AZURE_CLIENT_SECRET"
+        );
+    };
+
+    AzureOpenAILLM::new(
+        azure.endpoint.clone(),
+        azure.deployment.clone(),
+        azure.api_version.clone(),
+        auth,
+    )
+}
+
+/// Build the named provider from config
+fn build_llm_provider(
+    config: &AppConfig,
+    provider: &str,
+    client: &reqwest::Client,
+) -> anyhow::Result<Arc<dyn LLMProvider>> {
+    let model = config.model_for(provider);
+    let timeouts = HttpTimeouts {
+        connect: Duration::from_secs(config.llm_connect_timeout_secs),
+        read: Duration::from_secs(config.llm_read_timeout_secs),
+    };
+    Ok(match provider {
+        "groq" => Arc::new(
+            GroqLLM::new(config.groq_api_key.clone(), model)?
+                .with_retry_policy(RetryPolicy {
+                    max_retries: config.groq_max_retries,
+                    base_delay: Duration::from_millis(config.groq_retry_base_delay_ms),
+                    max_delay: Duration::from_millis(config.groq_retry_max_delay_ms),
+                })
+                .with_timeouts(timeouts)
+                .with_client(client.clone()),
+        ),
+        "ollama" => Arc::new(
+            OllamaLLM::new(config.ollama_base_url.clone(), model)
+                .with_timeouts(timeouts)
+                .with_client(client.clone()),
+        ),
+        "openai" => {
+            let compatible = &config.openai_compatible;
+            Arc::new(
+                OpenAICompatibleLLM::new(compatible.base_url.clone(), compatible.api_key.clone(), model)?
+                    .with_tools(compatible.supports_tools)
+                    .with_timeouts(timeouts)
+                    .with_client(client.clone()),
+            )
+        }
+        "azure" => Arc::new(
+            azure_llm(&config.azure_openai)?
+                .with_timeouts(timeouts)
+                .with_client(client.clone()),
+        ),
+        "gemini" => {
+            let safety = gemini_safety_settings(&config.gemini_safety_threshold, &config.gemini_safety_settings)?;
+            Arc::new(
+                GeminiLLM::new(config.gemini_api_key.clone(), model, safety)?
+                    .with_timeouts(timeouts)
+                    .with_client(client.clone()),
+            )
+        }
+        other => anyhow::bail!("Unknown LLM provider: {}", other),
+    })
+}
+
+/// The configured provider first, then every other provider with usable
+/// config, which can be chosen per request, all sharing `client`
+fn build_llm_providers(config: &AppConfig, client: &reqwest::Client) -> anyhow::Result<Vec<Arc<dyn LLMProvider>>> {
+    let provider = build_llm_provider(config, &config.llm_provider, client)
+        .map_err(|e| anyhow::anyhow!("Failed to initialize LLM provider {}: {}", config.llm_provider, e))?;
+    let mut providers = vec![provider];
+
+    let configured = [
+        ("groq", !config.groq_api_key.is_empty()),
+        ("ollama", true),
+        ("openai", !config.openai_compatible.base_url.is_empty()),
+        ("azure", !config.azure_openai.endpoint.is_empty()),
+        ("gemini", !config.gemini_api_key.is_empty()),
+    ];
+    for (name, _) in configured
+        .iter()
+        .filter(|(name, usable)| *usable && *name != config.llm_provider)
+    {
+        match build_llm_provider(config, name, client) {
+            Ok(provider) => providers.push(provider),
+            Err(e) => log::warn!("LLM provider {} unavailable: {}", name, e),
+        }
+    }
+    Ok(providers)
+}
+
+/// Document processor with the configured chunking defaults
+fn document_processor(config: &AppConfig) -> DocumentProcessor {
+    DocumentProcessor::new(config.default_chunk_size, config.default_chunk_overlap)
+        .with_parent_chunk_size(config.parent_chunk_size)
+        .with_profiles(config.chunking_profiles.clone())
+        .with_workers(config.ingest_workers)
+}
+
+/// Cross-origin policy for browsers: only the listed origins ("*" for any)
+/// may call the API with the listed methods and headers. Origins are read
+/// per request so a config reload can change them.
+fn cors(origins: Arc<RwLock<Vec<String>>>, methods: &[String], headers: &[String]) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origins
+                .read()
+                .unwrap()
+                .iter()
+                .any(|allowed| allowed == "*" || allowed.as_bytes() == origin.as_bytes())
+        })
+        .allowed_methods(methods.iter().map(String::as_str))
+        .allowed_headers(headers.iter().map(String::as_str))
+        .expose_headers(["x-request-id", "idempotent-replayed"])
+        .max_age(3600)
+}
+
+/// The built frontend in `dir`, with its `index.html` for paths that
+/// aren't files so the client-side router can handle them
+fn spa(dir: &Path) -> Files {
+    let index = dir.join("index.html");
+    Files::new("/", dir)
+        .index_file("index.html")
+        .redirect_to_slash_directory()
+        .default_handler(fn_service(move |req: ServiceRequest| {
+            let index = index.clone();
+            async move {
+                let (req, _) = req.into_parts();
+                let response = NamedFile::open_async(index).await?.into_response(&req);
+                Ok(ServiceResponse::new(req, response))
+            }
+        }))
+}
+
+/// Delete expired workspaces in the background, checking every minute or
+/// TTL, whichever is shorter
+fn purge_expired_workspaces(workspaces: web::Data<Workspaces>) {
+    let period = workspaces.ttl().min(Duration::from_secs(60));
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            workspaces.purge_expired();
+        }
+    });
+}
+
+/// The maintenance jobs configured with an interval
+fn maintenance_scheduler(
+    config: &AppConfig,
+    vector_store: &web::Data<Mutex<VectorStore>>,
+    webhooks: &web::Data<Webhooks>,
+    embedding_cache: Arc<dyn Cache<str, Vec<f32>>>,
+    response_cache: Arc<dyn Cache<str, CachedAnswer>>,
+    semantic_cache: Option<web::Data<SemanticCache>>,
+) -> Scheduler {
+    let mut scheduler = Scheduler::default();
+    if let Some(period) = ttl_secs(config.cleanup_interval_secs) {
+        let (store, webhooks) = (vector_store.clone(), webhooks.clone());
+        let (upload_dir, days) = (config.upload_dir.clone(), config.cleanup_max_age_days);
+        scheduler.add("cleanup", period, move || {
+            let report = cleanup_uploads(&upload_dir, &CleanupQuery::default(), days, &store, &webhooks)?;
+            Ok(serde_json::to_value(report)?)
+        });
+    }
+    if let Some(period) = ttl_secs(config.retention_interval_secs) {
+        let (store, webhooks) = (vector_store.clone(), webhooks.clone());
+        let upload_dir = config.upload_dir.clone();
+        let policy = RetentionPolicy::new(&config.retention_by_tag);
+        scheduler.add("retention", period, move || {
+            Ok(serde_json::to_value(purge_expired_documents(&policy, &upload_dir, &store, &webhooks)?)?)
+        });
+    }
+    if let Some(period) = ttl_secs(config.compaction_interval_secs) {
+        let store = vector_store.clone();
+        scheduler.add("compaction", period, move || {
+            let compacted = store.lock().unwrap().compact()?;
+            Ok(serde_json::json!({ "compacted": compacted }))
+        });
+    }
+    if let Some(period) = ttl_secs(config.snapshot_interval_secs) {
+        let store = vector_store.clone();
+        let (dir, keep) = (config.snapshot_dir.clone(), config.snapshot_keep);
+        scheduler.add("snapshot", period, move || {
+            let path = snapshot_store(&store, &dir, keep)?;
+            Ok(serde_json::json!({ "path": path }))
+        });
+    }
+    if let Some(period) = ttl_secs(config.cache_prune_interval_secs) {
+        scheduler.add("cache_prune", period, move || {
+            Ok(serde_json::json!({
+                "embeddings": embedding_cache.purge_expired(),
+                "answers": response_cache.purge_expired(),
+                "semantic": semantic_cache.as_ref().map_or(0, |cache| cache.purge_expired())
+            }))
+        });
+    }
+    scheduler
+}
+
+/// A cache TTL setting; 0 means entries don't expire
+fn ttl_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// The cache named `name` on the configured backend, `memory` when that's
+/// this process
+fn open_cache<V>(
+    config: &AppConfig,
+    name: &str,
+    memory: Arc<dyn Cache<str, V>>,
+    max_size: usize,
+    ttl: Option<Duration>,
+) -> anyhow::Result<Arc<dyn Cache<str, V>>>
+where
+    V: serde::Serialize + serde::de::DeserializeOwned + 'static,
+{
+    Ok(match config.cache_backend {
+        CacheBackend::Memory => memory,
+        CacheBackend::Disk => {
+            let cache = DiskCache::open(&config.cache_dir.join(name), max_size)?;
+            Arc::new(match ttl {
+                Some(ttl) => cache.with_ttl(ttl),
+                None => cache,
+            })
+        }
+        CacheBackend::Redis => {
+            let cache = RedisCache::open(&config.redis_url, &format!("knora:{}:", name))?;
+            Arc::new(match ttl {
+                Some(ttl) => cache.with_ttl(ttl),
+                None => cache,
+            })
+        }
+    })
+}
+
+/// Save the cached answers to `path`
+fn save_response_cache(cache: &LruCache<CachedAnswer>, path: &Path) {
+    match cache.save(path) {
+        Ok(count) => info!("Saved {} cached answers to {}", count, path.display()),
+        Err(e) => log::warn!("Failed to save cached answers: {}", e),
+    }
+}
+
+/// Save cached answers in the background every `period`, so a crash loses
+/// at most that much
+fn save_response_cache_periodically(cache: Arc<LruCache<CachedAnswer>>, path: PathBuf, period: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let (cache, path) = (cache.clone(), path.clone());
+            let _ = web::block(move || save_response_cache(&cache, &path)).await;
+        }
+    });
+}
+
+/// Reload the config on SIGHUP, like `POST /api/admin/config/reload`
+#[cfg(unix)]
+fn reload_on_hangup(reloader: web::Data<ConfigReloader>, audit_log: web::Data<AuditLog>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            log::warn!("Config reload on SIGHUP unavailable: {}", e);
+            return;
+        }
+    };
+    actix_web::rt::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let reloaded = reloader.reload();
+            if let Err(e) = &reloaded {
+                log::error!("Failed to reload configuration: {}", e);
+            }
+            audit_log.record(AuditEvent {
+                action: "config.reload".to_string(),
+                success: reloaded.is_ok(),
+                details: Some(match reloaded {
+                    Ok(report) => serde_json::json!({
+                        "trigger": "SIGHUP",
+                        "changed": report.changed,
+                        "requires_restart": report.requires_restart
+                    }),
+                    Err(e) => serde_json::json!({ "trigger": "SIGHUP", "error": e.to_string() }),
+                }),
+                ..Default::default()
+            });
+        }
+    });
+}
+
+/// LLM routes, or a 503 with the reason for all of them when the LLM
+/// handler didn't start
+fn llm_scope(available: bool) -> Scope {
+    if !available {
+        return web::scope("/llm").default_service(web::to(llm::llm_unavailable));
+    }
+
+    web::scope("/llm")
+        .route("/answer", web::post().to(llm::generate_answer))
+        .route("/answer/batch", web::post().to(llm::generate_answers))
+        .route("/summarize", web::post().to(llm::summarize))
+        .route("/classify", web::post().to(llm::classify_query))
+        .route("/model-info", web::get().to(llm::get_model_info))
+        .route("/models", web::get().to(llm::get_supported_models))
+        .route("/usage", web::get().to(llm::get_usage))
+}
+
+/// Retrieval and answer in one call, which needs the LLM handler
+fn query_scope(available: bool) -> Scope {
+    if !available {
+        return web::scope("/query").default_service(web::to(llm::llm_unavailable));
+    }
+
+    web::scope("/query")
+        .route("", web::post().to(llm::query))
+        .route("/compare", web::post().to(llm::compare))
+        .route("/pipelines", web::get().to(llm::list_pipelines))
+        .route("/experiment", web::get().to(llm::get_experiment))
+        .route("/feedback", web::post().to(llm::experiment_feedback))
+}
+
+/// Evaluation routes; only the RAG harness needs the LLM handler
+fn eval_scope(available: bool) -> Scope {
+    let scope = web::scope("/eval")
+        .route("/retrieval", web::post().to(eval::evaluate_retrieval))
+        .route("/runs", web::get().to(eval::list_runs))
+        .route("/runs/{id}", web::get().to(eval::get_run));
+
+    if !available {
+        return scope
+            .route("/rag", web::post().to(llm::llm_unavailable))
+            .route("/generate-testset", web::post().to(llm::llm_unavailable));
+    }
+    scope
+        .route("/rag", web::post().to(eval::evaluate_rag))
+        .route("/generate-testset", web::post().to(eval::generate_testset))
+}
+
+/// WebSocket routes, which all need the LLM handler
+fn ws_scope(available: bool) -> Scope {
+    if !available {
+        return web::scope("/ws").default_service(web::to(llm::llm_unavailable));
+    }
+
+    web::scope("/ws")
+        .route("/chat", web::get().to(chat::chat))
+}
+
+/// The LLM handler with every configured provider, checked with a test
+/// call unless `LLM_STARTUP_CHECK` is off
+async fn build_llm_handler(
+    config: &AppConfig,
+    http_client: &reqwest::Client,
+    vector_store: &web::Data<Mutex<VectorStore>>,
+    moderator: Moderator,
+    response_cache: Arc<dyn Cache<str, CachedAnswer>>,
+) -> anyhow::Result<LLMHandler> {
+    let mut providers = build_llm_providers(config, http_client)?.into_iter();
+    let provider = providers.next().expect("the configured provider comes first");
+
+    let mut tools = ToolRegistry::default();
+    tools.register(Arc::new(Calculator));
+    tools.register(Arc::new(SearchAgain::new(vector_store.clone().into_inner(), 5)));
+    tools.register(Arc::new(FetchDocument::new(vector_store.clone().into_inner(), 8000)));
+
+    let mut handler = LLMHandler::new(provider)
+        .with_tools(tools)
+        .with_verification(config.answer_verification)
+        .with_strict_mode(config.strict_mode)
+        .with_retrieval_mode(config.retrieval_mode)
+        .with_reranking(config.rerank_mode, config.rerank_model.clone())
+        .with_intent_routing(config.intent_routing)
+        .with_query_rewriting(config.query_rewriting)
+        .with_answer_score_floor(config.answer_score_floor)
+        .with_dedup_threshold(config.dedup_threshold)
+        .with_context_budget(config.context_budget)
+        .with_moderation(moderator)
+        .with_prices(parse_prices(&config.llm_prices))
+        .with_rate_limits(config.llm_requests_per_minute, config.llm_tokens_per_minute)
+        .with_failure_ttl(Duration::from_secs(config.llm_failure_cache_secs))
+        .with_response_cache(response_cache);
+    if let Some(system_prompt) = &config.system_prompt {
+        info!("Using custom system prompt");
+        handler = handler.with_system_prompt(system_prompt.clone());
+    }
+
+    for provider in providers {
+        handler.register_provider(provider);
+    }
+
+    if config.llm_startup_check {
+        handler
+            .check()
+            .await
+            .map_err(|e| anyhow::anyhow!("LLM provider {} failed its startup check: {}", config.llm_provider, e))?;
+    }
+
+    Ok(handler)
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    init_logging();
 
     let config = AppConfig::from_env();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            let telemetry = telemetry::init(&config)?;
+            let served = actix_web::rt::System::new().block_on(serve(config));
+            if let Some(telemetry) = telemetry {
+                telemetry.shutdown();
+            }
+            served?
+        }
+        Command::Ingest { paths, owner } => cli::ingest(&config, &paths, owner.as_deref())?,
+        Command::Search { query, top_k, json } => cli::search(&config, &query, top_k, json)?,
+        Command::Export { file } => cli::export(&config, &file)?,
+    }
+    Ok(())
+}
+
+/// Run the HTTP API until it's stopped
+async fn serve(config: AppConfig) -> std::io::Result<()> {
     info!("Starting {} v{}", config.app_name, config.app_version);
     info!("Server will listen on {}", config.server_addr());
 
+    // One pooled client for every outbound request: LLM providers and webhooks
+    let http_client = match config.http_client_settings().build() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Failed to configure the HTTP client: {:#}", e);
+            panic!("Cannot start server without an HTTP client");
+        }
+    };
+
     let store_path = config.vector_store_path.to_string_lossy().to_string();
     let embedding_model = config.embedding_model.clone();
-    let groq_api_key = config.groq_api_key.clone();
-    let default_llm_model = config.default_llm_model.clone();
     let upload_dir = config.upload_dir.to_string_lossy().to_string();
 
+    // The in-memory caches are used with CACHE_BACKEND=memory, answers then
+    // being saved to RESPONSE_CACHE_FILE
+    let embedding_ttl = ttl_secs(config.embedding_cache_ttl_secs);
+    let embeddings_in_memory = match embedding_ttl {
+        Some(ttl) => EmbeddingCache::new(config.embedding_cache_size).with_ttl(ttl),
+        None => EmbeddingCache::new(config.embedding_cache_size),
+    };
+    let response_ttl = ttl_secs(config.response_cache_ttl_secs);
+    let answers_in_memory = Arc::new(match response_ttl {
+        Some(ttl) => LruCache::new(config.response_cache_size).with_ttl(ttl),
+        None => LruCache::new(config.response_cache_size),
+    });
+    let caches = open_cache(
+        &config,
+        "embeddings",
+        Arc::new(embeddings_in_memory),
+        config.embedding_cache_size,
+        embedding_ttl,
+    )
+    .and_then(|embeddings| {
+        let answers = open_cache(
+            &config,
+            "answers",
+            answers_in_memory.clone(),
+            config.response_cache_size,
+            response_ttl,
+        )?;
+        Ok((embeddings, answers))
+    });
+    let (embedding_cache, response_cache) = match caches {
+        Ok(caches) => {
+            info!("Caching embeddings and answers in {:?}", config.cache_backend);
+            caches
+        }
+        Err(e) => {
+            eprintln!("Failed to open caches: {}", e);
+            panic!("Cannot start server without the configured cache backend");
+        }
+    };
+
     let vector_store = match VectorStore::new(&store_path, &embedding_model) {
         Ok(store) => {
+            let store = store
+                .with_embedding_cache(embedding_cache.clone())
+                .with_compression(config.store_compression);
             info!("Vector store initialized successfully");
             web::Data::new(Mutex::new(store))
         }
@@ -37,24 +594,255 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    let document_processor = web::Data::new(Mutex::new(DocumentProcessor::new(
-        config.default_chunk_size,
-        config.default_chunk_overlap,
-    )));
+    let document_processor = web::Data::new(Mutex::new(document_processor(&config)));
+
+    let moderator = match Moderator::load(config.moderation_rules.as_deref(), config.moderation_model.clone()) {
+        Ok(moderator) => {
+            if moderator.is_enabled() {
+                info!(
+                    "Moderation enabled: {} rules, guard model {}",
+                    moderator.rule_count(),
+                    moderator.model().unwrap_or("none")
+                );
+            }
+            moderator
+        }
+        Err(e) => {
+            eprintln!("Failed to load moderation rules: {}", e);
+            panic!("Refusing to start without the configured moderation rules");
+        }
+    };
 
-    let llm_handler = match LLMHandler::new(groq_api_key, default_llm_model) {
+    let handler = build_llm_handler(&config, &http_client, &vector_store, moderator, response_cache.clone()).await;
+    let (llm_handler, llm_status) = match handler {
         Ok(handler) => {
-            info!("LLM handler initialized successfully");
-            web::Data::new(Mutex::new(handler))
+            info!("LLM handler initialized successfully (provider: {})", config.llm_provider);
+            (Some(web::Data::new(handler)), LLMStatus::available())
+        }
+        Err(e) => {
+            log::error!("Starting without LLM features: {}", e);
+            (None, LLMStatus::unavailable(e.to_string()))
+        }
+    };
+    let llm_status = web::Data::new(llm_status);
+
+    let semantic_cache = (config.semantic_cache_threshold > 0.0 && llm_handler.is_some()).then(|| {
+        info!("Reusing answers for queries at similarity {} or more", config.semantic_cache_threshold);
+        let cache = SemanticCache::new(config.semantic_cache_threshold, config.semantic_cache_size);
+        web::Data::new(match response_ttl {
+            Some(ttl) => cache.with_ttl(ttl),
+            None => cache,
+        })
+    });
+
+    // Answers drawn from documents that changed since are stale; the handler
+    // is held weakly, as its tools hold the store
+    if let Some(cache) = &semantic_cache {
+        let cache = cache.clone();
+        vector_store.lock().unwrap().on_change(move |change| {
+            cache.invalidate(change);
+        });
+    }
+    if let Some(handler) = &llm_handler {
+        let handler = Arc::downgrade(&handler.clone().into_inner());
+        vector_store.lock().unwrap().on_change(move |change| {
+            if let Some(handler) = handler.upgrade() {
+                let dropped = handler.invalidate_answers(change);
+                if dropped > 0 {
+                    info!("Dropped {} cached answers after the index changed", dropped);
+                }
+            }
+        });
+    }
+
+    // Answers cached before the last shutdown
+    let cache_to_save = (config.cache_backend == CacheBackend::Memory && llm_handler.is_some())
+        .then_some(answers_in_memory)
+        .zip(config.response_cache_file.clone());
+    if let Some((cache, path)) = &cache_to_save {
+        match cache.load(path) {
+            Ok(count) => info!("Loaded {} cached answers from {}", count, path.display()),
+            Err(e) => log::warn!("Starting with no cached answers: {}", e),
+        }
+        if config.cache_save_interval_secs > 0 {
+            let period = Duration::from_secs(config.cache_save_interval_secs);
+            save_response_cache_periodically(cache.clone(), path.clone(), period);
+        }
+    }
+
+    let prompt_templates = match PromptTemplateStore::new(&config.prompts_dir) {
+        Ok(store) => {
+            info!("Loaded {} prompt templates from {}", store.list().len(), config.prompts_dir.display());
+            web::Data::new(Mutex::new(store))
+        }
+        Err(e) => {
+            eprintln!("Failed to load prompt templates: {}", e);
+            panic!("Cannot start server without prompt template directory");
+        }
+    };
+
+    let few_shot = match FewShotStore::new(&config.few_shot_file) {
+        Ok(store) => {
+            info!("Loaded {} few-shot examples from {}", store.examples().len(), config.few_shot_file.display());
+            web::Data::new(Mutex::new(store))
+        }
+        Err(e) => {
+            eprintln!("Failed to load few-shot examples: {}", e);
+            panic!("Cannot start server with invalid few-shot examples");
+        }
+    };
+
+    let pipelines = match PipelineStore::new(&config.pipelines_file, config.default_pipeline.clone()) {
+        Ok(store) => {
+            info!("Loaded {} query pipelines from {}", store.list().len(), config.pipelines_file.display());
+            web::Data::new(store)
+        }
+        Err(e) => {
+            eprintln!("Failed to load query pipelines: {}", e);
+            panic!("Cannot start server with invalid query pipelines");
+        }
+    };
+
+    let experiments = match Experiment::load(&config.experiment_file)
+        .and_then(|experiment| match experiment {
+            Some(experiment) => experiment.validate(&pipelines).map(|_| Some(experiment)),
+            None => Ok(None),
+        }) {
+        Ok(experiment) => {
+            if let Some(experiment) = &experiment {
+                info!("Running experiment '{}' with {} variants", experiment.name, experiment.variants.len());
+            }
+            web::Data::new(ExperimentTracker::new(experiment))
+        }
+        Err(e) => {
+            eprintln!("Failed to load experiment: {}", e);
+            panic!("Cannot start server with an invalid experiment");
+        }
+    };
+
+    let eval_runs = match EvalRunStore::new(&config.eval_runs_dir) {
+        Ok(store) => web::Data::new(store),
+        Err(e) => {
+            eprintln!("Failed to create evaluation run directory: {}", e);
+            panic!("Cannot start server without evaluation run directory");
+        }
+    };
+
+    let access = AccessControl::load(&config.api_keys_file).and_then(|access| match &config.admin_token {
+        Some(token) => access.with_admin_token(token.clone()),
+        None => Ok(access),
+    });
+    let access = match access {
+        Ok(access) => {
+            if access.is_enabled() {
+                info!("Loaded {} API keys from {}", access.key_count(), config.api_keys_file.display());
+            } else {
+                log::warn!("No API keys in {}; every route but /api/admin is open", config.api_keys_file.display());
+            }
+            if !access.admin_enabled() {
+                info!("Admin API disabled (set ADMIN_TOKEN or add an admin API key)");
+            }
+            web::Data::new(access)
+        }
+        Err(e) => {
+            eprintln!("Failed to load API keys: {}", e);
+            panic!("Cannot start server with invalid API keys");
         }
+    };
+
+    let audit_log = match AuditLog::open(&config.audit_log_file) {
+        Ok(audit_log) => web::Data::new(audit_log),
         Err(e) => {
-            eprintln!("Warning: Failed to initialize LLM handler: {}", e);
-            eprintln!("LLM features will be unavailable");
-            panic!("Cannot start server without LLM handler");
+            eprintln!("Failed to open audit log: {}", e);
+            panic!("Cannot start server without audit log");
         }
     };
+    info!("Recording sensitive operations in {}", audit_log.path().display());
+
+    let analytics = if config.analytics_enabled {
+        match QueryAnalytics::open(&config.analytics_file) {
+            Ok(analytics) => {
+                info!("Logging queries for analytics to {}", analytics.path().display());
+                Some(web::Data::new(analytics))
+            }
+            Err(e) => {
+                eprintln!("Failed to open analytics log: {}", e);
+                panic!("Cannot start server without analytics log");
+            }
+        }
+    } else {
+        None
+    };
+
+    let request_limits = web::Data::new(RequestLimits::new(config.rate_limit_per_minute, config.llm_rate_limit_per_minute));
+
+    let webhooks = web::Data::new(
+        Webhooks::new(config.webhook_urls.clone(), config.webhook_secret.clone()).with_client(http_client.clone()),
+    );
+    if webhooks.url_count() > 0 {
+        info!("Sending document events to {} webhook URLs", webhooks.url_count());
+    }
+
+    let workspaces = match Workspaces::new(
+        &config.workspaces_dir,
+        &embedding_model,
+        Duration::from_secs(config.workspace_ttl_secs),
+    ) {
+        Ok(workspaces) => web::Data::new(workspaces),
+        Err(e) => {
+            eprintln!("Failed to create workspace directory: {}", e);
+            panic!("Cannot start server without workspace directory");
+        }
+    };
+    purge_expired_workspaces(workspaces.clone());
+
+    let scheduler = web::Data::new(maintenance_scheduler(
+        &config,
+        &vector_store,
+        &webhooks,
+        embedding_cache,
+        response_cache,
+        semantic_cache.clone(),
+    ));
+    if !scheduler.is_empty() {
+        let jobs: Vec<&str> = scheduler.statuses().iter().map(|status| status.name).collect();
+        info!("Scheduled maintenance jobs: {}", jobs.join(", "));
+        Scheduler::spawn(scheduler.clone());
+    }
+    let cleanup_defaults = web::Data::new(CleanupDefaults {
+        max_age_days: config.cleanup_max_age_days,
+    });
+
+    if let Some(path) = &config.warmup_queries_file {
+        match warmup::load_queries(path) {
+            Ok(queries) => {
+                info!("Warming caches with {} queries from {}", queries.len(), path.display());
+                let warm_up = WarmUp::new(queries, vector_store.clone());
+                let warm_up = match &llm_handler {
+                    Some(handler) if config.warmup_generate => warm_up.with_generation(
+                        handler.clone(),
+                        prompt_templates.clone(),
+                        few_shot.clone(),
+                        pipelines.clone(),
+                        workspaces.clone(),
+                        semantic_cache.clone(),
+                    ),
+                    _ => warm_up,
+                };
+                let period = (config.warmup_interval_secs > 0).then(|| Duration::from_secs(config.warmup_interval_secs));
+                warm_up.spawn(period);
+            }
+            Err(e) => log::warn!("Skipping cache warm-up: {}", e),
+        }
+    }
 
     let upload_dir_data = web::Data::new(upload_dir.clone());
+    let upload_limits = web::Data::new(RwLock::new(UploadLimits::new(
+        config.max_upload_size_mb,
+        &config.upload_size_limits,
+    )));
+    let idempotency = web::Data::new(IdempotencyStore::new(Duration::from_secs(config.idempotency_ttl_secs)));
+    let json_limit = usize::try_from(config.max_json_payload_kb.saturating_mul(1024)).unwrap_or(usize::MAX);
 
     let host = config.server_host.clone();
     let port = config.server_port;
@@ -62,25 +850,89 @@ async fn main() -> std::io::Result<()> {
     info!("Initializing HTTP server...");
     info!("Upload directory: {}", upload_dir);
 
+    if config.cors_allowed_origins.is_empty() {
+        info!("CORS: cross-origin requests disabled (set CORS_ALLOWED_ORIGINS to allow a frontend)");
+    } else {
+        info!("CORS: allowing origins {}", config.cors_allowed_origins.join(", "));
+    }
+    let static_dir: Option<PathBuf> = config.static_dir.clone().filter(|dir| {
+        let found = dir.join("index.html").is_file();
+        if !found {
+            log::warn!("Not serving the frontend: no index.html in {}", dir.display());
+        }
+        found
+    });
+    if let Some(dir) = &static_dir {
+        info!("Serving the frontend from {}", dir.display());
+    }
+
+    let cors_origins = Arc::new(RwLock::new(config.cors_allowed_origins.clone()));
+    let cors_methods = config.cors_allowed_methods.clone();
+    let cors_headers = config.cors_allowed_headers.clone();
+
+    let reloader = web::Data::new(ConfigReloader::new(
+        config,
+        http_client,
+        document_processor.clone(),
+        llm_handler.clone(),
+        request_limits.clone(),
+        upload_limits.clone(),
+        cors_origins.clone(),
+    ));
+    #[cfg(unix)]
+    reload_on_hangup(reloader.clone(), audit_log.clone());
+
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header()
-            .max_age(3600);
+        let cors = cors(cors_origins.clone(), &cors_methods, &cors_headers);
 
         App::new()
             .app_data(vector_store.clone())
             .app_data(document_processor.clone())
-            .app_data(llm_handler.clone())
+            .app_data(llm_status.clone())
+            .configure(|cfg| {
+                if let Some(handler) = &llm_handler {
+                    cfg.app_data(handler.clone());
+                }
+            })
+            .app_data(prompt_templates.clone())
+            .app_data(few_shot.clone())
+            .app_data(pipelines.clone())
+            .app_data(experiments.clone())
+            .app_data(eval_runs.clone())
             .app_data(upload_dir_data.clone())
-            .wrap(middleware::Logger::default())
+            .app_data(upload_limits.clone())
+            .app_data(idempotency.clone())
+            .app_data(web::JsonConfig::default().limit(json_limit).error_handler(error::json_error))
+            .app_data(access.clone())
+            .app_data(request_limits.clone())
+            .app_data(webhooks.clone())
+            .app_data(reloader.clone())
+            .app_data(workspaces.clone())
+            .app_data(audit_log.clone())
+            .app_data(scheduler.clone())
+            .app_data(cleanup_defaults.clone())
+            .configure(|cfg| {
+                if let Some(semantic_cache) = &semantic_cache {
+                    cfg.app_data(semantic_cache.clone());
+                }
+                if let Some(analytics) = &analytics {
+                    cfg.app_data(analytics.clone());
+                }
+            })
+            .wrap(from_fn(limit_requests))
+            .wrap(from_fn(authorize))
+            .wrap(from_fn(limit_rejected_requests))
+            .wrap(from_fn(audit_requests))
+            .wrap(from_fn(trace_request))
+            .wrap(from_fn(assign_request_id))
+            .wrap(Logger::new(REQUEST_LOG_FORMAT))
             .wrap(cors)
             .service(
                 web::scope("/api")
                     .service(
                         web::scope("/health")
                             .route("", web::get().to(health::health_check))
+                            .route("/deep", web::get().to(health::deep_health_check))
                     )
                     .service(
                         web::scope("/documents")
@@ -88,6 +940,10 @@ async fn main() -> std::io::Result<()> {
                             .route("/stats", web::get().to(document::get_file_stats))
                             .route("/upload", web::post().to(upload::upload_file))
                             .route("/formats", web::get().to(upload::get_supported_formats))
+                            .route("/{id}/rechunk", web::post().to(document::rechunk_document))
+                            .route("/{id}/share", web::post().to(document::share_document))
+                            .route("/{id}/download", web::get().to(document::download_document))
+                            .route("/{id}", web::delete().to(document::delete_document))
                     )
                     .service(
                         web::scope("/search")
@@ -95,19 +951,57 @@ async fn main() -> std::io::Result<()> {
                             .route("/stats", web::get().to(search::get_vector_store_stats))
                             .route("/add", web::post().to(search::add_documents))
                             .route("/delete", web::delete().to(search::delete_document))
-                            .route("/clear", web::delete().to(search::clear_store))
-                            .route("/storage", web::get().to(search::get_storage_info))
-                            .route("/storage/cleanup", web::post().to(search::cleanup_old_files))
                     )
                     .service(
-                        web::scope("/llm")
-                            .route("/answer", web::post().to(llm::generate_answer))
-                            .route("/model-info", web::get().to(llm::get_model_info))
-                            .route("/models", web::get().to(llm::get_supported_models))
+                        web::scope("/admin")
+                            .route("/store", web::delete().to(admin::clear_store))
+                            .route("/storage", web::get().to(admin::get_storage_info))
+                            .route("/storage/cleanup", web::post().to(admin::cleanup_old_files))
+                            .route("/reindex", web::post().to(admin::reindex))
+                            .route("/export", web::get().to(admin::export_store))
+                            .route("/import", web::post().to(admin::import_store))
+                            .route("/config/reload", web::post().to(admin::reload_config))
+                            .route("/audit", web::get().to(admin::get_audit_log))
+                            .route("/scheduler", web::get().to(admin::get_scheduled_jobs))
+                            .route("/scheduler/{job}/run", web::post().to(admin::run_scheduled_job))
+                    )
+                    .route("/analytics", web::get().to(analytics::get_analytics))
+                    .service(
+                        web::scope("/workspaces")
+                            .route("", web::post().to(workspaces::create_workspace))
+                            .route("/{id}", web::get().to(workspaces::get_workspace))
+                            .route("/{id}", web::delete().to(workspaces::delete_workspace))
+                            .route("/{id}/upload", web::post().to(workspaces::upload_to_workspace))
+                    )
+                    .service(eval_scope(llm_status.available))
+                    .service(llm_scope(llm_status.available))
+                    .service(query_scope(llm_status.available))
+                    .service(ws_scope(llm_status.available))
+                    .service(
+                        web::scope("/prompts")
+                            .route("", web::get().to(prompts::list_templates))
+                            .route("/{name}", web::get().to(prompts::get_template))
+                            .route("/{name}", web::put().to(prompts::save_template))
+                            .route("/{name}", web::delete().to(prompts::delete_template))
+                    )
+                    .service(
+                        web::scope("/few-shot")
+                            .route("", web::get().to(prompts::list_examples))
+                            .route("", web::put().to(prompts::replace_examples))
                     )
             )
+            .configure(|cfg| {
+                if let Some(dir) = &static_dir {
+                    cfg.service(spa(dir));
+                }
+            })
     })
     .bind((host.as_str(), port))?
     .run()
-    .await
+    .await?;
+
+    if let Some((cache, path)) = &cache_to_save {
+        save_response_cache(cache, path);
+    }
+    Ok(())
 }
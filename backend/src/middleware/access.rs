@@ -0,0 +1,365 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ApiError, ErrorCode};
+
+/// What an API key may do; each role can do everything the ones before it can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Search, query, and read settings
+    Reader,
+    /// Also upload, re-chunk, and delete documents
+    Editor,
+    /// Also clear the store, clean up uploads, and change prompts and
+    /// examples
+    Admin,
+}
+
+/// A key clients send as `Authorization: Bearer <key>`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    /// Who the key belongs to, for logs
+    pub name: String,
+    pub key: String,
+    pub role: Role,
+}
+
+/// The key a request was made with, available to handlers through the
+/// request extensions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caller {
+    pub name: String,
+    pub role: Role,
+}
+
+impl Caller {
+    /// User whose own and shared documents the caller is limited to;
+    /// admins see every document
+    pub fn document_user(&self) -> Option<&str> {
+        (self.role < Role::Admin).then_some(self.name.as_str())
+    }
+}
+
+/// Routes that clear, re-index, import, or export the whole store. They
+/// need the admin token or an admin key, even when no API keys are set up.
+pub const ADMIN_SCOPE: &str = "/api/admin";
+
+/// Query analytics, which show what every user asked, are guarded like
+/// the admin scope
+pub const ANALYTICS_ROUTE: &str = "/api/analytics";
+
+/// Least role needed for requests with a method (any when `None`) and a
+/// path starting with the prefix. The first matching entry wins; routes
+/// without one need a reader key.
+const POLICIES: &[(Option<Method>, &str, Option<Role>)] = &[
+    (None, "/api/health/deep", Some(Role::Reader)),
+    (None, "/api/health", None),
+    (None, ADMIN_SCOPE, Some(Role::Admin)),
+    (None, ANALYTICS_ROUTE, Some(Role::Admin)),
+    (Some(Method::PUT), "/api/prompts", Some(Role::Admin)),
+    (Some(Method::DELETE), "/api/prompts", Some(Role::Admin)),
+    (Some(Method::PUT), "/api/few-shot", Some(Role::Admin)),
+    (Some(Method::POST), "/api/documents/upload", Some(Role::Editor)),
+    (Some(Method::POST), "/api/documents/process", Some(Role::Editor)),
+    (Some(Method::POST), "/api/documents/", Some(Role::Editor)),
+    (Some(Method::DELETE), "/api/documents/", Some(Role::Editor)),
+    (Some(Method::POST), "/api/search/add", Some(Role::Editor)),
+    (Some(Method::DELETE), "/api/search/delete", Some(Role::Editor)),
+    (Some(Method::POST), "/api/workspaces", Some(Role::Editor)),
+    (Some(Method::DELETE), "/api/workspaces", Some(Role::Editor)),
+];
+
+/// Role a request needs, or `None` for public routes
+pub fn required_role(method: &Method, path: &str) -> Option<Role> {
+    // The frontend's pages and assets, when the backend serves them
+    if !path.starts_with("/api") {
+        return None;
+    }
+    POLICIES
+        .iter()
+        .find(|(policy_method, prefix, _)| {
+            policy_method.as_ref().is_none_or(|m| m == method) && path.starts_with(prefix)
+        })
+        .map_or(Some(Role::Reader), |(_, _, role)| *role)
+}
+
+/// API keys and the roles they carry, and the admin token. With no keys
+/// every request outside the admin scope is allowed, as before access
+/// control existed.
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    keys: Vec<ApiKey>,
+    admin_token: Option<String>,
+}
+
+impl AccessControl {
+    pub fn new(keys: Vec<ApiKey>) -> Result<Self> {
+        let mut seen = HashSet::new();
+        for key in &keys {
+            if key.key.len() < 16 {
+                return Err(anyhow!("API key '{}' must be at least 16 characters", key.name));
+            }
+            if !seen.insert(key.key.as_str()) {
+                return Err(anyhow!("API key '{}' is listed twice", key.name));
+            }
+        }
+        Ok(AccessControl { keys, admin_token: None })
+    }
+
+    /// Also let `token` use the admin scope
+    pub fn with_admin_token(mut self, token: String) -> Result<Self> {
+        if token.len() < 16 {
+            return Err(anyhow!("ADMIN_TOKEN must be at least 16 characters"));
+        }
+        if self.keys.iter().any(|k| k.key == token) {
+            return Err(anyhow!("ADMIN_TOKEN must differ from every API key"));
+        }
+        self.admin_token = Some(token);
+        Ok(self)
+    }
+
+    /// Keys from a JSON array of `{name, key, role}` in `path`; none if
+    /// there's no such file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(json) => {
+                let keys: Vec<ApiKey> = serde_json::from_str(&json)
+                    .map_err(|e| anyhow!("Invalid API keys in {}: {}", path.display(), e))?;
+                Self::new(keys)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Whether anything can use the admin scope
+    pub fn admin_enabled(&self) -> bool {
+        self.admin_token.is_some() || self.keys.iter().any(|k| k.role == Role::Admin)
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// The caller a key identifies. The admin token identifies an admin
+    /// only in the admin scope.
+    pub fn identify_admin(&self, key: &str) -> Option<Caller> {
+        if self.admin_token.as_deref() == Some(key) {
+            return Some(Caller {
+                name: "admin".to_string(),
+                role: Role::Admin,
+            });
+        }
+        self.identify(key)
+    }
+
+    /// The caller a key identifies
+    pub fn identify(&self, key: &str) -> Option<Caller> {
+        self.keys.iter().find(|k| k.key == key).map(|k| Caller {
+            name: k.name.clone(),
+            role: k.role,
+        })
+    }
+}
+
+/// Path the router matches a request on: percent-encoded characters other
+/// than `%`, `/`, and `+` are decoded, so `/api/%61dmin` routes to
+/// `/api/admin`. Checks of what a path may do must use this, not the raw
+/// path.
+pub fn routed_path(req: &ServiceRequest) -> &str {
+    req.match_info().as_str()
+}
+
+/// Whether a raw path percent-encodes a letter, digit, or `-._~`, which
+/// clients never need to and which only serves to hide the route
+fn encodes_unreserved(path: &str) -> bool {
+    path.as_bytes().windows(3).any(|window| {
+        window[0] == b'%'
+            && std::str::from_utf8(&window[1..])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .is_some_and(|byte| byte.is_ascii_alphanumeric() || b"-._~".contains(&byte))
+    })
+}
+
+/// Key sent with a request: a bearer token, an `X-API-Key` header, or for
+/// WebSockets, which browsers can't add headers to, an `api_key` query
+/// parameter
+fn request_key(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(token) = headers
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+    {
+        return Some(token.trim().to_string());
+    }
+    if let Some(key) = headers.get("X-API-Key").and_then(|h| h.to_str().ok()) {
+        return Some(key.trim().to_string());
+    }
+    web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get("api_key").cloned())
+}
+
+/// Reject requests whose key is missing, unknown (401), or lacks the role
+/// the route needs (403); others go on with their `Caller` attached. The
+/// admin scope also takes the admin token, and is closed when neither it
+/// nor an admin key is set up. Paths that percent-encode unreserved
+/// characters are refused outright.
+pub async fn authorize(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if encodes_unreserved(req.path()) {
+        let response = ApiError::invalid("Paths may not percent-encode letters, digits, or '-._~'").error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    let access = req.app_data::<web::Data<AccessControl>>().cloned();
+    let path = routed_path(&req).to_string();
+    let required = required_role(req.method(), &path);
+    let (Some(access), Some(required)) = (access, required) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+    if req.method() == Method::OPTIONS {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let admin_scope = path.starts_with(ADMIN_SCOPE) || path.starts_with(ANALYTICS_ROUTE);
+    if admin_scope && !access.admin_enabled() {
+        let response = ApiError::forbidden("The admin API is disabled; set ADMIN_TOKEN or add an admin API key")
+            .error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    if !access.is_enabled() && !admin_scope {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let identify = |key: String| {
+        if admin_scope {
+            access.identify_admin(&key)
+        } else {
+            access.identify(&key)
+        }
+    };
+    let Some(caller) = request_key(&req).and_then(identify) else {
+        let response =
+            ApiError::new(ErrorCode::Unauthorized, "A valid API key is required").error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+    if caller.role < required {
+        log::warn!("{} ({:?}) was refused {} {}", caller.name, caller.role, req.method(), path);
+        let response = ApiError::forbidden(format!(
+            "This action needs the {} role",
+            format!("{:?}", required).to_lowercase()
+        ))
+        .error_response();
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    req.extensions_mut().insert(caller);
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{http::StatusCode, App, HttpResponse};
+
+    #[test]
+    fn test_route_policies_and_keys() {
+        assert_eq!(required_role(&Method::GET, "/api/health"), None);
+        assert_eq!(required_role(&Method::GET, "/_next/static/app.js"), None);
+        assert_eq!(required_role(&Method::GET, "/api/health/deep"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::POST, "/api/query"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::GET, "/api/prompts/concise"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::PUT, "/api/prompts/concise"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::POST, "/api/documents/abc/rechunk"), Some(Role::Editor));
+        assert_eq!(required_role(&Method::GET, "/api/documents/stats"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::GET, "/api/analytics"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::DELETE, "/api/documents/abc"), Some(Role::Editor));
+        assert_eq!(required_role(&Method::POST, "/api/workspaces"), Some(Role::Editor));
+        assert_eq!(required_role(&Method::POST, "/api/workspaces/abc/upload"), Some(Role::Editor));
+        assert_eq!(required_role(&Method::GET, "/api/workspaces/abc"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::DELETE, "/api/admin/store"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::GET, "/api/admin/export"), Some(Role::Admin));
+        assert!(Role::Admin > Role::Editor && Role::Editor > Role::Reader);
+
+        let key = |name: &str, key: &str, role| ApiKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            role,
+        };
+        let access = AccessControl::new(vec![key("ci", "reader-key-0123456789", Role::Reader)]).unwrap();
+        assert_eq!(access.identify("reader-key-0123456789").unwrap().role, Role::Reader);
+        assert!(access.identify("guess").is_none());
+        assert!(AccessControl::new(vec![key("short", "abc", Role::Admin)]).is_err());
+
+        assert!(!access.admin_enabled());
+        let access = access.with_admin_token("admin-token-0123456789".to_string()).unwrap();
+        assert!(access.admin_enabled());
+        assert_eq!(access.identify_admin("admin-token-0123456789").unwrap().role, Role::Admin);
+        assert!(access.identify("admin-token-0123456789").is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_encoded_paths_get_the_routed_policy() {
+        let key = |name: &str, key: &str, role| ApiKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            role,
+        };
+        let access = AccessControl::new(vec![key("reader", "reader-key-0123456789", Role::Reader)])
+            .unwrap()
+            .with_admin_token("admin-token-0123456789".to_string())
+            .unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(access))
+                .wrap(from_fn(authorize))
+                .route("/api/admin/store", web::delete().to(HttpResponse::Ok))
+                .route("/api/admin/audit", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let call = |method: Method, path: &str, key: &str| {
+            TestRequest::default()
+                .method(method)
+                .uri(path)
+                .insert_header(("Authorization", format!("Bearer {}", key)))
+                .to_request()
+        };
+        let reader = "reader-key-0123456789";
+        let admin = "admin-token-0123456789";
+        let cases = [
+            (Method::DELETE, "/api/admin/store", reader, StatusCode::FORBIDDEN),
+            (Method::DELETE, "/api/%61dmin/store", reader, StatusCode::BAD_REQUEST),
+            (Method::GET, "/api/%61dmin/audit", reader, StatusCode::BAD_REQUEST),
+            (Method::DELETE, "/api/%61dmin/store", admin, StatusCode::BAD_REQUEST),
+            (Method::DELETE, "/api/admin/store", admin, StatusCode::OK),
+        ];
+        for (method, path, key, expected) in cases {
+            let response = call_service(&app, call(method, path, key)).await;
+            assert_eq!(response.status(), expected, "{}", path);
+        }
+        assert!(encodes_unreserved("/api/%6Clm/answer"));
+        assert!(!encodes_unreserved("/api/documents/a%2Fb"));
+    }
+}
@@ -0,0 +1,148 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+
+use super::{routed_path, Caller};
+use crate::services::audit::{AuditEvent, AuditLog};
+
+/// Routes recorded in the audit log, as (method, path pattern, action).
+/// `{name}` segments match any one segment and name the target.
+const AUDITED: &[(Method, &str, &str)] = &[
+    (Method::POST, "/api/documents/upload", "document.upload"),
+    (Method::POST, "/api/documents/{id}/rechunk", "document.rechunk"),
+    (Method::POST, "/api/documents/{id}/share", "document.share"),
+    (Method::DELETE, "/api/documents/{id}", "document.delete"),
+    (Method::POST, "/api/search/add", "document.add"),
+    (Method::DELETE, "/api/search/delete", "document.delete"),
+    (Method::DELETE, "/api/admin/store", "store.clear"),
+    (Method::POST, "/api/admin/reindex", "store.reindex"),
+    (Method::GET, "/api/admin/export", "store.export"),
+    (Method::POST, "/api/admin/import", "store.import"),
+    (Method::POST, "/api/admin/storage/cleanup", "storage.cleanup"),
+    (Method::POST, "/api/admin/config/reload", "config.reload"),
+    (Method::PUT, "/api/prompts/{name}", "prompt.save"),
+    (Method::DELETE, "/api/prompts/{name}", "prompt.delete"),
+    (Method::PUT, "/api/few-shot", "few_shot.replace"),
+];
+
+/// What an audited request acted on, set by handlers when the path
+/// doesn't say, such as the file an upload stored
+#[derive(Debug, Clone)]
+pub struct AuditTarget(pub String);
+
+/// More about an audited request for its entry, set by handlers
+#[derive(Debug, Clone)]
+pub struct AuditDetails(pub serde_json::Value);
+
+/// Action and target of a request to an audited route
+fn audited(method: &Method, path: &str) -> Option<(&'static str, Option<String>)> {
+    AUDITED.iter().find_map(|(audited_method, pattern, action)| {
+        if audited_method != method {
+            return None;
+        }
+        let mut segments = path.trim_end_matches('/').split('/');
+        let mut target = None;
+        for expected in pattern.split('/') {
+            let segment = segments.next()?;
+            if expected.starts_with('{') {
+                target = Some(segment.to_string());
+            } else if segment != expected {
+                return None;
+            }
+        }
+        segments.next().is_none().then_some((*action, target))
+    })
+}
+
+/// Record requests to routes that change documents, the store, prompts, or
+/// the config in the audit log, with who made them and how they ended.
+/// Refused attempts are recorded too, so this wraps `authorize`. Routes are
+/// told apart by the path the router matches, so encoding a route's path
+/// doesn't keep it out of the log.
+pub async fn audit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let audit_log = req.app_data::<web::Data<AuditLog>>().cloned();
+    let (Some(audit_log), Some((action, path_target))) = (audit_log, audited(req.method(), routed_path(&req))) else {
+        return next.call(req).await;
+    };
+    let mut event = AuditEvent {
+        action: action.to_string(),
+        target: path_target.or_else(|| {
+            web::Query::<std::collections::HashMap<String, String>>::from_query(req.query_string())
+                .ok()
+                .and_then(|query| query.get("file_path").cloned())
+        }),
+        method: Some(req.method().to_string()),
+        path: Some(req.path().to_string()),
+        client_ip: req.peer_addr().map(|addr| addr.ip().to_string()),
+        request_id: super::current_request_id(),
+        ..Default::default()
+    };
+
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(response) => {
+            let extensions = response.request().extensions();
+            event.actor = extensions.get::<Caller>().map(|caller| caller.name.clone());
+            if let Some(AuditTarget(target)) = extensions.get::<AuditTarget>() {
+                event.target = Some(target.clone());
+            }
+            event.details = extensions.get::<AuditDetails>().map(|details| details.0.clone());
+            response.status()
+        }
+        Err(e) => e.as_response_error().status_code(),
+    };
+    event.status = Some(status.as_u16());
+    event.success = status.is_success();
+    audit_log.record(event);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::audit::AuditQuery;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{App, HttpResponse};
+
+    #[test]
+    fn test_audited_routes() {
+        assert_eq!(
+            audited(&Method::DELETE, "/api/documents/abc"),
+            Some(("document.delete", Some("abc".to_string())))
+        );
+        assert_eq!(
+            audited(&Method::POST, "/api/documents/abc/rechunk"),
+            Some(("document.rechunk", Some("abc".to_string())))
+        );
+        assert_eq!(audited(&Method::POST, "/api/documents/upload"), Some(("document.upload", None)));
+        assert_eq!(audited(&Method::DELETE, "/api/admin/store/"), Some(("store.clear", None)));
+        assert_eq!(audited(&Method::GET, "/api/documents/abc/download"), None);
+        assert_eq!(audited(&Method::GET, "/api/prompts/concise"), None);
+        assert_eq!(audited(&Method::POST, "/api/documents/abc/rechunk/extra"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_encoded_paths_are_audited_as_their_route() {
+        let dir = tempfile::tempdir().unwrap();
+        let audit_log = web::Data::new(AuditLog::open(&dir.path().join("audit.jsonl")).unwrap());
+        let app = init_service(
+            App::new()
+                .app_data(audit_log.clone())
+                .wrap(from_fn(audit_requests))
+                .route("/api/admin/store", web::delete().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = TestRequest::delete().uri("/api/admin/st%6Fre").to_request();
+        call_service(&app, req).await;
+
+        let page = audit_log.page(&AuditQuery::default()).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].event.action, "store.clear");
+    }
+}
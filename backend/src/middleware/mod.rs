@@ -0,0 +1,11 @@
+pub mod access;
+pub mod audit;
+pub mod rate_limit;
+pub mod request_id;
+pub mod trace;
+
+pub use access::{authorize, routed_path, AccessControl, Caller};
+pub use audit::{audit_requests, AuditDetails, AuditTarget};
+pub use rate_limit::{limit_rejected_requests, limit_requests, RequestLimits};
+pub use request_id::{assign_request_id, current_request_id, with_request_id};
+pub use trace::trace_request;
@@ -0,0 +1,121 @@
+use crate::error::{ApiError, ErrorCode};
+use crate::middleware::{routed_path, Caller};
+use crate::services::rate_limiter::ClientRateLimiter;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use std::time::Duration;
+
+/// Routes that call the LLM and get the stricter limit, matched against
+/// the routed path
+const LLM_ROUTES: &[&str] = &["/api/llm", "/api/query", "/api/ws"];
+
+/// Requests per minute allowed from each API key, or each IP address when
+/// access control is off, and requests refused for a missing or wrong key
+/// allowed from each IP address
+pub struct RequestLimits {
+    all: ClientRateLimiter,
+    llm: ClientRateLimiter,
+    rejected: ClientRateLimiter,
+}
+
+impl RequestLimits {
+    /// Limits of 0 allow every request
+    pub fn new(per_minute: u32, llm_per_minute: u32) -> Self {
+        RequestLimits {
+            all: ClientRateLimiter::new(per_minute),
+            llm: ClientRateLimiter::new(llm_per_minute),
+            rejected: ClientRateLimiter::new(per_minute),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.all.is_enabled() || self.llm.is_enabled()
+    }
+
+    pub fn set_per_minute(&self, per_minute: u32, llm_per_minute: u32) {
+        self.all.set_per_minute(per_minute);
+        self.llm.set_per_minute(llm_per_minute);
+        self.rejected.set_per_minute(per_minute);
+    }
+
+    /// Count a request to `path` from `client`, or return how long until
+    /// it would be allowed. A refused request counts against no limit.
+    fn check(&self, client: &str, path: &str) -> Result<(), Duration> {
+        if LLM_ROUTES.iter().any(|route| path.starts_with(route)) {
+            ClientRateLimiter::check_all(&[&self.all, &self.llm], client)
+        } else {
+            self.all.check(client)
+        }
+    }
+}
+
+fn is_limited(req: &ServiceRequest) -> Option<web::Data<RequestLimits>> {
+    let limits = req.app_data::<web::Data<RequestLimits>>().cloned();
+    limits.filter(|limits| limits.is_enabled() && routed_path(req).trim_end_matches('/') != "/api/health")
+}
+
+fn peer_ip(req: &ServiceRequest) -> String {
+    format!("ip:{}", req.peer_addr().map_or("unknown".to_string(), |addr| addr.ip().to_string()))
+}
+
+/// 429 with a `Retry-After` header for a request from `client` refused for
+/// `wait`
+fn too_many_requests<B>(req: ServiceRequest, client: &str, wait: Duration) -> ServiceResponse<EitherBody<B>> {
+    let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+    log::warn!("Rate limited {} on {} for {}s", client, req.path(), retry_after);
+    let mut response = ApiError::new(
+        ErrorCode::RateLimited,
+        format!("Too many requests; retry in {} seconds", retry_after),
+    )
+    .with_details(serde_json::json!({ "retry_after_secs": retry_after }))
+    .error_response();
+    response.headers_mut().insert(header::RETRY_AFTER, retry_after.into());
+    req.into_response(response).map_into_right_body()
+}
+
+/// Refuse requests from IP addresses that keep sending missing or wrong
+/// keys. Runs before `authorize`, so every request is counted against its
+/// address and given back unless `authorize` or the route refuses it with
+/// 401 or 403.
+pub async fn limit_rejected_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(limits) = is_limited(&req).filter(|limits| limits.rejected.is_enabled()) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let client = peer_ip(&req);
+    if let Err(wait) = limits.rejected.check(&client) {
+        return Ok(too_many_requests(req, &client, wait));
+    }
+    let response = next.call(req).await?;
+    if !matches!(response.status(), StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        limits.rejected.refund(&client);
+    }
+    Ok(response.map_into_left_body())
+}
+
+/// Refuse requests over the caller's limit with 429 and a `Retry-After`
+/// header. Runs after `authorize` so requests are counted per API key.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(limits) = is_limited(&req) else {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    };
+
+    let client = match req.extensions().get::<Caller>() {
+        Some(caller) => format!("key:{}", caller.name),
+        None => peer_ip(&req),
+    };
+    if let Err(wait) = limits.check(&client, routed_path(&req)) {
+        return Ok(too_many_requests(req, &client, wait));
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
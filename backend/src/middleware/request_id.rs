@@ -0,0 +1,80 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use std::future::Future;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest request id accepted from a client; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if any; set for everything a handler
+/// does in its own task
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().filter(|id| !id.is_empty())
+}
+
+/// Run `future` as part of request `id`, for work a handler spawns
+pub fn with_request_id<F: Future>(id: Option<String>, future: F) -> impl Future<Output = F::Output> {
+    REQUEST_ID.scope(id.unwrap_or_default(), future)
+}
+
+/// The client's id if it's short and printable, so it can't break log
+/// lines, or a new one
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+        })
+        .map_or_else(|| uuid::Uuid::new_v4().to_string(), str::to_string)
+}
+
+/// Tag each request with an `X-Request-Id`, kept from the client or
+/// generated, that log lines and error bodies written while handling it
+/// carry and that's echoed in the response
+pub async fn assign_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = request_id(&req);
+    let header = HeaderValue::from_str(&id).ok();
+    let mut response = REQUEST_ID.scope(id, next.call(req)).await?;
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[tokio::test]
+    async fn test_request_ids() {
+        let req = TestRequest::default()
+            .insert_header(("X-Request-Id", "upload-42"))
+            .to_srv_request();
+        assert_eq!(request_id(&req), "upload-42");
+
+        let req = TestRequest::default()
+            .insert_header(("X-Request-Id", "two words"))
+            .to_srv_request();
+        assert_eq!(request_id(&req).len(), 36);
+
+        assert_eq!(current_request_id(), None);
+        let id = with_request_id(Some("abc".to_string()), async { current_request_id() }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+}
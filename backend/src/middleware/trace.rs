@@ -0,0 +1,66 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::HeaderMap;
+use actix_web::middleware::Next;
+use actix_web::Error;
+use opentelemetry::propagation::Extractor;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Reads trace context from request headers
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// Handle each request in a server span named after its method and route,
+/// under the caller's trace when it sent a `traceparent` header. Spans
+/// recorded while handling it, such as extraction, embedding, retrieval,
+/// reranking, and generation, become its children.
+pub async fn trace_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let method = req.method().to_string();
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", method, req.path()),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %method,
+        http.route = Empty,
+        http.response.status_code = Empty,
+        request_id = %super::current_request_id().unwrap_or_default(),
+    );
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+
+    let response = next.call(req).instrument(span.clone()).await;
+    let status = match &response {
+        Ok(response) => {
+            // Routes rather than paths, so requests for different
+            // documents share a span name
+            if let Some(route) = response.request().match_pattern() {
+                span.record("otel.name", format!("{} {}", method, route));
+                span.record("http.route", route);
+            }
+            response.status()
+        }
+        Err(e) => e.as_response_error().status_code(),
+    };
+    span.record("http.response.status_code", status.as_u16());
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+    response
+}
@@ -0,0 +1,165 @@
+use crate::config::AppConfig;
+use crate::handlers::upload::UploadLimits;
+use crate::middleware::RequestLimits;
+use crate::services::{DocumentProcessor, LLMHandler};
+use actix_web::web;
+use serde::Serialize;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Providers whose default model can change on reload
+const LLM_PROVIDERS: &[&str] = &["groq", "ollama", "openai", "azure", "gemini"];
+
+/// Settings a reload applied, and edited ones that need a restart
+#[derive(Debug, Default, Serialize)]
+pub struct ReloadReport {
+    pub changed: Vec<&'static str>,
+    pub requires_restart: Vec<&'static str>,
+}
+
+/// Applies edits to the environment or `.env` to the running server:
+/// chunking defaults, default LLM models, request and upload limits, and
+/// CORS origins. The store, caches, sessions, and usage are kept.
+pub struct ConfigReloader {
+    config: Mutex<AppConfig>,
+    /// Shared with the providers built on reload
+    http_client: reqwest::Client,
+    processor: web::Data<Mutex<DocumentProcessor>>,
+    llm_handler: Option<web::Data<LLMHandler>>,
+    request_limits: web::Data<RequestLimits>,
+    upload_limits: web::Data<RwLock<UploadLimits>>,
+    cors_origins: Arc<RwLock<Vec<String>>>,
+}
+
+impl ConfigReloader {
+    /// `config` is what the server started with
+    pub fn new(
+        config: AppConfig,
+        http_client: reqwest::Client,
+        processor: web::Data<Mutex<DocumentProcessor>>,
+        llm_handler: Option<web::Data<LLMHandler>>,
+        request_limits: web::Data<RequestLimits>,
+        upload_limits: web::Data<RwLock<UploadLimits>>,
+        cors_origins: Arc<RwLock<Vec<String>>>,
+    ) -> Self {
+        ConfigReloader {
+            config: Mutex::new(config),
+            http_client,
+            processor,
+            llm_handler,
+            request_limits,
+            upload_limits,
+            cors_origins,
+        }
+    }
+
+    /// Read the config again and apply what changed since the last reload
+    pub fn reload(&self) -> anyhow::Result<ReloadReport> {
+        let new = AppConfig::reload()?;
+        let mut current = self.config.lock().unwrap();
+        let mut report = ReloadReport::default();
+
+        if (new.default_chunk_size, new.default_chunk_overlap, new.parent_chunk_size, &new.chunking_profiles)
+            != (
+                current.default_chunk_size,
+                current.default_chunk_overlap,
+                current.parent_chunk_size,
+                &current.chunking_profiles,
+            )
+        {
+            *self.processor.lock().unwrap() = crate::document_processor(&new);
+            report.changed.push("chunking");
+        }
+
+        if new.ingest_workers != current.ingest_workers {
+            let mut processor = self.processor.lock().unwrap();
+            *processor = processor.clone().with_workers(new.ingest_workers);
+            report.changed.push("ingest_workers");
+        }
+
+        if let Some(handler) = &self.llm_handler {
+            let models = |config: &AppConfig| LLM_PROVIDERS.iter().map(|p| config.model_for(p)).collect::<Vec<_>>();
+            if new.llm_provider != handler.default_provider() {
+                report.requires_restart.push("LLM_PROVIDER");
+            } else if models(&new) != models(&current) {
+                handler.replace_providers(crate::build_llm_providers(&new, &self.http_client)?)?;
+                report.changed.push("llm_models");
+            }
+        }
+
+        if (new.rate_limit_per_minute, new.llm_rate_limit_per_minute)
+            != (current.rate_limit_per_minute, current.llm_rate_limit_per_minute)
+        {
+            self.request_limits
+                .set_per_minute(new.rate_limit_per_minute, new.llm_rate_limit_per_minute);
+            report.changed.push("rate_limits");
+        }
+
+        if (new.max_upload_size_mb, &new.upload_size_limits) != (current.max_upload_size_mb, &current.upload_size_limits) {
+            *self.upload_limits.write().unwrap() = UploadLimits::new(new.max_upload_size_mb, &new.upload_size_limits);
+            report.changed.push("upload_limits");
+        }
+
+        if new.cors_allowed_origins != current.cors_allowed_origins {
+            *self.cors_origins.write().unwrap() = new.cors_allowed_origins.clone();
+            report.changed.push("cors_origins");
+        }
+
+        let needs_restart = [
+            ("SERVER_HOST", new.server_host != current.server_host),
+            ("SERVER_PORT", new.server_port != current.server_port),
+            ("VECTOR_STORE_PATH", new.vector_store_path != current.vector_store_path),
+            ("STORE_COMPRESSION", new.store_compression != current.store_compression),
+            ("LLM_CONNECT_TIMEOUT_SECS", new.llm_connect_timeout_secs != current.llm_connect_timeout_secs),
+            ("HTTP_POOL_MAX_IDLE_PER_HOST", new.http_pool_max_idle_per_host != current.http_pool_max_idle_per_host),
+            ("HTTP_POOL_IDLE_TIMEOUT_SECS", new.http_pool_idle_timeout_secs != current.http_pool_idle_timeout_secs),
+            ("HTTP_TCP_KEEPALIVE_SECS", new.http_tcp_keepalive_secs != current.http_tcp_keepalive_secs),
+            ("HTTP2", new.http2 != current.http2),
+            ("HTTP_PROXY_URL", new.http_proxy_url != current.http_proxy_url),
+            ("CLEANUP_INTERVAL_SECS", new.cleanup_interval_secs != current.cleanup_interval_secs),
+            ("CLEANUP_MAX_AGE_DAYS", new.cleanup_max_age_days != current.cleanup_max_age_days),
+            ("COMPACTION_INTERVAL_SECS", new.compaction_interval_secs != current.compaction_interval_secs),
+            ("SNAPSHOT_INTERVAL_SECS", new.snapshot_interval_secs != current.snapshot_interval_secs),
+            ("SNAPSHOT_DIR", new.snapshot_dir != current.snapshot_dir),
+            ("SNAPSHOT_KEEP", new.snapshot_keep != current.snapshot_keep),
+            ("CACHE_PRUNE_INTERVAL_SECS", new.cache_prune_interval_secs != current.cache_prune_interval_secs),
+            ("RETENTION_INTERVAL_SECS", new.retention_interval_secs != current.retention_interval_secs),
+            ("RETENTION_BY_TAG", new.retention_by_tag != current.retention_by_tag),
+            ("EMBEDDING_MODEL", new.embedding_model != current.embedding_model),
+            ("UPLOAD_DIR", new.upload_dir != current.upload_dir),
+            ("AUDIT_LOG_FILE", new.audit_log_file != current.audit_log_file),
+            ("CACHE_BACKEND", new.cache_backend != current.cache_backend),
+            ("CACHE_DIR", new.cache_dir != current.cache_dir),
+            ("REDIS_URL", new.redis_url != current.redis_url),
+            ("LLM_FAILURE_CACHE_SECS", new.llm_failure_cache_secs != current.llm_failure_cache_secs),
+            ("EMBEDDING_CACHE_SIZE", new.embedding_cache_size != current.embedding_cache_size),
+            ("EMBEDDING_CACHE_TTL_SECS", new.embedding_cache_ttl_secs != current.embedding_cache_ttl_secs),
+            ("RESPONSE_CACHE_SIZE", new.response_cache_size != current.response_cache_size),
+            ("RESPONSE_CACHE_TTL_SECS", new.response_cache_ttl_secs != current.response_cache_ttl_secs),
+            ("SEMANTIC_CACHE_THRESHOLD", new.semantic_cache_threshold != current.semantic_cache_threshold),
+            ("SEMANTIC_CACHE_SIZE", new.semantic_cache_size != current.semantic_cache_size),
+            ("RESPONSE_CACHE_FILE", new.response_cache_file != current.response_cache_file),
+            ("CACHE_SAVE_INTERVAL_SECS", new.cache_save_interval_secs != current.cache_save_interval_secs),
+            ("WARMUP_QUERIES_FILE", new.warmup_queries_file != current.warmup_queries_file),
+            ("WARMUP_GENERATE", new.warmup_generate != current.warmup_generate),
+            ("WARMUP_INTERVAL_SECS", new.warmup_interval_secs != current.warmup_interval_secs),
+            ("ANALYTICS_ENABLED", new.analytics_enabled != current.analytics_enabled),
+            ("ANALYTICS_FILE", new.analytics_file != current.analytics_file),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", new.otlp_endpoint != current.otlp_endpoint),
+            ("OTEL_SERVICE_NAME", new.otel_service_name != current.otel_service_name),
+            ("MAX_JSON_PAYLOAD_KB", new.max_json_payload_kb != current.max_json_payload_kb),
+            ("CORS_ALLOWED_METHODS", new.cors_allowed_methods != current.cors_allowed_methods),
+            ("CORS_ALLOWED_HEADERS", new.cors_allowed_headers != current.cors_allowed_headers),
+        ];
+        report
+            .requires_restart
+            .extend(needs_restart.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name));
+
+        log::info!(
+            "Reloaded configuration; changed: {:?}, needs a restart: {:?}",
+            report.changed,
+            report.requires_restart
+        );
+        *current = new;
+        Ok(report)
+    }
+}
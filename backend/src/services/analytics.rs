@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A document a response pointed the user to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentRef {
+    pub file_path: String,
+    pub file_name: String,
+}
+
+/// Each document in `documents`, given as (path, name), once
+pub fn distinct_documents<'a>(documents: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<DocumentRef> {
+    let mut distinct: Vec<DocumentRef> = Vec::new();
+    for (file_path, file_name) in documents {
+        if !distinct.iter().any(|d| d.file_path == file_path) {
+            distinct.push(DocumentRef {
+                file_path: file_path.to_string(),
+                file_name: file_name.to_string(),
+            });
+        }
+    }
+    distinct
+}
+
+/// Documents an answer's `citations` point to
+pub fn cited_documents(answer: &serde_json::Value) -> Vec<DocumentRef> {
+    let citations = answer["citations"].as_array().map(Vec::as_slice).unwrap_or_default();
+    distinct_documents(
+        citations
+            .iter()
+            .filter_map(|citation| Some((citation["file_path"].as_str()?, citation["file_name"].as_str()?))),
+    )
+}
+
+/// One search or answered query
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryEvent {
+    pub timestamp: DateTime<Utc>,
+    /// "search", "query", or "chat"
+    pub endpoint: String,
+    pub query: String,
+    /// Chunks retrieved
+    pub results: usize,
+    /// Documents in the search results, or cited by the answer
+    pub documents: Vec<DocumentRef>,
+    /// API key name of who asked
+    pub user: Option<String>,
+}
+
+impl QueryEvent {
+    pub fn new(endpoint: &str, query: &str, results: usize, documents: Vec<DocumentRef>, user: Option<&str>) -> Self {
+        QueryEvent {
+            timestamp: Utc::now(),
+            endpoint: endpoint.to_string(),
+            query: query.to_string(),
+            results,
+            documents,
+            user: user.map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryCount {
+    /// The query as first asked; differently cased or spaced repeats
+    /// count as the same query
+    pub query: String,
+    pub count: usize,
+    pub average_results: f64,
+    pub last_asked: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DocumentCount {
+    pub file_path: String,
+    pub file_name: String,
+    pub references: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyticsSummary {
+    pub days: i64,
+    pub total_queries: usize,
+    pub unique_queries: usize,
+    pub zero_result_rate: f64,
+    pub top_queries: Vec<QueryCount>,
+    /// Queries nothing was found for, most asked first: gaps in the
+    /// knowledge base
+    pub zero_result_queries: Vec<QueryCount>,
+    pub most_referenced_documents: Vec<DocumentCount>,
+}
+
+/// Opt-in JSON Lines log of what users search and ask, and which documents
+/// answer them, for curating the knowledge base
+pub struct QueryAnalytics {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl QueryAnalytics {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open analytics log {}", path.display()))?;
+        Ok(QueryAnalytics {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `event`; failures are logged rather than failing the query
+    pub fn record(&self, event: QueryEvent) {
+        let written = serde_json::to_string(&event).map_err(anyhow::Error::from).and_then(|mut line| {
+            line.push('\n');
+            self.file.lock().unwrap().write_all(line.as_bytes())?;
+            Ok(())
+        });
+        if let Err(e) = written {
+            log::warn!("Failed to record query analytics: {}", e);
+        }
+    }
+
+    /// Queries and documents of the last `days` days, the `limit` most
+    /// frequent of each
+    pub fn summary(&self, days: i64, limit: usize) -> Result<AnalyticsSummary> {
+        let file = File::open(&self.path)
+            .with_context(|| format!("Failed to read analytics log {}", self.path.display()))?;
+        let since = Utc::now() - Duration::days(days);
+        let mut events = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(event) = serde_json::from_str::<QueryEvent>(&line?) {
+                if event.timestamp >= since {
+                    events.push(event);
+                }
+            }
+        }
+        Ok(summarize(&events, days, limit))
+    }
+}
+
+/// Queries differing only in case and spacing are the same query
+fn normalize(query: &str) -> String {
+    query.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+fn summarize(events: &[QueryEvent], days: i64, limit: usize) -> AnalyticsSummary {
+    let mut queries: HashMap<String, (QueryCount, usize)> = HashMap::new();
+    let mut zero_results: HashMap<String, QueryCount> = HashMap::new();
+    let mut documents: HashMap<&str, DocumentCount> = HashMap::new();
+
+    for event in events {
+        let key = normalize(&event.query);
+        let (query, total_results) = queries.entry(key.clone()).or_insert_with(|| {
+            (
+                QueryCount {
+                    query: event.query.clone(),
+                    count: 0,
+                    average_results: 0.0,
+                    last_asked: event.timestamp,
+                },
+                0,
+            )
+        });
+        query.count += 1;
+        query.last_asked = query.last_asked.max(event.timestamp);
+        *total_results += event.results;
+
+        if event.results == 0 {
+            let query = zero_results.entry(key).or_insert_with(|| QueryCount {
+                query: event.query.clone(),
+                count: 0,
+                average_results: 0.0,
+                last_asked: event.timestamp,
+            });
+            query.count += 1;
+            query.last_asked = query.last_asked.max(event.timestamp);
+        }
+
+        for document in &event.documents {
+            documents
+                .entry(&document.file_path)
+                .or_insert_with(|| DocumentCount {
+                    file_path: document.file_path.clone(),
+                    file_name: document.file_name.clone(),
+                    references: 0,
+                })
+                .references += 1;
+        }
+    }
+
+    let unique_queries = queries.len();
+    let mut top_queries: Vec<QueryCount> = queries
+        .into_values()
+        .map(|(mut query, total_results)| {
+            query.average_results = total_results as f64 / query.count as f64;
+            query
+        })
+        .collect();
+    let mut zero_result_queries: Vec<QueryCount> = zero_results.into_values().collect();
+    for counts in [&mut top_queries, &mut zero_result_queries] {
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_asked.cmp(&a.last_asked)));
+        counts.truncate(limit);
+    }
+    let mut most_referenced_documents: Vec<DocumentCount> = documents.into_values().collect();
+    most_referenced_documents.sort_by(|a, b| b.references.cmp(&a.references).then(a.file_path.cmp(&b.file_path)));
+    most_referenced_documents.truncate(limit);
+
+    let zero_result_count = events.iter().filter(|e| e.results == 0).count();
+    AnalyticsSummary {
+        days,
+        total_queries: events.len(),
+        unique_queries,
+        zero_result_rate: if events.is_empty() {
+            0.0
+        } else {
+            zero_result_count as f64 / events.len() as f64
+        },
+        top_queries,
+        zero_result_queries,
+        most_referenced_documents,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(name: &str) -> DocumentRef {
+        DocumentRef {
+            file_path: format!("uploads/{}", name),
+            file_name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_summary_counts_queries_and_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let analytics = QueryAnalytics::open(&dir.path().join("analytics.jsonl")).unwrap();
+        analytics.record(QueryEvent::new("query", "Refund policy?", 4, vec![document("policy.pdf")], Some("alice")));
+        analytics.record(QueryEvent::new("search", "refund  policy?", 2, vec![document("policy.pdf")], None));
+        analytics.record(QueryEvent::new("search", "parking", 0, vec![], None));
+        analytics.record(QueryEvent::new(
+            "query",
+            "holidays",
+            3,
+            vec![document("handbook.pdf"), document("policy.pdf")],
+            None,
+        ));
+
+        let summary = analytics.summary(30, 10).unwrap();
+        assert_eq!(summary.total_queries, 4);
+        assert_eq!(summary.unique_queries, 3);
+        assert_eq!(summary.zero_result_rate, 0.25);
+        assert_eq!(summary.top_queries[0].query, "Refund policy?");
+        assert_eq!(summary.top_queries[0].count, 2);
+        assert_eq!(summary.top_queries[0].average_results, 3.0);
+        assert_eq!(summary.zero_result_queries.len(), 1);
+        assert_eq!(summary.zero_result_queries[0].query, "parking");
+        assert_eq!(summary.most_referenced_documents[0].file_name, "policy.pdf");
+        assert_eq!(summary.most_referenced_documents[0].references, 3);
+
+        assert_eq!(analytics.summary(30, 1).unwrap().most_referenced_documents.len(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Most entries returned by one page
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// Something sensitive that was done or attempted
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// What was done, e.g. "document.delete" or "config.reload"
+    pub action: String,
+    /// API key name of who did it; `None` for unauthenticated requests and
+    /// server-initiated changes
+    pub actor: Option<String>,
+    /// Document, template, or file acted on
+    pub target: Option<String>,
+    pub method: Option<String>,
+    pub path: Option<String>,
+    /// HTTP status of the response
+    pub status: Option<u16>,
+    pub success: bool,
+    pub client_ip: Option<String>,
+    pub request_id: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
+/// A recorded event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Increases by one per entry
+    pub id: u64,
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Which entries to list, newest first
+#[derive(Debug, Default, Deserialize)]
+pub struct AuditQuery {
+    /// Entries to skip
+    #[serde(default)]
+    pub offset: usize,
+    /// Entries per page; defaults to 50
+    pub limit: Option<usize>,
+    /// Only this action, or actions starting with it when it ends in '.'
+    pub action: Option<String>,
+    pub actor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditPage {
+    pub entries: Vec<AuditEntry>,
+    /// Entries matching the query across all pages
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+/// Append-only JSON Lines record of who did what and when. Entries are
+/// written and synced before the request that caused them completes, and
+/// never rewritten.
+pub struct AuditLog {
+    path: PathBuf,
+    /// The file opened for appending, and the id of the next entry
+    file: Mutex<(File, u64)>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open audit log {}", path.display()))?;
+        let next_id = read_entries(path)?.last().map_or(1, |entry| entry.id + 1);
+        Ok(AuditLog {
+            path: path.to_path_buf(),
+            file: Mutex::new((file, next_id)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append `event`; failures are logged rather than failing the
+    /// operation that was audited
+    pub fn record(&self, event: AuditEvent) {
+        let mut file = self.file.lock().unwrap();
+        let (file, next_id) = &mut *file;
+        let entry = AuditEntry {
+            id: *next_id,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            event,
+        };
+        let written = serde_json::to_string(&entry)
+            .map_err(anyhow::Error::from)
+            .and_then(|mut line| {
+                line.push('\n');
+                file.write_all(line.as_bytes())?;
+                file.sync_data()?;
+                Ok(())
+            });
+        match written {
+            Ok(()) => *next_id += 1,
+            Err(e) => log::error!("Failed to write audit entry for {}: {}", entry.event.action, e),
+        }
+    }
+
+    /// A page of the entries `query` matches, newest first
+    pub fn page(&self, query: &AuditQuery) -> Result<AuditPage> {
+        let limit = query.limit.unwrap_or(50).clamp(1, MAX_PAGE_SIZE);
+        let entries = {
+            // Not while an entry is half written
+            let _file = self.file.lock().unwrap();
+            read_entries(&self.path)?
+        };
+        let matching: Vec<AuditEntry> = entries
+            .into_iter()
+            .rev()
+            .filter(|entry| {
+                query.action.as_deref().is_none_or(|action| match action.strip_suffix('.') {
+                    Some(prefix) => entry.event.action.starts_with(prefix),
+                    None => entry.event.action == action,
+                })
+            })
+            .filter(|entry| query.actor.is_none() || entry.event.actor == query.actor)
+            .collect();
+        Ok(AuditPage {
+            total: matching.len(),
+            entries: matching.into_iter().skip(query.offset).take(limit).collect(),
+            offset: query.offset,
+            limit,
+        })
+    }
+}
+
+/// Every entry in the file, oldest first, skipping lines that aren't one
+fn read_entries(path: &Path) -> Result<Vec<AuditEntry>> {
+    let file = File::open(path).with_context(|| format!("Failed to read audit log {}", path.display()))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if !line.trim().is_empty() => log::warn!("Skipping unreadable audit entry: {}", e),
+            Err(_) => {}
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: &str, actor: &str) -> AuditEvent {
+        AuditEvent {
+            action: action.to_string(),
+            actor: Some(actor.to_string()),
+            success: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_entries_are_appended_and_paged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit").join("audit.jsonl");
+        let log = AuditLog::open(&path).unwrap();
+        log.record(event("document.upload", "alice"));
+        log.record(event("document.delete", "bob"));
+        log.record(event("store.clear", "admin"));
+
+        // Ids continue after a restart
+        let log = AuditLog::open(&path).unwrap();
+        log.record(event("document.delete", "alice"));
+
+        let page = log.page(&AuditQuery { limit: Some(2), ..Default::default() }).unwrap();
+        assert_eq!(page.total, 4);
+        assert_eq!(page.entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4, 3]);
+
+        let documents = log
+            .page(&AuditQuery { action: Some("document.".to_string()), ..Default::default() })
+            .unwrap();
+        assert_eq!(documents.total, 3);
+
+        let alice = log
+            .page(&AuditQuery {
+                action: Some("document.delete".to_string()),
+                actor: Some("alice".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(alice.entries.len(), 1);
+        assert_eq!(alice.entries[0].id, 4);
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Completed responses kept before the oldest are forgotten early
+const MAX_ENTRIES: usize = 1_000;
+
+/// Longest `Idempotency-Key` accepted
+pub const MAX_KEY_LEN: usize = 255;
+
+#[derive(Debug)]
+enum Entry {
+    InProgress(Instant),
+    Done(Instant, serde_json::Value),
+}
+
+impl Entry {
+    fn started(&self) -> Instant {
+        match self {
+            Entry::InProgress(at) | Entry::Done(at, _) => *at,
+        }
+    }
+}
+
+/// What to do with a request carrying an idempotency key
+#[derive(Debug, PartialEq)]
+pub enum Claim {
+    /// First time the key is seen; process the request, holding a
+    /// `ClaimedKey` to complete or release it
+    New,
+    /// The first request with the key hasn't finished yet
+    InProgress,
+    /// The key was used already; send this response again
+    Done(serde_json::Value),
+}
+
+/// Responses of requests sent with an `Idempotency-Key`, so retries of an
+/// upload that already succeeded return its result instead of indexing
+/// the document twice. Failed requests aren't remembered and may be
+/// retried with the same key.
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Keys are remembered for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyStore {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn claim(&self, key: &str) -> Claim {
+        self.claim_at(key, Instant::now())
+    }
+
+    fn claim_at(&self, key: &str, now: Instant) -> Claim {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| now.duration_since(entry.started()) < self.ttl);
+
+        match entries.get(key) {
+            Some(Entry::InProgress(_)) => return Claim::InProgress,
+            Some(Entry::Done(_, response)) => return Claim::Done(response.clone()),
+            None => {}
+        }
+
+        if entries.len() >= MAX_ENTRIES {
+            let oldest = entries
+                .iter()
+                .filter(|(_, entry)| matches!(entry, Entry::Done(..)))
+                .min_by_key(|(_, entry)| entry.started())
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key.to_string(), Entry::InProgress(now));
+        Claim::New
+    }
+
+    /// Remember the response to the request that claimed `key`
+    pub fn complete(&self, key: &str, response: serde_json::Value) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.get_mut(key) {
+            *entry = Entry::Done(entry.started(), response);
+        }
+    }
+
+    /// Forget `key` after its request failed, so it can be retried
+    pub fn release(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+/// A key claimed for a request, released when dropped unless completed,
+/// so a request cancelled by its client disconnecting can be retried
+/// instead of being refused until the key expires
+pub struct ClaimedKey<'a> {
+    store: &'a IdempotencyStore,
+    key: String,
+    completed: bool,
+}
+
+impl<'a> ClaimedKey<'a> {
+    pub fn new(store: &'a IdempotencyStore, key: String) -> Self {
+        ClaimedKey { store, key, completed: false }
+    }
+
+    /// Remember the response to the request that claimed the key
+    pub fn complete(mut self, response: serde_json::Value) {
+        self.store.complete(&self.key, response);
+        self.completed = true;
+    }
+}
+
+impl Drop for ClaimedKey<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.store.release(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_replay_until_they_expire() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert_eq!(store.claim_at("alice:upload-1", start), Claim::New);
+        assert_eq!(store.claim_at("alice:upload-1", start), Claim::InProgress);
+        store.complete("alice:upload-1", serde_json::json!({"success": true}));
+        assert_eq!(
+            store.claim_at("alice:upload-1", start + Duration::from_secs(30)),
+            Claim::Done(serde_json::json!({"success": true}))
+        );
+        assert_eq!(store.claim_at("alice:upload-1", start + Duration::from_secs(61)), Claim::New);
+
+        assert_eq!(store.claim_at("bob:upload-1", start), Claim::New);
+        store.release("bob:upload-1");
+        assert_eq!(store.claim_at("bob:upload-1", start), Claim::New);
+    }
+
+    #[test]
+    fn test_dropped_claims_are_released() {
+        let store = IdempotencyStore::new(Duration::from_secs(60));
+        assert_eq!(store.claim("alice:upload-1"), Claim::New);
+        drop(ClaimedKey::new(&store, "alice:upload-1".to_string()));
+        assert_eq!(store.claim("alice:upload-1"), Claim::New);
+
+        ClaimedKey::new(&store, "alice:upload-1".to_string()).complete(serde_json::json!({"success": true}));
+        assert_eq!(store.claim("alice:upload-1"), Claim::Done(serde_json::json!({"success": true})));
+    }
+}
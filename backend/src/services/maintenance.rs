@@ -0,0 +1,509 @@
+use crate::models::{document_id, DocumentLabels};
+use crate::services::vector_store::DocumentInfo;
+use crate::services::webhooks::{deleted_document, WebhookEvent, Webhooks};
+use crate::services::VectorStore;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use validator::Validate;
+
+/// Prefix of the snapshot files in the snapshot directory
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+
+/// Age past which `POST /api/admin/storage/cleanup` removes uploads when
+/// the request doesn't give one
+#[derive(Debug, Clone, Copy)]
+pub struct CleanupDefaults {
+    pub max_age_days: u64,
+}
+
+/// Which uploads `POST /api/admin/storage/cleanup` removes, from its
+/// query string
+#[derive(Debug, Clone, Default, Deserialize, Validate)]
+pub struct CleanupQuery {
+    /// Uploads last modified more than this many days ago; defaults to
+    /// `CLEANUP_MAX_AGE_DAYS`
+    #[validate(range(min = 1, max = 36500, message = "days must be between 1 and 36500"))]
+    pub days: Option<u64>,
+    /// Report what would be removed without removing anything
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Only files with these comma-separated extensions, e.g. `pdf,csv`
+    pub file_types: Option<String>,
+    /// Only uploads indexed in this collection
+    pub collection: Option<String>,
+    /// Only uploads indexed with this tag
+    pub tag: Option<String>,
+}
+
+impl CleanupQuery {
+    fn matches_type(&self, path: &Path) -> bool {
+        let Some(file_types) = &self.file_types else {
+            return true;
+        };
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+        file_types
+            .split(',')
+            .map(|t| t.trim().trim_start_matches('.').to_lowercase())
+            .any(|t| !t.is_empty() && t == extension)
+    }
+
+    /// Whether uploads indexed with `labels`, or not indexed when `None`,
+    /// pass the collection and tag filters
+    fn matches_labels(&self, labels: Option<&DocumentLabels>) -> bool {
+        if self.collection.is_none() && self.tag.is_none() {
+            return true;
+        }
+        let Some(labels) = labels else {
+            return false;
+        };
+        self.collection
+            .as_ref()
+            .is_none_or(|collection| labels.collection.as_ref().is_some_and(|c| c.eq_ignore_ascii_case(collection)))
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tag| labels.tags.iter().any(|t| t.eq_ignore_ascii_case(tag.trim())))
+    }
+}
+
+/// What `cleanup_uploads` removed, or would have on a dry run
+#[derive(Debug, Default, Serialize)]
+pub struct CleanupReport {
+    pub dry_run: bool,
+    pub deleted_files: usize,
+    pub freed_space_bytes: u64,
+    pub files: Vec<String>,
+    /// Ids of the documents indexed from the removed files
+    pub deleted_documents: Vec<String>,
+    pub older_than_days: u64,
+}
+
+/// Delete uploads last modified more than `query.days` (or `default_days`)
+/// ago that pass its filters, and the documents indexed from them,
+/// announcing each deletion and the cleanup to `webhooks`
+pub fn cleanup_uploads(
+    upload_dir: &Path,
+    query: &CleanupQuery,
+    default_days: u64,
+    vector_store: &Mutex<VectorStore>,
+    webhooks: &Webhooks,
+) -> Result<CleanupReport> {
+    let days = query.days.unwrap_or(default_days);
+    let mut report = CleanupReport {
+        dry_run: query.dry_run,
+        older_than_days: days,
+        ..Default::default()
+    };
+    let cutoff_time = days
+        .checked_mul(24 * 60 * 60)
+        .and_then(|secs| SystemTime::now().checked_sub(Duration::from_secs(secs)))
+        .ok_or_else(|| anyhow::anyhow!("Cannot clean up uploads older than {} days", days))?;
+
+    // Uploads are indexed under their path in the upload directory. The
+    // store is locked only to look them up and, at the end, to delete
+    // their documents in one go.
+    let indexed: HashMap<PathBuf, (String, DocumentLabels)> = {
+        let store = vector_store.lock().unwrap();
+        store
+            .document_paths(None)
+            .into_iter()
+            .filter(|file_path| Path::new(file_path).starts_with(upload_dir))
+            .filter_map(|file_path| {
+                let labels = store.document_info(file_path)?.labels.clone();
+                Some((PathBuf::from(file_path), (file_path.clone(), labels)))
+            })
+            .collect()
+    };
+    let mut documents = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(upload_dir) {
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file() || !metadata.modified().is_ok_and(|modified| modified < cutoff_time) {
+                continue;
+            }
+            let path = entry.path();
+            let document = indexed.get(&path);
+            if !query.matches_type(&path) || !query.matches_labels(document.map(|(_, labels)| labels)) {
+                continue;
+            }
+            if !query.dry_run {
+                if let Err(e) = fs::remove_file(&path) {
+                    log::warn!("Failed to delete old file {:?}: {}", entry.file_name(), e);
+                    continue;
+                }
+            }
+            report.deleted_files += 1;
+            report.freed_space_bytes += metadata.len();
+            report.files.push(entry.file_name().to_string_lossy().to_string());
+
+            if let Some((file_path, _)) = document {
+                documents.push(file_path.clone());
+            }
+        }
+    }
+
+    if query.dry_run {
+        report.deleted_documents = documents.iter().map(|file_path| document_id(file_path)).collect();
+        return Ok(report);
+    }
+    if !documents.is_empty() {
+        let deleted = vector_store.lock().unwrap().delete_documents(&documents);
+        match deleted {
+            Ok(deleted) => {
+                for file_path in deleted {
+                    log::info!("Removed document from vector store: {}", file_path);
+                    report.deleted_documents.push(document_id(&file_path));
+                    webhooks.notify(WebhookEvent::DocumentDeleted, deleted_document(&file_path, None));
+                }
+            }
+            Err(e) => log::warn!("Failed to delete documents from vector store: {}", e),
+        }
+    }
+    log::info!(
+        "Cleanup: deleted {} files and {} documents, freed {:.2} MB",
+        report.deleted_files,
+        report.deleted_documents.len(),
+        report.freed_space_bytes as f64 / (1024.0 * 1024.0)
+    );
+    webhooks.notify(
+        WebhookEvent::CleanupCompleted,
+        serde_json::json!({
+            "deleted_files": report.deleted_files,
+            "files": report.files,
+            "deleted_documents": report.deleted_documents,
+            "freed_space_bytes": report.freed_space_bytes,
+            "older_than_days": days
+        }),
+    );
+    Ok(report)
+}
+
+/// How long documents are kept: their own `retain_days`, else the longest
+/// period of any of their tags. Documents with neither are kept until
+/// deleted.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    by_tag: HashMap<String, u32>,
+}
+
+impl RetentionPolicy {
+    /// `by_tag` is `tag=days` pairs separated by commas, e.g.
+    /// `drafts=7,legal=3650`. Malformed entries are skipped.
+    pub fn new(by_tag: &str) -> Self {
+        let mut periods = HashMap::new();
+        for entry in by_tag.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let parsed = entry
+                .split_once('=')
+                .and_then(|(tag, days)| Some((tag.trim().to_lowercase(), days.trim().parse::<u32>().ok()?)))
+                .filter(|(tag, days)| !tag.is_empty() && *days > 0);
+            match parsed {
+                Some((tag, days)) => {
+                    periods.insert(tag, days);
+                }
+                None => log::warn!("Ignoring malformed retention period '{}', expected tag=days", entry),
+            }
+        }
+        RetentionPolicy { by_tag: periods }
+    }
+
+    pub fn retain_days(&self, labels: &DocumentLabels) -> Option<u32> {
+        labels
+            .retain_days
+            .or_else(|| labels.tags.iter().filter_map(|tag| self.by_tag.get(tag).copied()).max())
+    }
+
+    /// When the document at `file_path` is due for deletion. Documents
+    /// indexed before indexing times were recorded count from when their
+    /// file was last modified.
+    pub fn expires_at(&self, file_path: &str, info: &DocumentInfo) -> Option<DateTime<Utc>> {
+        let days = self.retain_days(&info.labels)?;
+        let indexed_at = match &info.indexed_at {
+            Some(indexed_at) => DateTime::parse_from_rfc3339(indexed_at).ok()?.with_timezone(&Utc),
+            None => fs::metadata(file_path).and_then(|m| m.modified()).ok()?.into(),
+        };
+        Some(indexed_at + chrono::Duration::days(days.into()))
+    }
+}
+
+/// A document removed by `purge_expired_documents`
+#[derive(Debug, Serialize)]
+pub struct ExpiredDocument {
+    pub document_id: String,
+    pub file_name: String,
+    pub retain_days: u32,
+    pub expired_at: DateTime<Utc>,
+}
+
+/// What `purge_expired_documents` removed
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub deleted_documents: usize,
+    pub deleted_files: usize,
+    pub freed_space_bytes: u64,
+    pub documents: Vec<ExpiredDocument>,
+    /// Ids of expired documents that couldn't be deleted; they're tried
+    /// again on the next run
+    pub failed_documents: Vec<String>,
+}
+
+/// Delete documents past their retention period, and their files when
+/// those were uploaded to `upload_dir`, announcing each deletion to
+/// `webhooks`
+pub fn purge_expired_documents(
+    policy: &RetentionPolicy,
+    upload_dir: &Path,
+    vector_store: &Mutex<VectorStore>,
+    webhooks: &Webhooks,
+) -> Result<RetentionReport> {
+    let now = Utc::now();
+    let mut report = RetentionReport::default();
+    let mut store = vector_store.lock().unwrap();
+    let expired: Vec<(String, ExpiredDocument)> = store
+        .document_paths(None)
+        .into_iter()
+        .filter_map(|file_path| {
+            let info = store.document_info(file_path)?;
+            let expired_at = policy.expires_at(file_path, info).filter(|at| *at <= now)?;
+            let document = ExpiredDocument {
+                document_id: document_id(file_path),
+                file_name: info.file_name.clone(),
+                retain_days: policy.retain_days(&info.labels)?,
+                expired_at,
+            };
+            Some((file_path.clone(), document))
+        })
+        .collect();
+
+    for (file_path, document) in expired {
+        match store.delete_document(&file_path) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                log::warn!("Failed to delete expired document {}: {}", file_path, e);
+                report.failed_documents.push(document.document_id);
+                continue;
+            }
+        }
+        log::info!("Deleted {} after its {} day retention period", file_path, document.retain_days);
+        webhooks.notify(WebhookEvent::DocumentDeleted, deleted_document(&file_path, None));
+        report.deleted_documents += 1;
+        report.documents.push(document);
+
+        let path = Path::new(&file_path);
+        if !path.starts_with(upload_dir) {
+            continue;
+        }
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        match fs::remove_file(path) {
+            Ok(()) => {
+                report.deleted_files += 1;
+                report.freed_space_bytes += size;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to delete expired upload {}: {}", file_path, e),
+        }
+    }
+    Ok(report)
+}
+
+/// Write every document to a timestamped file in `dir`, in the format of
+/// `GET /api/admin/export` so it can be sent back to `import`, then remove
+/// all but the newest `keep` snapshots
+pub fn snapshot_store(vector_store: &Mutex<VectorStore>, dir: &Path, keep: usize) -> Result<PathBuf> {
+    let documents = vector_store.lock().unwrap().export_documents();
+    let now = chrono::Utc::now();
+    let snapshot = serde_json::json!({
+        "exported_at": now.to_rfc3339(),
+        "count": documents.len(),
+        "documents": documents
+    });
+
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}{}.json", SNAPSHOT_PREFIX, now.format("%Y%m%dT%H%M%S%.3fZ")));
+    let partial = path.with_extension("json.tmp");
+    fs::write(&partial, serde_json::to_vec(&snapshot)?)
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    fs::rename(&partial, &path)?;
+
+    // Timestamped names sort oldest first
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "json")
+                && path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(SNAPSHOT_PREFIX))
+        })
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep.max(1));
+    for old in &snapshots[..excess] {
+        if let Err(e) = fs::remove_file(old) {
+            log::warn!("Failed to remove old snapshot {}: {}", old.display(), e);
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_keep_the_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Mutex::new(VectorStore::new(dir.path().join("store").to_str().unwrap(), "all-MiniLM-L6-v2").unwrap());
+        let snapshots = dir.path().join("snapshots");
+        fs::create_dir_all(&snapshots).unwrap();
+        fs::write(snapshots.join("notes.json"), "{}").unwrap();
+
+        let written: Vec<PathBuf> = (0..3)
+            .map(|_| {
+                std::thread::sleep(Duration::from_millis(5));
+                snapshot_store(&store, &snapshots, 2).unwrap()
+            })
+            .collect();
+
+        assert!(!written[0].exists());
+        assert!(written[1].exists() && written[2].exists());
+        assert!(snapshots.join("notes.json").exists());
+        let snapshot: serde_json::Value = serde_json::from_slice(&fs::read(&written[2]).unwrap()).unwrap();
+        assert_eq!(snapshot["count"], 0);
+    }
+
+    #[test]
+    fn test_cleanup_removes_filtered_uploads_and_their_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = dir.path().join("uploads");
+        fs::create_dir_all(&uploads).unwrap();
+        let store = Mutex::new(VectorStore::new(dir.path().join("store").to_str().unwrap(), "all-MiniLM-L6-v2").unwrap());
+        let old = SystemTime::now() - Duration::from_secs(40 * 24 * 60 * 60);
+        let upload = |name: &str, modified: SystemTime| {
+            let path = uploads.join(name);
+            fs::File::create(&path).unwrap().set_modified(modified).unwrap();
+            path
+        };
+        let handbook = upload("upload_handbook.txt", old);
+        let draft = upload("upload_draft.pdf", old);
+        let orphan = upload("upload_orphan.txt", old);
+        let recent = upload("upload_recent.txt", SystemTime::now());
+        let indexed = |path: &Path, labels: serde_json::Value| {
+            let mut document = serde_json::json!({
+                "file_path": path,
+                "file_name": path.file_name().unwrap().to_str().unwrap(),
+                "file_type": ".txt",
+                "num_chunks": 1,
+                "file_size": 0,
+                "chunks": [{ "text": "onboarding checklist", "size": 20, "chunk_id": 0 }]
+            });
+            document.as_object_mut().unwrap().extend(labels.as_object().unwrap().clone());
+            serde_json::from_value(document).unwrap()
+        };
+        store
+            .lock()
+            .unwrap()
+            .import_documents(vec![
+                indexed(&handbook, serde_json::json!({ "collection": "HR" })),
+                indexed(&draft, serde_json::json!({ "tags": ["drafts"] })),
+                indexed(&recent, serde_json::json!({ "collection": "hr" })),
+            ])
+            .unwrap();
+        let webhooks = Webhooks::new(Vec::new(), None);
+
+        let dry_run = CleanupQuery {
+            dry_run: true,
+            tag: Some("Drafts".to_string()),
+            ..Default::default()
+        };
+        let report = cleanup_uploads(&uploads, &dry_run, 30, &store, &webhooks).unwrap();
+        assert_eq!(report.files, ["upload_draft.pdf"]);
+        assert_eq!(report.deleted_documents, [document_id(&draft.to_string_lossy())]);
+        assert!(draft.exists() && store.lock().unwrap().document_info(&draft.to_string_lossy()).is_some());
+
+        let by_type = CleanupQuery {
+            file_types: Some(".TXT".to_string()),
+            ..Default::default()
+        };
+        let mut report = cleanup_uploads(&uploads, &by_type, 30, &store, &webhooks).unwrap();
+        report.files.sort();
+        assert_eq!(report.files, ["upload_handbook.txt", "upload_orphan.txt"]);
+        assert_eq!(report.deleted_documents, [document_id(&handbook.to_string_lossy())]);
+        assert!(!handbook.exists() && !orphan.exists() && draft.exists() && recent.exists());
+        assert!(cleanup_uploads(&uploads, &CleanupQuery::default(), u64::MAX, &store, &webhooks).is_err());
+        let too_long = CleanupQuery {
+            days: Some(36501),
+            ..Default::default()
+        };
+        assert!(too_long.validate().is_err());
+
+        let store = store.lock().unwrap();
+        assert!(store.document_info(&handbook.to_string_lossy()).is_none());
+        assert_eq!(store.document_paths(None).len(), 2);
+    }
+
+    #[test]
+    fn test_expired_documents_are_purged_with_their_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let uploads = dir.path().join("uploads");
+        fs::create_dir_all(&uploads).unwrap();
+        let store = Mutex::new(VectorStore::new(dir.path().join("store").to_str().unwrap(), "all-MiniLM-L6-v2").unwrap());
+        let long_ago = (Utc::now() - chrono::Duration::days(10)).to_rfc3339();
+        let document = |file_path: &Path, labels: serde_json::Value, indexed_at: &str| {
+            fs::write(file_path, "refund policy text").unwrap();
+            let mut document = serde_json::json!({
+                "file_path": file_path,
+                "file_name": "policy.txt",
+                "file_type": ".txt",
+                "num_chunks": 1,
+                "file_size": 18,
+                "indexed_at": indexed_at,
+                "chunks": [{ "text": "refund policy text", "size": 18, "chunk_id": 0 }]
+            });
+            document.as_object_mut().unwrap().extend(labels.as_object().unwrap().clone());
+            serde_json::from_value(document).unwrap()
+        };
+        let own_period = uploads.join("upload_own.txt");
+        let by_tag = uploads.join("upload_tagged.txt");
+        let recent = uploads.join("upload_recent.txt");
+        let untagged = uploads.join("upload_kept.txt");
+        let outside = dir.path().join("ingested.txt");
+        store
+            .lock()
+            .unwrap()
+            .import_documents(vec![
+                document(&own_period, serde_json::json!({ "retain_days": 7, "tags": ["legal"] }), &long_ago),
+                document(&by_tag, serde_json::json!({ "tags": ["drafts", "legal"] }), &long_ago),
+                document(&recent, serde_json::json!({ "tags": ["drafts"] }), &Utc::now().to_rfc3339()),
+                document(&untagged, serde_json::json!({}), &long_ago),
+                document(&outside, serde_json::json!({ "retain_days": 1 }), &long_ago),
+            ])
+            .unwrap();
+
+        let policy = RetentionPolicy::new("drafts=3, Legal=30, broken");
+        let report = purge_expired_documents(&policy, &uploads, &store, &Webhooks::new(Vec::new(), None)).unwrap();
+
+        assert_eq!((report.deleted_documents, report.deleted_files), (2, 1));
+        assert_eq!(report.freed_space_bytes, 18);
+        let mut retained: Vec<u32> = report.documents.iter().map(|d| d.retain_days).collect();
+        retained.sort();
+        assert_eq!(retained, [1, 7]);
+        assert!(!own_period.exists() && outside.exists());
+        let store = store.lock().unwrap();
+        let mut remaining = store.document_paths(None);
+        remaining.sort();
+        let mut expected: Vec<String> =
+            [&by_tag, &recent, &untagged].iter().map(|p| p.to_string_lossy().to_string()).collect();
+        expected.sort();
+        assert_eq!(remaining, expected.iter().collect::<Vec<_>>());
+    }
+}
@@ -1,8 +1,10 @@
-pub mod cache_manager;
-pub mod document_processor;
-pub mod llm_handler;
-pub mod vector_store;
+//! The RAG pipeline from `knora_core`, plus services only the server needs
+pub use knora_core::services::*;
 
-pub use document_processor::DocumentProcessor;
-pub use llm_handler::LLMHandler;
-pub use vector_store::VectorStore;
+pub mod analytics;
+pub mod audit;
+pub mod idempotency;
+pub mod maintenance;
+pub mod scheduler;
+pub mod webhooks;
+pub mod workspaces;
@@ -0,0 +1,149 @@
+use actix_web::web;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+/// Work done by a job; the value reports what it did
+type Task = Arc<dyn Fn() -> Result<serde_json::Value> + Send + Sync>;
+
+/// A job as shown to admins: its schedule and how its last run went
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub every_secs: u64,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_result: Option<serde_json::Value>,
+    pub last_error: Option<String>,
+}
+
+struct Job {
+    period: Duration,
+    task: Task,
+    status: Mutex<JobStatus>,
+}
+
+/// Maintenance jobs run in the background, each on its own interval. A
+/// job's runs never overlap: the next waits for the last to finish, and
+/// intervals missed meanwhile are skipped rather than run back to back.
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    /// Run `task` every `period`, first one period after `spawn`
+    pub fn add(
+        &mut self,
+        name: &'static str,
+        period: Duration,
+        task: impl Fn() -> Result<serde_json::Value> + Send + Sync + 'static,
+    ) {
+        self.jobs.push(Job {
+            period,
+            task: Arc::new(task),
+            status: Mutex::new(JobStatus {
+                name,
+                every_secs: period.as_secs(),
+                runs: 0,
+                failures: 0,
+                last_run: None,
+                last_result: None,
+                last_error: None,
+            }),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.iter().map(|job| job.status.lock().unwrap().clone()).collect()
+    }
+
+    /// Run the job named `name` now, off the async workers, and record how
+    /// it went
+    pub async fn run(&self, name: &str) -> Result<JobStatus> {
+        let job = self
+            .jobs
+            .iter()
+            .find(|job| job.status.lock().unwrap().name == name)
+            .ok_or_else(|| anyhow!("No scheduled job named {}", name))?;
+        Ok(Self::run_job(job).await)
+    }
+
+    async fn run_job(job: &Job) -> JobStatus {
+        let task = job.task.clone();
+        let started = Utc::now();
+        let outcome = web::block(move || task()).await.unwrap_or_else(|e| Err(anyhow!(e.to_string())));
+
+        let mut status = job.status.lock().unwrap();
+        status.runs += 1;
+        status.last_run = Some(started);
+        match outcome {
+            Ok(result) => {
+                log::info!("Scheduled job {} finished: {}", status.name, result);
+                status.last_result = Some(result);
+                status.last_error = None;
+            }
+            Err(e) => {
+                log::warn!("Scheduled job {} failed: {:#}", status.name, e);
+                status.failures += 1;
+                status.last_error = Some(format!("{:#}", e));
+            }
+        }
+        status.clone()
+    }
+
+    /// Start running every job on its interval
+    pub fn spawn(scheduler: web::Data<Scheduler>) {
+        for index in 0..scheduler.jobs.len() {
+            let scheduler = scheduler.clone();
+            actix_web::rt::spawn(async move {
+                let job = &scheduler.jobs[index];
+                let mut interval = actix_web::rt::time::interval(job.period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    Self::run_job(job).await;
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[actix_web::test]
+    async fn test_runs_record_results_and_failures() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::default();
+        let counted = calls.clone();
+        scheduler.add("count", Duration::from_secs(60), move || {
+            Ok(serde_json::json!({ "calls": counted.fetch_add(1, Ordering::SeqCst) + 1 }))
+        });
+        scheduler.add("fail", Duration::from_secs(3600), || Err(anyhow!("disk full")));
+
+        let status = scheduler.run("count").await.unwrap();
+        assert_eq!((status.runs, status.failures), (1, 0));
+        assert_eq!(status.last_result, Some(serde_json::json!({ "calls": 1 })));
+
+        let status = scheduler.run("fail").await.unwrap();
+        assert_eq!((status.runs, status.failures), (1, 1));
+        assert_eq!(status.last_error.as_deref(), Some("disk full"));
+        assert!(scheduler.run("missing").await.is_err());
+
+        let every: Vec<u64> = scheduler.statuses().iter().map(|s| s.every_secs).collect();
+        assert_eq!(every, [60, 3600]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
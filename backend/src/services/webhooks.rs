@@ -0,0 +1,180 @@
+use crate::models::{document_id, ProcessedDocument};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Attempts made to deliver one event to one URL
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Knowledge-base changes other systems can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum WebhookEvent {
+    /// A document was uploaded, added, or re-chunked into the store
+    #[serde(rename = "document.indexed")]
+    DocumentIndexed,
+    #[serde(rename = "document.deleted")]
+    DocumentDeleted,
+    /// An upload or re-chunk couldn't be processed or indexed
+    #[serde(rename = "ingestion.failed")]
+    IngestionFailed,
+    /// Old uploads were removed from disk and the store
+    #[serde(rename = "cleanup.completed")]
+    CleanupCompleted,
+}
+
+/// Body POSTed to every webhook URL
+#[derive(Debug, Serialize)]
+struct Delivery<'a> {
+    id: String,
+    event: WebhookEvent,
+    created_at: String,
+    data: &'a serde_json::Value,
+}
+
+/// Event data describing an indexed document
+pub fn indexed_document(document: &ProcessedDocument, owner: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "document_id": document_id(&document.file_path),
+        "file_path": document.file_path,
+        "file_name": document.file_name,
+        "file_type": document.file_type,
+        "num_chunks": document.num_chunks,
+        "owner": owner
+    })
+}
+
+/// Event data describing a deleted document
+pub fn deleted_document(file_path: &str, deleted_by: Option<&str>) -> serde_json::Value {
+    serde_json::json!({
+        "document_id": document_id(file_path),
+        "file_path": file_path,
+        "deleted_by": deleted_by
+    })
+}
+
+/// Hex HMAC-SHA256 of `message` under `secret`
+fn signature(secret: &str, message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Sends events to the configured URLs in the background. With a secret,
+/// each POST carries `X-Webhook-Signature: sha256=<hex>`, the HMAC-SHA256
+/// of `<X-Webhook-Timestamp>.<body>`, so receivers can check it came from
+/// here and isn't a replay.
+#[derive(Debug, Clone)]
+pub struct Webhooks {
+    urls: Vec<String>,
+    secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<String>, secret: Option<String>) -> Self {
+        Webhooks {
+            urls,
+            secret,
+            client: reqwest::Client::default(),
+        }
+    }
+
+    /// Deliver through `client`, typically the one shared with the LLM providers
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn url_count(&self) -> usize {
+        self.urls.len()
+    }
+
+    /// Send `event` to every URL without waiting; failed deliveries are
+    /// retried a few times, then logged
+    pub fn notify(&self, event: WebhookEvent, data: serde_json::Value) {
+        if self.urls.is_empty() {
+            return;
+        }
+        let delivery = Delivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            event,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            data: &data,
+        };
+        let body = match serde_json::to_string(&delivery) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("Could not serialize webhook event {:?}: {}", event, e);
+                return;
+            }
+        };
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let signature = self
+            .secret
+            .as_deref()
+            .map(|secret| format!("sha256={}", signature(secret, format!("{}.{}", timestamp, body).as_bytes())));
+
+        for url in &self.urls {
+            let request = self
+                .client
+                .post(url)
+                .timeout(DELIVERY_TIMEOUT)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Webhook-Id", &delivery.id)
+                .header("X-Webhook-Timestamp", &timestamp)
+                .body(body.clone());
+            let request = match &signature {
+                Some(signature) => request.header("X-Webhook-Signature", signature),
+                None => request,
+            };
+            let url = url.clone();
+            let id = delivery.id.clone();
+            tokio::spawn(async move { deliver(request, &url, &id, event).await });
+        }
+    }
+}
+
+async fn deliver(request: reqwest::RequestBuilder, url: &str, id: &str, event: WebhookEvent) {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let Some(request) = request.try_clone() else {
+            return;
+        };
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => {
+                log::debug!("Delivered webhook {} ({:?}) to {}", id, event, url);
+                return;
+            }
+            Ok(response) => format!("status {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == MAX_ATTEMPTS {
+            log::warn!("Giving up on webhook {} ({:?}) to {}: {}", id, event, url, error);
+        } else {
+            log::debug!("Webhook {} to {} failed ({}), retrying", id, url, error);
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt - 1)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_and_event_names() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            serde_json::to_value(WebhookEvent::IngestionFailed).unwrap(),
+            serde_json::json!("ingestion.failed")
+        );
+        // No URLs means nothing is spawned, so this needs no runtime
+        Webhooks::new(Vec::new(), None).notify(WebhookEvent::CleanupCompleted, serde_json::json!({}));
+    }
+}
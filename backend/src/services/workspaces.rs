@@ -0,0 +1,186 @@
+use crate::services::VectorStore;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Workspaces one caller may have open at once
+const MAX_WORKSPACES_PER_OWNER: usize = 20;
+
+struct Workspace {
+    owner: Option<String>,
+    last_used: Instant,
+    dir: PathBuf,
+    store: Arc<Mutex<VectorStore>>,
+}
+
+/// A workspace as shown to its owner
+#[derive(Debug, Serialize)]
+pub struct WorkspaceInfo {
+    pub id: String,
+    pub expires_at: String,
+    pub documents: Vec<String>,
+}
+
+/// Temporary collections for asking about files without adding them to the
+/// knowledge base. Each has its own store and uploads under `root`, and is
+/// deleted with them once unused for the TTL. Only the caller who created
+/// a workspace can use it.
+pub struct Workspaces {
+    root: PathBuf,
+    embedding_model: String,
+    ttl: Duration,
+    workspaces: Mutex<HashMap<String, Workspace>>,
+}
+
+impl Workspaces {
+    /// Workspaces left in `root` by an earlier run are deleted; nothing
+    /// else there is touched
+    pub fn new(root: &Path, embedding_model: &str, ttl: Duration) -> Result<Self> {
+        fs::create_dir_all(root)?;
+        for entry in fs::read_dir(root)?.flatten() {
+            let is_workspace = entry.file_name().to_str().is_some_and(|name| uuid::Uuid::parse_str(name).is_ok());
+            if is_workspace && entry.path().is_dir() {
+                delete_dir(&entry.path());
+            }
+        }
+        Ok(Workspaces {
+            root: root.to_path_buf(),
+            embedding_model: embedding_model.to_string(),
+            ttl,
+            workspaces: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn create(&self, owner: Option<&str>) -> Result<WorkspaceInfo> {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        let owned = workspaces.values().filter(|w| w.owner.as_deref() == owner).count();
+        if owned >= MAX_WORKSPACES_PER_OWNER {
+            return Err(anyhow!(
+                "At most {} workspaces can be open at once; delete one first",
+                MAX_WORKSPACES_PER_OWNER
+            ));
+        }
+
+        let id = uuid::Uuid::new_v4().to_string();
+        let dir = self.root.join(&id);
+        let store = VectorStore::new(&dir.join("store").to_string_lossy(), &self.embedding_model)?;
+        let workspace = Workspace {
+            owner: owner.map(str::to_string),
+            last_used: Instant::now(),
+            dir,
+            store: Arc::new(Mutex::new(store)),
+        };
+        let info = self.info_of(&id, &workspace);
+        workspaces.insert(id, workspace);
+        Ok(info)
+    }
+
+    /// Store and upload directory of the caller's workspace `id`, which
+    /// keeps it open for another TTL
+    pub fn open(&self, id: &str, owner: Option<&str>) -> Option<(Arc<Mutex<VectorStore>>, PathBuf)> {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        let workspace = workspaces
+            .get_mut(id)
+            .filter(|w| w.owner.as_deref() == owner && w.last_used.elapsed() < self.ttl)?;
+        workspace.last_used = Instant::now();
+        Some((workspace.store.clone(), workspace.dir.join("files")))
+    }
+
+    pub fn info(&self, id: &str, owner: Option<&str>) -> Option<WorkspaceInfo> {
+        let workspaces = self.workspaces.lock().unwrap();
+        workspaces
+            .get(id)
+            .filter(|w| w.owner.as_deref() == owner && w.last_used.elapsed() < self.ttl)
+            .map(|workspace| self.info_of(id, workspace))
+    }
+
+    fn info_of(&self, id: &str, workspace: &Workspace) -> WorkspaceInfo {
+        let remaining = self.ttl.saturating_sub(workspace.last_used.elapsed());
+        let expires_at = chrono::Utc::now() + chrono::Duration::from_std(remaining).unwrap_or_default();
+        let store = workspace.store.lock().unwrap();
+        WorkspaceInfo {
+            id: id.to_string(),
+            expires_at: expires_at.to_rfc3339(),
+            documents: store
+                .document_paths(None)
+                .into_iter()
+                .filter_map(|path| store.document_info(path).map(|info| info.file_name.clone()))
+                .collect(),
+        }
+    }
+
+    /// Delete the caller's workspace `id` now; false if there's none
+    pub fn remove(&self, id: &str, owner: Option<&str>) -> bool {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        if workspaces.get(id).is_none_or(|w| w.owner.as_deref() != owner) {
+            return false;
+        }
+        if let Some(workspace) = workspaces.remove(id) {
+            delete_dir(&workspace.dir);
+        }
+        true
+    }
+
+    /// Delete workspaces unused for the TTL, returning how many
+    pub fn purge_expired(&self) -> usize {
+        self.purge_expired_at(Instant::now())
+    }
+
+    fn purge_expired_at(&self, now: Instant) -> usize {
+        let mut workspaces = self.workspaces.lock().unwrap();
+        let expired: Vec<String> = workspaces
+            .iter()
+            .filter(|(_, w)| now.saturating_duration_since(w.last_used) >= self.ttl)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            if let Some(workspace) = workspaces.remove(id) {
+                delete_dir(&workspace.dir);
+            }
+        }
+        if !expired.is_empty() {
+            log::info!("Deleted {} expired workspaces", expired.len());
+        }
+        expired.len()
+    }
+}
+
+fn delete_dir(dir: &Path) {
+    if let Err(e) = fs::remove_dir_all(dir) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            log::warn!("Could not delete workspace {}: {}", dir.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspaces_are_private_and_expire() {
+        let root = tempfile::tempdir().unwrap();
+        let workspaces =
+            Workspaces::new(&root.path().join("workspaces"), "all-MiniLM-L6-v2", Duration::from_secs(60)).unwrap();
+
+        let created = workspaces.create(Some("alice")).unwrap();
+        assert!(workspaces.open(&created.id, Some("alice")).is_some());
+        assert!(workspaces.open(&created.id, Some("bob")).is_none());
+        assert!(!workspaces.remove(&created.id, None));
+
+        let dir = root.path().join("workspaces").join(&created.id);
+        assert!(dir.exists());
+        assert_eq!(workspaces.purge_expired_at(Instant::now() + Duration::from_secs(30)), 0);
+        assert_eq!(workspaces.purge_expired_at(Instant::now() + Duration::from_secs(61)), 1);
+        assert!(!dir.exists());
+        assert!(workspaces.info(&created.id, Some("alice")).is_none());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::config::AppConfig;
+use anyhow::Context;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+/// Exports the server's spans over OTLP until shut down
+pub struct Telemetry {
+    provider: TracerProvider,
+    /// Runs the exporter apart from the server, so spans still get flushed
+    /// after the server's runtime has stopped
+    runtime: tokio::runtime::Runtime,
+}
+
+impl Telemetry {
+    /// Export the spans still buffered and stop
+    pub fn shutdown(self) {
+        if let Err(e) = self.provider.shutdown() {
+            log::warn!("Failed to flush traces: {}", e);
+        }
+        self.runtime.shutdown_timeout(Duration::from_secs(5));
+    }
+}
+
+/// Send spans to the OTLP collector at `OTEL_EXPORTER_OTLP_ENDPOINT`, if
+/// set; without one, spans are never recorded. Requests carrying a W3C
+/// `traceparent` header continue the caller's trace.
+pub fn init(config: &AppConfig) -> anyhow::Result<Option<Telemetry>> {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(None);
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("otlp-export")
+        .enable_all()
+        .build()
+        .context("Failed to start the trace exporter")?;
+    let provider = {
+        let _guard = runtime.enter();
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint)
+            .build()
+            .context("Failed to create the OTLP trace exporter")?;
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+            .with_resource(Resource::new([
+                KeyValue::new("service.name", config.otel_service_name.clone()),
+                KeyValue::new("service.version", config.app_version.clone()),
+            ]))
+            .build()
+    };
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("knora")))
+        .try_init()
+        .context("Failed to install the trace subscriber")?;
+
+    log::info!("Exporting traces to {} as {}", endpoint, config.otel_service_name);
+    Ok(Some(Telemetry { provider, runtime }))
+}
@@ -0,0 +1,152 @@
+use crate::handlers::llm;
+use crate::models::QueryRequest;
+use crate::services::experiment::ExperimentTracker;
+use crate::services::few_shot::FewShotStore;
+use crate::services::pipeline::PipelineStore;
+use crate::services::semantic_cache::SemanticCache;
+use crate::services::workspaces::Workspaces;
+use crate::services::{LLMHandler, PromptTemplateStore, VectorStore};
+use actix_web::web;
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Queries in `path`, one per line, skipping blank lines and `#` comments
+pub fn load_queries(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read warm-up queries from {}", path.display()))?;
+    Ok(parse_queries(&text))
+}
+
+fn parse_queries(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// What answering a query the way `POST /api/query` does takes
+struct Generation {
+    llm_handler: web::Data<LLMHandler>,
+    templates: web::Data<Mutex<PromptTemplateStore>>,
+    few_shot: web::Data<Mutex<FewShotStore>>,
+    pipelines: web::Data<PipelineStore>,
+    workspaces: web::Data<Workspaces>,
+    semantic_cache: Option<web::Data<SemanticCache>>,
+}
+
+/// Runs frequent queries ahead of users, so the first to ask them find
+/// the query embeddings, and with generation the answers, already cached
+pub struct WarmUp {
+    queries: Vec<String>,
+    vector_store: web::Data<Mutex<VectorStore>>,
+    generation: Option<Generation>,
+}
+
+impl WarmUp {
+    pub fn new(queries: Vec<String>, vector_store: web::Data<Mutex<VectorStore>>) -> Self {
+        WarmUp {
+            queries,
+            vector_store,
+            generation: None,
+        }
+    }
+
+    /// Also answer each query as a request naming only the query would be,
+    /// outside any experiment and without recording analytics
+    pub fn with_generation(
+        mut self,
+        llm_handler: web::Data<LLMHandler>,
+        templates: web::Data<Mutex<PromptTemplateStore>>,
+        few_shot: web::Data<Mutex<FewShotStore>>,
+        pipelines: web::Data<PipelineStore>,
+        workspaces: web::Data<Workspaces>,
+        semantic_cache: Option<web::Data<SemanticCache>>,
+    ) -> Self {
+        self.generation = Some(Generation {
+            llm_handler,
+            templates,
+            few_shot,
+            pipelines,
+            workspaces,
+            semantic_cache,
+        });
+        self
+    }
+
+    /// Run every query once. A query that fails is logged and skipped.
+    pub async fn run(&self) {
+        let started = Instant::now();
+        let mut warmed = 0;
+        for query in &self.queries {
+            let result = match &self.generation {
+                Some(generation) => self.answer(query, generation).await,
+                None => self.vector_store.lock().unwrap().query_embedding(query).map(drop),
+            };
+            match result {
+                Ok(()) => warmed += 1,
+                Err(e) => log::warn!("Failed to warm caches with '{}': {}", query, e),
+            }
+        }
+        info!(
+            "Warmed caches with {} of {} queries in {:.1}s",
+            warmed,
+            self.queries.len(),
+            started.elapsed().as_secs_f32()
+        );
+    }
+
+    async fn answer(&self, query: &str, generation: &Generation) -> Result<()> {
+        let req: QueryRequest = serde_json::from_value(serde_json::json!({ "query": query }))?;
+        llm::query(
+            web::Json(req),
+            None,
+            generation.llm_handler.clone(),
+            self.vector_store.clone(),
+            generation.templates.clone(),
+            generation.few_shot.clone(),
+            generation.pipelines.clone(),
+            web::Data::new(ExperimentTracker::new(None)),
+            generation.workspaces.clone(),
+            None,
+            generation.semantic_cache.clone(),
+        )
+        .await
+        .map_err(|e| anyhow!("{}", e))?;
+        Ok(())
+    }
+
+    /// Run the queries in the background now, then every `period` if given
+    pub fn spawn(self, period: Option<Duration>) {
+        actix_web::rt::spawn(async move {
+            self.run().await;
+            let Some(period) = period else {
+                return;
+            };
+            let mut interval = actix_web::rt::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                self.run().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_skip_blank_lines_and_comments() {
+        let text = "# Asked every Monday\nHow do I reset my password?\n\n  What is the refund policy?  \n";
+        assert_eq!(
+            parse_queries(text),
+            vec!["How do I reset my password?", "What is the refund policy?"]
+        );
+    }
+}
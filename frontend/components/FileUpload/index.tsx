@@ -114,22 +114,21 @@ export default function FileUploadComponent() {
 
   const validateFile = (file: File): boolean => {
     const extension = "." + file.name.split(".").pop()?.toLowerCase();
-    const maxSizeMB = 100;
+    const format = supportedFormats.find(
+      (fmt) => fmt.extension.toLowerCase() === extension.toLowerCase(),
+    );
 
-    if (file.size > maxSizeMB * 1024 * 1024) {
+    if (!format) {
       toast.error(
-        `File "${file.name}" exceeds ${maxSizeMB}MB limit (${(file.size / (1024 * 1024)).toFixed(2)}MB)`,
+        `File type "${extension}" not supported. Supported types: ${supportedFormats.map((f) => f.extension).join(", ")}`,
       );
       return false;
     }
 
-    const isSupported = supportedFormats.some(
-      (fmt) => fmt.extension.toLowerCase() === extension.toLowerCase(),
-    );
-
-    if (!isSupported) {
+    const maxSizeMB = format.max_size_mb;
+    if (file.size > maxSizeMB * 1024 * 1024) {
       toast.error(
-        `File type "${extension}" not supported. Supported types: ${supportedFormats.map((f) => f.extension).join(", ")}`,
+        `File "${file.name}" exceeds ${maxSizeMB}MB limit (${(file.size / (1024 * 1024)).toFixed(2)}MB)`,
       );
       return false;
     }
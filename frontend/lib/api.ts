@@ -321,7 +321,7 @@ export const apiService = {
     apiClient.post("/search/add", documents),
   deleteDocument: (filePath: string) =>
     apiClient.delete("/search/delete", { params: { file_path: filePath } }),
-  clearStore: () => apiClient.delete("/search/clear"),
+  clearStore: () => apiClient.delete("/admin/store"),
 
   // LLM
   generateAnswer: async (data: AnswerRequest) => {
@@ -353,9 +353,9 @@ export const apiService = {
   getSupportedModels: () => apiClient.get<LLMModel[]>("/llm/models"),
 
   // Storage Management
-  getStorageInfo: () => apiClient.get<StorageInfo>("/search/storage"),
+  getStorageInfo: () => apiClient.get<StorageInfo>("/admin/storage"),
   cleanupOldFiles: () =>
-    apiClient.post<CleanupResponse>("/search/storage/cleanup", {}),
+    apiClient.post<CleanupResponse>("/admin/storage/cleanup", {}),
 };
 
 export default apiClient;
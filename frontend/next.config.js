@@ -1,7 +1,12 @@
 /** @type {import('next').NextConfig} */
+const staticExport = process.env.NEXT_STATIC_EXPORT === '1';
+
 const nextConfig = {
   reactStrictMode: true,
   swcMinify: true,
+  // `NEXT_STATIC_EXPORT=1 npm run build` writes plain files to out/ for
+  // the backend to serve with STATIC_DIR
+  ...(staticExport && { output: 'export', trailingSlash: true, images: { unoptimized: true } }),
   env: {
     NEXT_PUBLIC_API_URL: process.env.NEXT_PUBLIC_API_URL || 'http://localhost:8000/api',
   },